@@ -0,0 +1,86 @@
+#![no_std]
+#![no_main]
+
+use cortex_m_rt::entry;
+use cortex_m_semihosting::hprintln;
+use panic_semihosting as _; // panic handler
+use rtic_monotonic::Monotonic;
+use sam4e_xplained_pro::{
+    ethernet::{self, Buffers, Ethernet},
+    hal::{
+        pac::{CorePeripherals, Peripherals},
+        watchdog::*,
+    },
+    monotonic::RttMonotonic,
+    Board, XplainedBoard,
+};
+use smoltcp::{
+    iface::{Config, Interface, SocketSet, SocketStorage},
+    socket::udp,
+    time::Instant,
+    wire::{IpAddress, IpCidr},
+};
+
+/// UDP echo port
+const PORT: u16 = 7;
+
+#[entry]
+fn main() -> ! {
+    let core = CorePeripherals::take().unwrap();
+    let peripherals = Peripherals::take().unwrap();
+    let mut board = Board::new(core.SYST, peripherals);
+    board.watchdog.disable();
+    let mut clock = RttMonotonic::<1024>::new(board.rtt);
+    let mut now = || Instant::from_millis(clock.now().duration_since_epoch().to_millis());
+
+    let buffers = cortex_m::singleton!(: Buffers<8, 4> = Buffers::new()).unwrap();
+    let mac = ethernet::mac_address(board.unique_id);
+    let mut ethernet = Ethernet::new(board.gmac, buffers, mac).unwrap();
+    hprintln!("MAC address {}", mac).ok();
+
+    // Address of the board; change it to suit the network.
+    let address = IpCidr::new(IpAddress::v4(192, 168, 1, 50), 24);
+    let mut interface = Interface::new(Config::new(mac.into()), &mut ethernet, now());
+    interface.update_ip_addrs(|addresses| addresses.push(address).unwrap());
+
+    let rx_metadata =
+        cortex_m::singleton!(: [udp::PacketMetadata; 4] = [udp::PacketMetadata::EMPTY; 4]).unwrap();
+    let rx_payload = cortex_m::singleton!(: [u8; 1536] = [0; 1536]).unwrap();
+    let tx_metadata =
+        cortex_m::singleton!(: [udp::PacketMetadata; 4] = [udp::PacketMetadata::EMPTY; 4]).unwrap();
+    let tx_payload = cortex_m::singleton!(: [u8; 1536] = [0; 1536]).unwrap();
+    let socket = udp::Socket::new(
+        udp::PacketBuffer::new(&mut rx_metadata[..], &mut rx_payload[..]),
+        udp::PacketBuffer::new(&mut tx_metadata[..], &mut tx_payload[..]),
+    );
+    let mut storage = [SocketStorage::EMPTY; 1];
+    let mut sockets = SocketSet::new(&mut storage[..]);
+    let handle = sockets.add(socket);
+    sockets.get_mut::<udp::Socket>(handle).bind(PORT).unwrap();
+
+    let mut link = None;
+    loop {
+        if ethernet.poll_link() != link {
+            link = ethernet.link();
+            match link {
+                Some(link) => hprintln!(
+                    "Link up: {:?}, echoing UDP on {}:{}",
+                    link,
+                    address.address(),
+                    PORT
+                ),
+                None => hprintln!("Link down"),
+            }
+            .ok();
+        }
+
+        interface.poll(now(), &mut ethernet, &mut sockets);
+        let socket = sockets.get_mut::<udp::Socket>(handle);
+        let mut datagram = [0u8; 1472];
+        if let Ok((length, metadata)) = socket.recv_slice(&mut datagram) {
+            socket
+                .send_slice(&datagram[..length], metadata.endpoint)
+                .ok();
+        }
+    }
+}
@@ -0,0 +1,548 @@
+//! Ethernet through the GMAC and the KSZ8081RNA PHY
+//!
+//! [`Ethernet`] moves frames between the GMAC's receive and transmit
+//! descriptor rings and [`smoltcp`], as a [`smoltcp::phy::Device`].  The rings
+//! and their frame buffers are a [`Buffers`] in static memory, one buffer of
+//! [`BUFFER_SIZE`] bytes per descriptor, so every frame fits a single buffer:
+//!
+//! ```rust
+//! let mut board = Board::new(core.SYST, peripherals);
+//! let buffers = cortex_m::singleton!(: Buffers<8, 4> = Buffers::new()).unwrap();
+//! let mac = ethernet::mac_address(board.unique_id);
+//! let mut ethernet = Ethernet::new(board.gmac, buffers, mac)?;
+//!
+//! let mut interface = Interface::new(Config::new(mac.into()), &mut ethernet, now());
+//! loop {
+//!     ethernet.poll_link();
+//!     interface.poll(now(), &mut ethernet, &mut sockets);
+//! }
+//! ```
+//!
+//! The PHY auto-negotiates the link, and the GMAC follows the speed and duplex
+//! it comes up with in [`Ethernet::poll_link`].  Instead of polling,
+//! [`Ethernet::enable_link_interrupt`] has the PHY raise the PIOD interrupt
+//! on link changes, which calls [`Ethernet::on_link_interrupt`].
+
+use core::sync::atomic::{compiler_fence, Ordering};
+use core::{fmt, ptr};
+
+use cortex_m::peripheral::NVIC;
+use sam_xplained::hal::clock::{get_master_clock_frequency, Enabled, GmacClock};
+use sam_xplained::hal::pac::{self, Interrupt, GMAC};
+use sam_xplained::image::crc32;
+use sam_xplained::{Gmac, PhyInterrupt};
+use smoltcp::phy::{self, Device, DeviceCapabilities, Medium};
+use smoltcp::time::Instant;
+use smoltcp::wire::EthernetAddress;
+
+mod ksz8081;
+
+pub use self::ksz8081::{Link, Phy, Speed};
+
+/// Size of each frame buffer: a maximum size frame, which the GMAC limits to
+/// 1536 bytes, in multiples of 64 bytes
+pub const BUFFER_SIZE: usize = 1536;
+
+/// Largest frame handed to smoltcp, without the FCS
+const MTU: usize = 1514;
+
+// Receive descriptor bits
+const RX_OWNERSHIP: u32 = 1 << 0;
+const RX_WRAP: u32 = 1 << 1;
+const RX_LENGTH: u32 = 0x1FFF;
+const RX_START_OF_FRAME: u32 = 1 << 14;
+const RX_END_OF_FRAME: u32 = 1 << 15;
+// Transmit descriptor bits
+const TX_LAST_BUFFER: u32 = 1 << 15;
+const TX_WRAP: u32 = 1 << 30;
+const TX_USED: u32 = 1 << 31;
+
+/// PIO line of the PHY interrupt (PD28)
+const PHY_INT_MASK: u32 = 1 << 28;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// No KSZ8081 answers on the management port
+    NoPhy,
+    /// The PHY did not come out of reset
+    PhyReset,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::NoPhy => f.write_str("no PHY found"),
+            Error::PhyReset => f.write_str("PHY reset timed out"),
+        }
+    }
+}
+
+/// [`Ethernet::new`] failed.  The GMAC is left reset with its clock disabled,
+/// and handed back with the buffers for another attempt.
+pub struct InitError<const RX: usize, const TX: usize> {
+    pub error: Error,
+    pub gmac: Gmac,
+    pub buffers: &'static mut Buffers<RX, TX>,
+}
+
+impl<const RX: usize, const TX: usize> fmt::Debug for InitError<RX, TX> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("InitError")
+            .field("error", &self.error)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<const RX: usize, const TX: usize> fmt::Display for InitError<RX, TX> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.error.fmt(f)
+    }
+}
+
+/// MAC address of the board, derived from the unique identifier of the
+/// microcontroller: locally administered and unicast, as the board has no
+/// assigned address.
+pub fn mac_address(unique_id: [u32; 4]) -> EthernetAddress {
+    let mut bytes = [0u8; 16];
+    for (chunk, word) in bytes.chunks_exact_mut(4).zip(unique_id.iter()) {
+        chunk.copy_from_slice(&word.to_le_bytes());
+    }
+    let low = crc32(&bytes).to_be_bytes();
+    let high = (crc32(&bytes[..8]) ^ crc32(&bytes[8..])).to_be_bytes();
+    EthernetAddress([
+        (high[0] & 0xFC) | 0x02,
+        high[1],
+        low[0],
+        low[1],
+        low[2],
+        low[3],
+    ])
+}
+
+/// Buffer descriptor, as the GMAC reads and writes it
+#[derive(Clone, Copy)]
+#[repr(C)]
+struct Descriptor {
+    address: u32,
+    status: u32,
+}
+
+/// Descriptor ring with its frame buffers
+#[repr(C)]
+struct Ring<const N: usize> {
+    descriptors: [Descriptor; N],
+    data: [[u8; BUFFER_SIZE]; N],
+    /// Descriptor the driver processes next
+    next: usize,
+}
+
+impl<const N: usize> Ring<N> {
+    const fn new() -> Self {
+        Ring {
+            descriptors: [Descriptor {
+                address: 0,
+                status: 0,
+            }; N],
+            data: [[0; BUFFER_SIZE]; N],
+            next: 0,
+        }
+    }
+
+    fn address(&self) -> u32 {
+        self.descriptors.as_ptr() as u32
+    }
+
+    fn read(&self, index: usize) -> Descriptor {
+        unsafe { ptr::read_volatile(&self.descriptors[index]) }
+    }
+
+    fn write(&mut self, index: usize, descriptor: Descriptor) {
+        unsafe { ptr::write_volatile(&mut self.descriptors[index], descriptor) }
+    }
+
+    fn advance(&mut self) {
+        self.next = (self.next + 1) % N;
+    }
+
+    /// Hands receive buffers to the GMAC.
+    fn init_rx(&mut self) {
+        for index in 0..N {
+            let wrap = if index == N - 1 { RX_WRAP } else { 0 };
+            let address = self.data[index].as_ptr() as u32;
+            self.write(
+                index,
+                Descriptor {
+                    address: address | wrap,
+                    status: 0,
+                },
+            );
+        }
+        self.next = 0;
+    }
+
+    /// Leaves every transmit buffer to software.
+    fn init_tx(&mut self) {
+        for index in 0..N {
+            let wrap = if index == N - 1 { TX_WRAP } else { 0 };
+            let address = self.data[index].as_ptr() as u32;
+            self.write(
+                index,
+                Descriptor {
+                    address,
+                    status: TX_USED | wrap,
+                },
+            );
+        }
+        self.next = 0;
+    }
+}
+
+/// Descriptor rings and frame buffers for `RX` received and `TX` transmitted
+/// frames, which the GMAC accesses by DMA
+#[repr(C)]
+pub struct Buffers<const RX: usize, const TX: usize> {
+    rx: Ring<RX>,
+    tx: Ring<TX>,
+}
+
+impl<const RX: usize, const TX: usize> Buffers<RX, TX> {
+    pub const fn new() -> Self {
+        Buffers {
+            rx: Ring::new(),
+            tx: Ring::new(),
+        }
+    }
+}
+
+impl<const RX: usize, const TX: usize> Default for Buffers<RX, TX> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// GMAC and KSZ8081 PHY of the board
+pub struct Ethernet<const RX: usize, const TX: usize> {
+    gmac: GMAC,
+    _clock: GmacClock<Enabled>,
+    phy: Phy,
+    phy_interrupt: PhyInterrupt,
+    rx: &'static mut Ring<RX>,
+    tx: &'static mut Ring<TX>,
+    mac_address: EthernetAddress,
+    link: Option<Link>,
+}
+
+impl<const RX: usize, const TX: usize> Ethernet<RX, TX> {
+    /// Brings up the GMAC with the rings in `buffers`, resets the PHY and
+    /// starts auto-negotiation.  Frames flow once the link is up, see
+    /// [`Ethernet::poll_link`].
+    ///
+    /// Panics unless both rings have at least one descriptor.
+    pub fn new(
+        gmac: Gmac,
+        buffers: &'static mut Buffers<RX, TX>,
+        mac_address: EthernetAddress,
+    ) -> Result<Self, InitError<RX, TX>> {
+        assert!(RX > 0 && TX > 0, "empty descriptor ring");
+        let Gmac {
+            gmac,
+            clock,
+            phy_interrupt,
+        } = gmac;
+        let clock = clock.into_enabled_clock();
+
+        gmac.ncr.reset();
+        gmac.idr.write_with_zero(|w| unsafe { w.bits(0xFFFF_FFFF) });
+        gmac.rsr.write_with_zero(|w| unsafe { w.bits(0xF) });
+        gmac.tsr.write_with_zero(|w| unsafe { w.bits(0x1FF) });
+        let _ = gmac.isr.read();
+
+        // Set for both MII and RMII, as in Microchip's GMAC driver
+        gmac.ur.write(|w| w.mii().set_bit());
+        // MDC at no more than 2.5 MHz
+        let mck = get_master_clock_frequency().0;
+        gmac.ncfgr.write(|w| {
+            let w = match mck {
+                0..=20_000_000 => w.clk().mck_8(),
+                20_000_001..=40_000_000 => w.clk().mck_16(),
+                40_000_001..=80_000_000 => w.clk().mck_32(),
+                80_000_001..=120_000_000 => w.clk().mck_48(),
+                _ => w.clk().mck_64(),
+            };
+            w.maxfs()
+                .set_bit()
+                .rfcs()
+                .set_bit()
+                .spd()
+                .set_bit()
+                .fd()
+                .set_bit()
+        });
+        gmac.dcfgr
+            .write(|w| unsafe { w.fbldo().incr4().drbs().bits((BUFFER_SIZE / 64) as u8) });
+
+        let [b0, b1, b2, b3, b4, b5] = mac_address.0;
+        gmac.sab1
+            .write(|w| unsafe { w.bits(u32::from_le_bytes([b0, b1, b2, b3])) });
+        // Writing the top enables the filter.
+        gmac.sat1
+            .write(|w| unsafe { w.bits(u32::from(u16::from_le_bytes([b4, b5]))) });
+
+        buffers.rx.init_rx();
+        buffers.tx.init_tx();
+        compiler_fence(Ordering::Release);
+        gmac.rbqb.write(|w| unsafe { w.bits(buffers.rx.address()) });
+        gmac.tbqb.write(|w| unsafe { w.bits(buffers.tx.address()) });
+
+        gmac.ncr.write(|w| w.mpe().set_bit());
+        let phy = match Phy::find(&gmac) {
+            Some(phy) if phy.reset(&gmac) => Ok(phy),
+            Some(_) => Err(Error::PhyReset),
+            None => Err(Error::NoPhy),
+        };
+        let phy = match phy {
+            Ok(phy) => phy,
+            Err(error) => {
+                gmac.ncr.reset();
+                return Err(InitError {
+                    error,
+                    gmac: Gmac {
+                        gmac,
+                        clock: clock.into_disabled_clock(),
+                        phy_interrupt,
+                    },
+                    buffers,
+                });
+            }
+        };
+        phy.start_autonegotiation(&gmac);
+
+        gmac.ncr
+            .write(|w| w.mpe().set_bit().rxen().set_bit().txen().set_bit());
+
+        let Buffers { rx, tx } = buffers;
+        Ok(Ethernet {
+            gmac,
+            _clock: clock,
+            phy,
+            phy_interrupt,
+            rx,
+            tx,
+            mac_address,
+            link: None,
+        })
+    }
+
+    pub fn mac_address(&self) -> EthernetAddress {
+        self.mac_address
+    }
+
+    /// The link as of the last [`Ethernet::poll_link`] or
+    /// [`Ethernet::on_link_interrupt`]
+    pub fn link(&self) -> Option<Link> {
+        self.link
+    }
+
+    /// Reads the link from the PHY and sets the GMAC to its speed and duplex,
+    /// returning it.
+    pub fn poll_link(&mut self) -> Option<Link> {
+        let link = self.phy.link(&self.gmac);
+        if link != self.link {
+            if let Some(link) = link {
+                self.gmac.ncfgr.modify(|_, w| {
+                    w.spd()
+                        .bit(link.speed == Speed::Mbps100)
+                        .fd()
+                        .bit(link.full_duplex)
+                });
+            }
+            self.link = link;
+        }
+        link
+    }
+
+    /// Has the PHY interrupt on PD28 report link changes, and unmasks the
+    /// PIOD interrupt, which must call [`Ethernet::on_link_interrupt`].
+    pub fn enable_link_interrupt(&mut self) {
+        let pio = piod();
+        // INTRP is held low until the PHY's status is read.
+        pio.aimer
+            .write_with_zero(|w| unsafe { w.bits(PHY_INT_MASK) });
+        pio.esr.write_with_zero(|w| unsafe { w.bits(PHY_INT_MASK) });
+        pio.fellsr
+            .write_with_zero(|w| unsafe { w.bits(PHY_INT_MASK) });
+        self.phy.enable_link_interrupt(&self.gmac);
+        let _ = pio.isr.read();
+        pio.ier.write_with_zero(|w| unsafe { w.bits(PHY_INT_MASK) });
+        unsafe { NVIC::unmask(Interrupt::PIOD) };
+    }
+
+    /// Handles the PIOD interrupt like [`Ethernet::poll_link`], returning the
+    /// link.
+    pub fn on_link_interrupt(&mut self) -> Option<Link> {
+        if piod().isr.read().bits() & PHY_INT_MASK != 0 {
+            self.phy.clear_interrupt(&self.gmac);
+            self.poll_link();
+        }
+        self.link
+    }
+
+    /// Reads register `register` of the PHY.
+    pub fn read_phy(&self, register: u8) -> u16 {
+        self.phy.read(&self.gmac, register)
+    }
+
+    /// Writes register `register` of the PHY.
+    pub fn write_phy(&self, register: u8, value: u16) {
+        self.phy.write(&self.gmac, register, value)
+    }
+
+    /// Stops the GMAC and gives back the peripheral and the PHY interrupt
+    /// line.  The GMAC clock stays enabled.
+    pub fn free(self) -> (GMAC, PhyInterrupt) {
+        self.gmac.ncr.reset();
+        piod()
+            .idr
+            .write_with_zero(|w| unsafe { w.bits(PHY_INT_MASK) });
+        (self.gmac, self.phy_interrupt)
+    }
+
+    /// Restarts transmission from the start of the ring after an error,
+    /// which leaves the GMAC there.
+    fn recover_transmit(&mut self) {
+        let status = self.gmac.tsr.read();
+        if status.rle().bit_is_clear()
+            && status.tfc().bit_is_clear()
+            && status.und().bit_is_clear()
+            && status.hresp().bit_is_clear()
+        {
+            return;
+        }
+        self.gmac.ncr.modify(|_, w| w.txen().clear_bit());
+        self.tx.init_tx();
+        compiler_fence(Ordering::Release);
+        self.gmac.tsr.write_with_zero(|w| unsafe { w.bits(0x1FF) });
+        self.gmac.ncr.modify(|_, w| w.txen().set_bit());
+    }
+
+    fn can_transmit(&self) -> bool {
+        self.link.is_some() && self.tx.read(self.tx.next).status & TX_USED != 0
+    }
+
+    fn has_received(&self) -> bool {
+        self.rx.read(self.rx.next).address & RX_OWNERSHIP != 0
+    }
+}
+
+fn piod() -> &'static pac::piod::RegisterBlock {
+    unsafe { &*pac::PIOD::ptr() }
+}
+
+/// Frame received into the receive ring, released once consumed
+pub struct RxToken<'a, const N: usize> {
+    ring: &'a mut Ring<N>,
+}
+
+impl<'a, const N: usize> phy::RxToken for RxToken<'a, N> {
+    fn consume<R, F>(self, f: F) -> R
+    where
+        F: FnOnce(&[u8]) -> R,
+    {
+        let index = self.ring.next;
+        compiler_fence(Ordering::Acquire);
+        let Descriptor { address, status } = self.ring.read(index);
+        const WHOLE_FRAME: u32 = RX_START_OF_FRAME | RX_END_OF_FRAME;
+        // Frames never span buffers of the maximum frame size.
+        let length = if status & WHOLE_FRAME == WHOLE_FRAME {
+            ((status & RX_LENGTH) as usize).min(BUFFER_SIZE)
+        } else {
+            0
+        };
+        let result = f(&self.ring.data[index][..length]);
+        compiler_fence(Ordering::Release);
+        self.ring.write(
+            index,
+            Descriptor {
+                address: address & !RX_OWNERSHIP,
+                status: 0,
+            },
+        );
+        self.ring.advance();
+        result
+    }
+}
+
+/// Free buffer of the transmit ring, queued once filled
+pub struct TxToken<'a, const N: usize> {
+    ring: &'a mut Ring<N>,
+    gmac: &'a GMAC,
+}
+
+impl<'a, const N: usize> phy::TxToken for TxToken<'a, N> {
+    fn consume<R, F>(self, len: usize, f: F) -> R
+    where
+        F: FnOnce(&mut [u8]) -> R,
+    {
+        let index = self.ring.next;
+        let result = f(&mut self.ring.data[index][..len]);
+        let wrap = if index == N - 1 { TX_WRAP } else { 0 };
+        let address = self.ring.data[index].as_ptr() as u32;
+        compiler_fence(Ordering::Release);
+        self.ring.write(
+            index,
+            Descriptor {
+                address,
+                status: len as u32 | TX_LAST_BUFFER | wrap,
+            },
+        );
+        self.ring.advance();
+        self.gmac.ncr.modify(|_, w| w.tstart().set_bit());
+        result
+    }
+}
+
+impl<const RX: usize, const TX: usize> Device for Ethernet<RX, TX> {
+    type RxToken<'a>
+        = RxToken<'a, RX>
+    where
+        Self: 'a;
+    type TxToken<'a>
+        = TxToken<'a, TX>
+    where
+        Self: 'a;
+
+    fn receive(&mut self, _timestamp: Instant) -> Option<(Self::RxToken<'_>, Self::TxToken<'_>)> {
+        self.recover_transmit();
+        if !self.has_received() || !self.can_transmit() {
+            return None;
+        }
+        Some((
+            RxToken {
+                ring: &mut *self.rx,
+            },
+            TxToken {
+                ring: &mut *self.tx,
+                gmac: &self.gmac,
+            },
+        ))
+    }
+
+    fn transmit(&mut self, _timestamp: Instant) -> Option<Self::TxToken<'_>> {
+        self.recover_transmit();
+        if !self.can_transmit() {
+            return None;
+        }
+        Some(TxToken {
+            ring: &mut *self.tx,
+            gmac: &self.gmac,
+        })
+    }
+
+    fn capabilities(&self) -> DeviceCapabilities {
+        let mut capabilities = DeviceCapabilities::default();
+        capabilities.medium = Medium::Ethernet;
+        capabilities.max_transmission_unit = MTU;
+        capabilities.max_burst_size = Some(TX);
+        capabilities
+    }
+}
@@ -0,0 +1,145 @@
+//! Management of the KSZ8081 PHY over MDIO
+//!
+//! The GMAC's PHY maintenance register shifts clause 22 frames out on
+//! GMDC/GMDIO; each access takes 64 MDC cycles, about 26 us at 2.5 MHz.
+
+use sam_xplained::hal::pac::GMAC;
+
+// IEEE 802.3 registers
+const BMCR: u8 = 0x00;
+const BMSR: u8 = 0x01;
+const PHYID1: u8 = 0x02;
+const PHYID2: u8 = 0x03;
+const ANAR: u8 = 0x04;
+// KSZ8081 registers
+const INTERRUPT: u8 = 0x1B;
+const CONTROL1: u8 = 0x1E;
+
+// BMCR bits
+const RESET: u16 = 1 << 15;
+const AUTONEG_ENABLE: u16 = 1 << 12;
+const AUTONEG_RESTART: u16 = 1 << 9;
+// BMSR bits
+const LINK_UP: u16 = 1 << 2;
+const AUTONEG_COMPLETE: u16 = 1 << 5;
+/// ANAR: 100BASE-TX and 10BASE-T, full and half duplex, IEEE 802.3
+const ADVERTISE_ALL: u16 = 0x01E1;
+/// Interrupt control/status: link up and link down enables
+const LINK_INTERRUPTS: u16 = (1 << 8) | (1 << 10);
+
+/// PHY identifier of the KSZ8081, without the revision
+const KSZ8081_ID: (u16, u16) = (0x0022, 0x1560);
+const REVISION_MASK: u16 = 0x000F;
+
+/// Polls of BMCR for the end of a reset, well over its 500 us
+const RESET_POLLS: u32 = 1000;
+
+// GMAC_MAN operations
+const WRITE: u8 = 0b01;
+const READ: u8 = 0b10;
+
+/// Speed and duplex the link came up with
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Link {
+    pub speed: Speed,
+    pub full_duplex: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Speed {
+    Mbps10,
+    Mbps100,
+}
+
+/// KSZ8081 at a PHY address; the GMAC's management port must be enabled.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Phy {
+    address: u8,
+}
+
+impl Phy {
+    /// Looks for the KSZ8081 at every PHY address, as the address is set by
+    /// strapping pins.
+    pub fn find(gmac: &GMAC) -> Option<Phy> {
+        (0..32).map(|address| Phy { address }).find(|phy| {
+            phy.read(gmac, PHYID1) == KSZ8081_ID.0
+                && phy.read(gmac, PHYID2) & !REVISION_MASK == KSZ8081_ID.1
+        })
+    }
+
+    pub fn address(&self) -> u8 {
+        self.address
+    }
+
+    /// Reads PHY register `register`.
+    pub fn read(&self, gmac: &GMAC, register: u8) -> u16 {
+        self.transfer(gmac, READ, register, 0);
+        gmac.man.read().data().bits()
+    }
+
+    /// Writes PHY register `register`.
+    pub fn write(&self, gmac: &GMAC, register: u8, value: u16) {
+        self.transfer(gmac, WRITE, register, value);
+    }
+
+    fn transfer(&self, gmac: &GMAC, operation: u8, register: u8, value: u16) {
+        gmac.man.write(|w| unsafe {
+            w.cltto()
+                .set_bit()
+                .op()
+                .bits(operation)
+                .phya()
+                .bits(self.address)
+                .rega()
+                .bits(register)
+                .wtn()
+                .bits(0b10)
+                .data()
+                .bits(value)
+        });
+        while gmac.nsr.read().idle().bit_is_clear() {}
+    }
+
+    /// Resets the PHY, returning whether it came out of reset.
+    pub fn reset(&self, gmac: &GMAC) -> bool {
+        self.write(gmac, BMCR, RESET);
+        (0..RESET_POLLS).any(|_| self.read(gmac, BMCR) & RESET == 0)
+    }
+
+    /// Advertises every speed and duplex mode and restarts auto-negotiation.
+    pub fn start_autonegotiation(&self, gmac: &GMAC) {
+        self.write(gmac, ANAR, ADVERTISE_ALL);
+        self.write(gmac, BMCR, AUTONEG_ENABLE | AUTONEG_RESTART);
+    }
+
+    /// The link, once it is up and auto-negotiation has completed
+    pub fn link(&self, gmac: &GMAC) -> Option<Link> {
+        // The link status latches low: the first read reports a past loss.
+        self.read(gmac, BMSR);
+        let status = self.read(gmac, BMSR);
+        if status & (LINK_UP | AUTONEG_COMPLETE) != LINK_UP | AUTONEG_COMPLETE {
+            return None;
+        }
+        // Operation mode indication of PHY Control 1
+        let (speed, full_duplex) = match self.read(gmac, CONTROL1) & 0b111 {
+            0b001 => (Speed::Mbps10, false),
+            0b010 => (Speed::Mbps100, false),
+            0b101 => (Speed::Mbps10, true),
+            0b110 => (Speed::Mbps100, true),
+            _ => return None,
+        };
+        Some(Link { speed, full_duplex })
+    }
+
+    /// Drives INTRP low on link up and link down, until
+    /// [`Phy::clear_interrupt`].
+    pub fn enable_link_interrupt(&self, gmac: &GMAC) {
+        self.write(gmac, INTERRUPT, LINK_INTERRUPTS);
+        self.clear_interrupt(gmac);
+    }
+
+    /// Releases INTRP, returning the interrupt status bits.
+    pub fn clear_interrupt(&self, gmac: &GMAC) -> u16 {
+        self.read(gmac, INTERRUPT) & 0xFF
+    }
+}
@@ -1,67 +1,13 @@
+//! Board support for the SAM4E Xplained Pro
+//!
+//! This crate is the [`sam_xplained`] crate with the `sam4e` board selected;
+//! see there for the examples and documentation.
+//!
+//! With the `ethernet` feature, the [`ethernet`] module drives the GMAC and
+//! the KSZ8081 PHY as a `smoltcp` device.
 #![no_std]
 
-pub use atsam4_hal as hal;
-use paste::paste;
+pub use sam_xplained::*;
 
-use atsam4_hal::{define_pin_map, gpio::*};
-
-define_pin_map! {
-    struct Pins,
-
-    // Onboard LED
-    pin led0 = d22<Output<OpenDrain>, into_open_drain_output>,
-
-    // Onboard Button labeled SW0
-    pin sw0 = a2<Input<PullUp>, into_pull_up_input>,
-
-    // Serial Console (UART0)
-    pin uart0_rx = a9<PfA, into_peripheral_function_a>,
-    pin uart0_tx = a10<PfA, into_peripheral_function_a>,
-
-    // Static Memory Controller Pins
-    pin ncs1 = d18<PfA, into_peripheral_function_a>,
-    pin ncs3 = d19<PfA, into_peripheral_function_a>,
-
-    pin nrd = c11<PfA, into_peripheral_function_a>,
-    pin nwe = c8<PfA, into_peripheral_function_a>,
-
-    pin d0 = c0<PfA, into_peripheral_function_a>,
-    pin d1 = c1<PfA, into_peripheral_function_a>,
-    pin d2 = c2<PfA, into_peripheral_function_a>,
-    pin d3 = c3<PfA, into_peripheral_function_a>,
-    pin d4 = c4<PfA, into_peripheral_function_a>,
-    pin d5 = c5<PfA, into_peripheral_function_a>,
-    pin d6 = c6<PfA, into_peripheral_function_a>,
-    pin d7 = c7<PfA, into_peripheral_function_a>,
-
-    pin a0 = c18<PfA, into_peripheral_function_a>,
-    pin a1 = c19<PfA, into_peripheral_function_a>,
-    pin a2 = c20<PfA, into_peripheral_function_a>,
-    pin a3 = c21<PfA, into_peripheral_function_a>,
-    pin a4 = c22<PfA, into_peripheral_function_a>,
-    pin a5 = c23<PfA, into_peripheral_function_a>,
-    pin a6 = c24<PfA, into_peripheral_function_a>,
-    pin a7 = c25<PfA, into_peripheral_function_a>,
-    pin a8 = c26<PfA, into_peripheral_function_a>,
-    pin a9 = c27<PfA, into_peripheral_function_a>,
-
-    pin a10 = c28<PfA, into_peripheral_function_a>,
-    pin a11 = c29<PfA, into_peripheral_function_a>,
-    pin a12 = c30<PfA, into_peripheral_function_a>,
-    pin a13 = c31<PfA, into_peripheral_function_a>,
-
-    pin a14 = a18<PfC, into_peripheral_function_c>,
-    pin a15 = a19<PfC, into_peripheral_function_c>,
-    pin a16 = a20<PfC, into_peripheral_function_c>,
-
-    pin a17 = a0<PfC, into_peripheral_function_c>,
-    pin a18 = a1<PfC, into_peripheral_function_c>,
-
-    pin a19 = a23<PfC, into_peripheral_function_c>,
-    pin a20 = a24<PfC, into_peripheral_function_c>,
-
-    pin a21 = c16<PfA, into_peripheral_function_a>,
-    pin a22 = c17<PfA, into_peripheral_function_a>,
-
-    pin a23 = a25<PfC, into_peripheral_function_c>,
-}
+#[cfg(feature = "ethernet")]
+pub mod ethernet;
@@ -1,6 +0,0 @@
-MEMORY
-{
-  FLASH (rx) : ORIGIN = 0x00400000, LENGTH = 1024K
-  RAM (xrw)  : ORIGIN = 0x20000000, LENGTH = 80K
-}
-_stack_start = ORIGIN(RAM) + LENGTH(RAM);
@@ -1,17 +1,7 @@
+//! Board support for the SAM4N Xplained Pro
+//!
+//! This crate is the [`sam_xplained`] crate with the `sam4n` board selected;
+//! see there for the examples and documentation.
 #![no_std]
 
-pub use atsam4_hal as hal;
-use paste::paste;
-
-use atsam4_hal::{define_pin_map, gpio::*};
-
-define_pin_map! {
-    struct Pins,
-
-    // Onboard LED
-    pin led0 = b14<Output<OpenDrain>, into_open_drain_output>,
-
-    // Serial Console (UART0)
-    pin uart0_rx = a9<PfA, into_peripheral_function_a>,
-    pin uart0_tx = a10<PfA, into_peripheral_function_a>,
-}
+pub use sam_xplained::*;
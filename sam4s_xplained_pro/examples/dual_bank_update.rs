@@ -0,0 +1,72 @@
+#![no_std]
+#![no_main]
+
+use cortex_m::peripheral::SCB;
+use cortex_m_rt::entry;
+use cortex_m_semihosting::hprintln;
+use embedded_io::{Read, ReadReady};
+use panic_semihosting as _; // panic handler
+use sam4s_xplained_pro::{
+    compat::Compat,
+    eh02::watchdog::Watchdog as _,
+    hal::pac::{CorePeripherals, Peripherals},
+    hal::watchdog::Watchdog,
+    update::{ImageHeader, UpdateManager},
+    Board, Console, XplainedBoard,
+};
+
+#[entry]
+fn main() -> ! {
+    let core = CorePeripherals::take().unwrap();
+    let peripherals = Peripherals::take().unwrap();
+    // The watchdog stays running: the boot stage rolls back an image on
+    // trial that hangs before it confirms itself.
+    let mut board = Board::new(core.SYST, peripherals);
+
+    let mut updates = UpdateManager::new(&mut board.flash).unwrap();
+    hprintln!(
+        "Running {:?} from {:?}",
+        updates.running_image().unwrap(),
+        updates.active_bank()
+    )
+    .ok();
+
+    // A real application would run its self-test here.
+    updates.confirm().unwrap();
+
+    hprintln!("Send an image header followed by the image on the console").ok();
+    let console = Compat::from_mut(&mut board.console);
+    let mut bytes = [0u8; ImageHeader::LEN];
+    receive(console, &mut board.watchdog, &mut bytes);
+    let header = ImageHeader::from_bytes(&bytes).expect("not an image header");
+    updates.begin(header).unwrap();
+
+    let mut remaining = header.length as usize;
+    let mut buffer = [0u8; 256];
+    while remaining > 0 {
+        let count = remaining.min(buffer.len());
+        receive(console, &mut board.watchdog, &mut buffer[..count]);
+        updates.write(&buffer[..count]).unwrap();
+        remaining -= count;
+    }
+
+    match updates.finish() {
+        Ok(()) => {
+            hprintln!("Installed version {}, restarting", header.version).ok();
+            SCB::sys_reset();
+        }
+        Err(error) => panic!("update failed: {}", error),
+    }
+}
+
+/// Fills `buffer` from the console, feeding the watchdog for as long as the
+/// host takes to send it.
+fn receive(console: &mut Compat<Console>, watchdog: &mut Watchdog, buffer: &mut [u8]) {
+    let mut received = 0;
+    while received < buffer.len() {
+        watchdog.feed();
+        if console.read_ready().unwrap() {
+            received += console.read(&mut buffer[received..]).unwrap();
+        }
+    }
+}
@@ -0,0 +1,26 @@
+//! Boot stage for dual bank A/B updates
+//!
+//! Programmed at the start of both flash banks, ahead of the images, and
+//! never updated.  After each reset it abandons a trial image that didn't
+//! confirm itself in time, then starts the image behind it (see
+//! [`sam4s_xplained_pro::update`]).
+#![no_std]
+#![no_main]
+
+use cortex_m_rt::entry;
+use panic_halt as _; // panic handler
+use sam4s_xplained_pro::{
+    flash::InternalFlash,
+    hal::pac::{CorePeripherals, Peripherals},
+    update, ResetCause,
+};
+
+#[entry]
+fn main() -> ! {
+    let core = CorePeripherals::take().unwrap();
+    let peripherals = Peripherals::take().unwrap();
+    let reset_cause = ResetCause::new(&peripherals.RSTC);
+    // Nothing else uses the flash before the image starts.
+    let mut flash = unsafe { InternalFlash::steal() };
+    update::boot(&core.SCB, &mut flash, reset_cause)
+}
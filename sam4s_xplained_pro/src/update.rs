@@ -0,0 +1,143 @@
+//! Dual bank A/B firmware updates
+//!
+//! The ATSAM4SD32C has two 1 MB flash banks, and GPNVM2 selects the one that
+//! is mapped at the boot memory alias at 0x0.  Each bank starts with the boot
+//! stage, the `dual_bank_boot` binary built with the `bootloader` feature,
+//! which is programmed into both banks once and never updated.  Images built
+//! with the `application` feature are linked behind it at that alias, so the
+//! same image runs from either bank.  [`UpdateManager`] writes a new image
+//! into the bank that is not running, checks it against its [`ImageHeader`]
+//! (version and CRC-32) and flips GPNVM2, so the next reset boots the new
+//! image.
+//!
+//! A freshly installed image runs on trial until it calls
+//! [`UpdateManager::confirm`].  If it is reset by the watchdog, or resets in
+//! any other way before that, the boot stage switches back to the previous
+//! image, however little of the new image ran.  Trial images therefore keep
+//! the watchdog running:
+//!
+//! ```rust
+//! let mut board = Board::new(core.SYST, peripherals);
+//! let mut updates = UpdateManager::new(&mut board.flash)?;
+//! // ... self-test ...
+//! updates.confirm()?;
+//! ```
+//!
+//! Each bank holds the image up to [`IMAGE_CAPACITY`] bytes from
+//! [`IMAGE_START`], followed by an erase block with the header and the trial
+//! and confirmation markers (see [`sam_xplained::image::dual_bank`]).
+
+use sam_xplained::flash::{self, Gpnvm, InternalFlash};
+use sam_xplained::image::dual_bank::{DualBank, Layout};
+use sam_xplained::image::APP_START;
+#[cfg(feature = "bootloader")]
+use sam_xplained::{hal::pac::SCB, ResetCause};
+
+pub use sam_xplained::image::dual_bank::{Bank, Boot};
+pub use sam_xplained::image::ImageHeader;
+
+/// Flash offset of bank 1
+pub const BANK_SIZE: u32 = 0x0010_0000;
+/// Bank offset of the image, behind the boot stage
+pub const IMAGE_START: u32 = APP_START;
+/// Space for an image in each bank
+pub const IMAGE_CAPACITY: u32 = LAYOUT.image_capacity();
+
+/// The boot stage, the image and the block holding its header and markers
+pub const LAYOUT: Layout = Layout {
+    bank_size: BANK_SIZE,
+    image_start: IMAGE_START,
+    status_block: 0x000F_8000,
+};
+
+pub type Error = sam_xplained::image::dual_bank::Error<flash::Error>;
+
+/// Installs images into the bank that is not running
+pub struct UpdateManager<'a> {
+    banks: DualBank<&'a mut InternalFlash>,
+}
+
+impl<'a> UpdateManager<'a> {
+    pub fn new(flash: &'a mut InternalFlash) -> Result<Self, Error> {
+        let active = if flash.gpnvm(Gpnvm::BankSelect).map_err(Error::Flash)? {
+            Bank::Bank1
+        } else {
+            Bank::Bank0
+        };
+        Ok(UpdateManager {
+            banks: DualBank::new(flash, LAYOUT, active),
+        })
+    }
+
+    /// Bank the running image was booted from
+    pub fn active_bank(&self) -> Bank {
+        self.banks.active_bank()
+    }
+
+    /// Header of the running image, or `None` for an image programmed
+    /// directly (e.g. with a debugger)
+    pub fn running_image(&mut self) -> Result<Option<ImageHeader>, Error> {
+        self.banks.running_image()
+    }
+
+    /// Whether the running image is no longer on trial
+    pub fn is_confirmed(&mut self) -> Result<bool, Error> {
+        self.banks.is_confirmed()
+    }
+
+    /// Marks the running image as good, ending its trial.
+    pub fn confirm(&mut self) -> Result<(), Error> {
+        self.banks.confirm()
+    }
+
+    /// Starts installing the image described by `header`, erasing the image
+    /// in the other bank.  Only a confirmed image can install another one.
+    pub fn begin(&mut self, header: ImageHeader) -> Result<(), Error> {
+        self.banks.begin(header)
+    }
+
+    /// Writes the next part of the image.
+    pub fn write(&mut self, data: &[u8]) -> Result<(), Error> {
+        self.banks.write(data)
+    }
+
+    /// Checks the written image and makes the next reset boot it, on trial.
+    pub fn finish(&mut self) -> Result<(), Error> {
+        let bank = self.banks.finish()?;
+        self.select(bank)
+    }
+
+    /// Makes the next reset boot from `bank`.
+    fn select(&mut self, bank: Bank) -> Result<(), Error> {
+        let flash = self.banks.flash();
+        match bank {
+            Bank::Bank0 => flash.clear_gpnvm(Gpnvm::BankSelect),
+            Bank::Bank1 => flash.set_gpnvm(Gpnvm::BankSelect),
+        }
+        .map_err(Error::Flash)
+    }
+}
+
+/// Runs the boot stage: resets into the other bank if the image on trial in
+/// the boot bank has to be abandoned, and starts the image otherwise.
+///
+/// The boot stage leaves the clocks and the watchdog alone, so the image
+/// starts from the reset state.
+#[cfg(feature = "bootloader")]
+pub fn boot(scb: &SCB, flash: &mut InternalFlash, reset_cause: ResetCause) -> ! {
+    if let Ok(mut updates) = UpdateManager::new(flash) {
+        let watchdog_reset = reset_cause == ResetCause::Watchdog;
+        if let Ok(Boot::Revert) = updates.banks.boot(watchdog_reset) {
+            let bank = updates.active_bank().other();
+            if updates.select(bank).is_ok() {
+                SCB::sys_reset();
+            }
+        }
+    }
+
+    // Flash errors leave the image to deal with a trial.
+    unsafe {
+        scb.vtor.write(IMAGE_START);
+        cortex_m::asm::bootload(IMAGE_START as *const u32)
+    }
+}
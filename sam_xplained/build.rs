@@ -0,0 +1,206 @@
+use std::env;
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use sam_xplained_image::FirmwareInfo;
+
+/// Flash offset of the application behind `sam_xplained_bootloader`, unless
+/// set with `SAM_XPLAINED_APP_OFFSET`
+const DEFAULT_APP_OFFSET: u32 = 0x1_0000;
+/// Must match `image::APP_HEADER_SIZE`
+const APP_HEADER_SIZE: u32 = 0x200;
+
+fn main() {
+    // The board is selected through one of the mutually exclusive board
+    // features; lib.rs reports a missing or conflicting selection.
+    let boards: Vec<&str> = ["sam4e", "sam4s", "sam4n"]
+        .iter()
+        .copied()
+        .filter(|board| env::var_os(format!("CARGO_FEATURE_{}", board.to_uppercase())).is_some())
+        .collect();
+
+    let out = &PathBuf::from(env::var_os("OUT_DIR").unwrap());
+    let app_offset = app_offset();
+    File::create(out.join("layout.rs"))
+        .unwrap()
+        .write_all(
+            format!(
+                "/// Flash offset of the application header, where the bootloader ends\n\
+                 pub const APP_OFFSET: u32 = {:#x};\n",
+                app_offset
+            )
+            .as_bytes(),
+        )
+        .unwrap();
+
+    write_build_info(out, &boards);
+
+    if env::var_os("CARGO_FEATURE_RT").is_some() && boards.len() == 1 {
+        // Dual bank A/B images link against their own memory layout.
+        let layout = if env::var_os("CARGO_FEATURE_DUAL_BANK").is_some() && boards[0] == "sam4s" {
+            format!("{}_dual_bank", boards[0])
+        } else {
+            boards[0].to_string()
+        };
+        let memory_x = PathBuf::from("memory").join(format!("{}.x", layout));
+        let mut memory = std::fs::read_to_string(&memory_x).unwrap();
+
+        // The bootloader and the application behind it split the flash.
+        if env::var_os("CARGO_FEATURE_BOOTLOADER").is_some() {
+            memory = resize_flash(&memory, |origin, _| (origin, app_offset));
+        } else if env::var_os("CARGO_FEATURE_APPLICATION").is_some() {
+            let skip = app_offset + APP_HEADER_SIZE;
+            memory = resize_flash(&memory, |origin, length| (origin + skip, length - skip));
+        }
+
+        File::create(out.join("memory.x"))
+            .unwrap()
+            .write_all(memory.as_bytes())
+            .unwrap();
+        println!("cargo:rustc-link-search={}", out.display());
+        println!("cargo:rerun-if-changed={}", memory_x.display());
+    }
+    println!("cargo:rerun-if-env-changed=SAM_XPLAINED_APP_OFFSET");
+    println!("cargo:rerun-if-env-changed=SAM_XPLAINED_FIRMWARE_VERSION");
+    println!("cargo:rerun-if-env-changed=SOURCE_DATE_EPOCH");
+    println!("cargo:rerun-if-changed=build.rs");
+}
+
+/// Records the build in `build_info.rs`, as the encoded [`FirmwareInfo`].
+fn write_build_info(out: &Path, boards: &[&str]) {
+    let version = env::var("SAM_XPLAINED_FIRMWARE_VERSION")
+        .unwrap_or_else(|_| env::var("CARGO_PKG_VERSION").unwrap());
+    // Reproducible builds set the time themselves.
+    let timestamp = match env::var("SOURCE_DATE_EPOCH") {
+        Ok(value) => value.trim().parse().expect("invalid SOURCE_DATE_EPOCH"),
+        Err(_) => SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs(),
+    };
+    let board = match boards {
+        [board] => format!("{} Xplained Pro", board.to_uppercase()),
+        _ => String::new(),
+    };
+    let mut features: Vec<String> = env::vars()
+        .filter_map(|(name, _)| {
+            name.strip_prefix("CARGO_FEATURE_")
+                .map(|feature| feature.to_lowercase())
+        })
+        .collect();
+    features.sort();
+
+    let info = FirmwareInfo::new(
+        &version,
+        &git_commit(),
+        timestamp,
+        &board,
+        &features.join(","),
+    );
+    File::create(out.join("build_info.rs"))
+        .unwrap()
+        .write_all(
+            format!(
+                "/// Encoded [`FirmwareInfo`] of this build\n\
+                 const FIRMWARE_INFO: [u8; FirmwareInfo::LEN] = {:?};\n",
+                info.to_bytes()
+            )
+            .as_bytes(),
+        )
+        .unwrap();
+}
+
+/// Commit checked out where this crate is built from, `unknown` outside of
+/// a git checkout
+fn git_commit() -> String {
+    let git = |args: &[&str]| {
+        Command::new("git")
+            .args(args)
+            .output()
+            .ok()
+            .filter(|output| output.status.success())
+            .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string())
+    };
+    let commit = match git(&["rev-parse", "HEAD"]) {
+        Some(commit) => commit,
+        None => return "unknown".to_string(),
+    };
+    // Commits, checkouts and staged changes touch these.
+    if let Some(dir) = git(&["rev-parse", "--absolute-git-dir"]) {
+        for file in &["HEAD", "logs/HEAD", "index"] {
+            println!("cargo:rerun-if-changed={}/{}", dir, file);
+        }
+    }
+    // Without refreshing the index, which would trigger the next rebuild
+    match git(&[
+        "--no-optional-locks",
+        "status",
+        "--porcelain",
+        "--untracked-files=no",
+    ]) {
+        Some(changes) if !changes.is_empty() => format!("{}-dirty", commit),
+        _ => commit,
+    }
+}
+
+fn app_offset() -> u32 {
+    let offset = match env::var("SAM_XPLAINED_APP_OFFSET") {
+        Ok(value) => parse_size(&value)
+            .unwrap_or_else(|| panic!("invalid SAM_XPLAINED_APP_OFFSET `{}`", value)),
+        Err(_) => DEFAULT_APP_OFFSET,
+    };
+    // The application's region is erased in blocks of eight pages.
+    assert!(
+        offset > 0 && offset % 0x1000 == 0,
+        "SAM_XPLAINED_APP_OFFSET must be a non-zero multiple of 4K"
+    );
+    offset
+}
+
+/// Parses `0x` hexadecimal or decimal numbers with an optional `K` or `M`
+/// suffix, as used in memory.x.
+fn parse_size(value: &str) -> Option<u32> {
+    let value = value.trim();
+    let (number, scale) = match value.chars().last()? {
+        'K' | 'k' => (&value[..value.len() - 1], 1024),
+        'M' | 'm' => (&value[..value.len() - 1], 1024 * 1024),
+        _ => (value, 1),
+    };
+    let number = match number
+        .strip_prefix("0x")
+        .or_else(|| number.strip_prefix("0X"))
+    {
+        Some(hex) => u32::from_str_radix(&hex.replace('_', ""), 16).ok()?,
+        None => number.replace('_', "").parse().ok()?,
+    };
+    number.checked_mul(scale)
+}
+
+/// Replaces the origin and length of the FLASH region with the result of
+/// `resize(origin, length)`.
+fn resize_flash(memory: &str, resize: impl Fn(u32, u32) -> (u32, u32)) -> String {
+    let mut lines: Vec<String> = memory.lines().map(String::from).collect();
+    let line = lines
+        .iter_mut()
+        .find(|line| line.trim_start().starts_with("FLASH"))
+        .expect("no FLASH region in memory.x");
+
+    let field = |name: &str| {
+        let start = line.find(name).expect("malformed FLASH region") + name.len();
+        let rest = line[start..].trim_start().strip_prefix('=').unwrap();
+        let end = rest.find(',').unwrap_or(rest.len());
+        parse_size(&rest[..end]).expect("malformed FLASH region")
+    };
+    let (origin, length) = resize(field("ORIGIN"), field("LENGTH"));
+    *line = format!(
+        "  FLASH (rx) : ORIGIN = {:#010x}, LENGTH = {:#x}",
+        origin, length
+    );
+
+    let mut memory = lines.join("\n");
+    memory.push('\n');
+    memory
+}
@@ -0,0 +1,45 @@
+#![no_std]
+#![no_main]
+
+use cortex_m_rt::entry;
+use cortex_m_semihosting::hprintln;
+use panic_semihosting as _; // panic handler
+use sam_xplained::{
+    hal::{
+        clock::get_master_clock_frequency,
+        delay::DelayMs,
+        pac::{CorePeripherals, Peripherals},
+        watchdog::*,
+        OutputPin,
+    },
+    Board, XplainedBoard,
+};
+
+#[entry]
+fn main() -> ! {
+    hprintln!("Blinky example started on the {}", Board::NAME).ok();
+
+    let core = CorePeripherals::take().unwrap();
+    let peripherals = Peripherals::take().unwrap();
+    // Refuse to run on a board this firmware wasn't built for.
+    let mut board =
+        Board::try_new(core.SYST, peripherals).unwrap_or_else(|error| panic!("{}", error));
+
+    // Display why a processor reset occured.
+    hprintln!("Reset cause: {}", board.reset_cause).ok();
+
+    hprintln!("CPU Clock: {}", get_master_clock_frequency().0).ok();
+    hprintln!("{}", board.chip_id).ok();
+    hprintln!("Serial number: {}", board.serial_number()).ok();
+    hprintln!("Firmware: {}", Board::firmware_info()).ok();
+
+    // Disable the watchdog timer.
+    board.watchdog.disable();
+
+    loop {
+        board.led0.set_low().ok();
+        board.delay.delay_ms(1000u32);
+        board.led0.set_high().ok();
+        board.delay.delay_ms(1000u32);
+    }
+}
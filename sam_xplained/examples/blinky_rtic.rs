@@ -0,0 +1,75 @@
+#![no_std]
+#![no_main]
+
+use panic_semihosting as _; // panic handler
+
+// RTIC requires a free interrupt to dispatch the software tasks; TC5 (Timer/Counter #5)
+// is unused by this example.
+#[rtic::app(device = sam_xplained::hal::pac, peripherals = true, dispatchers = [TC5])]
+mod app {
+    use cortex_m_semihosting::hprintln;
+    use sam_xplained::{
+        hal::{clock::get_master_clock_frequency, watchdog::*, OutputPin},
+        monotonic::{ExtU32, RttMonotonic},
+        Board, Led0, XplainedBoard,
+    };
+
+    //
+    // Monotonic timer (RTT running from the slow clock)
+    //
+    #[monotonic(binds = RTT, default = true)]
+    type Mono = RttMonotonic<8192>;
+
+    //
+    // Resources used by tasks/interrupts
+    //
+    #[shared]
+    struct Shared {}
+
+    #[local]
+    struct Local {
+        led0: Led0,
+    }
+
+    //
+    // Initialization
+    //
+    #[init]
+    fn init(cx: init::Context) -> (Shared, Local, init::Monotonics) {
+        let mut board = Board::new(cx.core.SYST, cx.device);
+
+        hprintln!("CPU Clock: {}", get_master_clock_frequency().0).ok();
+
+        // Disable the watchdog timer.
+        board.watchdog.disable();
+
+        // Turn LED0 off.
+        board.led0.set_high().ok();
+
+        // Task scheduling
+        let mono = RttMonotonic::new(board.rtt);
+        blink_led::spawn_after(1.secs()).unwrap();
+
+        (
+            Shared {},
+            Local { led0: board.led0 },
+            init::Monotonics(mono),
+        )
+    }
+
+    //
+    // LED Blink Task
+    //
+    #[task(local = [led0, state: bool = false])]
+    fn blink_led(cx: blink_led::Context) {
+        if !*cx.local.state {
+            cx.local.led0.set_low().ok();
+            blink_led::spawn_after(50.millis()).unwrap();
+            *cx.local.state = true;
+        } else {
+            cx.local.led0.set_high().ok();
+            blink_led::spawn_after(500.millis()).unwrap();
+            *cx.local.state = false;
+        }
+    }
+}
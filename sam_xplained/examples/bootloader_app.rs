@@ -0,0 +1,41 @@
+#![no_std]
+#![no_main]
+
+use cortex_m::peripheral::SCB;
+use cortex_m_rt::entry;
+use cortex_m_semihosting::hprintln;
+use embedded_hal::{delay::DelayNs, digital::InputPin};
+use panic_semihosting as _; // panic handler
+use sam_xplained::{
+    hal::{
+        pac::{CorePeripherals, Peripherals},
+        watchdog::*,
+    },
+    image, Board, XplainedBoard,
+};
+
+#[entry]
+fn main() -> ! {
+    hprintln!(
+        "Application started behind the bootloader on the {}",
+        Board::NAME
+    )
+    .ok();
+
+    let core = CorePeripherals::take().unwrap();
+    let peripherals = Peripherals::take().unwrap();
+    let mut board = Board::new(core.SYST, peripherals);
+    board.watchdog.disable();
+
+    if let Some(header) = image::installed_application() {
+        hprintln!("Version {}, {} bytes", header.version, header.length).ok();
+    }
+
+    hprintln!("Press SW0 to restart into the bootloader's update mode").ok();
+    while !board.button().is_low().unwrap() {
+        board.delay().delay_ms(10);
+    }
+
+    image::request_update();
+    SCB::sys_reset();
+}
@@ -0,0 +1,68 @@
+#![no_std]
+#![no_main]
+
+use cortex_m_semihosting::hprintln;
+use embassy_executor::Spawner;
+use embassy_time::Timer;
+use panic_semihosting as _; // panic handler
+use sam_xplained::{
+    embassy::{self, AsyncButton, AsyncConsole, EXECUTOR_TC5},
+    hal::{
+        pac::{CorePeripherals, Interrupt, Peripherals},
+        watchdog::*,
+        OutputPin,
+    },
+    Board, Led0, XplainedBoard,
+};
+
+#[embassy_executor::main]
+async fn main(spawner: Spawner) {
+    hprintln!("Embassy example started on the {}", Board::NAME).ok();
+
+    let mut core = CorePeripherals::take().unwrap();
+    let peripherals = Peripherals::take().unwrap();
+    let mut board = Board::new(core.SYST, peripherals);
+
+    embassy::init(board.rtt);
+
+    // Disable the watchdog timer.
+    board.watchdog.disable();
+
+    // Run the blink task on the TC5 interrupt executor so it preempts the
+    // console task running in thread mode.
+    unsafe { core.NVIC.set_priority(Interrupt::TC5, 0xE0) };
+    let high_priority = EXECUTOR_TC5.start(Interrupt::TC5);
+    high_priority.spawn(blink(board.led0)).unwrap();
+
+    spawner
+        .spawn(console(AsyncConsole::new(board.console)))
+        .unwrap();
+
+    let mut button = AsyncButton::new(board.sw0);
+    loop {
+        button.wait_for_press().await;
+        hprintln!("SW0 pressed").ok();
+        button.wait_for_release().await;
+    }
+}
+
+#[embassy_executor::task]
+async fn blink(mut led0: Led0) {
+    loop {
+        led0.set_low().ok();
+        Timer::after_millis(50).await;
+        led0.set_high().ok();
+        Timer::after_millis(500).await;
+    }
+}
+
+#[embassy_executor::task]
+async fn console(mut console: AsyncConsole) {
+    console.write_str("Hello from the serial port!\r\n").await;
+    loop {
+        // Echo back everything that is received.
+        if let Ok(byte) = console.read_byte().await {
+            console.write(&[byte]).await;
+        }
+    }
+}
@@ -4,7 +4,7 @@
 use cortex_m_rt::entry;
 use cortex_m_semihosting::hprintln;
 use panic_semihosting as _; // panic handler
-use sam4e_xplained_pro::{
+use sam_xplained::{
     hal::{
         clock::*,
         gpio::Ports,
@@ -145,7 +145,9 @@ fn main() -> ! {
 
     hprintln!("Testing complete without error.").ok();
 
-    loop {}
+    loop {
+        cortex_m::asm::wfi();
+    }
 }
 
 fn test_memory_region(region_start_address: *mut u8, region_size_in_bytes: usize) {
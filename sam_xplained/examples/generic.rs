@@ -0,0 +1,50 @@
+#![no_std]
+#![no_main]
+
+use cortex_m_rt::entry;
+use embedded_hal::{
+    delay::DelayNs,
+    digital::{InputPin, StatefulOutputPin},
+};
+use embedded_io::Write;
+use panic_semihosting as _; // panic handler
+use sam_xplained::{
+    hal::{
+        pac::{CorePeripherals, Peripherals},
+        watchdog::*,
+    },
+    Board, XplainedBoard,
+};
+
+#[entry]
+fn main() -> ! {
+    let core = CorePeripherals::take().unwrap();
+    let peripherals = Peripherals::take().unwrap();
+    let mut board = Board::new(core.SYST, peripherals);
+
+    // Disable the watchdog timer.
+    board.watchdog.disable();
+
+    run(&mut board)
+}
+
+/// Application code written once against the board trait: blinks LED0 while
+/// SW0 is released and reports button presses on the console.
+fn run<B: XplainedBoard>(board: &mut B) -> ! {
+    let cause = board.reset_cause();
+    write!(board.console(), "{} (reset cause: {})\r\n", B::NAME, cause).ok();
+
+    let mut pressed = false;
+    loop {
+        let is_pressed = board.button().is_low().unwrap_or(false);
+        if is_pressed && !pressed {
+            board.console().write_all(b"SW0 pressed\r\n").ok();
+        }
+        pressed = is_pressed;
+
+        if !pressed {
+            board.led().toggle().ok();
+        }
+        board.delay().delay_ms(250);
+    }
+}
@@ -0,0 +1,45 @@
+#![no_std]
+#![no_main]
+
+use cortex_m::peripheral::SCB;
+use cortex_m_rt::entry;
+use cortex_m_semihosting::hprintln;
+use embedded_hal::{delay::DelayNs, digital::InputPin};
+use panic_semihosting as _; // panic handler
+use sam_xplained::{
+    flash::Gpnvm,
+    hal::{
+        pac::{CorePeripherals, Peripherals},
+        watchdog::*,
+    },
+    Board, XplainedBoard, STORAGE,
+};
+
+#[entry]
+fn main() -> ! {
+    hprintln!("GPNVM example started on the {}", Board::NAME).ok();
+
+    let core = CorePeripherals::take().unwrap();
+    let peripherals = Peripherals::take().unwrap();
+    let mut board = Board::new(core.SYST, peripherals);
+    board.watchdog.disable();
+
+    let flash = &mut board.flash;
+    hprintln!("Security bit: {}", flash.gpnvm(Gpnvm::Security).unwrap()).ok();
+    hprintln!("Boot from flash: {}", flash.gpnvm(Gpnvm::BootMode).unwrap()).ok();
+    hprintln!(
+        "Storage locked: {}",
+        flash.is_locked(STORAGE.start).unwrap()
+    )
+    .ok();
+
+    hprintln!("Press SW0 to restart into the SAM-BA boot ROM").ok();
+    while !board.button().is_low().unwrap() {
+        board.delay().delay_ms(10);
+    }
+
+    // The next reset boots from ROM.  Setting the bit again (from SAM-BA, or
+    // with `at91sam4 gpnvm set 1` in OpenOCD) boots the firmware.
+    board.flash.clear_gpnvm(Gpnvm::BootMode).unwrap();
+    SCB::sys_reset();
+}
@@ -0,0 +1,42 @@
+#![no_std]
+#![no_main]
+
+use cortex_m_rt::entry;
+use cortex_m_semihosting::hprintln;
+use panic_semihosting as _; // panic handler
+use sam_xplained::{
+    hal::{
+        pac::{CorePeripherals, Peripherals},
+        watchdog::*,
+    },
+    kv::KvStore,
+    Board, XplainedBoard, STORAGE,
+};
+
+/// Key the boot counter is stored under
+const BOOT_COUNT: u16 = 1;
+
+#[entry]
+fn main() -> ! {
+    hprintln!("Key-value store example started on the {}", Board::NAME).ok();
+
+    let core = CorePeripherals::take().unwrap();
+    let peripherals = Peripherals::take().unwrap();
+    let mut board = Board::new(core.SYST, peripherals);
+    board.watchdog.disable();
+
+    // Count the boots in the reserved flash region.
+    let mut store = KvStore::new(&mut board.flash, STORAGE).unwrap();
+    let mut buffer = [0u8; 4];
+    let count = match store.get(BOOT_COUNT, &mut buffer).unwrap() {
+        Some(4) => u32::from_le_bytes(buffer) + 1,
+        _ => 1,
+    };
+    store.set(BOOT_COUNT, &count.to_le_bytes()).unwrap();
+
+    hprintln!("Boot count: {}", count).ok();
+
+    loop {
+        cortex_m::asm::wfi();
+    }
+}
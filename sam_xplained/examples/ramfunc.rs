@@ -0,0 +1,48 @@
+#![no_std]
+#![no_main]
+
+use cortex_m_rt::entry;
+use cortex_m_semihosting::hprintln;
+use embedded_hal::digital::StatefulOutputPin;
+use panic_semihosting as _; // panic handler
+use sam_xplained::{
+    hal::{
+        pac::{CorePeripherals, Peripherals},
+        watchdog::*,
+    },
+    ramfunc, Board, XplainedBoard,
+};
+
+ramfunc! {
+    /// Spins for `count` iterations without fetching from the flash
+    fn spin(count: u32) {
+        unsafe {
+            core::arch::asm!(
+                "2:",
+                "subs {count}, #1",
+                "bne 2b",
+                count = inout(reg) count => _,
+                options(nomem, nostack),
+            );
+        }
+    }
+}
+
+#[entry]
+fn main() -> ! {
+    hprintln!("RAM function example started on the {}", Board::NAME).ok();
+
+    let core = CorePeripherals::take().unwrap();
+    let peripherals = Peripherals::take().unwrap();
+    // Copies the RAM functions into place before anything else.
+    let mut board = Board::new(core.SYST, peripherals);
+    board.watchdog.disable();
+
+    // Executes from SRAM (0x2000_0000 and up) rather than flash (0x0040_0000).
+    hprintln!("spin() is at {:p}", spin as fn(u32)).ok();
+
+    loop {
+        spin(1_000_000);
+        board.led().toggle().ok();
+    }
+}
@@ -0,0 +1,44 @@
+#![no_std]
+#![no_main]
+
+use cortex_m_rt::entry;
+use panic_semihosting as _; // panic handler
+use sam_xplained::{
+    compat::Compat,
+    hal::{
+        pac::{CorePeripherals, Peripherals},
+        watchdog::*,
+    },
+    kv::KvStore,
+    rpc::{BoardHandler, Server},
+    Board, XplainedBoard, STORAGE,
+};
+
+#[entry]
+fn main() -> ! {
+    let core = CorePeripherals::take().unwrap();
+    let peripherals = Peripherals::take().unwrap();
+    let mut board = Board::new(core.SYST, peripherals);
+
+    // Disable the watchdog timer.
+    board.watchdog.disable();
+
+    // The console carries the requests; the handler gets the rest.
+    let Board {
+        console,
+        led0,
+        chip_id,
+        reset_cause,
+        unique_id,
+        mut flash,
+        ..
+    } = board;
+    let store = KvStore::new(&mut flash, STORAGE).unwrap();
+    let mut handler = BoardHandler::new(led0, chip_id, reset_cause, unique_id).with_store(store);
+
+    let mut server = Server::new();
+    let mut console = Compat::new(console);
+    loop {
+        server.run(&mut handler, &mut console).ok();
+    }
+}
@@ -0,0 +1,49 @@
+#![no_std]
+#![no_main]
+
+use cortex_m_rt::entry;
+use cortex_m_semihosting::hprintln;
+use embedded_hal::{delay::DelayNs, digital::OutputPin};
+use embedded_io::Write;
+use panic_semihosting as _; // panic handler
+use sam_xplained::{
+    compat::Compat,
+    hal::{
+        clock::get_master_clock_frequency,
+        pac::{CorePeripherals, Peripherals},
+        watchdog::*,
+    },
+    Board, XplainedBoard,
+};
+
+#[entry]
+fn main() -> ! {
+    hprintln!("Serial example started on the {}", Board::NAME).ok();
+
+    let core = CorePeripherals::take().unwrap();
+    let peripherals = Peripherals::take().unwrap();
+    let mut board = Board::new(core.SYST, peripherals);
+
+    // Display why a processor reset occured.
+    hprintln!("Reset cause: {}", board.reset_cause).ok();
+
+    hprintln!("CPU Clock: {}", get_master_clock_frequency().0).ok();
+
+    // Disable the watchdog timer.
+    board.watchdog.disable();
+
+    // Expose the embedded-hal 1.0 / embedded-io traits.
+    let mut led0 = Compat::new(board.led0);
+    let mut delay = Compat::new(board.delay);
+    let mut serial_port = Compat::new(board.console);
+
+    loop {
+        serial_port
+            .write_all(b"Hello from the serial port!\r\n")
+            .ok();
+        led0.set_low().ok();
+        delay.delay_ms(1000);
+        led0.set_high().ok();
+        delay.delay_ms(1000);
+    }
+}
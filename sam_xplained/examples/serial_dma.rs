@@ -0,0 +1,93 @@
+#![no_std]
+#![no_main]
+
+use panic_semihosting as _; // panic handler
+
+// RTIC requires a free interrupt to dispatch the software tasks; TC5 (Timer/Counter #5)
+// is unused by this example.
+#[rtic::app(device = sam_xplained::hal::pac, peripherals = true, dispatchers = [TC5])]
+mod app {
+    use cortex_m_semihosting::hprintln;
+    use sam_xplained::{
+        dma::{DmaConsole, Event},
+        hal::watchdog::*,
+        monotonic::{ExtU32, RttMonotonic},
+        Board, XplainedBoard,
+    };
+
+    /// Telemetry sent straight from flash, without copying
+    const FRAME: &[u8] = b"$TLM,0123456789ABCDEF0123456789ABCDEF0123456789ABCDEF*00\r\n";
+
+    #[monotonic(binds = RTT, default = true)]
+    type Mono = RttMonotonic<8192>;
+
+    #[shared]
+    struct Shared {
+        console: DmaConsole,
+    }
+
+    #[local]
+    struct Local {}
+
+    #[init(local = [rx0: [u8; 64] = [0; 64], rx1: [u8; 64] = [0; 64]])]
+    fn init(cx: init::Context) -> (Shared, Local, init::Monotonics) {
+        let mut board = Board::new(cx.core.SYST, cx.device);
+
+        // Disable the watchdog timer.
+        board.watchdog.disable();
+
+        let console = DmaConsole::new(board.console, [cx.local.rx0, cx.local.rx1]);
+
+        let mono = RttMonotonic::new(board.rtt);
+        telemetry::spawn().unwrap();
+        idle_check::spawn().unwrap();
+
+        (Shared { console }, Local {}, init::Monotonics(mono))
+    }
+
+    fn handle(event: Event<'_>) {
+        match event {
+            Event::Received { data, idle } => {
+                hprintln!("received {} bytes (idle: {})", data.len(), idle).ok();
+            }
+            Event::Transmitted(_) => {}
+        }
+    }
+
+    //
+    // Console UART interrupt (UART1 on the SAM4S)
+    //
+    #[cfg(not(feature = "sam4s"))]
+    #[task(binds = UART0, shared = [console], priority = 3)]
+    fn console_uart0(mut cx: console_uart0::Context) {
+        cx.shared
+            .console
+            .lock(|console| console.on_interrupt(handle));
+    }
+
+    #[cfg(feature = "sam4s")]
+    #[task(binds = UART1, shared = [console], priority = 3)]
+    fn console_uart1(mut cx: console_uart1::Context) {
+        cx.shared
+            .console
+            .lock(|console| console.on_interrupt(handle));
+    }
+
+    //
+    // Streams a frame every 10 ms, skipping it while both PDC slots are busy
+    //
+    #[task(shared = [console])]
+    fn telemetry(mut cx: telemetry::Context) {
+        cx.shared.console.lock(|console| console.write(FRAME).ok());
+        telemetry::spawn_after(10.millis()).unwrap();
+    }
+
+    //
+    // Hands over received bytes once the line has been quiet for 1 ms
+    //
+    #[task(shared = [console])]
+    fn idle_check(mut cx: idle_check::Context) {
+        cx.shared.console.lock(|console| console.poll_idle(handle));
+        idle_check::spawn_after(1.millis()).unwrap();
+    }
+}
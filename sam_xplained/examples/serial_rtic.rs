@@ -0,0 +1,99 @@
+#![no_std]
+#![no_main]
+
+use panic_semihosting as _; // panic handler
+
+// RTIC requires a free interrupt to dispatch the software tasks; TC5 (Timer/Counter #5)
+// is unused by this example.
+#[rtic::app(device = sam_xplained::hal::pac, peripherals = true, dispatchers = [TC5])]
+mod app {
+    use core::fmt::Write;
+    use sam_xplained::{
+        buffered::{BufferedConsole, ConsoleBuffers},
+        hal::{hal::serial::Read, watchdog::*},
+        monotonic::{ExtU32, RttMonotonic},
+        Board, XplainedBoard,
+    };
+
+    //
+    // Console ring buffers: 64 bytes received, 1K queued for transmission
+    //
+    static CONSOLE: ConsoleBuffers<64, 1024> = ConsoleBuffers::new();
+
+    #[monotonic(binds = RTT, default = true)]
+    type Mono = RttMonotonic<8192>;
+
+    #[shared]
+    struct Shared {
+        console: BufferedConsole<64, 1024>,
+    }
+
+    #[local]
+    struct Local {}
+
+    #[init]
+    fn init(cx: init::Context) -> (Shared, Local, init::Monotonics) {
+        let mut board = Board::new(cx.core.SYST, cx.device);
+
+        // Disable the watchdog timer.
+        board.watchdog.disable();
+
+        let mut console = BufferedConsole::new(board.console, &CONSOLE);
+        writeln!(console, "Buffered console on the {}\r", Board::NAME).ok();
+
+        let mono = RttMonotonic::new(board.rtt);
+        report::spawn_after(1.secs()).unwrap();
+
+        (Shared { console }, Local {}, init::Monotonics(mono))
+    }
+
+    //
+    // Console UART interrupt (UART1 on the SAM4S)
+    //
+    #[cfg(not(feature = "sam4s"))]
+    #[task(binds = UART0, priority = 3)]
+    fn console_uart0(_: console_uart0::Context) {
+        CONSOLE.on_interrupt();
+        echo::spawn().ok();
+    }
+
+    #[cfg(feature = "sam4s")]
+    #[task(binds = UART1, priority = 3)]
+    fn console_uart1(_: console_uart1::Context) {
+        CONSOLE.on_interrupt();
+        echo::spawn().ok();
+    }
+
+    //
+    // Echoes received bytes
+    //
+    #[task(shared = [console])]
+    fn echo(mut cx: echo::Context) {
+        cx.shared.console.lock(|console| {
+            while let Ok(byte) = console.read() {
+                // Logging from here never waits for the UART, only for
+                // room in the transmit buffer.
+                console.write_nonblocking(&[byte]);
+            }
+        });
+    }
+
+    //
+    // Logs once a second
+    //
+    #[task(shared = [console], local = [count: u32 = 0])]
+    fn report(mut cx: report::Context) {
+        *cx.local.count += 1;
+        let count = *cx.local.count;
+        cx.shared.console.lock(|console| {
+            writeln!(
+                console,
+                "{} s, receive errors: {:?}\r",
+                count,
+                console.errors()
+            )
+            .ok();
+        });
+        report::spawn_after(1.secs()).unwrap();
+    }
+}
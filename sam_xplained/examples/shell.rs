@@ -0,0 +1,75 @@
+#![no_std]
+#![no_main]
+
+use cortex_m_rt::entry;
+use embedded_hal::{delay::DelayNs, digital::StatefulOutputPin};
+use panic_semihosting as _; // panic handler
+use sam_xplained::{
+    compat::Compat,
+    hal::{
+        delay::Delay,
+        pac::{CorePeripherals, Peripherals},
+        watchdog::*,
+    },
+    shell::{register_builtins, Command, Context, Error, Shell},
+    Board, XplainedBoard,
+};
+
+/// Application command: blinks LED0 `count` times
+fn blink(
+    context: &mut Context<Compat<Delay>>,
+    args: &mut sam_xplained::shell::Args<'_>,
+    _: &mut dyn core::fmt::Write,
+) -> Result<(), Error> {
+    let count = args.optional_number()?.unwrap_or(3);
+    args.finish()?;
+    let led = Compat::from_mut(&mut context.led0);
+    for _ in 0..count * 2 {
+        led.toggle().ok();
+        context.app.delay_ms(200);
+    }
+    Ok(())
+}
+
+#[entry]
+fn main() -> ! {
+    let core = CorePeripherals::take().unwrap();
+    let peripherals = Peripherals::take().unwrap();
+    let mut board = Board::new(core.SYST, peripherals);
+
+    // Disable the watchdog timer.
+    board.watchdog.disable();
+
+    // The console runs the shell; the commands get the rest.
+    let Board {
+        console,
+        led0,
+        chip_id,
+        reset_cause,
+        delay,
+        ..
+    } = board;
+    let mut context = Context {
+        led0,
+        chip_id,
+        reset_cause,
+        app: Compat::new(delay),
+    };
+
+    let mut shell: Shell<_, 16> = Shell::new("xplained> ");
+    register_builtins(&mut shell).unwrap();
+    shell
+        .register(Command {
+            name: "blink",
+            usage: "[count]",
+            help: "Blinks LED0",
+            handler: blink,
+        })
+        .unwrap();
+
+    let mut console = Compat::new(console);
+    embedded_io::Write::write_all(&mut console, b"\r\nType `help` for the commands.\r\n").ok();
+    loop {
+        shell.run(&mut context, &mut console).ok();
+    }
+}
@@ -0,0 +1,51 @@
+#![no_std]
+#![no_main]
+
+use cortex_m_rt::entry;
+use cortex_m_semihosting::hprintln;
+use panic_semihosting as _; // panic handler
+use sam_xplained::{
+    compat::Compat,
+    hal::{
+        pac::{CorePeripherals, Peripherals},
+        watchdog::*,
+    },
+    image::crc32,
+    ymodem::{MemorySink, Receiver, SerialTransport},
+    Board, XplainedBoard,
+};
+
+/// Received files land here
+static mut BUFFER: [u8; 32 * 1024] = [0; 32 * 1024];
+
+#[entry]
+fn main() -> ! {
+    hprintln!("YMODEM example started on the {}", Board::NAME).ok();
+
+    let core = CorePeripherals::take().unwrap();
+    let peripherals = Peripherals::take().unwrap();
+    let mut board = Board::new(core.SYST, peripherals);
+    board.watchdog.disable();
+
+    let buffer = unsafe { &mut *core::ptr::addr_of_mut!(BUFFER) };
+    let transport = SerialTransport::new(
+        Compat::from_mut(&mut board.console),
+        Compat::from_mut(&mut board.delay),
+    );
+    let mut receiver = Receiver::new(transport);
+
+    loop {
+        hprintln!("Send a file of up to 32K with YMODEM on the console").ok();
+        let mut sink = MemorySink::new(buffer);
+        match receiver.receive(&mut sink) {
+            Ok(file) => hprintln!(
+                "Received {} ({} bytes, CRC-32 {:08x})",
+                core::str::from_utf8(file.name()).unwrap_or("?"),
+                sink.data().len(),
+                crc32(sink.data())
+            ),
+            Err(error) => hprintln!("Transfer failed: {}", error),
+        }
+        .ok();
+    }
+}
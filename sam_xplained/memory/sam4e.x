@@ -0,0 +1,38 @@
+/* The last 16K of the flash are reserved for the key-value store */
+MEMORY
+{
+  FLASH (rx) : ORIGIN = 0x00400000, LENGTH = 1008K
+  RAM (xrw)  : ORIGIN = 0x20000000, LENGTH = 128K
+  CS0 (xrw)  : ORIGIN = 0x60000000, LENGTH = 16M
+  CS1 (xrw)  : ORIGIN = 0x61000000, LENGTH = 16M
+  CS2 (xrw)  : ORIGIN = 0x62000000, LENGTH = 16M
+  CS3 (xrw)  : ORIGIN = 0x63000000, LENGTH = 16M
+}
+_stack_start = ORIGIN(RAM) + LENGTH(RAM);
+
+/* Functions placed in RAM with `ramfunc!`, copied there by `ramfunc::init` */
+SECTIONS
+{
+  .ramfunc : ALIGN(4)
+  {
+    . = ALIGN(4);
+    __sramfunc = .;
+    *(.ramfunc .ramfunc.*);
+    . = ALIGN(4);
+    __eramfunc = .;
+  } > RAM AT>FLASH
+
+  __siramfunc = LOADADDR(.ramfunc);
+} INSERT AFTER .bss;
+
+/* Build information (`build_info`) at a fixed offset from the start of the
+   image, behind the vector table, so tools find it in flash dumps */
+EXTERN(__FIRMWARE_INFO);
+SECTIONS
+{
+  .firmware_info ORIGIN(FLASH) + 0x200 :
+  {
+    KEEP(*(.firmware_info));
+  } > FLASH
+} INSERT AFTER .vector_table;
+_stext = ORIGIN(FLASH) + 0x300;
@@ -0,0 +1,42 @@
+/* Dual bank A/B images are linked at the boot memory alias at 0x0, which maps
+   the bank GPNVM2 boots from, so the same image runs from either bank.  Each
+   bank starts with the boot stage (`bootloader` feature), followed by the
+   image (`application` feature).  The end of each bank holds the image header
+   and, in bank 1, the key-value store. */
+MEMORY
+{
+  FLASH (rx) : ORIGIN = 0x00000000, LENGTH = 992K
+  RAM (xrw)  : ORIGIN = 0x20000000, LENGTH = 160K
+  CS0 (xrw)  : ORIGIN = 0x60000000, LENGTH = 16M
+  CS1 (xrw)  : ORIGIN = 0x61000000, LENGTH = 16M
+  CS2 (xrw)  : ORIGIN = 0x62000000, LENGTH = 16M
+  CS3 (xrw)  : ORIGIN = 0x63000000, LENGTH = 16M
+}
+_stack_start = ORIGIN(RAM) + LENGTH(RAM);
+
+/* Functions placed in RAM with `ramfunc!`, copied there by `ramfunc::init` */
+SECTIONS
+{
+  .ramfunc : ALIGN(4)
+  {
+    . = ALIGN(4);
+    __sramfunc = .;
+    *(.ramfunc .ramfunc.*);
+    . = ALIGN(4);
+    __eramfunc = .;
+  } > RAM AT>FLASH
+
+  __siramfunc = LOADADDR(.ramfunc);
+} INSERT AFTER .bss;
+
+/* Build information (`build_info`) at a fixed offset from the start of the
+   image, behind the vector table, so tools find it in flash dumps */
+EXTERN(__FIRMWARE_INFO);
+SECTIONS
+{
+  .firmware_info ORIGIN(FLASH) + 0x200 :
+  {
+    KEEP(*(.firmware_info));
+  } > FLASH
+} INSERT AFTER .vector_table;
+_stext = ORIGIN(FLASH) + 0x300;
@@ -0,0 +1,97 @@
+//! Mock board for host tests, laid out like the SAM4E Xplained Pro
+
+use core::ops::Range;
+
+use crate::chip::ChipInfo;
+use crate::mock::{MockButton, MockConsole, MockDelay, MockFlash, MockLed};
+use crate::{ResetCause, XplainedBoard};
+
+pub type Led0 = MockLed;
+pub type Sw0 = MockButton;
+pub type Console = MockConsole;
+
+/// Flash region (offsets into the flash) reserved for `kv::KvStore`
+pub const STORAGE: Range<u32> = 0x000F_C000..0x0010_0000;
+
+/// Board resources, which tests script and inspect through the fields
+pub struct Board {
+    pub led0: Led0,
+    pub sw0: Sw0,
+    pub console: Console,
+    pub delay: MockDelay,
+    pub chip_id: ChipInfo,
+    pub reset_cause: ResetCause,
+    pub unique_id: [u32; 4],
+    pub flash: MockFlash,
+}
+
+impl Board {
+    /// A board fresh out of power up: LED off, button released, no input
+    /// and an erased flash
+    pub fn new() -> Self {
+        Board {
+            led0: MockLed::new(),
+            sw0: MockButton::new(),
+            console: MockConsole::new(),
+            delay: MockDelay::new(),
+            chip_id: ChipInfo::from_registers(0xA3CC_0CE0, 0x0012_0200),
+            reset_cause: ResetCause::FirstPowerUp,
+            unique_id: [0x3436_3730, 0x3136_3031, 0x3138_3030, 0x3531_3538],
+            flash: MockFlash::new(FLASH_SIZE),
+        }
+    }
+}
+
+impl Default for Board {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl XplainedBoard for Board {
+    type Led = MockLed;
+    type Button = MockButton;
+    type Console = MockConsole;
+    type Delay = MockDelay;
+    type Flash = MockFlash;
+
+    const NAME: &'static str = "SAM4E Xplained Pro (mock)";
+
+    fn led(&mut self) -> &mut Self::Led {
+        &mut self.led0
+    }
+
+    fn button(&mut self) -> &mut Self::Button {
+        &mut self.sw0
+    }
+
+    fn console(&mut self) -> &mut Self::Console {
+        &mut self.console
+    }
+
+    fn delay(&mut self) -> &mut Self::Delay {
+        &mut self.delay
+    }
+
+    fn chip_id(&self) -> &ChipInfo {
+        &self.chip_id
+    }
+
+    fn reset_cause(&self) -> ResetCause {
+        self.reset_cause
+    }
+
+    fn unique_id(&self) -> [u32; 4] {
+        self.unique_id
+    }
+
+    fn flash(&mut self) -> &mut Self::Flash {
+        &mut self.flash
+    }
+}
+
+/// Part name reported by [`ChipInfo::part_name`] for this board
+pub(crate) const PART: &str = "ATSAM4E16E";
+
+/// Size of the internal flash in bytes
+const FLASH_SIZE: usize = 1024 * 1024;
@@ -0,0 +1,278 @@
+//! SAM4S Xplained Pro (ATSAM4SD32C)
+
+use core::ops::Range;
+use paste::paste;
+
+use crate::chip::ChipInfo;
+use crate::compat::Compat;
+use crate::flash::InternalFlash;
+use crate::hal::pac::Interrupt;
+use crate::hal::{
+    clock::*, define_pin_map, delay::Delay, gpio::*, pac, serial::Serial1, watchdog::Watchdog,
+};
+use crate::ramfunc;
+use crate::unique_id;
+use crate::{ResetCause, XplainedBoard, CONSOLE_BAUD_RATE};
+
+#[cfg(feature = "embassy")]
+use crate::hal::pac::interrupt;
+
+define_pin_map! {
+    struct Pins,
+
+    // Onboard LED
+    pin led0 = c23<Output<OpenDrain>, into_open_drain_output>,
+
+    // Onboard Button labeled SW0
+    pin sw0 = c24<Input<PullUp>, into_pull_up_input>,
+
+    // Serial Console (UART1)
+    pin uart1_rx = b2<PfA, into_peripheral_function_a>,
+    pin uart1_tx = b3<PfA, into_peripheral_function_a>,
+}
+
+// Note: There's two pinmaps here because both configurations
+// can't be active at the same time due to the shared PC12/PC15
+// pins.
+define_pin_map! {
+    struct ExternalMemory,
+
+    // Static Memory Controller Pins
+    pin ncs1 = c15<PfA, into_peripheral_function_a>,
+    pin ncs3 = c12<PfA, into_peripheral_function_a>,
+
+    pin nrd = c11<PfA, into_peripheral_function_a>,
+    pin nwe = c8<PfA, into_peripheral_function_a>,
+
+    pin d0 = c0<PfA, into_peripheral_function_a>,
+    pin d1 = c1<PfA, into_peripheral_function_a>,
+    pin d2 = c2<PfA, into_peripheral_function_a>,
+    pin d3 = c3<PfA, into_peripheral_function_a>,
+    pin d4 = c4<PfA, into_peripheral_function_a>,
+    pin d5 = c5<PfA, into_peripheral_function_a>,
+    pin d6 = c6<PfA, into_peripheral_function_a>,
+    pin d7 = c7<PfA, into_peripheral_function_a>,
+
+    pin a0 = c18<PfA, into_peripheral_function_a>,
+    pin a1 = c19<PfA, into_peripheral_function_a>,
+    pin a2 = c20<PfA, into_peripheral_function_a>,
+    pin a3 = c21<PfA, into_peripheral_function_a>,
+    pin a4 = c22<PfA, into_peripheral_function_a>,
+    pin a5 = c23<PfA, into_peripheral_function_a>,
+    pin a6 = c24<PfA, into_peripheral_function_a>,
+    pin a7 = c25<PfA, into_peripheral_function_a>,
+    pin a8 = c26<PfA, into_peripheral_function_a>,
+    pin a9 = c27<PfA, into_peripheral_function_a>,
+
+    pin a10 = c28<PfA, into_peripheral_function_a>,
+    pin a11 = c29<PfA, into_peripheral_function_a>,
+    pin a12 = c30<PfA, into_peripheral_function_a>,
+    pin a13 = c31<PfA, into_peripheral_function_a>,
+
+    pin a14 = a18<PfC, into_peripheral_function_c>,
+    pin a15 = a19<PfC, into_peripheral_function_c>,
+    pin a16 = a20<PfC, into_peripheral_function_c>,
+
+    pin a17 = a0<PfC, into_peripheral_function_c>,
+    pin a18 = a1<PfC, into_peripheral_function_c>,
+
+    pin a19 = a23<PfC, into_peripheral_function_c>,
+    pin a20 = a24<PfC, into_peripheral_function_c>,
+
+    pin a21 = c16<PfA, into_peripheral_function_a>,
+    pin a22 = c17<PfA, into_peripheral_function_a>,
+
+    pin a23 = a25<PfC, into_peripheral_function_c>,
+}
+
+/// Onboard LED labeled LED0 (active low)
+pub type Led0 = Pc23<Output<OpenDrain>>;
+/// Onboard button labeled SW0 (active low)
+pub type Sw0 = Pc24<Input<PullUp>>;
+/// Serial console on the EDBG virtual COM port
+pub type Console = Serial1;
+
+/// Flash region (offsets into the internal flash) reserved for the key-value
+/// store: the last 16 KiB of bank 1, away from the code in bank 0
+pub const STORAGE: Range<u32> = 0x001F_C000..0x0020_0000;
+
+pub struct Board {
+    pub led0: Led0,
+    pub sw0: Sw0,
+    pub console: Console,
+    pub delay: Delay,
+    pub watchdog: Watchdog,
+    pub chip_id: ChipInfo,
+    pub reset_cause: ResetCause,
+    pub unique_id: [u32; 4],
+    pub flash: InternalFlash,
+    pub rtt: pac::RTT,
+}
+
+impl XplainedBoard for Board {
+    type Led = Compat<Led0>;
+    type Button = Compat<Sw0>;
+    type Console = Compat<Console>;
+    type Delay = Compat<Delay>;
+    type Flash = InternalFlash;
+
+    const NAME: &'static str = "SAM4S Xplained Pro";
+
+    fn new(syst: pac::SYST, peripherals: pac::Peripherals) -> Self {
+        unsafe { ramfunc::init() };
+
+        let clocks = ClockController::new(
+            peripherals.PMC,
+            &peripherals.SUPC,
+            &peripherals.EFC0,
+            &peripherals.EFC1,
+            MainClock::RcOscillator12Mhz,
+            SlowClock::RcOscillator32Khz,
+        );
+
+        // The unique identifier is read through EFC0, which controls bank 0.
+        let unique_id = unsafe { unique_id::read(pac::EFC0::ptr() as usize) };
+
+        let gpio_ports = Ports::new(
+            (
+                peripherals.PIOA,
+                clocks.peripheral_clocks.pio_a.into_enabled_clock(),
+            ),
+            (
+                peripherals.PIOB,
+                clocks.peripheral_clocks.pio_b.into_enabled_clock(),
+            ),
+            (
+                peripherals.PIOC,
+                clocks.peripheral_clocks.pio_c.into_enabled_clock(),
+            ),
+        );
+        let pins = Pins::new(gpio_ports);
+
+        let console = Serial1::new(
+            peripherals.UART1,
+            clocks.peripheral_clocks.uart_1.into_enabled_clock(),
+            pins.uart1_rx,
+            pins.uart1_tx,
+            CONSOLE_BAUD_RATE,
+            None,
+        );
+
+        Board {
+            led0: pins.led0,
+            sw0: pins.sw0,
+            console,
+            delay: Delay::new(syst),
+            watchdog: Watchdog::new(peripherals.WDT),
+            chip_id: ChipInfo::new(&peripherals.CHIPID),
+            reset_cause: ResetCause::new(&peripherals.RSTC),
+            unique_id,
+            flash: InternalFlash::new(),
+            rtt: peripherals.RTT,
+        }
+    }
+
+    fn led(&mut self) -> &mut Self::Led {
+        Compat::from_mut(&mut self.led0)
+    }
+
+    fn button(&mut self) -> &mut Self::Button {
+        Compat::from_mut(&mut self.sw0)
+    }
+
+    fn console(&mut self) -> &mut Self::Console {
+        Compat::from_mut(&mut self.console)
+    }
+
+    fn delay(&mut self) -> &mut Self::Delay {
+        Compat::from_mut(&mut self.delay)
+    }
+
+    fn chip_id(&self) -> &ChipInfo {
+        &self.chip_id
+    }
+
+    fn reset_cause(&self) -> ResetCause {
+        self.reset_cause
+    }
+
+    fn unique_id(&self) -> [u32; 4] {
+        self.unique_id
+    }
+
+    fn flash(&mut self) -> &mut Self::Flash {
+        &mut self.flash
+    }
+}
+
+//
+// Board resources shared with the compat, buffered and embassy modules
+//
+
+/// Part name reported by [`ChipInfo::part_name`] for this board
+pub(crate) const PART: &str = "ATSAM4SD32C";
+
+/// Size of the internal flash in bytes
+pub(crate) const FLASH_SIZE: usize = 2048 * 1024;
+
+/// Flash offset of bank 1
+const FLASH_BANK_1: u32 = 1024 * 1024;
+
+/// Flash controller for flash offset `offset`, and the first offset it
+/// controls: EFC0 drives bank 0, EFC1 bank 1
+pub(crate) fn flash_controller(offset: u32) -> (usize, u32) {
+    if offset < FLASH_BANK_1 {
+        (pac::EFC0::ptr() as usize, 0)
+    } else {
+        (pac::EFC1::ptr() as usize, FLASH_BANK_1)
+    }
+}
+
+/// PIO line of SW0 (PC24)
+#[cfg(feature = "embassy")]
+pub(crate) const SW0_MASK: u32 = 1 << 24;
+#[cfg(feature = "embassy")]
+pub(crate) const SW0_INTERRUPT: Interrupt = Interrupt::PIOC;
+
+// The PIO controllers (and likewise the UARTs) share one register layout; the
+// PAC just generates a separate module for each instance.
+#[cfg(feature = "embassy")]
+pub(crate) fn sw0_pio() -> &'static pac::pioa::RegisterBlock {
+    unsafe { &*(pac::PIOC::ptr() as *const pac::pioa::RegisterBlock) }
+}
+
+pub(crate) const CONSOLE_INTERRUPT: Interrupt = Interrupt::UART1;
+
+pub(crate) fn console_uart() -> &'static pac::uart0::RegisterBlock {
+    unsafe { &*(pac::UART1::ptr() as *const pac::uart0::RegisterBlock) }
+}
+
+/// Base address and number of lines of the PIO controller of `port` (`'a'`
+/// for PIOA)
+#[cfg(feature = "shell")]
+pub(crate) fn pio(port: char) -> Option<(usize, u32)> {
+    match port {
+        'a' => Some((pac::PIOA::ptr() as usize, 32)),
+        'b' => Some((pac::PIOB::ptr() as usize, 15)),
+        'c' => Some((pac::PIOC::ptr() as usize, 32)),
+        _ => None,
+    }
+}
+
+crate::compat::gpio_compat! {
+    PIOA: Pa [0 1 2 3 4 5 6 7 8 9 10 11 12 13 14 15 16 17 18 19 20 21 22 23 24 25 26 27 28 29 30 31],
+    PIOB: Pb [0 1 2 3 4 5 6 7 8 9 10 11 12 13 14],
+    PIOC: Pc [0 1 2 3 4 5 6 7 8 9 10 11 12 13 14 15 16 17 18 19 20 21 22 23 24 25 26 27 28 29 30 31],
+}
+
+#[cfg(feature = "embassy")]
+#[interrupt]
+fn PIOC() {
+    crate::embassy::on_sw0_interrupt();
+}
+
+#[cfg(feature = "embassy")]
+#[interrupt]
+fn UART1() {
+    crate::embassy::on_console_interrupt();
+}
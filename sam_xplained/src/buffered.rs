@@ -0,0 +1,355 @@
+//! Interrupt-driven serial console with ring buffers
+//!
+//! [`BufferedConsole`] queues transmitted bytes and collects received ones in
+//! the static ring buffers of a [`ConsoleBuffers`], which the console UART
+//! interrupt drains and fills on RXRDY/TXRDY.  Writing only blocks when the
+//! transmit buffer is full, so tasks can log without waiting for the UART.
+//!
+//! The application binds the board's console interrupt (UART0 on the SAM4E
+//! and SAM4N, UART1 on the SAM4S) to [`ConsoleBuffers::on_interrupt`]; with
+//! RTIC:
+//!
+//! ```rust
+//! static CONSOLE: ConsoleBuffers<64, 1024> = ConsoleBuffers::new();
+//!
+//! #[task(binds = UART0, priority = 3)]
+//! fn console(_: console::Context) {
+//!     CONSOLE.on_interrupt();
+//! }
+//! ```
+//!
+//! The interrupt is owned by `AsyncConsole` while the `embassy` feature is
+//! enabled.
+
+use core::cell::UnsafeCell;
+use core::convert::Infallible;
+use core::fmt;
+use core::sync::atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering};
+use cortex_m::peripheral::NVIC;
+
+use crate::board::{console_uart as uart, CONSOLE_INTERRUPT};
+use crate::hal::hal::serial;
+use crate::Console;
+
+// UART status/interrupt register bits
+const RXRDY: u32 = 1 << 0;
+const TXRDY: u32 = 1 << 1;
+const OVRE: u32 = 1 << 5;
+const FRAME: u32 = 1 << 6;
+const PARE: u32 = 1 << 7;
+const TXEMPTY: u32 = 1 << 9;
+
+/// Single-producer, single-consumer byte queue shared with the interrupt
+/// handler
+struct RingBuffer<const N: usize> {
+    data: UnsafeCell<[u8; N]>,
+    // Free-running counts of the bytes pushed and popped
+    head: AtomicUsize,
+    tail: AtomicUsize,
+}
+
+impl<const N: usize> RingBuffer<N> {
+    const CAPACITY: usize = {
+        assert!(
+            N.is_power_of_two(),
+            "ring buffer sizes must be powers of two"
+        );
+        N
+    };
+
+    const fn new() -> Self {
+        RingBuffer {
+            data: UnsafeCell::new([0; N]),
+            head: AtomicUsize::new(0),
+            tail: AtomicUsize::new(0),
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.head
+            .load(Ordering::Acquire)
+            .wrapping_sub(self.tail.load(Ordering::Acquire))
+    }
+
+    /// Appends `byte`, unless the buffer is full.  Producer side only.
+    fn push(&self, byte: u8) -> bool {
+        let head = self.head.load(Ordering::Relaxed);
+        if head.wrapping_sub(self.tail.load(Ordering::Acquire)) == Self::CAPACITY {
+            return false;
+        }
+        unsafe { (*self.data.get())[head % N] = byte };
+        self.head.store(head.wrapping_add(1), Ordering::Release);
+        true
+    }
+
+    /// Removes the oldest byte.  Consumer side only.
+    fn pop(&self) -> Option<u8> {
+        let tail = self.tail.load(Ordering::Relaxed);
+        if self.head.load(Ordering::Acquire) == tail {
+            return None;
+        }
+        let byte = unsafe { (*self.data.get())[tail % N] };
+        self.tail.store(tail.wrapping_add(1), Ordering::Release);
+        Some(byte)
+    }
+}
+
+/// Receive errors counted by [`ConsoleBuffers::on_interrupt`]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ErrorCounts {
+    /// Bytes lost because the UART received the next one before the
+    /// interrupt handler read them
+    pub overrun: u32,
+    /// Bytes discarded for a missing stop bit
+    pub framing: u32,
+    /// Bytes discarded for a parity mismatch
+    pub parity: u32,
+    /// Bytes lost because the receive buffer was full
+    pub dropped: u32,
+}
+
+/// Receive and transmit buffers of a [`BufferedConsole`], holding `RX` and
+/// `TX` bytes (both powers of two)
+pub struct ConsoleBuffers<const RX: usize, const TX: usize> {
+    rx: RingBuffer<RX>,
+    tx: RingBuffer<TX>,
+    taken: AtomicBool,
+    overrun: AtomicU32,
+    framing: AtomicU32,
+    parity: AtomicU32,
+    dropped: AtomicU32,
+}
+
+// The console and the interrupt handler each only use one end of each buffer.
+unsafe impl<const RX: usize, const TX: usize> Sync for ConsoleBuffers<RX, TX> {}
+
+impl<const RX: usize, const TX: usize> ConsoleBuffers<RX, TX> {
+    pub const fn new() -> Self {
+        ConsoleBuffers {
+            rx: RingBuffer::new(),
+            tx: RingBuffer::new(),
+            taken: AtomicBool::new(false),
+            overrun: AtomicU32::new(0),
+            framing: AtomicU32::new(0),
+            parity: AtomicU32::new(0),
+            dropped: AtomicU32::new(0),
+        }
+    }
+
+    /// Moves received bytes into the receive buffer and queued ones out of
+    /// the transmit buffer.  Call it from the console UART interrupt handler.
+    pub fn on_interrupt(&self) {
+        let uart = uart();
+        let status = uart.sr.read().bits();
+
+        if status & (OVRE | FRAME | PARE) != 0 {
+            count(&self.overrun, status & OVRE);
+            count(&self.framing, status & FRAME);
+            count(&self.parity, status & PARE);
+            uart.cr.write_with_zero(|w| w.rststa().set_bit());
+        }
+        if status & RXRDY != 0 {
+            let byte = uart.rhr.read().rxchr().bits();
+            if status & (FRAME | PARE) == 0 && !self.rx.push(byte) {
+                count(&self.dropped, 1);
+            }
+        }
+        if status & TXRDY != 0 && uart.imr.read().bits() & TXRDY != 0 {
+            match self.tx.pop() {
+                Some(byte) => uart
+                    .thr
+                    .write_with_zero(|w| unsafe { w.txchr().bits(byte) }),
+                None => uart.idr.write_with_zero(|w| w.txrdy().set_bit()),
+            }
+        }
+    }
+
+    /// Receive errors since the buffers were first used
+    pub fn errors(&self) -> ErrorCounts {
+        ErrorCounts {
+            overrun: self.overrun.load(Ordering::Relaxed),
+            framing: self.framing.load(Ordering::Relaxed),
+            parity: self.parity.load(Ordering::Relaxed),
+            dropped: self.dropped.load(Ordering::Relaxed),
+        }
+    }
+}
+
+impl<const RX: usize, const TX: usize> Default for ConsoleBuffers<RX, TX> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn count(counter: &AtomicU32, condition: u32) {
+    if condition != 0 {
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Serial console transmitting and receiving through [`ConsoleBuffers`]
+///
+/// The embedded-hal 0.2 `serial::Read`/`serial::Write` implementations never
+/// wait and return `nb::Error::WouldBlock` instead; the embedded-io and
+/// `fmt::Write` implementations wait for buffer space or received bytes.
+pub struct BufferedConsole<const RX: usize, const TX: usize> {
+    serial: Console,
+    buffers: &'static ConsoleBuffers<RX, TX>,
+}
+
+impl<const RX: usize, const TX: usize> BufferedConsole<RX, TX> {
+    /// Takes over the console UART and unmasks its interrupt, which must be
+    /// bound to `buffers.on_interrupt()`.
+    ///
+    /// Panics if `buffers` are in use by another console.
+    pub fn new(serial: Console, buffers: &'static ConsoleBuffers<RX, TX>) -> Self {
+        assert!(
+            !buffers.taken.swap(true, Ordering::AcqRel),
+            "console buffers already in use"
+        );
+        // Rejects buffer sizes that are not powers of two at compile time
+        let _ = (RingBuffer::<RX>::CAPACITY, RingBuffer::<TX>::CAPACITY);
+
+        let uart = uart();
+        uart.idr.write_with_zero(|w| unsafe { w.bits(0xFFFF_FFFF) });
+        uart.cr.write_with_zero(|w| w.rststa().set_bit());
+        uart.ier.write_with_zero(|w| w.rxrdy().set_bit());
+        unsafe { NVIC::unmask(CONSOLE_INTERRUPT) };
+
+        BufferedConsole { serial, buffers }
+    }
+
+    /// Queues as much of `data` as fits into the transmit buffer without
+    /// waiting and returns the number of bytes queued.
+    pub fn write_nonblocking(&mut self, data: &[u8]) -> usize {
+        let count = data
+            .iter()
+            .take_while(|&&byte| self.buffers.tx.push(byte))
+            .count();
+        if count > 0 {
+            uart().ier.write_with_zero(|w| w.txrdy().set_bit());
+        }
+        count
+    }
+
+    /// Number of received bytes waiting to be read
+    pub fn available(&self) -> usize {
+        self.buffers.rx.len()
+    }
+
+    /// Number of bytes waiting to be transmitted
+    pub fn pending(&self) -> usize {
+        self.buffers.tx.len()
+    }
+
+    /// Receive errors since the buffers were first used
+    pub fn errors(&self) -> ErrorCounts {
+        self.buffers.errors()
+    }
+
+    /// Disables the console UART interrupts and releases the serial port and
+    /// the buffers.  Queued bytes that have not been transmitted are lost.
+    pub fn free(self) -> Console {
+        uart()
+            .idr
+            .write_with_zero(|w| unsafe { w.bits(0xFFFF_FFFF) });
+        NVIC::mask(CONSOLE_INTERRUPT);
+        while self.buffers.tx.pop().is_some() {}
+        while self.buffers.rx.pop().is_some() {}
+        self.buffers.taken.store(false, Ordering::Release);
+        self.serial
+    }
+}
+
+impl<const RX: usize, const TX: usize> serial::Read<u8> for BufferedConsole<RX, TX> {
+    type Error = Infallible;
+
+    fn read(&mut self) -> nb::Result<u8, Self::Error> {
+        self.buffers.rx.pop().ok_or(nb::Error::WouldBlock)
+    }
+}
+
+impl<const RX: usize, const TX: usize> serial::Write<u8> for BufferedConsole<RX, TX> {
+    type Error = Infallible;
+
+    fn write(&mut self, byte: u8) -> nb::Result<(), Self::Error> {
+        match self.write_nonblocking(&[byte]) {
+            0 => Err(nb::Error::WouldBlock),
+            _ => Ok(()),
+        }
+    }
+
+    /// Completes once every queued byte has left the shift register
+    fn flush(&mut self) -> nb::Result<(), Self::Error> {
+        if self.buffers.tx.len() == 0 && uart().sr.read().bits() & TXEMPTY != 0 {
+            Ok(())
+        } else {
+            Err(nb::Error::WouldBlock)
+        }
+    }
+}
+
+impl<const RX: usize, const TX: usize> embedded_io::ErrorType for BufferedConsole<RX, TX> {
+    type Error = Infallible;
+}
+
+impl<const RX: usize, const TX: usize> embedded_io::Read for BufferedConsole<RX, TX> {
+    /// Waits until at least one byte has been received, then returns
+    /// everything buffered so far (up to `buffer.len()`).
+    fn read(&mut self, buffer: &mut [u8]) -> Result<usize, Self::Error> {
+        if buffer.is_empty() {
+            return Ok(0);
+        }
+        while self.buffers.rx.len() == 0 {}
+
+        let mut count = 0;
+        while count < buffer.len() {
+            match self.buffers.rx.pop() {
+                Some(byte) => {
+                    buffer[count] = byte;
+                    count += 1;
+                }
+                None => break,
+            }
+        }
+        Ok(count)
+    }
+}
+
+impl<const RX: usize, const TX: usize> embedded_io::ReadReady for BufferedConsole<RX, TX> {
+    fn read_ready(&mut self) -> Result<bool, Self::Error> {
+        Ok(self.buffers.rx.len() != 0)
+    }
+}
+
+impl<const RX: usize, const TX: usize> embedded_io::Write for BufferedConsole<RX, TX> {
+    /// Waits until the transmit buffer has room, then queues as much of
+    /// `buffer` as fits.
+    fn write(&mut self, buffer: &[u8]) -> Result<usize, Self::Error> {
+        if buffer.is_empty() {
+            return Ok(0);
+        }
+        loop {
+            match self.write_nonblocking(buffer) {
+                0 => continue,
+                count => return Ok(count),
+            }
+        }
+    }
+
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        nb::block!(serial::Write::flush(self))
+    }
+}
+
+impl<const RX: usize, const TX: usize> embedded_io::WriteReady for BufferedConsole<RX, TX> {
+    fn write_ready(&mut self) -> Result<bool, Self::Error> {
+        Ok(self.buffers.tx.len() < TX)
+    }
+}
+
+impl<const RX: usize, const TX: usize> fmt::Write for BufferedConsole<RX, TX> {
+    fn write_str(&mut self, text: &str) -> fmt::Result {
+        embedded_io::Write::write_all(self, text.as_bytes()).map_err(|_| fmt::Error)
+    }
+}
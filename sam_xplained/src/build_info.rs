@@ -0,0 +1,29 @@
+//! Build information
+//!
+//! build.rs records the firmware version, git commit, build time, board and
+//! the enabled features of this crate in a [`FirmwareInfo`].  It is linked
+//! into the `.firmware_info` section at [`FirmwareInfo::OFFSET`] from the
+//! start of the image, right behind the vector table, where
+//! `sam_xplained_imgtool info` finds it in ELF files, image files and flash
+//! dumps.
+//!
+//! The version is this crate's unless set with the
+//! `SAM_XPLAINED_FIRMWARE_VERSION` environment variable, the commit is the
+//! one checked out where this crate is built from (with `-dirty` for
+//! uncommitted changes) and `SOURCE_DATE_EPOCH` overrides the build time.
+
+pub use sam_xplained_image::FirmwareInfo;
+
+include!(concat!(env!("OUT_DIR"), "/build_info.rs"));
+
+// memory.x keeps the symbol, even when nothing refers to it.
+#[no_mangle]
+#[used]
+#[cfg_attr(not(feature = "mock"), link_section = ".firmware_info")]
+static __FIRMWARE_INFO: [u8; FirmwareInfo::LEN] = FIRMWARE_INFO;
+
+/// Information of the running build, read from the flash
+pub fn firmware_info() -> FirmwareInfo {
+    let bytes = unsafe { core::ptr::read_volatile(&__FIRMWARE_INFO) };
+    FirmwareInfo::from_bytes(&bytes).unwrap()
+}
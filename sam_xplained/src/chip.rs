@@ -0,0 +1,255 @@
+//! Chip identification
+//!
+//! [`ChipInfo`] decodes the CHIPID registers (CIDR and EXID) into the
+//! processor, memory sizes and part name, and checks that the firmware is
+//! running on the part the selected board carries:
+//!
+//! ```ignore
+//! let chip = ChipInfo::new(&peripherals.CHIPID);
+//! hprintln!("{}", chip).ok();
+//! chip.verify()?;
+//! ```
+
+use core::fmt;
+
+#[cfg(not(feature = "mock"))]
+use crate::hal::pac;
+
+// CIDR fields
+const VERSION_MASK: u32 = 0x1F;
+const EXT: u32 = 1 << 31;
+
+/// Contents of the CHIPID registers
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ChipInfo {
+    cidr: u32,
+    exid: u32,
+}
+
+impl ChipInfo {
+    #[cfg(not(feature = "mock"))]
+    pub fn new(chipid: &pac::CHIPID) -> Self {
+        Self::from_registers(chipid.cidr.read().bits(), chipid.exid.read().bits())
+    }
+
+    /// Decodes register values read by other means (e.g. over SAM-BA)
+    pub const fn from_registers(cidr: u32, exid: u32) -> Self {
+        ChipInfo { cidr, exid }
+    }
+
+    /// Raw chip identification register
+    pub fn cidr(&self) -> u32 {
+        self.cidr
+    }
+
+    /// Raw chip extension register
+    pub fn exid(&self) -> u32 {
+        self.exid
+    }
+
+    /// Silicon revision
+    pub fn version(&self) -> u8 {
+        (self.cidr & VERSION_MASK) as u8
+    }
+
+    pub fn processor(&self) -> Option<&'static str> {
+        match (self.cidr >> 5) & 0x7 {
+            1 => Some("ARM946ES"),
+            2 => Some("ARM7TDMI"),
+            3 => Some("Cortex-M3"),
+            4 => Some("ARM920T"),
+            5 => Some("ARM926EJS"),
+            6 => Some("Cortex-A5"),
+            7 => Some("Cortex-M4"),
+            _ => None,
+        }
+    }
+
+    /// Size of the (first) flash memory in bytes
+    pub fn flash_size(&self) -> Option<usize> {
+        decode_flash_size((self.cidr >> 8) & 0xF)
+    }
+
+    /// Size of the second flash memory in bytes, if the part has one
+    pub fn flash2_size(&self) -> Option<usize> {
+        decode_flash_size((self.cidr >> 12) & 0xF)
+    }
+
+    /// Size of the internal SRAM in bytes
+    pub fn sram_size(&self) -> usize {
+        let kib = match (self.cidr >> 16) & 0xF {
+            0 => 48,
+            1 => 192,
+            2 => 2,
+            3 => 6,
+            4 => 24,
+            5 => 4,
+            6 => 80,
+            7 => 160,
+            8 => 8,
+            9 => 16,
+            10 => 32,
+            11 => 64,
+            12 => 128,
+            13 => 256,
+            14 => 96,
+            _ => 512,
+        };
+        kib * 1024
+    }
+
+    /// Architecture identifier (ARCH field)
+    pub fn architecture(&self) -> u8 {
+        (self.cidr >> 20) as u8
+    }
+
+    /// Name of the product series the architecture identifier denotes
+    pub fn architecture_name(&self) -> Option<&'static str> {
+        match self.architecture() {
+            0x3C => Some("SAM4E"),
+            0x88 => Some("SAM4SxA"),
+            0x89 => Some("SAM4SxB"),
+            0x8A => Some("SAM4SxC"),
+            0x93 => Some("SAM4NxA"),
+            0x94 => Some("SAM4NxB"),
+            0x95 => Some("SAM4NxC"),
+            0x99 => Some("SAM4SDxB"),
+            0x9A => Some("SAM4SDxC"),
+            _ => None,
+        }
+    }
+
+    /// Extension identifier, for parts that need EXID to tell them apart
+    pub fn extension_id(&self) -> Option<u32> {
+        if self.cidr & EXT != 0 {
+            Some(self.exid)
+        } else {
+            None
+        }
+    }
+
+    /// Part name, e.g. "ATSAM4E16E"
+    pub fn part_name(&self) -> Option<&'static str> {
+        let name = match self.cidr & !VERSION_MASK {
+            0x288B_07E0 => "ATSAM4S2A",
+            0x289B_07E0 => "ATSAM4S2B",
+            0x28AB_07E0 => "ATSAM4S2C",
+            0x288B_09E0 => "ATSAM4S4A",
+            0x289B_09E0 => "ATSAM4S4B",
+            0x28AB_09E0 => "ATSAM4S4C",
+            0x289C_0AE0 => "ATSAM4S8B",
+            0x28AC_0AE0 => "ATSAM4S8C",
+            0x289C_0CE0 => "ATSAM4S16B",
+            0x28AC_0CE0 => "ATSAM4S16C",
+            0x2897_0CE0 => "ATSAM4SA16B",
+            0x28A7_0CE0 => "ATSAM4SA16C",
+            0x2997_0CE0 => "ATSAM4SD16B",
+            0x29A7_0CE0 => "ATSAM4SD16C",
+            0x2997_0EE0 => "ATSAM4SD32B",
+            0x29A7_0EE0 => "ATSAM4SD32C",
+
+            0x293B_0AE0 => "ATSAM4N8A",
+            0x294B_0AE0 => "ATSAM4N8B",
+            0x295B_0AE0 => "ATSAM4N8C",
+            0x2946_0CE0 => "ATSAM4N16B",
+            0x2956_0CE0 => "ATSAM4N16C",
+
+            0xA3CC_0CE0 => match self.exid {
+                0x0012_0209 => "ATSAM4E8C",
+                0x0012_0208 => "ATSAM4E8E",
+                0x0012_0201 => "ATSAM4E16C",
+                0x0012_0200 => "ATSAM4E16E",
+                _ => return None,
+            },
+
+            _ => return None,
+        };
+        Some(name)
+    }
+
+    /// Checks that this is the part fitted to the board the crate was built for
+    pub fn verify(&self) -> Result<(), ChipMismatch> {
+        if self.part_name() == Some(crate::board::PART) {
+            Ok(())
+        } else {
+            Err(ChipMismatch {
+                expected: crate::board::PART,
+                found: *self,
+            })
+        }
+    }
+}
+
+fn decode_flash_size(value: u32) -> Option<usize> {
+    let kib = match value {
+        1 => 8,
+        2 => 16,
+        3 => 32,
+        5 => 64,
+        7 => 128,
+        9 => 256,
+        10 => 512,
+        12 => 1024,
+        14 => 2048,
+        _ => return None,
+    };
+    Some(kib * 1024)
+}
+
+/// Multi-line report of the decoded registers
+impl fmt::Display for ChipInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Part: {}", self.part_name().unwrap_or("unknown"))?;
+        writeln!(
+            f,
+            "Processor: {} (revision {})",
+            self.processor().unwrap_or("unknown"),
+            self.version()
+        )?;
+        writeln!(
+            f,
+            "Architecture: {:#04x} ({})",
+            self.architecture(),
+            self.architecture_name().unwrap_or("unknown")
+        )?;
+        match self.flash_size() {
+            Some(size) => write!(f, "Flash: {} KiB", size / 1024)?,
+            None => write!(f, "Flash: unknown")?,
+        }
+        if let Some(size) = self.flash2_size() {
+            write!(f, " + {} KiB", size / 1024)?;
+        }
+        writeln!(f, ", SRAM: {} KiB", self.sram_size() / 1024)?;
+        write!(f, "CIDR: {:#010x}", self.cidr)?;
+        if let Some(exid) = self.extension_id() {
+            write!(f, ", EXID: {:#010x}", exid)?;
+        }
+        Ok(())
+    }
+}
+
+/// The firmware was built for a different board than the one it runs on
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ChipMismatch {
+    /// Part fitted to the board selected at build time
+    pub expected: &'static str,
+    /// Identification of the part actually found
+    pub found: ChipInfo,
+}
+
+impl fmt::Display for ChipMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.found.part_name() {
+            Some(part) => write!(
+                f,
+                "firmware built for the {} is running on an {}",
+                self.expected, part
+            ),
+            None => write!(
+                f,
+                "firmware built for the {} is running on an unknown part (CIDR {:#010x}, EXID {:#010x})",
+                self.expected, self.found.cidr, self.found.exid
+            ),
+        }
+    }
+}
@@ -0,0 +1,410 @@
+//! embedded-hal 1.0 and embedded-io support
+//!
+//! The HAL implements the embedded-hal 0.2 traits.  Wrapping a board resource in
+//! [`Compat`] exposes the embedded-hal 1.0 traits instead:
+//!
+//! * GPIO pins implement `digital::{InputPin, OutputPin, StatefulOutputPin}`
+//!   directly on the PIO registers.
+//! * [`Delay`] implements `delay::DelayNs`.
+//! * The console UART implements `embedded_io::{Read, Write}`.
+//! * Any embedded-hal 0.2 blocking SPI or I2C implementation is adapted to
+//!   `spi::SpiBus` and `i2c::I2c`.  I2C transactions are limited to what
+//!   0.2 can issue with repeated STARTs: a read, a write, or a write then a
+//!   read.
+//!
+//! With the `eh02` feature enabled, wrapped pins keep implementing the 0.2
+//! digital traits so they can be handed to drivers of either generation.
+//!
+//! ```rust
+//! let mut led0 = Compat::new(pins.led0);
+//! let mut delay = Compat::new(Delay::new(core.SYST));
+//! led0.toggle().ok();
+//! delay.delay_ms(500);
+//! ```
+
+use core::fmt::Debug;
+use embedded_hal::{delay, i2c, spi};
+use embedded_hal_02::blocking;
+
+use crate::board::console_uart;
+use crate::hal::{
+    delay::{Delay, DelayUs},
+    hal::serial::{Read as _, Write as _},
+    serial,
+};
+use crate::Console;
+
+/// Adapter exposing embedded-hal 1.0 / embedded-io traits for a board resource
+#[repr(transparent)]
+pub struct Compat<T>(T);
+
+impl<T> Compat<T> {
+    pub fn new(inner: T) -> Self {
+        Compat(inner)
+    }
+
+    /// Views a borrowed resource through the adapter without moving it
+    pub fn from_mut(inner: &mut T) -> &mut Self {
+        // `Compat` is `repr(transparent)`, so both have the same layout.
+        unsafe { &mut *(inner as *mut T as *mut Self) }
+    }
+
+    pub fn inner(&self) -> &T {
+        &self.0
+    }
+
+    pub fn inner_mut(&mut self) -> &mut T {
+        &mut self.0
+    }
+
+    /// Releases the wrapped resource
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+/// Error reported through the 1.0 traits for an underlying 0.2 error
+#[derive(Debug)]
+pub struct Error<E>(pub E);
+
+impl<E: Debug> spi::Error for Error<E> {
+    fn kind(&self) -> spi::ErrorKind {
+        spi::ErrorKind::Other
+    }
+}
+
+impl<E: Debug> i2c::Error for Error<E> {
+    fn kind(&self) -> i2c::ErrorKind {
+        i2c::ErrorKind::Other
+    }
+}
+
+impl<E: Debug> embedded_io::Error for Error<E> {
+    fn kind(&self) -> embedded_io::ErrorKind {
+        embedded_io::ErrorKind::Other
+    }
+}
+
+//
+// GPIO
+//
+
+/// Implements the digital traits for `Compat`-wrapped pins.  Invoked by each
+/// board module with the ports and pin numbers of its package.
+macro_rules! gpio_compat {
+    ($($PIO:ident: $Pin:ident [$($i:literal)+],)+) => {
+        $($(::paste::paste! {
+            impl<MODE> ::embedded_hal::digital::ErrorType
+                for $crate::compat::Compat<$crate::hal::gpio::[<$Pin $i>]<$crate::hal::gpio::Input<MODE>>>
+            {
+                type Error = ::core::convert::Infallible;
+            }
+
+            impl<MODE> ::embedded_hal::digital::InputPin
+                for $crate::compat::Compat<$crate::hal::gpio::[<$Pin $i>]<$crate::hal::gpio::Input<MODE>>>
+            {
+                fn is_high(&mut self) -> Result<bool, Self::Error> {
+                    Ok(unsafe { (*$crate::hal::pac::$PIO::ptr()).pdsr.read().bits() } & (1 << $i) != 0)
+                }
+
+                fn is_low(&mut self) -> Result<bool, Self::Error> {
+                    ::embedded_hal::digital::InputPin::is_high(self).map(|high| !high)
+                }
+            }
+
+            impl<MODE> ::embedded_hal::digital::ErrorType
+                for $crate::compat::Compat<$crate::hal::gpio::[<$Pin $i>]<$crate::hal::gpio::Output<MODE>>>
+            {
+                type Error = ::core::convert::Infallible;
+            }
+
+            impl<MODE> ::embedded_hal::digital::OutputPin
+                for $crate::compat::Compat<$crate::hal::gpio::[<$Pin $i>]<$crate::hal::gpio::Output<MODE>>>
+            {
+                fn set_high(&mut self) -> Result<(), Self::Error> {
+                    unsafe { (*$crate::hal::pac::$PIO::ptr()).sodr.write_with_zero(|w| w.bits(1 << $i)) };
+                    Ok(())
+                }
+
+                fn set_low(&mut self) -> Result<(), Self::Error> {
+                    unsafe { (*$crate::hal::pac::$PIO::ptr()).codr.write_with_zero(|w| w.bits(1 << $i)) };
+                    Ok(())
+                }
+            }
+
+            impl<MODE> ::embedded_hal::digital::StatefulOutputPin
+                for $crate::compat::Compat<$crate::hal::gpio::[<$Pin $i>]<$crate::hal::gpio::Output<MODE>>>
+            {
+                fn is_set_high(&mut self) -> Result<bool, Self::Error> {
+                    Ok(unsafe { (*$crate::hal::pac::$PIO::ptr()).odsr.read().bits() } & (1 << $i) != 0)
+                }
+
+                fn is_set_low(&mut self) -> Result<bool, Self::Error> {
+                    ::embedded_hal::digital::StatefulOutputPin::is_set_high(self).map(|high| !high)
+                }
+            }
+
+            #[cfg(feature = "eh02")]
+            impl<MODE> ::embedded_hal_02::digital::v2::InputPin
+                for $crate::compat::Compat<$crate::hal::gpio::[<$Pin $i>]<$crate::hal::gpio::Input<MODE>>>
+            {
+                type Error = ::core::convert::Infallible;
+
+                fn is_high(&self) -> Result<bool, Self::Error> {
+                    Ok(unsafe { (*$crate::hal::pac::$PIO::ptr()).pdsr.read().bits() } & (1 << $i) != 0)
+                }
+
+                fn is_low(&self) -> Result<bool, Self::Error> {
+                    ::embedded_hal_02::digital::v2::InputPin::is_high(self).map(|high| !high)
+                }
+            }
+
+            #[cfg(feature = "eh02")]
+            impl<MODE> ::embedded_hal_02::digital::v2::OutputPin
+                for $crate::compat::Compat<$crate::hal::gpio::[<$Pin $i>]<$crate::hal::gpio::Output<MODE>>>
+            {
+                type Error = ::core::convert::Infallible;
+
+                fn set_high(&mut self) -> Result<(), Self::Error> {
+                    ::embedded_hal::digital::OutputPin::set_high(self)
+                }
+
+                fn set_low(&mut self) -> Result<(), Self::Error> {
+                    ::embedded_hal::digital::OutputPin::set_low(self)
+                }
+            }
+
+            #[cfg(feature = "eh02")]
+            impl<MODE> ::embedded_hal_02::digital::v2::StatefulOutputPin
+                for $crate::compat::Compat<$crate::hal::gpio::[<$Pin $i>]<$crate::hal::gpio::Output<MODE>>>
+            {
+                fn is_set_high(&self) -> Result<bool, Self::Error> {
+                    Ok(unsafe { (*$crate::hal::pac::$PIO::ptr()).odsr.read().bits() } & (1 << $i) != 0)
+                }
+
+                fn is_set_low(&self) -> Result<bool, Self::Error> {
+                    ::embedded_hal_02::digital::v2::StatefulOutputPin::is_set_high(self).map(|high| !high)
+                }
+            }
+        })+)+
+    };
+}
+
+pub(crate) use gpio_compat;
+
+//
+// Delay
+//
+
+impl delay::DelayNs for Compat<Delay> {
+    fn delay_ns(&mut self, ns: u32) {
+        // SysTick resolution is well below a microsecond; round up.
+        self.delay_us(ns.div_ceil(1_000));
+    }
+
+    fn delay_us(&mut self, mut us: u32) {
+        // The HAL converts its argument to core clock cycles in 32 bits, so
+        // keep each call short enough not to overflow.
+        const MAX_US: u32 = 10_000;
+        while us > MAX_US {
+            self.0.delay_us(MAX_US);
+            us -= MAX_US;
+        }
+        self.0.delay_us(us);
+    }
+
+    fn delay_ms(&mut self, ms: u32) {
+        for _ in 0..ms {
+            self.delay_us(1_000);
+        }
+    }
+}
+
+//
+// Serial console
+//
+
+// UART status register bits
+const TXEMPTY: u32 = 1 << 9;
+
+impl embedded_io::ErrorType for Compat<Console> {
+    type Error = Error<serial::Error>;
+}
+
+impl embedded_io::Read for Compat<Console> {
+    /// Blocks until at least one byte is available, then returns everything
+    /// that has been received so far (up to `buffer.len()`).
+    fn read(&mut self, buffer: &mut [u8]) -> Result<usize, Self::Error> {
+        if buffer.is_empty() {
+            return Ok(0);
+        }
+
+        let mut count = 0;
+        while count < buffer.len() {
+            match self.0.read() {
+                Ok(byte) => {
+                    buffer[count] = byte;
+                    count += 1;
+                }
+                Err(nb::Error::WouldBlock) if count == 0 => continue,
+                Err(nb::Error::WouldBlock) => break,
+                Err(nb::Error::Other(error)) => {
+                    // Clear the error so the next byte can be received.
+                    console_uart().cr.write_with_zero(|w| w.rststa().set_bit());
+                    return Err(Error(error));
+                }
+            }
+        }
+
+        Ok(count)
+    }
+}
+
+impl embedded_io::ReadReady for Compat<Console> {
+    fn read_ready(&mut self) -> Result<bool, Self::Error> {
+        Ok(console_uart().sr.read().rxrdy().bit_is_set())
+    }
+}
+
+impl embedded_io::Write for Compat<Console> {
+    /// Blocks until at least one byte is queued, then queues as many as the
+    /// transmitter accepts without waiting.
+    fn write(&mut self, buffer: &[u8]) -> Result<usize, Self::Error> {
+        if buffer.is_empty() {
+            return Ok(0);
+        }
+
+        let mut count = 0;
+        while count < buffer.len() {
+            match self.0.write(buffer[count]) {
+                Ok(()) => count += 1,
+                Err(nb::Error::WouldBlock) if count == 0 => continue,
+                Err(nb::Error::WouldBlock) => break,
+                Err(nb::Error::Other(error)) => return Err(Error(error)),
+            }
+        }
+
+        Ok(count)
+    }
+
+    /// Waits until the last byte has left the shift register
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        // The HAL's `flush` never completes, so poll TXEMPTY directly.
+        while console_uart().sr.read().bits() & TXEMPTY == 0 {}
+        Ok(())
+    }
+}
+
+impl embedded_io::WriteReady for Compat<Console> {
+    fn write_ready(&mut self) -> Result<bool, Self::Error> {
+        Ok(console_uart().sr.read().txrdy().bit_is_set())
+    }
+}
+
+//
+// SPI (adapted from an embedded-hal 0.2 blocking implementation)
+//
+
+impl<T, E> spi::ErrorType for Compat<T>
+where
+    T: blocking::spi::Transfer<u8, Error = E> + blocking::spi::Write<u8, Error = E>,
+    E: Debug,
+{
+    type Error = Error<E>;
+}
+
+impl<T, E> spi::SpiBus<u8> for Compat<T>
+where
+    T: blocking::spi::Transfer<u8, Error = E> + blocking::spi::Write<u8, Error = E>,
+    E: Debug,
+{
+    fn read(&mut self, words: &mut [u8]) -> Result<(), Self::Error> {
+        words.iter_mut().for_each(|word| *word = 0x00);
+        self.0.transfer(words).map(|_| ()).map_err(Error)
+    }
+
+    fn write(&mut self, words: &[u8]) -> Result<(), Self::Error> {
+        self.0.write(words).map_err(Error)
+    }
+
+    fn transfer(&mut self, read: &mut [u8], write: &[u8]) -> Result<(), Self::Error> {
+        for index in 0..read.len().max(write.len()) {
+            let mut word = [write.get(index).copied().unwrap_or(0x00)];
+            self.0.transfer(&mut word).map_err(Error)?;
+            if let Some(slot) = read.get_mut(index) {
+                *slot = word[0];
+            }
+        }
+        Ok(())
+    }
+
+    fn transfer_in_place(&mut self, words: &mut [u8]) -> Result<(), Self::Error> {
+        self.0.transfer(words).map(|_| ()).map_err(Error)
+    }
+
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        // 0.2 blocking operations have completed when they return.
+        Ok(())
+    }
+}
+
+//
+// I2C (adapted from an embedded-hal 0.2 blocking implementation)
+//
+
+/// Error of the I2C adapter
+#[derive(Debug)]
+pub enum I2cError<E> {
+    /// Error of the 0.2 implementation
+    Bus(E),
+    /// Transaction the 0.2 traits cannot issue with repeated STARTs between
+    /// its operations
+    Unsupported,
+}
+
+impl<E: Debug> i2c::Error for I2cError<E> {
+    fn kind(&self) -> i2c::ErrorKind {
+        i2c::ErrorKind::Other
+    }
+}
+
+impl<T, E> i2c::ErrorType for Compat<T>
+where
+    T: blocking::i2c::Read<Error = E>
+        + blocking::i2c::Write<Error = E>
+        + blocking::i2c::WriteRead<Error = E>,
+    E: Debug,
+{
+    type Error = I2cError<E>;
+}
+
+impl<T, E> i2c::I2c for Compat<T>
+where
+    T: blocking::i2c::Read<Error = E>
+        + blocking::i2c::Write<Error = E>
+        + blocking::i2c::WriteRead<Error = E>,
+    E: Debug,
+{
+    /// Issues a single read, a single write, or a write followed by a read
+    /// (as `WriteRead`, with a repeated START).  Any other sequence fails
+    /// with [`I2cError::Unsupported`] before touching the bus: the 0.2 traits
+    /// can neither merge adjacent operations of the same kind nor chain more
+    /// than two.
+    fn transaction(
+        &mut self,
+        address: u8,
+        operations: &mut [i2c::Operation<'_>],
+    ) -> Result<(), Self::Error> {
+        use i2c::Operation::{Read, Write};
+        match operations {
+            [] => Ok(()),
+            [Read(read)] => self.0.read(address, read).map_err(I2cError::Bus),
+            [Write(write)] => self.0.write(address, write).map_err(I2cError::Bus),
+            [Write(write), Read(read)] => self
+                .0
+                .write_read(address, write, read)
+                .map_err(I2cError::Bus),
+            _ => Err(I2cError::Unsupported),
+        }
+    }
+}
@@ -0,0 +1,283 @@
+//! Console UART transfers through the Peripheral DMA Controller (PDC)
+//!
+//! [`DmaConsole`] transmits `&'static [u8]` buffers without copying them and
+//! receives into two buffers in turn, so the CPU is only involved once per
+//! buffer instead of once per byte.  Completed transfers are reported as
+//! [`Event`]s from [`DmaConsole::on_interrupt`], which the application calls
+//! from the board's console UART interrupt (UART0 on the SAM4E and SAM4N,
+//! UART1 on the SAM4S).  RTIC tasks need distinct names, even when only one
+//! of them is compiled in:
+//!
+//! ```rust
+//! #[cfg(not(feature = "sam4s"))]
+//! #[task(binds = UART0, shared = [console], priority = 3)]
+//! fn console_uart0(mut cx: console_uart0::Context) {
+//!     cx.shared.console.lock(|console| console.on_interrupt(handle));
+//! }
+//!
+//! #[cfg(feature = "sam4s")]
+//! #[task(binds = UART1, shared = [console], priority = 3)]
+//! fn console_uart1(mut cx: console_uart1::Context) {
+//!     cx.shared.console.lock(|console| console.on_interrupt(handle));
+//! }
+//!
+//! fn handle(event: Event<'_>) {
+//!     match event {
+//!         Event::Received { data, .. } => process(data),
+//!         Event::Transmitted(buffer) => release(buffer),
+//!     }
+//! }
+//! ```
+//!
+//! The UART has no receiver timeout, so bytes that do not fill a receive
+//! buffer are handed over by [`DmaConsole::poll_idle`], called periodically.
+//!
+//! The interrupt is owned by `AsyncConsole` while the `embassy` feature is
+//! enabled, and only one of `DmaConsole` and `BufferedConsole` can use it.
+
+use core::sync::atomic::{compiler_fence, Ordering};
+use cortex_m::peripheral::NVIC;
+
+use crate::board::{console_uart as uart, CONSOLE_INTERRUPT};
+use crate::Console;
+
+// UART status/interrupt register bits
+const ENDRX: u32 = 1 << 3;
+const ENDTX: u32 = 1 << 4;
+
+/// Largest transfer the 16-bit PDC counters can describe
+pub const MAX_TRANSFER: usize = 0xFFFF;
+
+/// Completed transfer, reported to the handler passed to
+/// [`DmaConsole::on_interrupt`] and [`DmaConsole::poll_idle`]
+#[derive(Debug, PartialEq, Eq)]
+pub enum Event<'a> {
+    /// Buffer queued with [`DmaConsole::write`] has been transmitted and is
+    /// released
+    Transmitted(&'static [u8]),
+    /// Received bytes: a full receive buffer, or with `idle` set the bytes
+    /// received before the line went quiet.  The buffer is reused once the
+    /// handler returns.
+    Received { data: &'a [u8], idle: bool },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// Buffers are limited to [`MAX_TRANSFER`] bytes
+    TooLong,
+}
+
+/// Console UART driven by the PDC
+pub struct DmaConsole {
+    serial: Console,
+    rx: [&'static mut [u8]; 2],
+    /// Receive buffer the PDC is filling; the other one is queued next.
+    current: usize,
+    /// Receive counter seen by the last `poll_idle`
+    last_count: u32,
+    /// Transmit buffers handed to the PDC, oldest first
+    tx: [Option<&'static [u8]>; 2],
+}
+
+impl DmaConsole {
+    /// Takes over the console UART, starts receiving into `rx[0]` then
+    /// `rx[1]` and unmasks the UART interrupt, which must call
+    /// [`DmaConsole::on_interrupt`].
+    ///
+    /// Panics if a receive buffer is empty or longer than [`MAX_TRANSFER`].
+    pub fn new(serial: Console, rx: [&'static mut [u8]; 2]) -> Self {
+        assert!(
+            rx.iter()
+                .all(|buffer| !buffer.is_empty() && buffer.len() <= MAX_TRANSFER),
+            "invalid receive buffer length"
+        );
+
+        let uart = uart();
+        uart.idr.write_with_zero(|w| unsafe { w.bits(0xFFFF_FFFF) });
+        uart.ptcr
+            .write_with_zero(|w| w.rxtdis().set_bit().txtdis().set_bit());
+        uart.tcr.write(|w| unsafe { w.bits(0) });
+        uart.tncr.write(|w| unsafe { w.bits(0) });
+
+        let mut console = DmaConsole {
+            serial,
+            rx,
+            current: 0,
+            last_count: 0,
+            tx: [None, None],
+        };
+        console.start_receive(0);
+        console.queue_receive(1);
+        uart.ptcr
+            .write_with_zero(|w| w.rxten().set_bit().txten().set_bit());
+        uart.ier.write_with_zero(|w| unsafe { w.bits(ENDRX) });
+        unsafe { NVIC::unmask(CONSOLE_INTERRUPT) };
+
+        console
+    }
+
+    /// Queues `data` for transmission.  Up to two buffers are queued at a
+    /// time; each is returned by an [`Event::Transmitted`] once sent.  Empty
+    /// buffers are ignored.
+    pub fn write(&mut self, data: &'static [u8]) -> nb::Result<(), Error> {
+        if data.len() > MAX_TRANSFER {
+            return Err(nb::Error::Other(Error::TooLong));
+        }
+        if data.is_empty() {
+            return Ok(());
+        }
+        let slot = match self.tx.iter().position(Option::is_none) {
+            Some(slot) => slot,
+            None => return Err(nb::Error::WouldBlock),
+        };
+
+        let uart = uart();
+        let address = data.as_ptr() as u32;
+        let count = data.len() as u32;
+        compiler_fence(Ordering::Release);
+        if slot == 0 {
+            uart.tpr.write(|w| unsafe { w.bits(address) });
+            uart.tcr.write(|w| unsafe { w.bits(count) });
+        } else {
+            uart.tnpr.write(|w| unsafe { w.bits(address) });
+            uart.tncr.write(|w| unsafe { w.bits(count) });
+            // The first buffer may have completed before the second was
+            // queued, leaving the PDC idle.
+            if uart.tcr.read().bits() == 0 && uart.tncr.read().bits() != 0 {
+                uart.tpr.write(|w| unsafe { w.bits(address) });
+                uart.tcr.write(|w| unsafe { w.bits(count) });
+                uart.tncr.write(|w| unsafe { w.bits(0) });
+            }
+        }
+        self.tx[slot] = Some(data);
+        uart.ier.write_with_zero(|w| unsafe { w.bits(ENDTX) });
+        Ok(())
+    }
+
+    /// Whether both transmit slots are in use
+    pub fn is_write_busy(&self) -> bool {
+        self.tx.iter().all(Option::is_some)
+    }
+
+    /// Whether every queued buffer has been handed to the UART
+    pub fn is_write_complete(&self) -> bool {
+        self.tx.iter().all(Option::is_none)
+    }
+
+    /// Reports completed transfers to `handler` and requeues full receive
+    /// buffers.  Call it from the console UART interrupt handler.
+    pub fn on_interrupt(&mut self, mut handler: impl FnMut(Event<'_>)) {
+        self.complete_transmit(&mut handler);
+
+        let uart = uart();
+        if uart.rncr.read().bits() != 0 {
+            return;
+        }
+        // The PDC moved on to the next buffer, and may have filled that as
+        // well if the handler fell behind.
+        let both = uart.rcr.read().bits() == 0;
+        let full = self.current;
+        self.complete_receive(full, self.rx[full].len(), false, &mut handler);
+        if both {
+            self.complete_receive(full ^ 1, self.rx[full ^ 1].len(), false, &mut handler);
+            self.start_receive(full);
+            self.queue_receive(full ^ 1);
+        } else {
+            self.current = full ^ 1;
+            self.queue_receive(full);
+        }
+        self.last_count = uart.rcr.read().bits();
+    }
+
+    /// Hands over the bytes received into the current buffer if none arrived
+    /// since the previous call.  Calling it every few character times (about
+    /// 87 µs per byte at 115200 baud) bounds the latency of short messages.
+    pub fn poll_idle(&mut self, mut handler: impl FnMut(Event<'_>)) {
+        let uart = uart();
+        let count = uart.rcr.read().bits();
+        let len = self.rx[self.current].len() as u32;
+        if count != self.last_count || count == len || count == 0 {
+            self.last_count = count;
+            return;
+        }
+
+        // Switch to the next buffer early, as if the current one were full.
+        uart.ptcr.write_with_zero(|w| w.rxtdis().set_bit());
+        let count = uart.rcr.read().bits();
+        if uart.rncr.read().bits() == 0 || count == 0 {
+            // Completed meanwhile; left to the interrupt handler.
+            uart.ptcr.write_with_zero(|w| w.rxten().set_bit());
+            return;
+        }
+        let idle = self.current;
+        uart.rncr.write(|w| unsafe { w.bits(0) });
+        self.start_receive(idle ^ 1);
+        uart.ptcr.write_with_zero(|w| w.rxten().set_bit());
+
+        self.complete_receive(idle, (len - count) as usize, true, &mut handler);
+        self.queue_receive(idle);
+        self.last_count = uart.rcr.read().bits();
+    }
+
+    /// Stops both directions and releases the serial port and the receive
+    /// buffers.  Transmissions in progress are abandoned.
+    pub fn free(self) -> (Console, [&'static mut [u8]; 2]) {
+        let uart = uart();
+        uart.idr.write_with_zero(|w| unsafe { w.bits(0xFFFF_FFFF) });
+        NVIC::mask(CONSOLE_INTERRUPT);
+        uart.ptcr
+            .write_with_zero(|w| w.rxtdis().set_bit().txtdis().set_bit());
+        compiler_fence(Ordering::Acquire);
+        (self.serial, self.rx)
+    }
+
+    /// Releases the transmit buffers the PDC is done with.
+    fn complete_transmit(&mut self, handler: &mut impl FnMut(Event<'_>)) {
+        let uart = uart();
+        let active =
+            (uart.tcr.read().bits() != 0) as usize + (uart.tncr.read().bits() != 0) as usize;
+        let queued = self.tx.iter().filter(|slot| slot.is_some()).count();
+        for _ in active..queued {
+            if let Some(buffer) = self.tx[0].take() {
+                handler(Event::Transmitted(buffer));
+            }
+            self.tx.swap(0, 1);
+        }
+        if self.tx[0].is_none() {
+            uart.idr.write_with_zero(|w| unsafe { w.bits(ENDTX) });
+        }
+    }
+
+    /// Hands the first `len` bytes of receive buffer `index` to `handler`.
+    fn complete_receive(
+        &self,
+        index: usize,
+        len: usize,
+        idle: bool,
+        handler: &mut impl FnMut(Event<'_>),
+    ) {
+        compiler_fence(Ordering::Acquire);
+        let data = &self.rx[index][..len];
+        handler(Event::Received { data, idle });
+    }
+
+    /// Points the PDC at receive buffer `index`.
+    fn start_receive(&mut self, index: usize) {
+        let uart = uart();
+        let buffer = &mut self.rx[index];
+        uart.rpr
+            .write(|w| unsafe { w.bits(buffer.as_mut_ptr() as u32) });
+        uart.rcr.write(|w| unsafe { w.bits(buffer.len() as u32) });
+        self.current = index;
+    }
+
+    /// Queues receive buffer `index` behind the current one.
+    fn queue_receive(&mut self, index: usize) {
+        let uart = uart();
+        let buffer = &mut self.rx[index];
+        compiler_fence(Ordering::Release);
+        uart.rnpr
+            .write(|w| unsafe { w.bits(buffer.as_mut_ptr() as u32) });
+        uart.rncr.write(|w| unsafe { w.bits(buffer.len() as u32) });
+    }
+}
@@ -0,0 +1,38 @@
+//! Embassy integration
+//!
+//! Enabling the `embassy` feature provides:
+//! * an `embassy-time` driver on the RTT (8192 Hz tick), started with [`init`]
+//! * an [`InterruptExecutor`] dispatched from the otherwise unused TC5 interrupt
+//! * [`AsyncButton`] and [`AsyncConsole`] for SW0 and the EDBG serial port
+//!
+//! The RTT and TC5 interrupt handlers, as well as those of the SW0 port and the
+//! console UART, are defined by this crate and cannot be used by the
+//! application while the feature is enabled.
+
+mod button;
+mod console;
+mod time_driver;
+
+pub use button::AsyncButton;
+pub use console::AsyncConsole;
+pub use embassy_executor::InterruptExecutor;
+
+pub(crate) use button::on_interrupt as on_sw0_interrupt;
+pub(crate) use console::on_interrupt as on_console_interrupt;
+
+use crate::hal::pac::{self, interrupt};
+
+/// Executor running on the TC5 interrupt.  Start it with
+/// `EXECUTOR_TC5.start(Interrupt::TC5)` after setting the TC5 priority; tasks
+/// spawned on it preempt those running on the thread-mode executor.
+pub static EXECUTOR_TC5: InterruptExecutor = InterruptExecutor::new();
+
+#[interrupt]
+unsafe fn TC5() {
+    EXECUTOR_TC5.on_interrupt();
+}
+
+/// Starts the `embassy-time` driver.  Must be called before any timer is used.
+pub fn init(rtt: pac::RTT) {
+    time_driver::init(rtt);
+}
@@ -0,0 +1,82 @@
+//! Async access to the SW0 push button
+
+use core::future::poll_fn;
+use core::task::Poll;
+use cortex_m::peripheral::NVIC;
+use embassy_sync::waitqueue::AtomicWaker;
+
+use crate::board::{sw0_pio as pio, SW0_INTERRUPT, SW0_MASK};
+use crate::Sw0;
+
+static WAKER: AtomicWaker = AtomicWaker::new();
+
+/// SW0 with futures that resolve on button edges.
+///
+/// The change interrupt of SW0's PIO port is owned by this type while
+/// the `embassy` feature is enabled.  SW0 is active low.
+pub struct AsyncButton {
+    pin: Sw0,
+}
+
+impl AsyncButton {
+    pub fn new(pin: Sw0) -> Self {
+        pio().idr.write_with_zero(|w| unsafe { w.bits(SW0_MASK) });
+        let _ = pio().isr.read();
+        unsafe { NVIC::unmask(SW0_INTERRUPT) };
+
+        AsyncButton { pin }
+    }
+
+    /// Returns true while the button is held down
+    pub fn is_pressed(&self) -> bool {
+        !Self::is_high()
+    }
+
+    // The HAL's `InputPin` implementation does not sample the line yet, so
+    // the level is read from the pin data status register directly.
+    fn is_high() -> bool {
+        pio().pdsr.read().bits() & SW0_MASK != 0
+    }
+
+    /// Waits until the button is pressed (returns immediately if it already is)
+    pub async fn wait_for_press(&mut self) {
+        self.wait_for_level(false).await
+    }
+
+    /// Waits until the button is released (returns immediately if it already is)
+    pub async fn wait_for_release(&mut self) {
+        self.wait_for_level(true).await
+    }
+
+    async fn wait_for_level(&mut self, high: bool) {
+        poll_fn(|cx| {
+            WAKER.register(cx.waker());
+
+            // Enable the change interrupt before sampling the line so an edge
+            // in between is not lost.
+            pio().ier.write_with_zero(|w| unsafe { w.bits(SW0_MASK) });
+            if Self::is_high() == high {
+                pio().idr.write_with_zero(|w| unsafe { w.bits(SW0_MASK) });
+                Poll::Ready(())
+            } else {
+                Poll::Pending
+            }
+        })
+        .await
+    }
+
+    /// Releases the underlying pin
+    pub fn free(self) -> Sw0 {
+        pio().idr.write_with_zero(|w| unsafe { w.bits(SW0_MASK) });
+        self.pin
+    }
+}
+
+/// Called from the board's PIO interrupt handler
+pub(crate) fn on_interrupt() {
+    // Reading the status register clears the change flags of the whole port.
+    if pio().isr.read().bits() & SW0_MASK != 0 {
+        pio().idr.write_with_zero(|w| unsafe { w.bits(SW0_MASK) });
+        WAKER.wake();
+    }
+}
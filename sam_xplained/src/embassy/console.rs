@@ -0,0 +1,126 @@
+//! Async serial console on the EDBG virtual COM port
+
+use core::future::poll_fn;
+use core::task::Poll;
+use cortex_m::peripheral::NVIC;
+use embassy_sync::waitqueue::AtomicWaker;
+
+use crate::board::{console_uart as uart, CONSOLE_INTERRUPT};
+use crate::hal::{
+    hal::serial::{Read, Write},
+    serial::Error,
+};
+use crate::Console;
+
+// UART status/interrupt register bits
+const RXRDY: u32 = 1 << 0;
+const TXRDY: u32 = 1 << 1;
+const TXEMPTY: u32 = 1 << 9;
+
+static RX_WAKER: AtomicWaker = AtomicWaker::new();
+static TX_WAKER: AtomicWaker = AtomicWaker::new();
+
+/// Serial console whose reads and writes wait on the UART interrupt instead of
+/// spinning.
+///
+/// The console UART interrupt is owned by this type while the `embassy`
+/// feature is enabled.
+pub struct AsyncConsole {
+    serial: Console,
+}
+
+impl AsyncConsole {
+    pub fn new(serial: Console) -> Self {
+        uart()
+            .idr
+            .write_with_zero(|w| unsafe { w.bits(0xFFFF_FFFF) });
+        unsafe { NVIC::unmask(CONSOLE_INTERRUPT) };
+
+        AsyncConsole { serial }
+    }
+
+    /// Waits for the next received byte
+    pub async fn read_byte(&mut self) -> Result<u8, Error> {
+        poll_fn(|cx| {
+            RX_WAKER.register(cx.waker());
+            match self.serial.read() {
+                Ok(byte) => Poll::Ready(Ok(byte)),
+                Err(nb::Error::Other(error)) => {
+                    // Clear the error so the next byte can be received.
+                    uart().cr.write_with_zero(|w| w.rststa().set_bit());
+                    Poll::Ready(Err(error))
+                }
+                Err(nb::Error::WouldBlock) => {
+                    uart().ier.write_with_zero(|w| w.rxrdy().set_bit());
+                    Poll::Pending
+                }
+            }
+        })
+        .await
+    }
+
+    /// Fills `buffer` with received bytes
+    pub async fn read(&mut self, buffer: &mut [u8]) -> Result<(), Error> {
+        for byte in buffer.iter_mut() {
+            *byte = self.read_byte().await?;
+        }
+        Ok(())
+    }
+
+    /// Queues every byte of `data` for transmission
+    pub async fn write(&mut self, data: &[u8]) {
+        for &byte in data {
+            poll_fn(|cx| {
+                TX_WAKER.register(cx.waker());
+                match self.serial.write(byte) {
+                    Ok(()) => Poll::Ready(()),
+                    Err(_) => {
+                        uart().ier.write_with_zero(|w| w.txrdy().set_bit());
+                        Poll::Pending
+                    }
+                }
+            })
+            .await
+        }
+    }
+
+    pub async fn write_str(&mut self, data: &str) {
+        self.write(data.as_bytes()).await
+    }
+
+    /// Waits until the last queued byte has left the shift register
+    pub async fn flush(&mut self) {
+        poll_fn(|cx| {
+            TX_WAKER.register(cx.waker());
+            if uart().sr.read().txempty().bit_is_set() {
+                Poll::Ready(())
+            } else {
+                uart().ier.write_with_zero(|w| w.txempty().set_bit());
+                Poll::Pending
+            }
+        })
+        .await
+    }
+
+    /// Releases the underlying serial port
+    pub fn free(self) -> Console {
+        uart()
+            .idr
+            .write_with_zero(|w| unsafe { w.bits(0xFFFF_FFFF) });
+        self.serial
+    }
+}
+
+/// Called from the board's console UART interrupt handler
+pub(crate) fn on_interrupt() {
+    let uart = uart();
+    let pending = uart.sr.read().bits() & uart.imr.read().bits();
+    uart.idr.write_with_zero(|w| unsafe { w.bits(pending) });
+
+    if pending & RXRDY != 0 {
+        RX_WAKER.wake();
+    }
+    if pending & (TXRDY | TXEMPTY) != 0 {
+        TX_WAKER.wake();
+    }
+}
@@ -0,0 +1,147 @@
+//! `embassy-time` driver running on the Real-time Timer (RTT)
+
+use core::cell::{Cell, RefCell};
+use core::task::Waker;
+use cortex_m::peripheral::NVIC;
+use critical_section::{CriticalSection, Mutex};
+use embassy_time_driver::Driver;
+use embassy_time_queue_utils::Queue;
+
+use crate::hal::pac::{self, interrupt, Interrupt};
+use crate::monotonic::read_rtt;
+
+/// RTT prescaler producing the tick rate selected through `tick-hz-8_192`.
+const PRESCALER: u16 = (32_768 / embassy_time_driver::TICK_HZ) as u16;
+
+/// Alarms are never armed further than half a counter wrap ahead so that
+/// `now` is guaranteed to observe every wrap of the 32-bit counter.
+const MAX_ALARM_DISTANCE: u64 = 1 << 31;
+
+struct RttDriver {
+    /// Upper 32 bits of the 64-bit timestamp.
+    high: Mutex<Cell<u32>>,
+    /// Counter value seen by the last call to `now`, used to detect a wrap.
+    last: Mutex<Cell<u32>>,
+    queue: Mutex<RefCell<Queue>>,
+}
+
+embassy_time_driver::time_driver_impl!(static DRIVER: RttDriver = RttDriver {
+    high: Mutex::new(Cell::new(0)),
+    last: Mutex::new(Cell::new(0)),
+    queue: Mutex::new(RefCell::new(Queue::new())),
+});
+
+fn rtt() -> &'static pac::rtt::RegisterBlock {
+    unsafe { &*pac::RTT::ptr() }
+}
+
+impl RttDriver {
+    fn init(&'static self, _rtt: pac::RTT) {
+        let rtt = rtt();
+        rtt.mr.modify(|_, w| {
+            w.rttdis()
+                .set_bit()
+                .almien()
+                .clear_bit()
+                .rttincien()
+                .clear_bit()
+        });
+        rtt.mr.modify(|_, w| unsafe {
+            w.rtpres()
+                .bits(PRESCALER)
+                .rtc1hz()
+                .clear_bit()
+                .rttdis()
+                .clear_bit()
+                .rttrst()
+                .set_bit()
+        });
+        let _ = rtt.sr.read();
+
+        critical_section::with(|cs| {
+            self.set_alarm(cs, u64::MAX);
+        });
+
+        unsafe { NVIC::unmask(Interrupt::RTT) };
+    }
+
+    fn now_cs(&self, cs: CriticalSection) -> u64 {
+        let counter = read_rtt(rtt());
+
+        let high = self.high.borrow(cs);
+        let last = self.last.borrow(cs);
+        if counter < last.get() {
+            high.set(high.get() + 1);
+        }
+        last.set(counter);
+
+        ((high.get() as u64) << 32) | counter as u64
+    }
+
+    /// Arms the RTT alarm for `timestamp`.  Returns `false` if the timestamp
+    /// has already passed, in which case the alarm is left unarmed.
+    fn set_alarm(&self, cs: CriticalSection, timestamp: u64) -> bool {
+        let rtt = rtt();
+        let now = self.now_cs(cs);
+        if timestamp <= now {
+            rtt.mr.modify(|_, w| w.almien().clear_bit());
+            return false;
+        }
+
+        let target = timestamp.min(now + MAX_ALARM_DISTANCE);
+
+        // ALMIEN must be disabled while a new alarm value is written.  The
+        // alarm fires when the counter reaches ALMV + 1.
+        rtt.mr.modify(|_, w| w.almien().clear_bit());
+        rtt.ar
+            .write(|w| unsafe { w.almv().bits((target as u32).wrapping_sub(1)) });
+        rtt.mr.modify(|_, w| w.almien().set_bit());
+
+        // The counter may have reached the target while it was being written.
+        if self.now_cs(cs) >= target {
+            NVIC::pend(Interrupt::RTT);
+        }
+
+        true
+    }
+
+    fn on_interrupt(&self) {
+        critical_section::with(|cs| {
+            // Reading the status register clears ALMS.
+            let _ = rtt().sr.read();
+
+            let mut queue = self.queue.borrow(cs).borrow_mut();
+            let mut next = queue.next_expiration(self.now_cs(cs));
+            while !self.set_alarm(cs, next) {
+                next = queue.next_expiration(self.now_cs(cs));
+            }
+        });
+    }
+}
+
+impl Driver for RttDriver {
+    fn now(&self) -> u64 {
+        critical_section::with(|cs| self.now_cs(cs))
+    }
+
+    fn schedule_wake(&self, at: u64, waker: &Waker) {
+        critical_section::with(|cs| {
+            let mut queue = self.queue.borrow(cs).borrow_mut();
+            if queue.schedule_wake(at, waker) {
+                let mut next = queue.next_expiration(self.now_cs(cs));
+                while !self.set_alarm(cs, next) {
+                    next = queue.next_expiration(self.now_cs(cs));
+                }
+            }
+        });
+    }
+}
+
+#[interrupt]
+fn RTT() {
+    DRIVER.on_interrupt();
+}
+
+pub(super) fn init(rtt: pac::RTT) {
+    DRIVER.init(rtt);
+}
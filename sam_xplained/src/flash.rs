@@ -0,0 +1,332 @@
+//! Internal flash
+//!
+//! [`InternalFlash`] implements the embedded-storage [`NorFlash`] traits on
+//! top of the enhanced embedded flash controller (EEFC), addressed by offset
+//! from the start of the flash array.  It backs the `kv` store in the
+//! board's [`STORAGE`](crate::STORAGE) region:
+//!
+//! ```rust
+//! let mut store = KvStore::new(&mut board.flash, STORAGE)?;
+//! ```
+//!
+//! Writes use partial page programming, so any aligned 16 bytes can be
+//! programmed once between erases.  Erases clear blocks of eight pages.  The
+//! flash cannot be fetched from while a command runs, so commands are issued
+//! from RAM (see [`crate::ramfunc`]) with interrupts disabled.
+//!
+//! It also manages the general-purpose NVM bits ([`Gpnvm`]) and the lock bits
+//! that protect regions of the flash against writes and erases, e.g. to
+//! return a board to the SAM-BA boot ROM without a debugger:
+//!
+//! ```rust
+//! board.flash.clear_gpnvm(Gpnvm::BootMode)?;
+//! SCB::sys_reset();
+//! ```
+
+use core::ops::Range;
+use embedded_storage::nor_flash::{
+    check_erase, check_read, check_write, ErrorType, NorFlash, NorFlashError, NorFlashErrorKind,
+    ReadNorFlash,
+};
+
+use crate::board;
+
+/// Start of the flash array (of bank 0 on dual bank parts)
+pub(crate) const FLASH_BASE: usize = 0x0040_0000;
+
+const PAGE_SIZE: usize = 512;
+/// Size of the regions the lock bits protect
+pub const LOCK_REGION_SIZE: usize = 16 * PAGE_SIZE;
+
+// EEFC_FCR write key and commands
+pub(crate) const FKEY: u32 = 0x5A << 24;
+const WP: u32 = 0x01;
+const EPA: u32 = 0x07;
+const SLB: u32 = 0x08;
+const CLB: u32 = 0x09;
+const GLB: u32 = 0x0A;
+const SGPB: u32 = 0x0B;
+const CGPB: u32 = 0x0C;
+const GGPB: u32 = 0x0D;
+/// EPA argument selecting an erase of eight pages
+const EPA_8_PAGES: u32 = 1;
+/// Offset of the result register (EEFC_FRR)
+const EEFC_FRR: usize = 0x0C;
+
+// EEFC_FSR flags
+const FCMDE: u32 = 1 << 1;
+const FLOCKE: u32 = 1 << 2;
+const FLERR: u32 = 1 << 3;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// The offset or length is not a multiple of the write or erase size
+    NotAligned,
+    /// The range extends past the end of the flash
+    OutOfBounds,
+    /// The controller rejected the command
+    Command,
+    /// The range lies in a locked region
+    Locked,
+    /// The controller failed to program or erase the range
+    Programming,
+}
+
+impl NorFlashError for Error {
+    fn kind(&self) -> NorFlashErrorKind {
+        match self {
+            Error::NotAligned => NorFlashErrorKind::NotAligned,
+            Error::OutOfBounds => NorFlashErrorKind::OutOfBounds,
+            _ => NorFlashErrorKind::Other,
+        }
+    }
+}
+
+impl From<NorFlashErrorKind> for Error {
+    fn from(kind: NorFlashErrorKind) -> Self {
+        match kind {
+            NorFlashErrorKind::NotAligned => Error::NotAligned,
+            NorFlashErrorKind::OutOfBounds => Error::OutOfBounds,
+            _ => Error::Command,
+        }
+    }
+}
+
+/// General-purpose NVM bits
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Gpnvm {
+    /// Disables the debug port and SAM-BA access to the flash.  Once set it
+    /// is only cleared by a full erase through the ERASE pin.
+    Security,
+    /// Boots from the flash when set, from the SAM-BA boot ROM when clear
+    BootMode,
+    /// Boots from bank 1 when set, from bank 0 when clear
+    #[cfg(feature = "sam4s")]
+    BankSelect,
+}
+
+impl Gpnvm {
+    fn bit(self) -> u32 {
+        match self {
+            Gpnvm::Security => 0,
+            Gpnvm::BootMode => 1,
+            #[cfg(feature = "sam4s")]
+            Gpnvm::BankSelect => 2,
+        }
+    }
+}
+
+/// The internal flash of the board's microcontroller
+pub struct InternalFlash {
+    _private: (),
+}
+
+impl InternalFlash {
+    /// Only the board creates the flash, so it has a single owner.
+    pub(crate) fn new() -> Self {
+        InternalFlash { _private: () }
+    }
+
+    /// The flash for boot code that runs without creating the board, so the
+    /// clocks stay in their reset state.  Copies the RAM functions into
+    /// place.
+    ///
+    /// # Safety
+    ///
+    /// There must be no other `InternalFlash`, e.g. of a board.
+    #[cfg(feature = "bootloader")]
+    pub unsafe fn steal() -> Self {
+        crate::ramfunc::init();
+        Self::new()
+    }
+
+    /// Programs `data` at byte `at` of the page starting at `page_start`.
+    fn write_page(&mut self, page_start: u32, at: usize, data: &[u8]) -> Result<(), Error> {
+        // Fill the whole page buffer: the words outside `data` are written as
+        // all ones, which leaves them unprogrammed.
+        let buffer = (FLASH_BASE + page_start as usize) as *mut u32;
+        for word in 0..PAGE_SIZE / 4 {
+            let index = word * 4;
+            let value = if index >= at && index < at + data.len() {
+                let bytes = &data[index - at..index - at + 4];
+                u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+            } else {
+                0xFFFF_FFFF
+            };
+            unsafe { buffer.add(word).write_volatile(value) };
+        }
+        cortex_m::asm::dsb();
+
+        page_command(page_start, WP, 0)
+    }
+
+    /// Reads a general-purpose NVM bit
+    pub fn gpnvm(&mut self, bit: Gpnvm) -> Result<bool, Error> {
+        let (efc, _) = board::flash_controller(0);
+        command(efc, GGPB, 0)?;
+        Ok(result(efc) & 1 << bit.bit() != 0)
+    }
+
+    /// Sets a general-purpose NVM bit.  The bits keep their state through
+    /// resets and power cycles.
+    pub fn set_gpnvm(&mut self, bit: Gpnvm) -> Result<(), Error> {
+        let (efc, _) = board::flash_controller(0);
+        command(efc, SGPB, bit.bit())
+    }
+
+    /// Clears a general-purpose NVM bit.  The security bit can't be cleared
+    /// from firmware and fails with [`Error::Command`].
+    pub fn clear_gpnvm(&mut self, bit: Gpnvm) -> Result<(), Error> {
+        if bit == Gpnvm::Security {
+            return Err(Error::Command);
+        }
+        let (efc, _) = board::flash_controller(0);
+        command(efc, CGPB, bit.bit())
+    }
+
+    /// Locks the lock regions overlapping `range` (flash offsets) against
+    /// writes and erases.
+    pub fn lock(&mut self, range: Range<u32>) -> Result<(), Error> {
+        for region in lock_regions(range)? {
+            page_command(region, SLB, 0)?;
+        }
+        Ok(())
+    }
+
+    /// Unlocks the lock regions overlapping `range` (flash offsets).
+    pub fn unlock(&mut self, range: Range<u32>) -> Result<(), Error> {
+        for region in lock_regions(range)? {
+            page_command(region, CLB, 0)?;
+        }
+        Ok(())
+    }
+
+    /// Whether the lock region containing flash offset `offset` is locked
+    pub fn is_locked(&mut self, offset: u32) -> Result<bool, Error> {
+        if offset as usize >= board::FLASH_SIZE {
+            return Err(Error::OutOfBounds);
+        }
+
+        let (efc, bank_start) = board::flash_controller(offset);
+        let region = (offset - bank_start) as usize / LOCK_REGION_SIZE;
+        command(efc, GLB, 0)?;
+
+        // The lock bits read back 32 regions per EEFC_FRR read.
+        let mut bits = 0;
+        for _ in 0..=region / 32 {
+            bits = result(efc);
+        }
+        Ok(bits & 1 << (region % 32) != 0)
+    }
+}
+
+/// Start offsets of the lock regions overlapping `range`
+fn lock_regions(range: Range<u32>) -> Result<impl Iterator<Item = u32>, Error> {
+    if range.start > range.end || range.end as usize > board::FLASH_SIZE {
+        return Err(Error::OutOfBounds);
+    }
+    let first = range.start - range.start % LOCK_REGION_SIZE as u32;
+    Ok((first..range.end).step_by(LOCK_REGION_SIZE))
+}
+
+impl ErrorType for InternalFlash {
+    type Error = Error;
+}
+
+impl ReadNorFlash for InternalFlash {
+    const READ_SIZE: usize = 1;
+
+    fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
+        check_read(self, offset, bytes.len())?;
+
+        let flash = (FLASH_BASE + offset as usize) as *const u8;
+        for (index, byte) in bytes.iter_mut().enumerate() {
+            *byte = unsafe { flash.add(index).read_volatile() };
+        }
+        Ok(())
+    }
+
+    fn capacity(&self) -> usize {
+        board::FLASH_SIZE
+    }
+}
+
+impl NorFlash for InternalFlash {
+    const WRITE_SIZE: usize = 16;
+    const ERASE_SIZE: usize = 8 * PAGE_SIZE;
+
+    fn erase(&mut self, from: u32, to: u32) -> Result<(), Self::Error> {
+        check_erase(self, from, to)?;
+
+        for block in (from..to).step_by(Self::ERASE_SIZE) {
+            page_command(block, EPA, EPA_8_PAGES)?;
+        }
+        Ok(())
+    }
+
+    fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error> {
+        check_write(self, offset, bytes.len())?;
+
+        let mut offset = offset as usize;
+        let mut bytes = bytes;
+        while !bytes.is_empty() {
+            let at = offset % PAGE_SIZE;
+            let count = bytes.len().min(PAGE_SIZE - at);
+            self.write_page((offset - at) as u32, at, &bytes[..count])?;
+            offset += count;
+            bytes = &bytes[count..];
+        }
+        Ok(())
+    }
+}
+
+/// Runs `command` on the page at flash offset `offset`, with `argument` in
+/// the low bits of the page number.
+fn page_command(offset: u32, command: u32, argument: u32) -> Result<(), Error> {
+    let (efc, bank_start) = board::flash_controller(offset);
+    let page = (offset - bank_start) / PAGE_SIZE as u32;
+    self::command(efc, command, page | argument)
+}
+
+/// Runs `command` with `argument` on the flash controller at `efc`.
+fn command(efc: usize, command: u32, argument: u32) -> Result<(), Error> {
+    let fcr = FKEY | argument << 8 | command;
+    let status = cortex_m::interrupt::free(|_| unsafe { command_from_ram(efc, fcr) });
+    if status & FCMDE != 0 {
+        Err(Error::Command)
+    } else if status & FLOCKE != 0 {
+        Err(Error::Locked)
+    } else if status & FLERR != 0 {
+        Err(Error::Programming)
+    } else {
+        Ok(())
+    }
+}
+
+/// Reads the next word of the result of the last command.
+fn result(efc: usize) -> u32 {
+    unsafe { ((efc + EEFC_FRR) as *const u32).read_volatile() }
+}
+
+crate::ramfunc! {
+    // Writes `fcr` to EEFC_FCR and waits for EEFC_FSR.FRDY, returning every
+    // flag seen on the way (reading EEFC_FSR clears the error flags).
+    unsafe fn command_from_ram(efc: usize, fcr: u32) -> u32 {
+        let status: u32;
+        core::arch::asm!(
+            "movs {status}, #0",
+            "str {fcr}, [{efc}, #4]",
+            "2:",
+            "ldr {tmp}, [{efc}, #8]",
+            "orrs {status}, {tmp}",
+            "tst {tmp}, #1",
+            "beq 2b",
+            efc = in(reg) efc,
+            fcr = in(reg) fcr,
+            status = out(reg) status,
+            tmp = out(reg) _,
+            options(nostack),
+        );
+        status
+    }
+}
@@ -0,0 +1,112 @@
+//! Firmware images
+//!
+//! Images installed by `sam_xplained_bootloader` (and by the SAM4S dual bank
+//! update manager) are described by an [`ImageHeader`]: version, length and
+//! CRC-32 of the image.  Behind the bootloader, the flash holds
+//!
+//! * the bootloader, from the start of the flash to [`APP_OFFSET`]
+//! * the header of the application at [`APP_OFFSET`]
+//! * the application itself from [`APP_START`], linked there with the
+//!   `application` feature
+//! * for signed images, the [`SignatureTrailer`] right behind the application
+//!
+//! The image format itself comes from the `sam_xplained_image` crate,
+//! re-exported here.
+//!
+//! `APP_OFFSET` defaults to 64K and is set with the `SAM_XPLAINED_APP_OFFSET`
+//! environment variable when building both the bootloader and the
+//! application.
+
+use crate::flash::FLASH_BASE;
+use crate::hal::pac;
+
+pub use sam_xplained_image::*;
+
+include!(concat!(env!("OUT_DIR"), "/layout.rs"));
+
+/// Space reserved for the header ahead of the application's vector table,
+/// which needs this alignment
+pub const APP_HEADER_SIZE: u32 = 0x200;
+/// Flash offset of the application (its vector table)
+pub const APP_START: u32 = APP_OFFSET + APP_HEADER_SIZE;
+/// Address of the application's vector table
+pub const APP_ADDRESS: u32 = FLASH_BASE as u32 + APP_START;
+/// Largest application the flash has room for, up to the key-value store
+pub const APP_CAPACITY: u32 = crate::STORAGE.start - APP_START;
+
+/// Backup register (GPBR7) holding an update request across the reset
+const UPDATE_REQUEST_REGISTER: usize = 7;
+const UPDATE_REQUEST: u32 = 0x5550_4454; // "UPDT"
+
+/// Header of the application installed behind the bootloader, if the image
+/// matches it and starts with a plausible vector table
+pub fn installed_application() -> Option<ImageHeader> {
+    let mut bytes = [0u8; ImageHeader::LEN];
+    for (index, byte) in bytes.iter_mut().enumerate() {
+        *byte =
+            unsafe { ((FLASH_BASE + APP_OFFSET as usize + index) as *const u8).read_volatile() };
+    }
+    let header = ImageHeader::from_bytes(&bytes)?;
+    if header.length < 8 || header.length > APP_CAPACITY {
+        return None;
+    }
+
+    let image =
+        unsafe { core::slice::from_raw_parts(APP_ADDRESS as *const u8, header.length as usize) };
+    if crc32(image) != header.crc {
+        return None;
+    }
+
+    // The initial stack pointer points into SRAM, the reset vector into the
+    // image.
+    let word = |index: usize| {
+        u32::from_le_bytes([
+            image[index],
+            image[index + 1],
+            image[index + 2],
+            image[index + 3],
+        ])
+    };
+    let (stack_pointer, reset) = (word(0), word(4));
+    if stack_pointer & 0xFFF0_0000 != 0x2000_0000
+        || !(APP_ADDRESS..APP_ADDRESS + header.length).contains(&(reset & !1))
+    {
+        return None;
+    }
+    Some(header)
+}
+
+/// Signature trailer stored behind the application described by `header`,
+/// for signed images
+pub fn installed_signature(header: &ImageHeader) -> Option<SignatureTrailer> {
+    if header.length > APP_CAPACITY - SignatureTrailer::LEN as u32 {
+        return None;
+    }
+    let mut bytes = [0u8; SignatureTrailer::LEN];
+    for (index, byte) in bytes.iter_mut().enumerate() {
+        *byte = unsafe {
+            ((APP_ADDRESS + header.length) as *const u8)
+                .add(index)
+                .read_volatile()
+        };
+    }
+    SignatureTrailer::from_bytes(&bytes)
+}
+
+/// Makes the bootloader enter update mode after the next reset, even though
+/// the application is valid.
+pub fn request_update() {
+    unsafe { update_request_register().write_volatile(UPDATE_REQUEST) };
+}
+
+/// Whether the application requested update mode, clearing the request
+pub fn take_update_request() -> bool {
+    let register = update_request_register();
+    let requested = unsafe { register.read_volatile() } == UPDATE_REQUEST;
+    unsafe { register.write_volatile(0) };
+    requested
+}
+
+fn update_request_register() -> *mut u32 {
+    unsafe { (pac::GPBR::ptr() as *mut u32).add(UPDATE_REQUEST_REGISTER) }
+}
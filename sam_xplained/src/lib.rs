@@ -0,0 +1,234 @@
+//! Board support for the Microchip/Atmel SAM4 XPlained Pro development boards
+//!
+//! The board is selected with exactly one of the `sam4e`, `sam4s` or `sam4n`
+//! features.  Everything that differs between the boards lives in that
+//! board's module under `board/` and is re-exported from the crate root, so
+//! application code written against [`Board`] and [`XplainedBoard`] compiles
+//! unchanged for every board.
+//!
+//! The subsystems are optional, each behind the feature of its module:
+//! `kv`, `image`, `rpc`, `shell` and `ymodem`.
+//!
+//! The `mock` feature selects a mock board instead, which builds for the host
+//! with `std`, so that application code can be unit tested there (see
+//! [`mock`]).
+#![cfg_attr(not(feature = "mock"), no_std)]
+
+#[cfg(not(any(
+    feature = "sam4e",
+    feature = "sam4s",
+    feature = "sam4n",
+    feature = "mock"
+)))]
+compile_error!(
+    "Select a board by enabling one of the `sam4e`, `sam4s`, `sam4n` or `mock` features"
+);
+
+#[cfg(any(
+    all(feature = "sam4e", feature = "sam4s"),
+    all(feature = "sam4e", feature = "sam4n"),
+    all(feature = "sam4s", feature = "sam4n"),
+    all(
+        feature = "mock",
+        any(feature = "sam4e", feature = "sam4s", feature = "sam4n")
+    ),
+))]
+compile_error!("The `sam4e`, `sam4s`, `sam4n` and `mock` features are mutually exclusive");
+
+#[cfg(all(feature = "dual_bank", not(feature = "sam4s")))]
+compile_error!("The `dual_bank` feature requires the dual bank SAM4S (`sam4s` feature)");
+
+#[cfg(all(feature = "bootloader", feature = "application"))]
+compile_error!("The `bootloader` and `application` features are mutually exclusive");
+
+#[cfg(all(
+    feature = "dual_bank",
+    not(any(feature = "bootloader", feature = "application"))
+))]
+compile_error!(
+    "The `dual_bank` feature needs `bootloader` (the boot stage) or `application` (the images)"
+);
+
+#[cfg(not(feature = "mock"))]
+pub use atsam4_hal as hal;
+#[cfg(feature = "eh02")]
+pub use embedded_hal_02 as eh02;
+#[cfg(feature = "kv")]
+pub use sam_xplained_kv as kv;
+#[cfg(feature = "ymodem")]
+pub use sam_xplained_ymodem as ymodem;
+
+use build_info::FirmwareInfo;
+use chip::ChipInfo;
+#[cfg(not(feature = "mock"))]
+use chip::ChipMismatch;
+use core::fmt;
+use embedded_hal::{delay::DelayNs, digital};
+use embedded_storage::nor_flash::NorFlash;
+#[cfg(not(feature = "mock"))]
+use hal::{pac, time::rate::BitsPerSecond};
+use unique_id::SerialNumber;
+
+#[cfg(feature = "sam4e")]
+#[path = "board/sam4e.rs"]
+mod board;
+#[cfg(feature = "sam4s")]
+#[path = "board/sam4s.rs"]
+mod board;
+#[cfg(feature = "sam4n")]
+#[path = "board/sam4n.rs"]
+mod board;
+#[cfg(feature = "mock")]
+#[path = "board/mock.rs"]
+mod board;
+
+pub use board::*;
+
+#[cfg(not(feature = "mock"))]
+pub mod buffered;
+pub mod build_info;
+pub mod chip;
+#[cfg(not(feature = "mock"))]
+pub mod compat;
+#[cfg(not(feature = "mock"))]
+pub mod dma;
+#[cfg(feature = "embassy")]
+pub mod embassy;
+#[cfg(not(feature = "mock"))]
+pub mod flash;
+#[cfg(all(feature = "image", not(feature = "mock")))]
+pub mod image;
+#[cfg(feature = "mock")]
+pub mod mock;
+#[cfg(not(feature = "mock"))]
+pub mod monotonic;
+#[cfg(not(feature = "mock"))]
+pub mod ramfunc;
+#[cfg(all(feature = "rpc", not(feature = "mock")))]
+pub mod rpc;
+#[cfg(feature = "rtt")]
+pub mod rtt;
+#[cfg(all(feature = "shell", not(feature = "mock")))]
+pub mod shell;
+pub mod unique_id;
+
+/// Baud rate the console UART is configured for by [`XplainedBoard::new`]
+#[cfg(not(feature = "mock"))]
+pub const CONSOLE_BAUD_RATE: BitsPerSecond = BitsPerSecond(115_200);
+
+/// Resets the processor once the console UART has had time to shift out what
+/// was last written to it (under 1 ms at [`CONSOLE_BAUD_RATE`]).
+#[cfg(not(feature = "mock"))]
+pub fn reset_after_console_drain() -> ! {
+    cortex_m::asm::delay(hal::clock::get_master_clock_frequency().0 / 500);
+    cortex_m::peripheral::SCB::sys_reset()
+}
+
+/// Common interface of the board modules
+///
+/// Application code generic over this trait runs unchanged on every board.
+/// The accessors hand out the board resources through the embedded-hal 1.0
+/// and embedded-io traits; the `Board` fields give direct access to the HAL
+/// types.
+///
+/// ```ignore
+/// fn blink<B: XplainedBoard>(board: &mut B) {
+///     board.led().toggle().ok();
+///     board.delay().delay_ms(500);
+/// }
+/// ```
+pub trait XplainedBoard: Sized {
+    /// Onboard LED labeled LED0
+    type Led: digital::StatefulOutputPin;
+    /// Onboard button labeled SW0
+    type Button: digital::InputPin;
+    /// Serial console on the EDBG virtual COM port
+    type Console: embedded_io::Read + embedded_io::Write;
+    type Delay: DelayNs;
+    /// Internal flash, for the key-value store in the board's `STORAGE`
+    /// region
+    type Flash: NorFlash;
+
+    /// Name of the board, e.g. "SAM4E Xplained Pro"
+    const NAME: &'static str;
+
+    /// Copies the RAM functions into place and brings up the clocks, GPIO
+    /// ports and console UART of the board.
+    ///
+    /// The watchdog is left running; disable or feed it through the board's
+    /// `watchdog` field.
+    #[cfg(not(feature = "mock"))]
+    fn new(syst: pac::SYST, peripherals: pac::Peripherals) -> Self;
+
+    /// Like [`XplainedBoard::new`], but first checks that the microcontroller
+    /// is the one fitted to this board.  Nothing is configured when it isn't.
+    #[cfg(not(feature = "mock"))]
+    fn try_new(syst: pac::SYST, peripherals: pac::Peripherals) -> Result<Self, ChipMismatch> {
+        ChipInfo::new(&peripherals.CHIPID).verify()?;
+        Ok(Self::new(syst, peripherals))
+    }
+
+    fn led(&mut self) -> &mut Self::Led;
+    fn button(&mut self) -> &mut Self::Button;
+    fn console(&mut self) -> &mut Self::Console;
+    fn delay(&mut self) -> &mut Self::Delay;
+
+    /// Identification of the microcontroller on the board
+    fn chip_id(&self) -> &ChipInfo;
+    /// Cause of the reset preceding [`XplainedBoard::new`]
+    fn reset_cause(&self) -> ResetCause;
+
+    /// 128-bit unique identifier of the microcontroller
+    fn unique_id(&self) -> [u32; 4];
+
+    fn flash(&mut self) -> &mut Self::Flash;
+
+    /// Unique identifier formatted as a serial number string
+    fn serial_number(&self) -> SerialNumber {
+        SerialNumber::new(self.unique_id())
+    }
+
+    /// Version, git commit, build time, board and features of the running
+    /// firmware
+    fn firmware_info() -> FirmwareInfo {
+        build_info::firmware_info()
+    }
+}
+
+/// Cause of the last processor reset, as reported by the reset controller
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ResetCause {
+    FirstPowerUp,
+    ReturnFromBackup,
+    Watchdog,
+    Software,
+    NrstPin,
+    Reserved(u8),
+}
+
+impl ResetCause {
+    #[cfg(not(feature = "mock"))]
+    pub fn new(rstc: &pac::RSTC) -> Self {
+        match rstc.sr.read().rsttyp().bits() {
+            0 => ResetCause::FirstPowerUp,
+            1 => ResetCause::ReturnFromBackup,
+            2 => ResetCause::Watchdog,
+            3 => ResetCause::Software,
+            4 => ResetCause::NrstPin,
+            value => ResetCause::Reserved(value),
+        }
+    }
+}
+
+impl fmt::Display for ResetCause {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ResetCause::FirstPowerUp => f.write_str("First power up reset"),
+            ResetCause::ReturnFromBackup => f.write_str("Return from backup mode"),
+            ResetCause::Watchdog => f.write_str("Watchdog timer"),
+            ResetCause::Software => f.write_str("Software"),
+            ResetCause::NrstPin => f.write_str("NRST pin detected low"),
+            ResetCause::Reserved(_) => f.write_str("RESERVED RESET VALUE!!"),
+        }
+    }
+}
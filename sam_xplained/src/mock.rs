@@ -0,0 +1,515 @@
+//! Mock board resources for host tests
+//!
+//! With the `mock` feature, the crate builds for the host with `std` and
+//! [`Board`](crate::Board) is made of these mocks instead of the HAL's pins,
+//! UART, SysTick delay and flash.  Inputs are scripted and outputs recorded,
+//! so code written against [`XplainedBoard`](crate::XplainedBoard) or the
+//! embedded-hal and embedded-io traits can be unit tested with `cargo test`:
+//!
+//! ```ignore
+//! let mut board = Board::new();
+//! board.console.push_input(b"led on\r");
+//! board.sw0.script([false, true, true, false]);
+//! run(&mut board);
+//! assert_eq!(board.console.take_output(), b"SW0 pressed\r\n");
+//! assert!(board.led0.is_on());
+//! ```
+
+use std::cell::Cell;
+use std::collections::VecDeque;
+use std::convert::Infallible;
+use std::fmt;
+use std::rc::Rc;
+use std::time::Duration;
+
+use embedded_hal::delay::DelayNs;
+use embedded_hal::digital::{self, InputPin, OutputPin, StatefulOutputPin};
+use embedded_storage::nor_flash::{
+    check_erase, check_read, check_write, ErrorType, NorFlash, NorFlashErrorKind, ReadNorFlash,
+};
+
+/// Active low LED, like LED0, that records the levels it is driven to
+#[derive(Debug)]
+pub struct MockLed {
+    high: bool,
+    levels: Vec<bool>,
+}
+
+impl MockLed {
+    /// An LED that is off
+    pub fn new() -> Self {
+        MockLed {
+            high: true,
+            levels: Vec::new(),
+        }
+    }
+
+    pub fn is_on(&self) -> bool {
+        !self.high
+    }
+
+    /// Levels driven so far, `true` for high (off)
+    pub fn levels(&self) -> &[bool] {
+        &self.levels
+    }
+
+    /// Number of times the LED went from off to on
+    pub fn flashes(&self) -> usize {
+        let mut high = true;
+        let mut flashes = 0;
+        for &level in &self.levels {
+            if high && !level {
+                flashes += 1;
+            }
+            high = level;
+        }
+        flashes
+    }
+
+    fn drive(&mut self, high: bool) {
+        self.high = high;
+        self.levels.push(high);
+    }
+}
+
+impl Default for MockLed {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl digital::ErrorType for MockLed {
+    type Error = Infallible;
+}
+
+impl OutputPin for MockLed {
+    fn set_low(&mut self) -> Result<(), Infallible> {
+        self.drive(false);
+        Ok(())
+    }
+
+    fn set_high(&mut self) -> Result<(), Infallible> {
+        self.drive(true);
+        Ok(())
+    }
+}
+
+impl StatefulOutputPin for MockLed {
+    fn is_set_high(&mut self) -> Result<bool, Infallible> {
+        Ok(self.high)
+    }
+
+    fn is_set_low(&mut self) -> Result<bool, Infallible> {
+        Ok(!self.high)
+    }
+}
+
+#[cfg(feature = "eh02")]
+impl embedded_hal_02::digital::v2::OutputPin for MockLed {
+    type Error = Infallible;
+
+    fn set_low(&mut self) -> Result<(), Infallible> {
+        OutputPin::set_low(self)
+    }
+
+    fn set_high(&mut self) -> Result<(), Infallible> {
+        OutputPin::set_high(self)
+    }
+}
+
+#[cfg(feature = "eh02")]
+impl embedded_hal_02::digital::v2::StatefulOutputPin for MockLed {
+    fn is_set_high(&self) -> Result<bool, Infallible> {
+        Ok(self.high)
+    }
+
+    fn is_set_low(&self) -> Result<bool, Infallible> {
+        Ok(!self.high)
+    }
+}
+
+#[cfg(feature = "eh02")]
+impl embedded_hal_02::digital::v2::toggleable::Default for MockLed {}
+
+/// Active low push button, like SW0, read from a script
+#[derive(Debug, Default)]
+pub struct MockButton {
+    pressed: bool,
+    script: VecDeque<bool>,
+    reads: usize,
+}
+
+impl MockButton {
+    /// A released button
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn press(&mut self) {
+        self.script.clear();
+        self.pressed = true;
+    }
+
+    pub fn release(&mut self) {
+        self.script.clear();
+        self.pressed = false;
+    }
+
+    /// States the next reads return, `true` for pressed.  The button stays
+    /// in the last one.
+    pub fn script(&mut self, pressed: impl IntoIterator<Item = bool>) {
+        self.script = pressed.into_iter().collect();
+    }
+
+    /// Number of times the button was read
+    pub fn reads(&self) -> usize {
+        self.reads
+    }
+
+    fn read(&mut self) -> bool {
+        if let Some(pressed) = self.script.pop_front() {
+            self.pressed = pressed;
+        }
+        self.reads += 1;
+        self.pressed
+    }
+}
+
+impl digital::ErrorType for MockButton {
+    type Error = Infallible;
+}
+
+impl InputPin for MockButton {
+    fn is_high(&mut self) -> Result<bool, Infallible> {
+        Ok(!self.read())
+    }
+
+    fn is_low(&mut self) -> Result<bool, Infallible> {
+        Ok(self.read())
+    }
+}
+
+#[cfg(feature = "eh02")]
+impl embedded_hal_02::digital::v2::InputPin for MockButton {
+    type Error = Infallible;
+
+    // Reads count, but the trait only lends the button out shared.
+    fn is_high(&self) -> Result<bool, Infallible> {
+        Ok(!self.script.front().copied().unwrap_or(self.pressed))
+    }
+
+    fn is_low(&self) -> Result<bool, Infallible> {
+        Ok(self.script.front().copied().unwrap_or(self.pressed))
+    }
+}
+
+/// Jumper wire between two pins: what one clone drives, every clone reads
+#[derive(Clone, Debug, Default)]
+pub struct MockWire {
+    high: Rc<Cell<bool>>,
+}
+
+impl MockWire {
+    /// A wire driven low
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl digital::ErrorType for MockWire {
+    type Error = Infallible;
+}
+
+impl OutputPin for MockWire {
+    fn set_low(&mut self) -> Result<(), Infallible> {
+        self.high.set(false);
+        Ok(())
+    }
+
+    fn set_high(&mut self) -> Result<(), Infallible> {
+        self.high.set(true);
+        Ok(())
+    }
+}
+
+impl StatefulOutputPin for MockWire {
+    fn is_set_high(&mut self) -> Result<bool, Infallible> {
+        Ok(self.high.get())
+    }
+
+    fn is_set_low(&mut self) -> Result<bool, Infallible> {
+        Ok(!self.high.get())
+    }
+}
+
+impl InputPin for MockWire {
+    fn is_high(&mut self) -> Result<bool, Infallible> {
+        Ok(self.high.get())
+    }
+
+    fn is_low(&mut self) -> Result<bool, Infallible> {
+        Ok(!self.high.get())
+    }
+}
+
+/// Serial console with scripted input that records its output
+#[derive(Debug, Default)]
+pub struct MockConsole {
+    input: VecDeque<u8>,
+    output: Vec<u8>,
+    looped_back: bool,
+}
+
+impl MockConsole {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A console that also reads back what it writes, like a UART in local
+    /// loopback
+    pub fn looped_back() -> Self {
+        MockConsole {
+            looped_back: true,
+            ..Self::default()
+        }
+    }
+
+    /// Queues bytes for the firmware to read.
+    pub fn push_input(&mut self, data: &[u8]) {
+        self.input.extend(data);
+    }
+
+    /// Bytes queued and not read yet
+    pub fn pending_input(&self) -> usize {
+        self.input.len()
+    }
+
+    /// Everything written since the last [`MockConsole::take_output`]
+    pub fn output(&self) -> &[u8] {
+        &self.output
+    }
+
+    pub fn take_output(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.output)
+    }
+}
+
+impl embedded_io::ErrorType for MockConsole {
+    type Error = Infallible;
+}
+
+/// Returns 0, like the end of a stream, once the input runs out.
+impl embedded_io::Read for MockConsole {
+    fn read(&mut self, buffer: &mut [u8]) -> Result<usize, Infallible> {
+        let len = buffer.len().min(self.input.len());
+        for (byte, input) in buffer.iter_mut().zip(self.input.drain(..len)) {
+            *byte = input;
+        }
+        Ok(len)
+    }
+}
+
+impl embedded_io::ReadReady for MockConsole {
+    fn read_ready(&mut self) -> Result<bool, Infallible> {
+        Ok(!self.input.is_empty())
+    }
+}
+
+impl embedded_io::Write for MockConsole {
+    fn write(&mut self, data: &[u8]) -> Result<usize, Infallible> {
+        self.output.extend_from_slice(data);
+        if self.looped_back {
+            self.input.extend(data);
+        }
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> Result<(), Infallible> {
+        Ok(())
+    }
+}
+
+impl embedded_io::WriteReady for MockConsole {
+    fn write_ready(&mut self) -> Result<bool, Infallible> {
+        Ok(true)
+    }
+}
+
+impl fmt::Write for MockConsole {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        embedded_io::Write::write(self, s.as_bytes()).ok();
+        Ok(())
+    }
+}
+
+/// `WouldBlock` once the input runs out
+#[cfg(feature = "eh02")]
+impl embedded_hal_02::serial::Read<u8> for MockConsole {
+    type Error = Infallible;
+
+    fn read(&mut self) -> nb::Result<u8, Infallible> {
+        self.input.pop_front().ok_or(nb::Error::WouldBlock)
+    }
+}
+
+#[cfg(feature = "eh02")]
+impl embedded_hal_02::serial::Write<u8> for MockConsole {
+    type Error = Infallible;
+
+    fn write(&mut self, byte: u8) -> nb::Result<(), Infallible> {
+        embedded_io::Write::write(self, &[byte]).ok();
+        Ok(())
+    }
+
+    fn flush(&mut self) -> nb::Result<(), Infallible> {
+        Ok(())
+    }
+}
+
+/// Delay that returns at once and adds up the time it should have taken.
+/// Clones share the time, so that one can serve as the clock of a
+/// [`MockCounter`] while another is handed out.
+#[derive(Clone, Debug, Default)]
+pub struct MockDelay {
+    elapsed: Rc<Cell<Duration>>,
+}
+
+impl MockDelay {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Total of the delays so far
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed.get()
+    }
+
+    /// Counter of `frequency` ticks per second of the delays, like the DWT
+    /// cycle counter or the RTT
+    pub fn counter(&self, frequency: u32) -> MockCounter {
+        MockCounter {
+            clock: self.clone(),
+            frequency,
+        }
+    }
+
+    fn advance(&self, time: Duration) {
+        self.elapsed.set(self.elapsed.get() + time);
+    }
+}
+
+impl DelayNs for MockDelay {
+    fn delay_ns(&mut self, ns: u32) {
+        self.advance(Duration::from_nanos(ns as u64));
+    }
+}
+
+#[cfg(feature = "eh02")]
+impl embedded_hal_02::blocking::delay::DelayMs<u32> for MockDelay {
+    fn delay_ms(&mut self, ms: u32) {
+        self.advance(Duration::from_millis(ms as u64));
+    }
+}
+
+#[cfg(feature = "eh02")]
+impl embedded_hal_02::blocking::delay::DelayUs<u32> for MockDelay {
+    fn delay_us(&mut self, us: u32) {
+        self.advance(Duration::from_micros(us as u64));
+    }
+}
+
+/// Free-running counter of the time of a [`MockDelay`]
+#[derive(Clone, Debug)]
+pub struct MockCounter {
+    clock: MockDelay,
+    frequency: u32,
+}
+
+impl MockCounter {
+    /// Ticks so far, wrapping.  Each read takes a tick, so that code polling
+    /// the counter sees time pass.
+    pub fn count(&mut self) -> u32 {
+        let tick = Duration::from_secs(1) / self.frequency;
+        self.clock.advance(tick);
+        (self.clock.elapsed().as_nanos() * self.frequency as u128 / 1_000_000_000) as u32
+    }
+
+    /// Ticks per second
+    pub fn frequency(&self) -> u32 {
+        self.frequency
+    }
+}
+
+/// Flash in memory with the write and erase sizes of the internal flash.
+/// Writes clear bits only, like the real one, so data written over without an
+/// erase reads back corrupted.
+#[derive(Debug)]
+pub struct MockFlash {
+    memory: Vec<u8>,
+    erases: usize,
+}
+
+impl MockFlash {
+    /// Erased flash of `capacity` bytes
+    pub fn new(capacity: usize) -> Self {
+        MockFlash {
+            memory: vec![0xFF; capacity],
+            erases: 0,
+        }
+    }
+
+    pub fn memory(&self) -> &[u8] {
+        &self.memory
+    }
+
+    /// Contents to start a test from, e.g. a store written by a previous
+    /// firmware version
+    pub fn memory_mut(&mut self) -> &mut [u8] {
+        &mut self.memory
+    }
+
+    /// Number of erase blocks erased so far
+    pub fn erases(&self) -> usize {
+        self.erases
+    }
+}
+
+impl ErrorType for MockFlash {
+    type Error = NorFlashErrorKind;
+}
+
+impl ReadNorFlash for MockFlash {
+    const READ_SIZE: usize = 1;
+
+    fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
+        check_read(self, offset, bytes.len())?;
+        let offset = offset as usize;
+        bytes.copy_from_slice(&self.memory[offset..offset + bytes.len()]);
+        Ok(())
+    }
+
+    fn capacity(&self) -> usize {
+        self.memory.len()
+    }
+}
+
+impl NorFlash for MockFlash {
+    const WRITE_SIZE: usize = 16;
+    const ERASE_SIZE: usize = 8 * 512;
+
+    fn erase(&mut self, from: u32, to: u32) -> Result<(), Self::Error> {
+        check_erase(self, from, to)?;
+        self.memory[from as usize..to as usize].fill(0xFF);
+        self.erases += (to - from) as usize / Self::ERASE_SIZE;
+        Ok(())
+    }
+
+    fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error> {
+        check_write(self, offset, bytes.len())?;
+        let offset = offset as usize;
+        for (cell, byte) in self.memory[offset..].iter_mut().zip(bytes) {
+            *cell &= byte;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,128 @@
+//! RTIC monotonic timer backed by the Real-time Timer (RTT)
+
+use crate::hal::pac::{rtt::RegisterBlock, RTT};
+use rtic_monotonic::Monotonic;
+
+pub use fugit::{self, ExtU32};
+
+/// Frequency of the slow clock feeding the RTT prescaler.
+const SLOW_CLOCK_HZ: u32 = 32_768;
+
+/// RTIC monotonic timer driven by the 32.768 kHz slow clock through the RTT
+/// prescaler.
+///
+/// Unlike the DWT cycle counter the RTT does not need trace to be enabled and
+/// keeps counting while the core is asleep.  `TIMER_HZ` selects the tick rate
+/// and must divide 32768 with a prescaler of at least 3 (e.g. 1024 or 8192).
+/// At 8192 Hz the 32-bit counter wraps after roughly six days.
+///
+/// ```rust
+/// #[monotonic(binds = RTT, default = true)]
+/// type Mono = RttMonotonic<8192>;
+/// ```
+pub struct RttMonotonic<const TIMER_HZ: u32> {
+    rtt: RTT,
+}
+
+impl<const TIMER_HZ: u32> RttMonotonic<TIMER_HZ> {
+    const PRESCALER: u16 = {
+        let prescaler = SLOW_CLOCK_HZ / TIMER_HZ;
+        assert!(
+            prescaler >= 3 && prescaler * TIMER_HZ == SLOW_CLOCK_HZ,
+            "TIMER_HZ must divide 32768 with a prescaler of at least 3"
+        );
+        prescaler as u16
+    };
+
+    /// Takes ownership of the RTT and configures its prescaler.  The counter
+    /// is restarted by RTIC (through `Monotonic::reset`) once `init` returns.
+    pub fn new(rtt: RTT) -> Self {
+        rtt.mr.modify(|_, w| {
+            w.rttdis()
+                .set_bit()
+                .almien()
+                .clear_bit()
+                .rttincien()
+                .clear_bit()
+        });
+        rtt.mr.modify(|_, w| unsafe {
+            w.rtpres()
+                .bits(Self::PRESCALER)
+                .rtc1hz()
+                .clear_bit()
+                .rttdis()
+                .clear_bit()
+        });
+
+        RttMonotonic { rtt }
+    }
+
+    /// Releases the RTT resource
+    pub fn free(self) -> RTT {
+        self.rtt
+    }
+}
+
+/// Reads the RTT counter.
+///
+/// The counter runs from the asynchronous slow clock, so this keeps reading
+/// until two consecutive values agree.
+pub(crate) fn read_rtt(rtt: &RegisterBlock) -> u32 {
+    let mut counter = rtt.vr.read().crtv().bits();
+    loop {
+        let again = rtt.vr.read().crtv().bits();
+        if again == counter {
+            return counter;
+        }
+        counter = again;
+    }
+}
+
+impl<const TIMER_HZ: u32> Monotonic for RttMonotonic<TIMER_HZ> {
+    type Instant = fugit::TimerInstantU32<TIMER_HZ>;
+    type Duration = fugit::TimerDurationU32<TIMER_HZ>;
+
+    fn now(&mut self) -> Self::Instant {
+        Self::Instant::from_ticks(read_rtt(&self.rtt))
+    }
+
+    fn set_compare(&mut self, instant: Self::Instant) {
+        // ALMIEN must be disabled while a new alarm value is written.
+        let almien = self.rtt.mr.read().almien().bit_is_set();
+        if almien {
+            self.rtt.mr.modify(|_, w| w.almien().clear_bit());
+        }
+
+        // The alarm fires when the counter reaches ALMV + 1.
+        self.rtt
+            .ar
+            .write(|w| unsafe { w.almv().bits(instant.ticks().wrapping_sub(1)) });
+
+        if almien {
+            self.rtt.mr.modify(|_, w| w.almien().set_bit());
+        }
+    }
+
+    fn clear_compare_flag(&mut self) {
+        // Reading the status register clears ALMS.
+        let _ = self.rtt.sr.read();
+    }
+
+    fn zero() -> Self::Instant {
+        Self::Instant::from_ticks(0)
+    }
+
+    unsafe fn reset(&mut self) {
+        // Restart both the prescaler and the 32-bit counter from zero.
+        self.rtt.mr.modify(|_, w| w.rttrst().set_bit());
+        let _ = self.rtt.sr.read();
+    }
+
+    fn enable_timer(&mut self) {
+        self.rtt.mr.modify(|_, w| w.almien().set_bit());
+    }
+
+    fn disable_timer(&mut self) {
+        self.rtt.mr.modify(|_, w| w.almien().clear_bit());
+    }
+}
@@ -0,0 +1,77 @@
+//! Functions executed from RAM
+//!
+//! While the flash controller programs, erases or maps the unique identifier,
+//! nothing can be fetched from the flash it controls.  [`ramfunc!`] places
+//! functions in the `.ramfunc` section, which the board's linker script loads
+//! into flash and [`init`] copies to RAM.  `XplainedBoard::new` calls [`init`]
+//! before anything else.
+//!
+//! ```rust
+//! sam_xplained::ramfunc! {
+//!     /// Waits for the flash controller to become ready.
+//!     unsafe fn wait_ready(efc: usize) {
+//!         core::arch::asm!(/* ... */);
+//!     }
+//! }
+//! ```
+//!
+//! A RAM function must not call back into the flash while it is busy.  That
+//! includes compiler intrinsics and, in unoptimized builds, even trivial core
+//! functions, so write the busy part as a single `asm!` block.
+//!
+//! Applications with their own linker script need to provide the `.ramfunc`
+//! section and its symbols as in `memory/<board>.x`.
+
+use core::ptr;
+
+/// Places the functions it wraps in RAM
+///
+/// Accepts any number of (optionally `unsafe`) function items with their
+/// attributes and visibility.  Functions defined this way are only usable
+/// after [`init`](crate::ramfunc::init).
+#[macro_export]
+macro_rules! ramfunc {
+    () => {};
+    ($(#[$attr:meta])* $vis:vis unsafe fn $name:ident $args:tt $(-> $ret:ty)? $body:block $($rest:tt)*) => {
+        $(#[$attr])*
+        #[inline(never)]
+        #[link_section = concat!(".ramfunc.", stringify!($name))]
+        $vis unsafe fn $name $args $(-> $ret)? $body
+
+        $crate::ramfunc! { $($rest)* }
+    };
+    ($(#[$attr:meta])* $vis:vis fn $name:ident $args:tt $(-> $ret:ty)? $body:block $($rest:tt)*) => {
+        $(#[$attr])*
+        #[inline(never)]
+        #[link_section = concat!(".ramfunc.", stringify!($name))]
+        $vis fn $name $args $(-> $ret)? $body
+
+        $crate::ramfunc! { $($rest)* }
+    };
+}
+
+/// Copies the `.ramfunc` section from flash to RAM.
+///
+/// # Safety
+///
+/// No RAM function may be running, e.g. in an interrupt handler.
+pub unsafe fn init() {
+    extern "C" {
+        static mut __sramfunc: u32;
+        static mut __eramfunc: u32;
+        static __siramfunc: u32;
+    }
+
+    let start = ptr::addr_of_mut!(__sramfunc);
+    let end = ptr::addr_of_mut!(__eramfunc);
+    let load = ptr::addr_of!(__siramfunc);
+
+    let words = (end as usize - start as usize) / 4;
+    for index in 0..words {
+        start.add(index).write_volatile(load.add(index).read());
+    }
+
+    // Make sure the copied code is what gets fetched.
+    cortex_m::asm::dsb();
+    cortex_m::asm::isb();
+}
@@ -0,0 +1,185 @@
+//! Structured requests from a host over the serial console
+//!
+//! Re-exports the `sam_xplained_rpc` crate and answers its requests for the
+//! board with [`BoardHandler`], e.g. for automated tests driven by
+//! `sam_xplained_host`:
+//!
+//! ```rust
+//! let Board { console, led0, chip_id, reset_cause, unique_id, mut flash, .. } = board;
+//! let store = KvStore::new(&mut flash, STORAGE).unwrap();
+//! let mut handler = BoardHandler::new(led0, chip_id, reset_cause, unique_id).with_store(store);
+//! Server::new().run(&mut handler, &mut Compat::new(console)).ok();
+//! ```
+//!
+//! Memory requests access whatever address they are given; unmapped
+//! addresses end in a bus fault.
+
+use core::ptr;
+use embedded_hal::digital::{OutputPin, StatefulOutputPin};
+
+pub use sam_xplained_rpc::*;
+
+use crate::build_info::firmware_info;
+use crate::chip::ChipInfo;
+use crate::compat::Compat;
+use crate::flash::InternalFlash;
+use crate::image;
+use crate::kv::{self, KvStore};
+use crate::Led0;
+
+/// Answers the requests with the board resources it holds
+pub struct BoardHandler<'a> {
+    pub led0: Led0,
+    pub chip_id: ChipInfo,
+    pub reset_cause: crate::ResetCause,
+    pub unique_id: [u32; 4],
+    /// Store behind the config requests, which are unsupported without one
+    pub store: Option<KvStore<&'a mut InternalFlash>>,
+    reset_requested: bool,
+}
+
+impl<'a> BoardHandler<'a> {
+    pub fn new(
+        led0: Led0,
+        chip_id: ChipInfo,
+        reset_cause: crate::ResetCause,
+        unique_id: [u32; 4],
+    ) -> Self {
+        BoardHandler {
+            led0,
+            chip_id,
+            reset_cause,
+            unique_id,
+            store: None,
+            reset_requested: false,
+        }
+    }
+
+    /// Serves the config requests from `store`.
+    pub fn with_store(self, store: KvStore<&'a mut InternalFlash>) -> Self {
+        BoardHandler {
+            store: Some(store),
+            ..self
+        }
+    }
+
+    fn led(&mut self, action: LedAction) -> Response<'static> {
+        let led = Compat::from_mut(&mut self.led0);
+        // LED0 is active low.
+        match action {
+            LedAction::On => led.set_low().ok(),
+            LedAction::Off => led.set_high().ok(),
+            LedAction::Toggle => led.toggle().ok(),
+        };
+        Response::Led {
+            on: led.is_set_low().unwrap_or(false),
+        }
+    }
+
+    fn config<'b>(&mut self, request: Request<'_>, buffer: &'b mut [u8]) -> Response<'b> {
+        let store = match self.store.as_mut() {
+            Some(store) => store,
+            None => return Response::Error(RemoteError::Unsupported),
+        };
+        let result = match request {
+            Request::ConfigGet(key) => {
+                return match store.get(key, buffer) {
+                    Ok(len) => Response::Config(len.map(move |len| &buffer[..len])),
+                    Err(error) => Response::Error(store_error(error)),
+                };
+            }
+            Request::ConfigSet { key, value } => store.set(key, value),
+            Request::ConfigRemove(key) => store.remove(key),
+            _ => unreachable!(),
+        };
+        match result {
+            Ok(()) => Response::Done,
+            Err(error) => Response::Error(store_error(error)),
+        }
+    }
+}
+
+impl Handler for BoardHandler<'_> {
+    fn handle<'b>(&'b mut self, request: Request<'_>, buffer: &'b mut [u8]) -> Response<'b> {
+        match request {
+            Request::Ping(value) => Response::Pong(value),
+            Request::ChipId => Response::ChipId {
+                cidr: self.chip_id.cidr(),
+                exid: self.chip_id.exid(),
+            },
+            Request::ResetCause => Response::ResetCause(self.reset_cause.into()),
+            Request::FirmwareInfo => {
+                let info = firmware_info().to_bytes();
+                buffer[..info.len()].copy_from_slice(&info);
+                Response::FirmwareInfo(&buffer[..info.len()])
+            }
+            Request::UniqueId => Response::UniqueId(self.unique_id),
+            Request::Led(action) => self.led(action),
+            Request::ReadMemory { address, len } => {
+                let len = len as usize;
+                if !word_aligned(address, len) || len > buffer.len() {
+                    return Response::Error(RemoteError::InvalidArgument);
+                }
+                for (index, word) in buffer[..len].chunks_exact_mut(4).enumerate() {
+                    let address = address.wrapping_add(index as u32 * 4);
+                    let value = unsafe { ptr::read_volatile(address as *const u32) };
+                    word.copy_from_slice(&value.to_le_bytes());
+                }
+                Response::Memory(&buffer[..len])
+            }
+            Request::WriteMemory { address, data } => {
+                if !word_aligned(address, data.len()) {
+                    return Response::Error(RemoteError::InvalidArgument);
+                }
+                for (index, word) in data.chunks_exact(4).enumerate() {
+                    let address = address.wrapping_add(index as u32 * 4);
+                    let value = u32::from_le_bytes([word[0], word[1], word[2], word[3]]);
+                    unsafe { ptr::write_volatile(address as *mut u32, value) };
+                }
+                Response::Done
+            }
+            Request::ConfigGet(_) | Request::ConfigSet { .. } | Request::ConfigRemove(_) => {
+                self.config(request, buffer)
+            }
+            Request::Reset => {
+                self.reset_requested = true;
+                Response::Done
+            }
+            Request::Update => {
+                image::request_update();
+                self.reset_requested = true;
+                Response::Done
+            }
+        }
+    }
+
+    fn sent(&mut self) {
+        if self.reset_requested {
+            crate::reset_after_console_drain()
+        }
+    }
+}
+
+impl From<crate::ResetCause> for ResetCause {
+    fn from(cause: crate::ResetCause) -> Self {
+        match cause {
+            crate::ResetCause::FirstPowerUp => ResetCause::FirstPowerUp,
+            crate::ResetCause::ReturnFromBackup => ResetCause::ReturnFromBackup,
+            crate::ResetCause::Watchdog => ResetCause::Watchdog,
+            crate::ResetCause::Software => ResetCause::Software,
+            crate::ResetCause::NrstPin => ResetCause::NrstPin,
+            crate::ResetCause::Reserved(value) => ResetCause::Reserved(value),
+        }
+    }
+}
+
+fn word_aligned(address: u32, len: usize) -> bool {
+    (address as usize | len) & 3 == 0
+}
+
+fn store_error<E>(error: kv::Error<E>) -> RemoteError {
+    match error {
+        kv::Error::InvalidKey | kv::Error::ValueTooLarge => RemoteError::InvalidArgument,
+        _ => RemoteError::Storage,
+    }
+}
@@ -0,0 +1,58 @@
+//! Output over RTT, which the debug probe reads from RAM, for when the
+//! console UART is busy or not wired up
+//!
+//! OpenOCD serves the channel on a TCP port:
+//!
+//! ```text
+//! > rtt setup 0x20000000 0x20000 "SEGGER RTT"
+//! > rtt start
+//! > rtt server start 9090 0
+//! ```
+
+use core::convert::Infallible;
+
+use rtt_target::{rtt_init, ChannelMode, UpChannel};
+
+/// Size of the up channel buffer in bytes
+const BUFFER_SIZE: usize = 1024;
+
+/// RTT up channel 0 ("Terminal"), blocking while the buffer is full so that
+/// nothing is lost
+pub struct RttConsole(UpChannel);
+
+impl RttConsole {
+    /// Sets up the RTT control block.  Panics when called a second time.
+    pub fn init() -> Self {
+        let channels = rtt_init! {
+            up: {
+                0: {
+                    size: BUFFER_SIZE,
+                    mode: ChannelMode::BlockIfFull,
+                    name: "Terminal"
+                }
+            }
+        };
+        RttConsole(channels.up.0)
+    }
+}
+
+impl embedded_io::ErrorType for RttConsole {
+    type Error = Infallible;
+}
+
+impl embedded_io::Write for RttConsole {
+    fn write(&mut self, data: &[u8]) -> Result<usize, Infallible> {
+        Ok(self.0.write(data))
+    }
+
+    fn flush(&mut self) -> Result<(), Infallible> {
+        Ok(())
+    }
+}
+
+impl core::fmt::Write for RttConsole {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        self.0.write(s.as_bytes());
+        Ok(())
+    }
+}
@@ -0,0 +1,310 @@
+//! Command shell on the serial console
+//!
+//! Re-exports the `sam_xplained_shell` crate and adds the board's built-in
+//! commands, which work on a [`Context`] split off the [`Board`]:
+//!
+//! ```rust
+//! let Board { console, led0, chip_id, reset_cause, delay, .. } = board;
+//! let mut context = Context { led0, chip_id, reset_cause, app: delay };
+//! let mut shell: Shell<Context<Delay>, 16> = Shell::new("> ");
+//! register_builtins(&mut shell).unwrap();
+//! shell.run(&mut context, &mut Compat::new(console)).ok();
+//! ```
+//!
+//! `peek`, `poke` and `memtest` access whatever address they are given;
+//! unmapped addresses end in a bus fault.
+//!
+//! [`Board`]: crate::Board
+
+use core::fmt;
+use core::ptr;
+use embedded_hal::digital::{OutputPin, StatefulOutputPin};
+
+pub use sam_xplained_shell::*;
+
+use crate::board::pio;
+use crate::chip::ChipInfo;
+use crate::compat::Compat;
+use crate::hal::{clock::get_master_clock_frequency, pac};
+use crate::{Led0, ResetCause};
+
+/// Board resources used by the built-in commands, and the application's own
+/// state `A` for its commands
+pub struct Context<A = ()> {
+    pub led0: Led0,
+    pub chip_id: ChipInfo,
+    pub reset_cause: ResetCause,
+    pub app: A,
+}
+
+/// Registers `led`, `reset`, `reset-cause`, `clocks`, `chipid`, `peek`,
+/// `poke`, `gpio` and (except on the SAM4N, which has no external bus)
+/// `memtest`.
+pub fn register_builtins<A, const COMMANDS: usize>(
+    shell: &mut Shell<Context<A>, COMMANDS>,
+) -> Result<(), RegisterError> {
+    shell.register(Command {
+        name: "led",
+        usage: "on|off|toggle",
+        help: "Switches LED0",
+        handler: led,
+    })?;
+    shell.register(Command {
+        name: "reset",
+        usage: "",
+        help: "Resets the microcontroller",
+        handler: reset,
+    })?;
+    shell.register(Command {
+        name: "reset-cause",
+        usage: "",
+        help: "Shows the cause of the last reset",
+        handler: |context, args, out| {
+            args.finish()?;
+            writeln!(out, "{}", context.reset_cause)?;
+            Ok(())
+        },
+    })?;
+    shell.register(Command {
+        name: "clocks",
+        usage: "",
+        help: "Shows the master clock and the enabled peripheral clocks",
+        handler: clocks,
+    })?;
+    shell.register(Command {
+        name: "chipid",
+        usage: "",
+        help: "Identifies the microcontroller",
+        handler: |context, args, out| {
+            args.finish()?;
+            writeln!(out, "{}", context.chip_id)?;
+            Ok(())
+        },
+    })?;
+    #[cfg(not(feature = "sam4n"))]
+    shell.register(Command {
+        name: "memtest",
+        usage: "<cs> [bytes]",
+        help: "Tests the SRAM on an external bus chip select (512K by default)",
+        handler: memtest,
+    })?;
+    shell.register(Command {
+        name: "peek",
+        usage: "<address> [words]",
+        help: "Reads memory words",
+        handler: peek,
+    })?;
+    shell.register(Command {
+        name: "poke",
+        usage: "<address> <value>",
+        help: "Writes a memory word",
+        handler: |_, args, _| {
+            let address = word_address(args.number()?)?;
+            let value = args.number()?;
+            args.finish()?;
+            unsafe { ptr::write_volatile(address as *mut u32, value) };
+            Ok(())
+        },
+    })?;
+    shell.register(Command {
+        name: "gpio",
+        usage: "<pin> [high|low]",
+        help: "Shows the state of a pin (e.g. pa2), or drives a PIO output",
+        handler: gpio,
+    })
+}
+
+fn led<A>(
+    context: &mut Context<A>,
+    args: &mut Args<'_>,
+    _: &mut dyn fmt::Write,
+) -> Result<(), Error> {
+    let led = Compat::from_mut(&mut context.led0);
+    // LED0 is active low.
+    match args.required()? {
+        "on" => led.set_low().ok(),
+        "off" => led.set_high().ok(),
+        "toggle" => led.toggle().ok(),
+        _ => return Err(Error::Usage),
+    };
+    args.finish()
+}
+
+fn reset<A>(
+    _: &mut Context<A>,
+    args: &mut Args<'_>,
+    out: &mut dyn fmt::Write,
+) -> Result<(), Error> {
+    args.finish()?;
+    writeln!(out, "Resetting")?;
+    crate::reset_after_console_drain()
+}
+
+fn clocks<A>(
+    _: &mut Context<A>,
+    args: &mut Args<'_>,
+    out: &mut dyn fmt::Write,
+) -> Result<(), Error> {
+    args.finish()?;
+    // PMC_PCSR0 and PMC_PCSR1
+    let pmc = pac::PMC::ptr() as usize;
+    let enabled = unsafe {
+        [
+            ptr::read_volatile((pmc + 0x18) as *const u32),
+            ptr::read_volatile((pmc + 0x108) as *const u32),
+        ]
+    };
+    writeln!(out, "Master clock: {} Hz", get_master_clock_frequency().0)?;
+    write!(out, "Peripheral clocks enabled (IDs):")?;
+    for id in 0..64 {
+        if enabled[id / 32] & (1 << (id % 32)) != 0 {
+            write!(out, " {}", id)?;
+        }
+    }
+    writeln!(out)?;
+    Ok(())
+}
+
+#[cfg(not(feature = "sam4n"))]
+fn memtest<A>(
+    _: &mut Context<A>,
+    args: &mut Args<'_>,
+    out: &mut dyn fmt::Write,
+) -> Result<(), Error> {
+    /// SRAM fitted to the SAM4E Xplained Pro
+    const DEFAULT_SIZE: u32 = 512 * 1024;
+
+    let cs = args.number()?;
+    let size = args.optional_number()?.unwrap_or(DEFAULT_SIZE);
+    args.finish()?;
+    if cs > 3 || size == 0 || size > 0x0100_0000 || size % 4 != 0 {
+        return Err(Error::Usage);
+    }
+    // Each chip select decodes 16M of the external bus.
+    let base = 0x6000_0000 + cs * 0x0100_0000;
+    let words = (size / 4) as usize;
+    writeln!(out, "Testing {} bytes at {:#010x}", size, base)?;
+
+    // Every word holds its own address, which catches shorted or open address
+    // lines, then the inverse, which flips every data bit.
+    let memory = base as *mut u32;
+    for pattern in [0, 0xFFFF_FFFF].iter() {
+        for index in 0..words {
+            let value = (base + index as u32 * 4) ^ pattern;
+            unsafe { ptr::write_volatile(memory.add(index), value) };
+        }
+        for index in 0..words {
+            let expected = (base + index as u32 * 4) ^ pattern;
+            let value = unsafe { ptr::read_volatile(memory.add(index)) };
+            if value != expected {
+                writeln!(
+                    out,
+                    "{:#010x}: read {:#010x}, expected {:#010x}",
+                    base + index as u32 * 4,
+                    value,
+                    expected
+                )?;
+                return Err(Error::Failed("memory test failed"));
+            }
+        }
+    }
+    writeln!(out, "OK")?;
+    Ok(())
+}
+
+fn peek<A>(_: &mut Context<A>, args: &mut Args<'_>, out: &mut dyn fmt::Write) -> Result<(), Error> {
+    let address = word_address(args.number()?)?;
+    let count = args.optional_number()?.unwrap_or(1);
+    args.finish()?;
+    for index in 0..count {
+        let address = address.wrapping_add(index.wrapping_mul(4));
+        if index % 4 == 0 {
+            if index > 0 {
+                writeln!(out)?;
+            }
+            write!(out, "{:#010x}:", address)?;
+        }
+        let value = unsafe { ptr::read_volatile(address as *const u32) };
+        write!(out, " {:#010x}", value)?;
+    }
+    writeln!(out)?;
+    Ok(())
+}
+
+fn word_address(address: u32) -> Result<u32, Error> {
+    match address % 4 {
+        0 => Ok(address),
+        _ => Err(Error::Failed("address must be word aligned")),
+    }
+}
+
+// PIO register offsets
+const PIO_PSR: usize = 0x08;
+const PIO_OSR: usize = 0x18;
+const PIO_SODR: usize = 0x30;
+const PIO_CODR: usize = 0x34;
+const PIO_PDSR: usize = 0x3C;
+const PIO_MDSR: usize = 0x58;
+const PIO_PUSR: usize = 0x68;
+const PIO_ABCDSR1: usize = 0x70;
+const PIO_ABCDSR2: usize = 0x74;
+
+fn gpio<A>(_: &mut Context<A>, args: &mut Args<'_>, out: &mut dyn fmt::Write) -> Result<(), Error> {
+    let name = args.required()?;
+    let drive = args.next();
+    args.finish()?;
+
+    // `pa2` or `PA02`
+    let mut chars = name.chars();
+    let (port, base, line) = match (chars.next(), chars.next()) {
+        (Some('p'), Some(port)) | (Some('P'), Some(port)) => {
+            let port = port.to_ascii_lowercase();
+            let (base, lines) = pio(port).ok_or(Error::Failed("no such port"))?;
+            let line = parse_number(chars.as_str())
+                .filter(|&line| line < lines)
+                .ok_or(Error::Failed("no such pin"))?;
+            (port.to_ascii_uppercase(), base, line)
+        }
+        _ => return Err(Error::Usage),
+    };
+    let read = |offset: usize| unsafe { ptr::read_volatile((base + offset) as *const u32) } & (1 << line) != 0;
+    let pio_controlled = read(PIO_PSR);
+    let output = read(PIO_OSR);
+
+    match drive {
+        None => {}
+        Some(level) if pio_controlled && output => {
+            let offset = match level {
+                "high" => PIO_SODR,
+                "low" => PIO_CODR,
+                _ => return Err(Error::Usage),
+            };
+            unsafe { ptr::write_volatile((base + offset) as *mut u32, 1 << line) };
+        }
+        Some(_) => return Err(Error::Failed("not a PIO output")),
+    }
+
+    write!(out, "P{}{}: ", port, line)?;
+    if pio_controlled {
+        write!(
+            out,
+            "PIO {}",
+            match (output, read(PIO_MDSR)) {
+                (false, _) => "input",
+                (true, false) => "output",
+                (true, true) => "open drain output",
+            }
+        )?;
+    } else {
+        let function = read(PIO_ABCDSR1) as u8 | (read(PIO_ABCDSR2) as u8) << 1;
+        write!(out, "peripheral {}", (b'A' + function) as char)?;
+    }
+    writeln!(
+        out,
+        ", {}{}",
+        if read(PIO_PDSR) { "high" } else { "low" },
+        // PUSR reads 0 for enabled pull-ups.
+        if read(PIO_PUSR) { "" } else { ", pull-up" }
+    )?;
+    Ok(())
+}
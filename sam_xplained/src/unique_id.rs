@@ -0,0 +1,107 @@
+//! 128-bit unique identifier
+//!
+//! Every SAM4 carries a unique identifier that the enhanced embedded flash
+//! controller (EEFC) maps over the start of the flash array between its
+//! "Start Read Unique Identifier" and "Stop Read Unique Identifier" commands.
+//! The flash cannot be fetched from while the identifier is mapped, so the
+//! command sequence runs from RAM (see [`crate::ramfunc`]) with interrupts
+//! disabled.
+
+use core::fmt;
+use core::str;
+
+#[cfg(not(feature = "mock"))]
+use crate::flash::{FKEY, FLASH_BASE};
+
+// EEFC_FCR commands
+#[cfg(not(feature = "mock"))]
+const STUI: u32 = 0x0E;
+#[cfg(not(feature = "mock"))]
+const SPUI: u32 = 0x0F;
+
+/// Reads the unique identifier through the flash controller at `efc`.
+///
+/// # Safety
+///
+/// `efc` must be the address of the EEFC that controls the flash at
+/// `FLASH_BASE`, and no flash operation may be in progress on it.
+#[cfg(not(feature = "mock"))]
+pub(crate) unsafe fn read(efc: usize) -> [u32; 4] {
+    let mut id = [0u32; 4];
+    cortex_m::interrupt::free(|_| read_from_ram(efc, id.as_mut_ptr()));
+    id
+}
+
+#[cfg(not(feature = "mock"))]
+crate::ramfunc! {
+    // The whole sequence is a single asm block: at opt-level 0 even pointer
+    // arithmetic turns into calls, which would land in the unmapped flash.
+    unsafe fn read_from_ram(efc: usize, id: *mut u32) {
+        core::arch::asm!(
+            // EEFC_FCR = STUI, then wait for EEFC_FSR.FRDY to fall
+            "str {start}, [{efc}, #4]",
+            "2:",
+            "ldr {tmp}, [{efc}, #8]",
+            "tst {tmp}, #1",
+            "bne 2b",
+            // The identifier now reads back from the start of the flash array
+            "ldr {tmp}, [{flash}, #0]",
+            "str {tmp}, [{id}, #0]",
+            "ldr {tmp}, [{flash}, #4]",
+            "str {tmp}, [{id}, #4]",
+            "ldr {tmp}, [{flash}, #8]",
+            "str {tmp}, [{id}, #8]",
+            "ldr {tmp}, [{flash}, #12]",
+            "str {tmp}, [{id}, #12]",
+            // EEFC_FCR = SPUI, then wait for EEFC_FSR.FRDY to rise
+            "str {stop}, [{efc}, #4]",
+            "3:",
+            "ldr {tmp}, [{efc}, #8]",
+            "tst {tmp}, #1",
+            "beq 3b",
+            efc = in(reg) efc,
+            flash = in(reg) FLASH_BASE,
+            id = in(reg) id,
+            start = in(reg) FKEY | STUI,
+            stop = in(reg) FKEY | SPUI,
+            tmp = out(reg) _,
+            options(nostack),
+        );
+    }
+}
+
+/// Unique identifier formatted as 32 upper case hex digits, e.g. for use as a
+/// USB serial number string
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct SerialNumber([u8; 32]);
+
+impl SerialNumber {
+    pub fn new(unique_id: [u32; 4]) -> Self {
+        const DIGITS: &[u8; 16] = b"0123456789ABCDEF";
+
+        let mut text = [0u8; 32];
+        for (index, digit) in text.iter_mut().enumerate() {
+            let word = unique_id[index / 8];
+            let shift = 28 - 4 * (index % 8);
+            *digit = DIGITS[((word >> shift) & 0xF) as usize];
+        }
+        SerialNumber(text)
+    }
+
+    pub fn as_str(&self) -> &str {
+        // Only ever contains ASCII hex digits.
+        str::from_utf8(&self.0).unwrap()
+    }
+}
+
+impl fmt::Display for SerialNumber {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl fmt::Debug for SerialNumber {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self.as_str(), f)
+    }
+}
@@ -0,0 +1,153 @@
+//! Board test suites from `sam_xplained_test`, reported over the console UART
+//! (or RTT with the `rtt` feature) for `xplained test` to collect:
+//!
+//! ```shell
+//! $ xplained test --port /dev/ttyACM0 -- cargo test --features sam4e --test hardware
+//! ```
+//!
+//! The GPIO suite needs a jumper between the two lines in `target::JUMPER`.
+//! The flash suite erases the key-value store in `STORAGE`.
+//!
+//! With the `mock` feature the same suites run on the host against the mock
+//! board:
+//! `cargo test --target <host> --no-default-features --features mock --test hardware`
+#![cfg_attr(not(feature = "mock"), no_std)]
+#![cfg_attr(not(feature = "mock"), no_main)]
+
+use sam_xplained::{Board, STORAGE};
+use sam_xplained_test::suites::{clocks, flash, gpio, sram, uart};
+use sam_xplained_test::Harness;
+
+/// How far the core clock may be off the slow clock, in percent: the board
+/// runs the slow clock from the RC oscillator, specified at 20 to 44 kHz.
+const CLOCK_TOLERANCE: u32 = 40;
+
+#[cfg(not(feature = "mock"))]
+mod target;
+
+#[cfg(not(feature = "mock"))]
+use panic_semihosting as _; // panic handler
+
+#[cfg(not(feature = "mock"))]
+#[cortex_m_rt::entry]
+fn main() -> ! {
+    use embedded_io::Write;
+    use sam_xplained::compat::Compat;
+    use sam_xplained::hal::{pac, watchdog::*};
+    use sam_xplained::XplainedBoard;
+
+    let mut core = pac::CorePeripherals::take().unwrap();
+    let peripherals = pac::Peripherals::take().unwrap();
+    let mut board = Board::new(core.SYST, peripherals);
+    board.watchdog.disable();
+
+    #[cfg(feature = "rtt")]
+    let mut harness = Harness::new(sam_xplained::rtt::RttConsole::init());
+    #[cfg(not(feature = "rtt"))]
+    let mut harness = Harness::new(Compat::from_mut(&mut board.console));
+
+    write!(harness.out(), "GPIO loopback: {}\r\n", target::JUMPER).ok();
+    let delay = Compat::from_mut(&mut board.delay);
+    let (mut output, mut input) = target::jumper();
+    gpio::run(
+        &mut harness,
+        &mut output,
+        &mut input,
+        Compat::from_mut(&mut board.led0),
+        Compat::from_mut(&mut board.sw0),
+        delay,
+    );
+    uart::run(&mut harness, &mut target::LoopbackUart::new(), delay);
+    #[cfg(feature = "sam4e")]
+    for cs in [1, 3] {
+        let memory = unsafe { target::external_sram(cs) };
+        sram::run(&mut harness, memory.as_ptr() as u32, memory);
+    }
+    flash::run(&mut harness, &mut board.flash, STORAGE);
+    clocks::run(
+        &mut harness,
+        delay,
+        &mut target::Cycles::enable(&mut core.DCB, &mut core.DWT),
+        &mut target::SlowClock::new(board.rtt),
+        CLOCK_TOLERANCE,
+    );
+    harness.finish();
+
+    loop {
+        cortex_m::asm::wfi();
+    }
+}
+
+/// Report on stdout
+#[cfg(feature = "mock")]
+struct Stdout;
+
+#[cfg(feature = "mock")]
+impl embedded_io::ErrorType for Stdout {
+    type Error = core::convert::Infallible;
+}
+
+#[cfg(feature = "mock")]
+impl embedded_io::Write for Stdout {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        use std::io::Write;
+        std::io::stdout().write_all(buf).ok();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        use std::io::Write;
+        std::io::stdout().flush().ok();
+        Ok(())
+    }
+}
+
+/// Counter of the mock board's time
+#[cfg(feature = "mock")]
+struct Ticks(sam_xplained::mock::MockCounter);
+
+#[cfg(feature = "mock")]
+impl clocks::Counter for Ticks {
+    fn count(&mut self) -> u32 {
+        self.0.count()
+    }
+
+    fn frequency(&self) -> u32 {
+        self.0.frequency()
+    }
+}
+
+#[cfg(feature = "mock")]
+fn main() {
+    use sam_xplained::mock::{MockConsole, MockWire};
+
+    let mut board = Board::new();
+    let mut harness = Harness::new(Stdout);
+    let wire = MockWire::new();
+    gpio::run(
+        &mut harness,
+        &mut wire.clone(),
+        &mut wire.clone(),
+        &mut board.led0,
+        &mut board.sw0,
+        &mut board.delay,
+    );
+    uart::run(
+        &mut harness,
+        &mut MockConsole::looped_back(),
+        &mut board.delay,
+    );
+    let mut sram = vec![0u8; 512 * 1024];
+    sram::run(&mut harness, 0x6100_0000, &mut sram);
+    flash::run(&mut harness, &mut board.flash, STORAGE);
+    let cycles = board.delay.counter(120_000_000);
+    let slow_clock = board.delay.counter(8192);
+    clocks::run(
+        &mut harness,
+        &mut board.delay,
+        &mut Ticks(cycles),
+        &mut Ticks(slow_clock),
+        CLOCK_TOLERANCE,
+    );
+    std::process::exit(if harness.finish() { 0 } else { 1 });
+}
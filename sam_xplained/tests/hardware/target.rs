@@ -0,0 +1,283 @@
+//! Board peripherals the suites test, set up through their registers: the
+//! board crate hands out neither spare GPIO lines nor the second UART.
+
+use core::ptr;
+
+use embedded_hal::digital::{ErrorType, InputPin, OutputPin};
+use rtic_monotonic::Monotonic;
+use sam_xplained::hal::{clock::get_master_clock_frequency, pac};
+use sam_xplained::monotonic::RttMonotonic;
+use sam_xplained_test::suites::clocks::Counter;
+
+unsafe fn write(address: usize, value: u32) {
+    ptr::write_volatile(address as *mut u32, value)
+}
+
+unsafe fn read(address: usize) -> u32 {
+    ptr::read_volatile(address as *const u32)
+}
+
+/// Enables the peripheral clock of peripheral `id`.
+unsafe fn enable_clock(id: u32) {
+    const PMC_PCER0: usize = 0x10;
+    const PMC_PCER1: usize = 0x100;
+    let pmc = pac::PMC::ptr() as usize;
+    if id < 32 {
+        write(pmc + PMC_PCER0, 1 << id);
+    } else {
+        write(pmc + PMC_PCER1, 1 << (id - 32));
+    }
+}
+
+// PIO register offsets
+const PIO_PER: usize = 0x00;
+const PIO_OER: usize = 0x10;
+const PIO_ODR: usize = 0x14;
+const PIO_SODR: usize = 0x30;
+const PIO_CODR: usize = 0x34;
+const PIO_PDSR: usize = 0x3C;
+const PIO_MDDR: usize = 0x54;
+const PIO_PUDR: usize = 0x60;
+const PIO_PUER: usize = 0x64;
+
+/// A PIO line, whose port clock `Board::new` has enabled
+pub struct Line {
+    pio: usize,
+    mask: u32,
+}
+
+impl Line {
+    /// Push-pull output, driven low
+    unsafe fn output(pio: usize, line: u32) -> Self {
+        let mask = 1 << line;
+        write(pio + PIO_CODR, mask);
+        write(pio + PIO_MDDR, mask);
+        write(pio + PIO_PUDR, mask);
+        write(pio + PIO_OER, mask);
+        write(pio + PIO_PER, mask);
+        Line { pio, mask }
+    }
+
+    /// Input with the pull-up on, so that it reads high when left open
+    unsafe fn input(pio: usize, line: u32) -> Self {
+        let mask = 1 << line;
+        write(pio + PIO_ODR, mask);
+        write(pio + PIO_PUER, mask);
+        write(pio + PIO_PER, mask);
+        Line { pio, mask }
+    }
+}
+
+impl ErrorType for Line {
+    type Error = core::convert::Infallible;
+}
+
+impl OutputPin for Line {
+    fn set_low(&mut self) -> Result<(), Self::Error> {
+        unsafe { write(self.pio + PIO_CODR, self.mask) };
+        Ok(())
+    }
+
+    fn set_high(&mut self) -> Result<(), Self::Error> {
+        unsafe { write(self.pio + PIO_SODR, self.mask) };
+        Ok(())
+    }
+}
+
+impl InputPin for Line {
+    fn is_high(&mut self) -> Result<bool, Self::Error> {
+        Ok(unsafe { read(self.pio + PIO_PDSR) } & self.mask != 0)
+    }
+
+    fn is_low(&mut self) -> Result<bool, Self::Error> {
+        self.is_high().map(|high| !high)
+    }
+}
+
+/// Output and input of the GPIO loopback, to be wired together
+#[cfg(feature = "sam4e")]
+pub const JUMPER: &str = "PD28 to PD17";
+#[cfg(any(feature = "sam4s", feature = "sam4n"))]
+pub const JUMPER: &str = "PA15 to PA16";
+
+pub fn jumper() -> (Line, Line) {
+    #[cfg(feature = "sam4e")]
+    let (pio, output, input) = (pac::PIOD::ptr() as usize, 28, 17);
+    #[cfg(any(feature = "sam4s", feature = "sam4n"))]
+    let (pio, output, input) = (pac::PIOA::ptr() as usize, 15, 16);
+    unsafe { (Line::output(pio, output), Line::input(pio, input)) }
+}
+
+// UART register offsets
+const UART_CR: usize = 0x00;
+const UART_MR: usize = 0x04;
+const UART_IDR: usize = 0x0C;
+const UART_SR: usize = 0x14;
+const UART_RHR: usize = 0x18;
+const UART_THR: usize = 0x1C;
+const UART_BRGR: usize = 0x20;
+
+const UART_CR_RSTRX: u32 = 1 << 2;
+const UART_CR_RSTTX: u32 = 1 << 3;
+const UART_CR_RXEN: u32 = 1 << 4;
+const UART_CR_TXEN: u32 = 1 << 6;
+const UART_CR_RSTSTA: u32 = 1 << 8;
+const UART_MR_PAR_NO: u32 = 4 << 9;
+const UART_MR_CHMODE_LOCAL_LOOPBACK: u32 = 2 << 14;
+const UART_SR_RXRDY: u32 = 1 << 0;
+const UART_SR_TXRDY: u32 = 1 << 1;
+const UART_SR_TXEMPTY: u32 = 1 << 9;
+
+/// The UART the console doesn't use, in local loopback at the console baud
+/// rate: no pins involved
+pub struct LoopbackUart {
+    uart: usize,
+}
+
+impl LoopbackUart {
+    pub fn new() -> Self {
+        #[cfg(feature = "sam4e")]
+        let (uart, id) = (pac::UART1::ptr() as usize, 45);
+        #[cfg(feature = "sam4s")]
+        let (uart, id) = (pac::UART0::ptr() as usize, 8);
+        #[cfg(feature = "sam4n")]
+        let (uart, id) = (pac::UART1::ptr() as usize, 9);
+
+        let divisor = get_master_clock_frequency().0 / (16 * sam_xplained::CONSOLE_BAUD_RATE.0);
+        unsafe {
+            enable_clock(id);
+            write(
+                uart + UART_CR,
+                UART_CR_RSTRX | UART_CR_RSTTX | UART_CR_RSTSTA,
+            );
+            write(uart + UART_IDR, u32::MAX);
+            write(
+                uart + UART_MR,
+                UART_MR_PAR_NO | UART_MR_CHMODE_LOCAL_LOOPBACK,
+            );
+            write(uart + UART_BRGR, divisor);
+            write(uart + UART_CR, UART_CR_RXEN | UART_CR_TXEN);
+        }
+        LoopbackUart { uart }
+    }
+
+    fn status(&self) -> u32 {
+        unsafe { read(self.uart + UART_SR) }
+    }
+}
+
+impl embedded_io::ErrorType for LoopbackUart {
+    type Error = core::convert::Infallible;
+}
+
+impl embedded_io::Read for LoopbackUart {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        while self.status() & UART_SR_RXRDY == 0 {}
+        buf[0] = unsafe { read(self.uart + UART_RHR) } as u8;
+        Ok(1)
+    }
+}
+
+impl embedded_io::ReadReady for LoopbackUart {
+    fn read_ready(&mut self) -> Result<bool, Self::Error> {
+        Ok(self.status() & UART_SR_RXRDY != 0)
+    }
+}
+
+impl embedded_io::Write for LoopbackUart {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        let Some(&byte) = buf.first() else {
+            return Ok(0);
+        };
+        while self.status() & UART_SR_TXRDY == 0 {}
+        unsafe { write(self.uart + UART_THR, byte as u32) };
+        Ok(1)
+    }
+
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        while self.status() & UART_SR_TXEMPTY == 0 {}
+        Ok(())
+    }
+}
+
+/// Size of each of the SRAM chips on NCS1 and NCS3
+#[cfg(feature = "sam4e")]
+const SRAM_SIZE: usize = 512 * 1024;
+
+/// Sets up chip select `cs` of the static memory controller for an SRAM chip,
+/// with the timings of examples/external_memory.rs, and returns the memory.
+/// `Board::new` has already muxed the bus pins.
+///
+/// # Safety
+///
+/// The memory must not be in use elsewhere.
+#[cfg(feature = "sam4e")]
+pub unsafe fn external_sram(cs: usize) -> &'static mut [u8] {
+    const SMC_ID: u32 = 8;
+    const SMC_MODE_READ_MODE: u32 = 1 << 0;
+    const SMC_MODE_WRITE_MODE: u32 = 1 << 1;
+    let registers = pac::SMC::ptr() as usize + 0x10 * cs;
+    // Setup, pulse and cycle lengths, in master clock cycles
+    let setup = 1;
+    let pulse = 6;
+    let cycle = 7;
+
+    enable_clock(SMC_ID);
+    write(registers, setup | setup << 8 | setup << 16 | setup << 24);
+    write(
+        registers + 0x04,
+        pulse | pulse << 8 | pulse << 16 | pulse << 24,
+    );
+    write(registers + 0x08, cycle | cycle << 16);
+    write(registers + 0x0C, SMC_MODE_READ_MODE | SMC_MODE_WRITE_MODE);
+    let base = 0x6000_0000 + cs * 0x0100_0000;
+    core::slice::from_raw_parts_mut(base as *mut u8, SRAM_SIZE)
+}
+
+/// The DWT cycle counter, counting core clock cycles
+pub struct Cycles;
+
+impl Cycles {
+    pub fn enable(dcb: &mut pac::DCB, dwt: &mut pac::DWT) -> Self {
+        dcb.enable_trace();
+        dwt.enable_cycle_counter();
+        Cycles
+    }
+}
+
+impl Counter for Cycles {
+    fn count(&mut self) -> u32 {
+        pac::DWT::cycle_count()
+    }
+
+    fn frequency(&self) -> u32 {
+        get_master_clock_frequency().0
+    }
+}
+
+/// Rate of [`SlowClock`]
+const SLOW_CLOCK_HZ: u32 = 8192;
+
+/// The RTT, counting the slow clock
+pub struct SlowClock(RttMonotonic<SLOW_CLOCK_HZ>);
+
+impl SlowClock {
+    pub fn new(rtt: pac::RTT) -> Self {
+        let mut rtt = RttMonotonic::new(rtt);
+        unsafe { rtt.reset() };
+        SlowClock(rtt)
+    }
+}
+
+impl Counter for SlowClock {
+    fn count(&mut self) -> u32 {
+        self.0.now().ticks()
+    }
+
+    fn frequency(&self) -> u32 {
+        SLOW_CLOCK_HZ
+    }
+}
@@ -0,0 +1,101 @@
+//! Application logic run against the mock board:
+//! `cargo test --target <host> --no-default-features --features mock,kv --tests`
+#![cfg(feature = "mock")]
+
+use embedded_hal::{
+    delay::DelayNs,
+    digital::{InputPin, StatefulOutputPin},
+};
+use embedded_io::{Read, Write};
+use sam_xplained::{kv::KvStore, Board, XplainedBoard, STORAGE};
+
+/// Key the boot counter is stored under
+const BOOT_COUNT: u16 = 1;
+
+/// One pass of the loop in examples/generic.rs: blinks LED0 while SW0 is
+/// released and reports button presses on the console.
+fn step<B: XplainedBoard>(board: &mut B, pressed: &mut bool) {
+    let is_pressed = board.button().is_low().unwrap_or(false);
+    if is_pressed && !*pressed {
+        board.console().write_all(b"SW0 pressed\r\n").ok();
+    }
+    *pressed = is_pressed;
+
+    if !*pressed {
+        board.led().toggle().ok();
+    }
+    board.delay().delay_ms(250);
+}
+
+/// Echoes console lines back in upper case until the input runs out.
+fn shout<B: XplainedBoard>(board: &mut B) {
+    let mut byte = [0u8];
+    while let Ok(1) = board.console().read(&mut byte) {
+        let byte = byte[0].to_ascii_uppercase();
+        board.console().write_all(&[byte]).ok();
+        if byte == b'\r' {
+            board.console().write_all(b"\n").ok();
+        }
+    }
+}
+
+fn count_boot<B: XplainedBoard>(board: &mut B) -> u32 {
+    let mut store = KvStore::new(board.flash(), STORAGE).unwrap();
+    let mut buffer = [0u8; 4];
+    let count = match store.get(BOOT_COUNT, &mut buffer).unwrap() {
+        Some(4) => u32::from_le_bytes(buffer) + 1,
+        _ => 1,
+    };
+    store.set(BOOT_COUNT, &count.to_le_bytes()).unwrap();
+    count
+}
+
+#[test]
+fn blinks_until_pressed() {
+    let mut board = Board::new();
+    board
+        .sw0
+        .script([false, false, false, false, true, true, false]);
+    let mut pressed = false;
+    for _ in 0..7 {
+        step(&mut board, &mut pressed);
+    }
+
+    assert_eq!(board.console.take_output(), b"SW0 pressed\r\n");
+    assert_eq!(board.led0.flashes(), 3);
+    assert!(board.led0.is_on());
+    assert_eq!(board.sw0.reads(), 7);
+    assert_eq!(board.delay.elapsed().as_millis(), 7 * 250);
+}
+
+#[test]
+fn answers_on_the_console() {
+    let mut board = Board::new();
+    board.console.push_input(b"hello\rboard\r");
+    shout(&mut board);
+    assert_eq!(board.console.output(), b"HELLO\r\nBOARD\r\n");
+    assert_eq!(board.console.pending_input(), 0);
+}
+
+#[test]
+fn counts_boots_in_flash() {
+    let mut board = Board::new();
+    assert_eq!(count_boot(&mut board), 1);
+    // Formatting the blank region erased it once.
+    let erases = board.flash.erases();
+    assert_eq!(count_boot(&mut board), 2);
+    assert_eq!(board.flash.erases(), erases);
+    assert!(
+        board.flash.memory()[STORAGE.start as usize..STORAGE.end as usize]
+            .iter()
+            .any(|&byte| byte != 0xFF)
+    );
+}
+
+#[test]
+fn identifies_the_chip() {
+    let board = Board::new();
+    assert_eq!(board.chip_id().part_name(), Some("ATSAM4E16E"));
+    assert_eq!(board.chip_id().flash_size(), Some(1024 * 1024));
+    assert!(board.chip_id().verify().is_ok());
+}
@@ -0,0 +1,49 @@
+use std::env;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::PathBuf;
+
+fn main() {
+    // With the `signed` feature, only images signed with the key in the file
+    // named by SAM_XPLAINED_PUBLIC_KEY (as written by `sam_xplained_imgtool
+    // keygen`) are installed.
+    if env::var_os("CARGO_FEATURE_SIGNED").is_some() {
+        let path = env::var("SAM_XPLAINED_PUBLIC_KEY").unwrap_or_else(|_| {
+            panic!("the `signed` feature needs SAM_XPLAINED_PUBLIC_KEY set to a public key file")
+        });
+        let text = fs::read_to_string(&path)
+            .unwrap_or_else(|error| panic!("cannot read public key `{}`: {}", path, error));
+        let key = parse_key(&text)
+            .unwrap_or_else(|| panic!("`{}` is not a hexadecimal Ed25519 public key", path));
+
+        let out = PathBuf::from(env::var_os("OUT_DIR").unwrap());
+        File::create(out.join("key.rs"))
+            .unwrap()
+            .write_all(
+                format!(
+                    "/// Key images are signed with, from `{}`\n\
+                     const PUBLIC_KEY: [u8; 32] = {:?};\n",
+                    path.escape_default(),
+                    key
+                )
+                .as_bytes(),
+            )
+            .unwrap();
+        println!("cargo:rerun-if-changed={}", path);
+    }
+    println!("cargo:rerun-if-env-changed=SAM_XPLAINED_PUBLIC_KEY");
+    println!("cargo:rerun-if-changed=build.rs");
+}
+
+/// Parses the 64 hexadecimal digits of a public key file.
+fn parse_key(text: &str) -> Option<[u8; 32]> {
+    let text = text.trim();
+    if text.len() != 64 || !text.is_ascii() {
+        return None;
+    }
+    let mut key = [0u8; 32];
+    for (index, byte) in key.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&text[2 * index..2 * index + 2], 16).ok()?;
+    }
+    Some(key)
+}
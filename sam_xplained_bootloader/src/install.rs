@@ -0,0 +1,213 @@
+//! Installing received images as the application
+
+use core::fmt;
+
+use embedded_storage::nor_flash::NorFlash;
+#[cfg(feature = "signed")]
+use sam_xplained::image::{SignatureError, SignatureTrailer, APP_ADDRESS};
+use sam_xplained::{
+    flash::{self, InternalFlash},
+    image::{self, Crc32, ImageHeader, APP_CAPACITY, APP_OFFSET, APP_START},
+    ymodem::{FileInfo, FlashSink, Sink, SinkError},
+    STORAGE,
+};
+
+#[cfg(feature = "signed")]
+include!(concat!(env!("OUT_DIR"), "/key.rs"));
+
+/// Length of what follows the image in the file: the signature, if required
+#[cfg(feature = "signed")]
+const TRAILER_LEN: u32 = SignatureTrailer::LEN as u32;
+#[cfg(not(feature = "signed"))]
+const TRAILER_LEN: u32 = 0;
+
+#[derive(Debug)]
+pub enum Error {
+    /// The file doesn't start with an image header
+    Header,
+    /// The image is empty or doesn't fit in front of the key-value store
+    TooLarge,
+    /// The image is older than the installed application
+    Downgrade,
+    Flash(flash::Error),
+    /// The file ended before the image did
+    Incomplete,
+    /// The received image doesn't match the header's CRC
+    BadCrc,
+    /// The image isn't an application linked for [`APP_START`]
+    NotBootable,
+    /// The file has no signature behind the image
+    #[cfg(feature = "signed")]
+    Unsigned,
+    /// The image wasn't signed with [`PUBLIC_KEY`]
+    #[cfg(feature = "signed")]
+    Signature(SignatureError),
+}
+
+impl From<flash::Error> for Error {
+    fn from(error: flash::Error) -> Self {
+        Error::Flash(error)
+    }
+}
+
+impl From<SinkError<flash::Error>> for Error {
+    fn from(error: SinkError<flash::Error>) -> Self {
+        match error {
+            SinkError::Full => Error::TooLarge,
+            SinkError::Flash(error) => Error::Flash(error),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Header => f.write_str("file does not start with an image header"),
+            Error::TooLarge => write!(f, "image must be 1 to {} bytes", APP_CAPACITY - TRAILER_LEN),
+            Error::Downgrade => f.write_str("image is older than the installed application"),
+            Error::Flash(error) => write!(f, "flash error: {:?}", error),
+            Error::Incomplete => f.write_str("file ended before the image"),
+            Error::BadCrc => f.write_str("image CRC does not match its header"),
+            Error::NotBootable => f.write_str("image is not linked behind the bootloader"),
+            #[cfg(feature = "signed")]
+            Error::Unsigned => f.write_str("image is not signed"),
+            #[cfg(feature = "signed")]
+            Error::Signature(error) => write!(f, "bad signature: {}", error),
+        }
+    }
+}
+
+/// [`Sink`] for image files: the [`ImageHeader`] followed by the image
+///
+/// The image is programmed from [`APP_START`] as it arrives, which erases
+/// the installed application's header on the way.  The new header is only
+/// written by [`ApplicationSink::install`], once the whole image checked out.
+/// With the `signed` feature, the file continues with the
+/// [`SignatureTrailer`], which is programmed behind the image and checked
+/// against [`PUBLIC_KEY`] before the header is written.  Anything after that,
+/// such as XMODEM padding, is dropped.
+pub struct ApplicationSink<'a> {
+    flash: FlashSink<&'a mut InternalFlash>,
+    installed: Option<ImageHeader>,
+    header: [u8; ImageHeader::LEN],
+    header_len: usize,
+    image: Option<ImageHeader>,
+    received: u32,
+    crc: Crc32,
+}
+
+impl<'a> ApplicationSink<'a> {
+    pub fn new(flash: &'a mut InternalFlash, installed: Option<ImageHeader>) -> Self {
+        ApplicationSink {
+            flash: FlashSink::new(flash, APP_START..STORAGE.start),
+            installed,
+            header: [0; ImageHeader::LEN],
+            header_len: 0,
+            image: None,
+            received: 0,
+            crc: Crc32::new(),
+        }
+    }
+
+    /// Writes the header of the received image, making it the application.
+    pub fn install(self) -> Result<ImageHeader, Error> {
+        let header = self.image.ok_or(Error::Incomplete)?;
+        let flash = self.flash.into_inner();
+        flash.write(APP_OFFSET, &header.to_bytes())?;
+        if image::installed_application() != Some(header) {
+            return Err(Error::NotBootable);
+        }
+        Ok(header)
+    }
+
+    /// Checks the header once it is complete.
+    fn check_header(&mut self) -> Result<(), Error> {
+        let header = ImageHeader::from_bytes(&self.header).ok_or(Error::Header)?;
+        if header.length == 0 || header.length > APP_CAPACITY - TRAILER_LEN {
+            return Err(Error::TooLarge);
+        }
+        if self
+            .installed
+            .is_some_and(|installed| header.version < installed.version)
+        {
+            return Err(Error::Downgrade);
+        }
+        self.image = Some(header);
+        Ok(())
+    }
+}
+
+impl Sink for ApplicationSink<'_> {
+    type Error = Error;
+
+    fn begin(&mut self, file: &FileInfo) -> Result<(), Error> {
+        if let Some(size) = file.size {
+            if size <= ImageHeader::LEN as u32 {
+                return Err(Error::Header);
+            }
+            if size - ImageHeader::LEN as u32 > APP_CAPACITY {
+                return Err(Error::TooLarge);
+            }
+        }
+        self.header_len = 0;
+        self.image = None;
+        self.received = 0;
+        self.crc = Crc32::new();
+        let mut image = *file;
+        image.size = file.size.map(|size| size - ImageHeader::LEN as u32);
+        self.flash.begin(&image)?;
+        Ok(())
+    }
+
+    fn write(&mut self, mut data: &[u8]) -> Result<(), Error> {
+        if self.image.is_none() {
+            let count = data.len().min(ImageHeader::LEN - self.header_len);
+            self.header[self.header_len..self.header_len + count].copy_from_slice(&data[..count]);
+            self.header_len += count;
+            data = &data[count..];
+            if self.header_len < ImageHeader::LEN {
+                return Ok(());
+            }
+            self.check_header()?;
+        }
+
+        let length = self.image.map_or(0, |image| image.length);
+        let count = data
+            .len()
+            .min((length + TRAILER_LEN - self.received) as usize);
+        let image = count.min(length.saturating_sub(self.received) as usize);
+        self.crc.update(&data[..image]);
+        self.flash.write(&data[..count])?;
+        self.received += count as u32;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), Error> {
+        // Only a complete and intact image is left to install.
+        let header = self.image.take().ok_or(Error::Incomplete)?;
+        if self.received < header.length {
+            return Err(Error::Incomplete);
+        }
+        self.flash.finish()?;
+        if self.crc.finish() != header.crc {
+            return Err(Error::BadCrc);
+        }
+        #[cfg(feature = "signed")]
+        verify_signature(&header, self.received)?;
+        self.image = Some(header);
+        Ok(())
+    }
+}
+
+/// Checks the signature programmed behind the image against [`PUBLIC_KEY`],
+/// reading the image back from the flash.
+#[cfg(feature = "signed")]
+fn verify_signature(header: &ImageHeader, received: u32) -> Result<(), Error> {
+    if received < header.length + TRAILER_LEN {
+        return Err(Error::Unsigned);
+    }
+    let trailer = image::installed_signature(header).ok_or(Error::Unsigned)?;
+    let image =
+        unsafe { core::slice::from_raw_parts(APP_ADDRESS as *const u8, header.length as usize) };
+    image::verify(&PUBLIC_KEY, header, image, &trailer).map_err(Error::Signature)
+}
@@ -0,0 +1,106 @@
+//! Serial bootloader for the SAM4 Xplained Pro boards
+//!
+//! Sits at the start of the flash and boots the application installed behind
+//! it at [`APP_START`](image::APP_START) if its header (at
+//! [`APP_OFFSET`](image::APP_OFFSET)) checks out.  The
+//! bootloader doesn't touch the clocks on that path, so the application starts
+//! from the reset state, just with VTOR pointing at its vector table.
+//!
+//! Without a valid application, or when the application asked for it with
+//! `image::request_update`, it enters update mode and receives an image file
+//! (the [`ImageHeader`] followed by the image) over YMODEM or XMODEM-CRC on
+//! the EDBG virtual COM port (115200 8N1).  Once the image checks out it
+//! writes the header and resets into the new application.
+//!
+//! With the `signed` feature, images also need a signature by the key in
+//! `SAM_XPLAINED_PUBLIC_KEY` at build time.
+#![no_std]
+#![no_main]
+
+mod install;
+
+use cortex_m::peripheral::SCB;
+use cortex_m_rt::entry;
+use embedded_hal::delay::DelayNs;
+use embedded_io::Write;
+use panic_halt as _; // panic handler
+use sam_xplained::{
+    compat::Compat,
+    hal::{
+        pac::{CorePeripherals, Peripherals},
+        watchdog::*,
+    },
+    image::{self, ImageHeader, APP_ADDRESS},
+    ymodem::{self, Receiver, SerialTransport},
+    Board, XplainedBoard,
+};
+
+use install::ApplicationSink;
+
+#[entry]
+fn main() -> ! {
+    let core = CorePeripherals::take().unwrap();
+
+    if !image::take_update_request() && image::installed_application().is_some() {
+        unsafe {
+            core.SCB.vtor.write(APP_ADDRESS);
+            cortex_m::asm::bootload(APP_ADDRESS as *const u32)
+        }
+    }
+
+    let peripherals = Peripherals::take().unwrap();
+    let mut board = Board::new(core.SYST, peripherals);
+    // Transfers wait on the sender indefinitely.  The reset into the new
+    // application re-enables the watchdog.
+    board.watchdog.disable();
+
+    let installed = image::installed_application();
+    let console = board.console();
+    writeln!(
+        console,
+        "\r\nsam_xplained_bootloader: update mode on the {}\r",
+        Board::NAME
+    )
+    .ok();
+    writeln!(console, "bootloader {}\r", Board::firmware_info()).ok();
+    match installed {
+        Some(header) => writeln!(
+            console,
+            "installed application: version {}\r",
+            header.version
+        ),
+        None => writeln!(console, "no valid application installed\r"),
+    }
+    .ok();
+
+    loop {
+        writeln!(board.console(), "send the image file with YMODEM\r").ok();
+        match update(&mut board, installed) {
+            Ok(header) => {
+                let console = board.console();
+                writeln!(console, "installed version {}, resetting\r", header.version).ok();
+                console.flush().ok();
+                SCB::sys_reset();
+            }
+            Err(error) => {
+                // Let the sender give up before reporting.
+                board.delay().delay_ms(1_000);
+                writeln!(board.console(), "error: {}\r", error).ok();
+            }
+        }
+    }
+}
+
+type ConsoleError = <<Board as XplainedBoard>::Console as embedded_io::ErrorType>::Error;
+type UpdateError = ymodem::Error<ConsoleError, install::Error>;
+
+/// Receives an image file and installs it as the application.
+fn update(board: &mut Board, installed: Option<ImageHeader>) -> Result<ImageHeader, UpdateError> {
+    let transport = SerialTransport::new(
+        Compat::from_mut(&mut board.console),
+        Compat::from_mut(&mut board.delay),
+    );
+    let mut sink = ApplicationSink::new(&mut board.flash, installed);
+    Receiver::new(transport).receive(&mut sink)?;
+    sink.install().map_err(ymodem::Error::Sink)
+}
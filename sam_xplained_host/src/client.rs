@@ -0,0 +1,284 @@
+//! RPC client
+
+use std::convert::TryInto;
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::time::{Duration, Instant};
+
+use sam_xplained_image::FirmwareInfo;
+
+use crate::rpc::frame::write_frame;
+use crate::rpc::{
+    self, decode, encode, FrameDecoder, LedAction, RemoteError, Request, ResetCause, Response,
+    MAX_DATA, MAX_PAYLOAD,
+};
+
+/// Time [`Client`] waits for a response by default
+pub const TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    /// The request doesn't fit in a frame
+    TooLong,
+    /// The board couldn't carry out the request
+    Remote(RemoteError),
+    /// The board's response doesn't fit the request
+    Protocol,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(error) => error.fmt(f),
+            Error::TooLong => f.write_str("request too long"),
+            Error::Remote(error) => write!(f, "board error: {}", error),
+            Error::Protocol => f.write_str("unexpected response from the board"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Self {
+        Error::Io(error)
+    }
+}
+
+/// Sends requests to a board and receives its responses
+pub struct Client<P> {
+    port: P,
+    timeout: Duration,
+    next_id: u16,
+    decoder: FrameDecoder,
+    /// Bytes read from the port and not decoded yet
+    received: Vec<u8>,
+    /// Payload of the last response
+    response: Vec<u8>,
+}
+
+impl<P: Read + Write> Client<P> {
+    pub fn new(port: P) -> Self {
+        Client {
+            port,
+            timeout: TIMEOUT,
+            next_id: 0,
+            decoder: FrameDecoder::new(),
+            received: Vec::new(),
+            response: Vec::new(),
+        }
+    }
+
+    /// Sets the time to wait for a response.  Reads from the port may time
+    /// out sooner; they are retried until then.
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    pub fn port(&mut self) -> &mut P {
+        &mut self.port
+    }
+
+    pub fn into_inner(self) -> P {
+        self.port
+    }
+
+    /// Sends `request` and returns the response to it.  Frames that fail to
+    /// decode and responses to earlier requests are skipped, but count
+    /// towards the timeout.
+    pub fn call(&mut self, request: &Request<'_>) -> Result<Response<'_>, Error> {
+        let deadline = Instant::now() + self.timeout;
+        let id = self.next_id;
+        self.next_id = self.next_id.wrapping_add(1);
+
+        let mut buffer = [0u8; MAX_PAYLOAD];
+        let payload = encode(id, request, &mut buffer).ok_or(Error::TooLong)?;
+        let mut frame = Vec::new();
+        write_frame::<()>(payload, |block| {
+            frame.extend_from_slice(block);
+            Ok(())
+        })
+        .unwrap();
+        self.port.write_all(&frame)?;
+        self.port.flush()?;
+
+        loop {
+            self.receive_frame(deadline)?;
+            match decode::<Response<'_>>(&self.response) {
+                Ok((response_id, _)) | Err(rpc::DecodeError::Message(response_id))
+                    if response_id != id => {}
+                Ok((_, Response::Error(error))) => return Err(Error::Remote(error)),
+                Ok((_, _)) => break,
+                Err(_) => return Err(Error::Protocol),
+            }
+        }
+        Ok(decode::<Response<'_>>(&self.response).unwrap().1)
+    }
+
+    /// Reads from the port until a frame decodes, into `self.response`, or
+    /// `deadline` passes.
+    fn receive_frame(&mut self, deadline: Instant) -> Result<(), Error> {
+        loop {
+            if Instant::now() >= deadline {
+                return Err(io::Error::from(io::ErrorKind::TimedOut).into());
+            }
+            if self.received.is_empty() {
+                let mut buffer = [0u8; 256];
+                let len = match self.port.read(&mut buffer) {
+                    Ok(0) => return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into()),
+                    Ok(len) => len,
+                    Err(error) if is_timeout(&error) => continue,
+                    Err(error) => return Err(error.into()),
+                };
+                self.received.extend_from_slice(&buffer[..len]);
+            }
+            let mut used = 0;
+            let mut complete = false;
+            for &byte in &self.received {
+                used += 1;
+                if let Some(Ok(payload)) = self.decoder.feed(byte) {
+                    self.response.clear();
+                    self.response.extend_from_slice(payload);
+                    complete = true;
+                    break;
+                }
+            }
+            self.received.drain(..used);
+            if complete {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Sends [`Request::Ping`], returning the value echoed by the board.
+    pub fn ping(&mut self, value: u32) -> Result<u32, Error> {
+        match self.call(&Request::Ping(value))? {
+            Response::Pong(value) => Ok(value),
+            _ => Err(Error::Protocol),
+        }
+    }
+
+    /// CIDR and EXID registers of the board's microcontroller
+    pub fn chip_id(&mut self) -> Result<(u32, u32), Error> {
+        match self.call(&Request::ChipId)? {
+            Response::ChipId { cidr, exid } => Ok((cidr, exid)),
+            _ => Err(Error::Protocol),
+        }
+    }
+
+    pub fn reset_cause(&mut self) -> Result<ResetCause, Error> {
+        match self.call(&Request::ResetCause)? {
+            Response::ResetCause(cause) => Ok(cause),
+            _ => Err(Error::Protocol),
+        }
+    }
+
+    /// Build information of the firmware running on the board
+    pub fn firmware_info(&mut self) -> Result<FirmwareInfo, Error> {
+        match self.call(&Request::FirmwareInfo)? {
+            Response::FirmwareInfo(bytes) => bytes
+                .try_into()
+                .ok()
+                .and_then(FirmwareInfo::from_bytes)
+                .ok_or(Error::Protocol),
+            _ => Err(Error::Protocol),
+        }
+    }
+
+    pub fn unique_id(&mut self) -> Result<[u32; 4], Error> {
+        match self.call(&Request::UniqueId)? {
+            Response::UniqueId(id) => Ok(id),
+            _ => Err(Error::Protocol),
+        }
+    }
+
+    /// Switches LED0, returning whether it is on.
+    pub fn led(&mut self, action: LedAction) -> Result<bool, Error> {
+        match self.call(&Request::Led(action))? {
+            Response::Led { on } => Ok(on),
+            _ => Err(Error::Protocol),
+        }
+    }
+
+    /// Reads `len` bytes from the word aligned `address`, a word at a time.
+    pub fn read_memory(&mut self, address: u32, len: usize) -> Result<Vec<u8>, Error> {
+        let mut data = Vec::with_capacity(len);
+        while data.len() < len {
+            let chunk = (len - data.len()).min(MAX_DATA);
+            let request = Request::ReadMemory {
+                address: address.wrapping_add(data.len() as u32),
+                len: chunk as u16,
+            };
+            match self.call(&request)? {
+                Response::Memory(bytes) if bytes.len() == chunk => data.extend_from_slice(bytes),
+                _ => return Err(Error::Protocol),
+            }
+        }
+        Ok(data)
+    }
+
+    /// Writes `data`, whole words, to the word aligned `address`.
+    pub fn write_memory(&mut self, address: u32, data: &[u8]) -> Result<(), Error> {
+        for (index, chunk) in data.chunks(MAX_DATA).enumerate() {
+            let request = Request::WriteMemory {
+                address: address.wrapping_add((index * MAX_DATA) as u32),
+                data: chunk,
+            };
+            self.done(&request)?;
+        }
+        Ok(())
+    }
+
+    /// Reads `key` from the board's key-value store.
+    pub fn config_get(&mut self, key: u16) -> Result<Option<Vec<u8>>, Error> {
+        match self.call(&Request::ConfigGet(key))? {
+            Response::Config(value) => Ok(value.map(<[u8]>::to_vec)),
+            _ => Err(Error::Protocol),
+        }
+    }
+
+    /// Stores `value`, up to [`MAX_DATA`] bytes, under `key`.
+    pub fn config_set(&mut self, key: u16, value: &[u8]) -> Result<(), Error> {
+        if value.len() > MAX_DATA {
+            return Err(Error::TooLong);
+        }
+        self.done(&Request::ConfigSet { key, value })
+    }
+
+    pub fn config_remove(&mut self, key: u16) -> Result<(), Error> {
+        self.done(&Request::ConfigRemove(key))
+    }
+
+    /// Resets the board, which answers first.
+    pub fn reset(&mut self) -> Result<(), Error> {
+        self.done(&Request::Reset)
+    }
+
+    /// Resets the board into the bootloader's update mode.
+    pub fn update(&mut self) -> Result<(), Error> {
+        self.done(&Request::Update)
+    }
+
+    fn done(&mut self, request: &Request<'_>) -> Result<(), Error> {
+        match self.call(request)? {
+            Response::Done => Ok(()),
+            _ => Err(Error::Protocol),
+        }
+    }
+}
+
+/// Whether a read failed only for the lack of data within the port's timeout
+pub(crate) fn is_timeout(error: &io::Error) -> bool {
+    matches!(
+        error.kind(),
+        io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock
+    )
+}
@@ -0,0 +1,77 @@
+//! Finding the boards' serial ports
+//!
+//! The boards' console UART is the virtual COM port of the embedded debugger
+//! (EDBG), a USB CDC interface of the debugger's composite device.  Boards
+//! booted into the SAM-BA monitor show up as its USB CDC device instead.
+
+use std::io;
+use std::time::Duration;
+
+use serialport::{SerialPort, SerialPortInfo, SerialPortType};
+
+use crate::samba;
+
+/// USB vendor ID of the EDBG (Atmel)
+pub const EDBG_VID: u16 = 0x03EB;
+/// USB product ID of the EDBG
+pub const EDBG_PID: u16 = 0x2111;
+
+/// Baud rate of the boards' console
+pub const BAUD_RATE: u32 = 115_200;
+/// Read timeout the ports are opened with
+pub const READ_TIMEOUT: Duration = Duration::from_millis(50);
+
+/// USB serial port of a board
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BoardPort {
+    /// Name to open the port with, e.g. `/dev/ttyACM0` or `COM3`
+    pub path: String,
+    /// Serial number of the debugger, printed on the board's label
+    pub serial_number: Option<String>,
+    pub product: Option<String>,
+}
+
+/// Picks the EDBG virtual COM ports out of `ports`.
+pub fn edbg_ports(ports: &[SerialPortInfo]) -> Vec<BoardPort> {
+    usb_ports(ports, EDBG_VID, EDBG_PID)
+}
+
+/// Picks the ports of SAM-BA monitors out of `ports`.
+pub fn samba_ports(ports: &[SerialPortInfo]) -> Vec<BoardPort> {
+    usb_ports(ports, samba::USB_VID, samba::USB_PID)
+}
+
+fn usb_ports(ports: &[SerialPortInfo], vid: u16, pid: u16) -> Vec<BoardPort> {
+    ports
+        .iter()
+        .filter_map(|port| match &port.port_type {
+            SerialPortType::UsbPort(usb) if usb.vid == vid && usb.pid == pid => Some(BoardPort {
+                path: port.port_name.clone(),
+                serial_number: usb.serial_number.clone(),
+                product: usb.product.clone(),
+            }),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Lists the EDBG virtual COM ports of the connected boards.
+pub fn discover() -> io::Result<Vec<BoardPort>> {
+    let ports = serialport::available_ports().map_err(io::Error::from)?;
+    Ok(edbg_ports(&ports))
+}
+
+/// Lists the ports of the boards booted into the SAM-BA monitor.
+pub fn discover_samba() -> io::Result<Vec<BoardPort>> {
+    let ports = serialport::available_ports().map_err(io::Error::from)?;
+    Ok(samba_ports(&ports))
+}
+
+/// Opens a board's console at [`BAUD_RATE`], 8N1, reading with
+/// [`READ_TIMEOUT`].
+pub fn open(path: &str) -> io::Result<Box<dyn SerialPort>> {
+    serialport::new(path, BAUD_RATE)
+        .timeout(READ_TIMEOUT)
+        .open()
+        .map_err(io::Error::from)
+}
@@ -0,0 +1,28 @@
+//! Host side of the SAM4 XPlained Pro board crates
+//!
+//! * [`Client`] sends the requests of `sam_xplained_rpc` (re-exported as
+//!   [`rpc`]) over a serial port, or anything else that reads and writes
+//!   bytes, and waits for the board's responses
+//! * [`discover`] finds the boards' virtual COM ports
+//! * [`monitor`] decodes the boards' console output
+//! * [`upload`] sends firmware images to `sam_xplained_bootloader`
+//! * [`samba`] programs the flash through the SAM-BA monitor in ROM
+//! * [`test_runner`] collects the results of on-target tests
+//!
+//! ```ignore
+//! let mut client = Client::new(discover::open("/dev/ttyACM0")?);
+//! println!("{}", client.firmware_info()?);
+//! client.led(LedAction::Toggle)?;
+//! ```
+//!
+//! The `xplained` binary puts them on the command line.
+
+mod client;
+pub mod discover;
+pub mod monitor;
+pub mod samba;
+pub mod test_runner;
+pub mod upload;
+
+pub use client::{Client, Error, TIMEOUT};
+pub use sam_xplained_rpc as rpc;
@@ -0,0 +1,555 @@
+//! `xplained`: command line front end of `sam_xplained_host`
+
+use std::env;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+use std::process::{self, Child, Command, Stdio};
+use std::thread;
+use std::time::Duration;
+
+use sam_xplained_host::discover::{self, BoardPort};
+use sam_xplained_host::rpc::LedAction;
+use sam_xplained_host::samba::{self, Flasher, Link, Samba};
+use sam_xplained_host::test_runner::{self, TimedReader};
+use sam_xplained_host::{monitor, upload, Client};
+use serialport::SerialPort;
+
+const USAGE: &str = "\
+usage: xplained list
+       xplained monitor [--port <port>] [--timestamps]
+       xplained ping|chipid|reset-cause|info|uid [--port <port>]
+       xplained led on|off|toggle [--port <port>]
+       xplained config get|remove <key> [--port <port>]
+       xplained config set <key> <value> [--hex] [--port <port>]
+       xplained reset [--port <port>]
+       xplained upload [--port <port>] [--in-bootloader] <image>
+       xplained samba info [--port <port>] [--uart]
+       xplained samba write <binary> [--offset <offset>] [--boot] [--port <port>] [--uart]
+       xplained samba gpnvm <bit> [--set|--clear] [--port <port>] [--uart]
+       xplained test [--port <port>|--rtt <address>|--stdout] [--timeout <seconds>]
+                     [-- <command>...]
+
+list         lists the boards' EDBG virtual COM ports
+monitor      shows the console output, with RPC frames decoded; typed lines
+             are sent to the board
+ping ... uid check the connection, identify the microcontroller, show the
+             cause of the last reset, the firmware's build information and the
+             unique ID (RPC)
+led, config  switch LED0 and access the key-value store (RPC); --hex takes
+             and shows values as hexadecimal
+reset        resets the board (RPC)
+upload       has the firmware reset into the bootloader (RPC), unless it is
+             there already, and sends it an image file
+samba        identifies the chip, programs a binary into the flash (at the
+             offset given, 0 by default) and sets or shows a GPNVM bit,
+             through the SAM-BA monitor in ROM; --boot sets the GPNVM bit that
+             boots from the flash afterwards, --uart has the monitor on UART0
+test         runs a command that flashes and starts a test binary (e.g.
+             `cargo test --features sam4e --test hardware`), if given, and
+             collects the test report from the console, from OpenOCD's RTT
+             server at the address given, or from the command's output; fails
+             when a test does, or when nothing comes for the timeout (30 s by
+             default)
+
+The port is the only connected board's unless given with --port; for samba,
+the only SAM-BA monitor's USB port.";
+
+type Result<T> = std::result::Result<T, String>;
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let result = match args.first().map(String::as_str) {
+        Some("list") => list(&args[1..]),
+        Some("monitor") => run_monitor(&args[1..]),
+        Some("ping") => ping(&args[1..]),
+        Some("chipid") => chip_id(&args[1..]),
+        Some("reset-cause") => reset_cause(&args[1..]),
+        Some("info") => info(&args[1..]),
+        Some("uid") => unique_id(&args[1..]),
+        Some("led") => led(&args[1..]),
+        Some("config") => config(&args[1..]),
+        Some("reset") => reset(&args[1..]),
+        Some("upload") => run_upload(&args[1..]),
+        Some("samba") => run_samba(&args[1..]),
+        Some("test") => run_tests(&args[1..]),
+        _ => Err(USAGE.to_string()),
+    };
+    if let Err(error) = result {
+        eprintln!("{}", error);
+        process::exit(1);
+    }
+}
+
+/// Options, flags and positional arguments of a subcommand
+struct Args {
+    port: Option<String>,
+    values: Vec<(String, String)>,
+    flags: Vec<String>,
+    positional: Vec<String>,
+}
+
+impl Args {
+    /// Parses `args`, which may hold `--port` and the `options` given.  An
+    /// option ending in `=` (e.g. `"offset="`) takes a value.
+    fn parse(args: &[String], options: &[&str], positional: usize) -> Result<Self> {
+        let mut parsed = Args {
+            port: None,
+            values: Vec::new(),
+            flags: Vec::new(),
+            positional: Vec::new(),
+        };
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let name = match arg.strip_prefix("--") {
+                Some(name) => name,
+                None => {
+                    parsed.positional.push(arg.clone());
+                    continue;
+                }
+            };
+            if name == "port" {
+                let port = args.next().ok_or("`--port` needs a value")?;
+                parsed.port = Some(port.clone());
+            } else if options.contains(&format!("{}=", name).as_str()) {
+                let value = args
+                    .next()
+                    .ok_or_else(|| format!("`{}` needs a value", arg))?;
+                parsed.values.push((name.to_string(), value.clone()));
+            } else if options.contains(&name) {
+                parsed.flags.push(name.to_string());
+            } else {
+                return Err(format!("unknown option `{}`\n\n{}", arg, USAGE));
+            }
+        }
+        if parsed.positional.len() != positional {
+            return Err(USAGE.to_string());
+        }
+        Ok(parsed)
+    }
+
+    fn flag(&self, name: &str) -> bool {
+        self.flags.iter().any(|flag| flag == name)
+    }
+
+    /// Value of option `name`, given without the `=`
+    fn value(&self, name: &str) -> Option<&str> {
+        self.values
+            .iter()
+            .rev()
+            .find(|(option, _)| option == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Opens the given port, or the only board's.
+    fn open(&self) -> Result<Box<dyn SerialPort>> {
+        let path = match &self.port {
+            Some(path) => path.clone(),
+            None => only_board()?.path,
+        };
+        discover::open(&path).map_err(|error| format!("cannot open {}: {}", path, error))
+    }
+
+    fn client(&self) -> Result<Client<Box<dyn SerialPort>>> {
+        Ok(Client::new(self.open()?))
+    }
+
+    /// Connects to the SAM-BA monitor on the given port, or the only one.
+    fn samba(&self) -> Result<Samba<Box<dyn SerialPort>>> {
+        let path = match &self.port {
+            Some(path) => path.clone(),
+            None => {
+                let mut ports = discover::discover_samba()
+                    .map_err(|error| format!("cannot list the serial ports: {}", error))?;
+                match ports.len() {
+                    1 => ports.remove(0).path,
+                    0 => return Err("no SAM-BA monitor found, give its port with --port".into()),
+                    _ => return Err("several SAM-BA monitors found, pick one with --port".into()),
+                }
+            }
+        };
+        let port =
+            discover::open(&path).map_err(|error| format!("cannot open {}: {}", path, error))?;
+        let link = if self.flag("uart") {
+            Link::Serial
+        } else {
+            Link::Usb
+        };
+        Samba::connect(port, link)
+            .map_err(|error| format!("no SAM-BA monitor on {}: {}", path, error))
+    }
+}
+
+fn boards() -> Result<Vec<BoardPort>> {
+    discover::discover().map_err(|error| format!("cannot list the serial ports: {}", error))
+}
+
+fn only_board() -> Result<BoardPort> {
+    let mut boards = boards()?;
+    match boards.len() {
+        0 => Err("no board found, give its port with --port".to_string()),
+        1 => Ok(boards.remove(0)),
+        _ => Err(format!(
+            "{} boards found, pick one with --port (see `xplained list`)",
+            boards.len()
+        )),
+    }
+}
+
+fn parse_number(text: &str) -> Result<u32> {
+    let number = match text.strip_prefix("0x") {
+        Some(hex) => u32::from_str_radix(hex, 16),
+        None => text.parse(),
+    };
+    number.map_err(|_| format!("invalid number `{}`", text))
+}
+
+fn list(args: &[String]) -> Result<()> {
+    Args::parse(args, &[], 0)?;
+    for board in boards()? {
+        println!(
+            "{}  {}  {}",
+            board.path,
+            board.serial_number.as_deref().unwrap_or("-"),
+            board.product.as_deref().unwrap_or("")
+        );
+    }
+    Ok(())
+}
+
+fn run_monitor(args: &[String]) -> Result<()> {
+    let args = Args::parse(args, &["timestamps"], 0)?;
+    let mut port = args.open()?;
+    let mut input = port
+        .try_clone()
+        .map_err(|error| format!("cannot share the port: {}", error))?;
+    // Typed lines go to the board as they are, with a CR as the Enter key.
+    thread::spawn(move || {
+        let mut line = String::new();
+        while matches!(io::stdin().read_line(&mut line), Ok(len) if len > 0) {
+            let command = format!("{}\r", line.trim_end_matches(&['\r', '\n'][..]));
+            if input.write_all(command.as_bytes()).is_err() {
+                break;
+            }
+            line.clear();
+        }
+    });
+    monitor::run(&mut port, &mut io::stdout(), args.flag("timestamps"))
+        .map_err(|error| format!("monitor stopped: {}", error))
+}
+
+fn ping(args: &[String]) -> Result<()> {
+    let mut client = Args::parse(args, &[], 0)?.client()?;
+    let value = 0x5AA5_0FF0;
+    match client.ping(value).map_err(|error| error.to_string())? {
+        echoed if echoed == value => {
+            println!("OK");
+            Ok(())
+        }
+        echoed => Err(format!("ping answered with {:#010x}", echoed)),
+    }
+}
+
+fn chip_id(args: &[String]) -> Result<()> {
+    let mut client = Args::parse(args, &[], 0)?.client()?;
+    let (cidr, exid) = client.chip_id().map_err(|error| error.to_string())?;
+    println!(
+        "CIDR {:#010x}, EXID {:#010x}: {}, revision {}",
+        cidr,
+        exid,
+        architecture_name((cidr >> 20) as u8).unwrap_or("unknown architecture"),
+        cidr & 0x1F
+    );
+    Ok(())
+}
+
+/// Product series of a CHIPID architecture identifier
+fn architecture_name(architecture: u8) -> Option<&'static str> {
+    match architecture {
+        0x3C => Some("SAM4E"),
+        0x88 => Some("SAM4SxA"),
+        0x89 => Some("SAM4SxB"),
+        0x8A => Some("SAM4SxC"),
+        0x93 => Some("SAM4NxA"),
+        0x94 => Some("SAM4NxB"),
+        0x95 => Some("SAM4NxC"),
+        0x99 => Some("SAM4SDxB"),
+        0x9A => Some("SAM4SDxC"),
+        _ => None,
+    }
+}
+
+fn reset_cause(args: &[String]) -> Result<()> {
+    let mut client = Args::parse(args, &[], 0)?.client()?;
+    let cause = client.reset_cause().map_err(|error| error.to_string())?;
+    println!("{}", cause);
+    Ok(())
+}
+
+fn info(args: &[String]) -> Result<()> {
+    let mut client = Args::parse(args, &[], 0)?.client()?;
+    let info = client.firmware_info().map_err(|error| error.to_string())?;
+    println!("{}", info);
+    Ok(())
+}
+
+fn unique_id(args: &[String]) -> Result<()> {
+    let mut client = Args::parse(args, &[], 0)?.client()?;
+    let id = client.unique_id().map_err(|error| error.to_string())?;
+    println!("{:08X}{:08X}{:08X}{:08X}", id[0], id[1], id[2], id[3]);
+    Ok(())
+}
+
+fn led(args: &[String]) -> Result<()> {
+    let args = Args::parse(args, &[], 1)?;
+    let action = match args.positional[0].as_str() {
+        "on" => LedAction::On,
+        "off" => LedAction::Off,
+        "toggle" => LedAction::Toggle,
+        _ => return Err(USAGE.to_string()),
+    };
+    let on = args
+        .client()?
+        .led(action)
+        .map_err(|error| error.to_string())?;
+    println!("LED0 {}", if on { "on" } else { "off" });
+    Ok(())
+}
+
+fn config(args: &[String]) -> Result<()> {
+    let (action, args) = args.split_first().ok_or(USAGE)?;
+    let args = Args::parse(args, &["hex"], if action == "set" { 2 } else { 1 })?;
+    let key = parse_number(&args.positional[0])?;
+    if key >= 0xFFFF {
+        return Err(format!("key {} out of range", key));
+    }
+    let key = key as u16;
+    let hex = args.flag("hex");
+    let mut client = args.client()?;
+    match action.as_str() {
+        "get" => match client.config_get(key).map_err(|error| error.to_string())? {
+            Some(value) if hex => println!("{}", to_hex(&value)),
+            Some(value) => println!("{}", String::from_utf8_lossy(&value)),
+            None => return Err(format!("key {} is not set", key)),
+        },
+        "set" => {
+            let value = match hex {
+                true => from_hex(&args.positional[1])?,
+                false => args.positional[1].clone().into_bytes(),
+            };
+            client
+                .config_set(key, &value)
+                .map_err(|error| error.to_string())?;
+        }
+        "remove" => client
+            .config_remove(key)
+            .map_err(|error| error.to_string())?,
+        _ => return Err(USAGE.to_string()),
+    }
+    Ok(())
+}
+
+fn to_hex(data: &[u8]) -> String {
+    data.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn from_hex(text: &str) -> Result<Vec<u8>> {
+    let digits = text.as_bytes();
+    if digits.len() & 1 != 0 {
+        return Err(format!("odd number of hexadecimal digits in `{}`", text));
+    }
+    digits
+        .chunks(2)
+        .map(|pair| {
+            std::str::from_utf8(pair)
+                .ok()
+                .and_then(|pair| u8::from_str_radix(pair, 16).ok())
+                .ok_or_else(|| format!("invalid hexadecimal value `{}`", text))
+        })
+        .collect()
+}
+
+fn reset(args: &[String]) -> Result<()> {
+    let mut client = Args::parse(args, &[], 0)?.client()?;
+    client.reset().map_err(|error| error.to_string())
+}
+
+fn run_upload(args: &[String]) -> Result<()> {
+    let args = Args::parse(args, &["in-bootloader"], 1)?;
+    let path = &args.positional[0];
+    let file = fs::read(path).map_err(|error| format!("cannot read {}: {}", path, error))?;
+    let mut port = args.open()?;
+
+    if !args.flag("in-bootloader") {
+        let mut client = Client::new(port);
+        if let Err(error) = client.update() {
+            return Err(format!(
+                "the firmware didn't reset into the bootloader ({}); \
+                 with the bootloader in update mode already, use --in-bootloader",
+                error
+            ));
+        }
+        port = client.into_inner();
+    }
+
+    eprintln!("waiting for the bootloader");
+    let total = file.len();
+    let version = upload::upload(&mut port, &file, &mut |sent| {
+        eprint!("\rsent {} of {} bytes", sent, total);
+    })
+    .map_err(|error| format!("\nupload failed: {}", error))?;
+    eprintln!();
+    println!("installed version {}", version);
+    Ok(())
+}
+
+fn run_samba(args: &[String]) -> Result<()> {
+    let (action, args) = args.split_first().ok_or(USAGE)?;
+    match action.as_str() {
+        "info" => {
+            let args = Args::parse(args, &["uart"], 0)?;
+            let mut samba = args.samba()?;
+            let version = samba.version().map_err(|error| error.to_string())?;
+            let mut flasher = Flasher::new(samba).map_err(|error| error.to_string())?;
+            let boot_mode = flasher
+                .gpnvm(samba::GPNVM_BOOT_MODE)
+                .map_err(|error| error.to_string())?;
+            println!("monitor: {}", version);
+            println!(
+                "chip: CIDR {:#010x}, {}, {} KiB flash",
+                flasher.cidr(),
+                architecture_name((flasher.cidr() >> 20) as u8).unwrap_or("unknown architecture"),
+                flasher.flash_size() / 1024
+            );
+            println!("boots from: {}", if boot_mode { "flash" } else { "SAM-BA" });
+        }
+        "write" => {
+            let args = Args::parse(args, &["uart", "offset=", "boot"], 1)?;
+            let path = &args.positional[0];
+            let binary =
+                fs::read(path).map_err(|error| format!("cannot read {}: {}", path, error))?;
+            let offset = match args.value("offset") {
+                Some(offset) => parse_number(offset)?,
+                None => 0,
+            };
+            let mut flasher = Flasher::new(args.samba()?).map_err(|error| error.to_string())?;
+            let total = binary.len();
+            flasher
+                .write(offset, &binary, &mut |written| {
+                    eprint!("\rprogrammed {} of {} bytes", written, total);
+                })
+                .map_err(|error| format!("\nprogramming failed: {}", error))?;
+            eprintln!();
+            if args.flag("boot") {
+                flasher
+                    .set_gpnvm(samba::GPNVM_BOOT_MODE, true)
+                    .map_err(|error| error.to_string())?;
+                eprintln!("the board boots from the flash from the next reset on");
+            }
+        }
+        "gpnvm" => {
+            let args = Args::parse(args, &["uart", "set", "clear"], 1)?;
+            let bit = parse_number(&args.positional[0])?;
+            if bit > 2 {
+                return Err(format!("no GPNVM bit {}", bit));
+            }
+            let bit = bit as u8;
+            let mut flasher = Flasher::new(args.samba()?).map_err(|error| error.to_string())?;
+            if args.flag("set") || args.flag("clear") {
+                flasher
+                    .set_gpnvm(bit, args.flag("set"))
+                    .map_err(|error| error.to_string())?;
+            }
+            let value = flasher.gpnvm(bit).map_err(|error| error.to_string())?;
+            println!("GPNVM bit {}: {}", bit, value as u8);
+        }
+        _ => return Err(USAGE.to_string()),
+    }
+    Ok(())
+}
+
+fn run_tests(args: &[String]) -> Result<()> {
+    let (args, command) = match args.iter().position(|arg| arg == "--") {
+        Some(split) => (&args[..split], &args[split + 1..]),
+        None => (args, &[][..]),
+    };
+    let args = Args::parse(args, &["rtt=", "timeout=", "stdout"], 0)?;
+    let timeout = match args.value("timeout") {
+        Some(seconds) => Duration::from_secs(parse_number(seconds)? as u64),
+        None => test_runner::TIMEOUT,
+    };
+    let sources = args.port.is_some() as usize
+        + args.value("rtt").is_some() as usize
+        + args.flag("stdout") as usize;
+    if sources > 1 {
+        return Err("give only one of --port, --rtt and --stdout".to_string());
+    }
+
+    let mut child = None;
+    let mut source: Box<dyn Read> = if args.flag("stdout") {
+        let mut spawned = spawn(command, Stdio::piped())?;
+        let output = spawned.stdout.take().expect("piped");
+        child = Some(spawned);
+        Box::new(TimedReader::new(output, Duration::from_millis(100)))
+    } else if let Some(address) = args.value("rtt") {
+        let stream = TcpStream::connect(address)
+            .map_err(|error| format!("cannot connect to {}: {}", address, error))?;
+        stream
+            .set_read_timeout(Some(Duration::from_millis(100)))
+            .map_err(|error| error.to_string())?;
+        Box::new(stream)
+    } else {
+        Box::new(args.open()?)
+    };
+    // Listening already, so that nothing of the report is missed
+    if child.is_none() && !command.is_empty() {
+        child = Some(spawn(command, Stdio::from(io::stderr()))?);
+    }
+
+    let result = test_runner::run(&mut source, &mut io::stdout(), timeout, || {
+        match child.as_mut().map(Child::try_wait).transpose()? {
+            Some(Some(status)) if !status.success() => Err(io::Error::other(format!(
+                "`{}` failed ({})",
+                command.join(" "),
+                status
+            ))),
+            _ => Ok(()),
+        }
+    });
+    if let Some(mut child) = child {
+        // A report cut short may be down to the command failing.
+        if matches!(&result, Ok(report) if !report.done) {
+            for _ in 0..10 {
+                match child.try_wait() {
+                    Ok(Some(status)) if !status.success() => {
+                        return Err(format!("`{}` failed ({})", command.join(" "), status))
+                    }
+                    Ok(None) => thread::sleep(Duration::from_millis(100)),
+                    _ => break,
+                }
+            }
+        }
+        // Debugger runners keep going after the tests.
+        child.kill().ok();
+        child.wait().ok();
+    }
+    let report = result.map_err(|error| format!("test run stopped: {}", error))?;
+    println!("\n{}", report);
+    if report.success() {
+        Ok(())
+    } else {
+        Err("tests failed".to_string())
+    }
+}
+
+/// Starts `command`, with its standard output going to `stdout`.
+fn spawn(command: &[String], stdout: Stdio) -> Result<Child> {
+    let (program, args) = command
+        .split_first()
+        .ok_or("--stdout needs a command to run")?;
+    Command::new(program)
+        .args(args)
+        .stdout(stdout)
+        .spawn()
+        .map_err(|error| format!("cannot run {}: {}", program, error))
+}
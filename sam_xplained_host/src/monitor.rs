@@ -0,0 +1,147 @@
+//! Serial monitor
+//!
+//! [`LogDecoder`] splits what a board prints on its console into lines of
+//! text and RPC frames, which start with a zero byte and are shown decoded.
+
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::time::Instant;
+
+use crate::client::is_timeout;
+use crate::rpc::{decode, FrameDecoder, FrameError, Request, Response};
+
+/// Output of the board
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Record {
+    /// Text without the line ending, with control characters escaped
+    Line(String),
+    /// RPC message and its request ID
+    Frame { id: u16, message: String },
+    /// RPC frame that failed to decode
+    BadFrame(FrameError),
+}
+
+impl fmt::Display for Record {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Record::Line(line) => f.write_str(line),
+            Record::Frame { id, message } => write!(f, "[rpc {}] {}", id, message),
+            Record::BadFrame(error) => write!(f, "[rpc] dropped frame: {:?}", error),
+        }
+    }
+}
+
+/// Decodes the console output of a board
+#[derive(Default)]
+pub struct LogDecoder {
+    line: Vec<u8>,
+    frames: FrameDecoder,
+    in_frame: bool,
+}
+
+impl LogDecoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Takes the next bytes received, returning the records they complete.
+    pub fn feed(&mut self, data: &[u8]) -> Vec<Record> {
+        let mut records = Vec::new();
+        for &byte in data {
+            if self.in_frame {
+                match self.frames.feed(byte) {
+                    Some(Ok(payload)) => records.push(message(payload)),
+                    Some(Err(error)) => records.push(Record::BadFrame(error)),
+                    None => continue,
+                }
+                self.in_frame = false;
+                continue;
+            }
+            match byte {
+                // The delimiter starting a frame
+                0 => {
+                    records.extend(self.flush());
+                    self.frames.reset();
+                    self.in_frame = true;
+                }
+                b'\n' => records.push(self.take_line()),
+                b'\r' => {}
+                _ => self.line.push(byte),
+            }
+        }
+        records
+    }
+
+    /// Ends a partial line, e.g. a prompt, once the board has gone quiet.
+    pub fn flush(&mut self) -> Option<Record> {
+        if self.line.is_empty() {
+            None
+        } else {
+            Some(self.take_line())
+        }
+    }
+
+    fn take_line(&mut self) -> Record {
+        let mut line = String::new();
+        for c in String::from_utf8_lossy(&self.line).chars() {
+            if c.is_control() && c != '\t' {
+                line.extend(c.escape_default());
+            } else {
+                line.push(c);
+            }
+        }
+        self.line.clear();
+        Record::Line(line)
+    }
+}
+
+fn message(payload: &[u8]) -> Record {
+    // Boards send responses; requests show up when a port loops back.
+    match decode::<Response<'_>>(payload) {
+        Ok((id, response)) => Record::Frame {
+            id,
+            message: format!("{:?}", response),
+        },
+        Err(_) => match decode::<Request<'_>>(payload) {
+            Ok((id, request)) => Record::Frame {
+                id,
+                message: format!("{:?}", request),
+            },
+            Err(_) => Record::BadFrame(FrameError::Malformed),
+        },
+    }
+}
+
+/// Prints the output of the board on `port` to `out`, optionally with the
+/// time since the start, until the port is closed or fails.
+pub fn run<P: Read, W: Write>(port: &mut P, out: &mut W, timestamps: bool) -> io::Result<()> {
+    let start = Instant::now();
+    let mut decoder = LogDecoder::new();
+    let mut buffer = [0u8; 256];
+    let mut closed = false;
+    while !closed {
+        let records = match port.read(&mut buffer) {
+            Ok(0) => {
+                closed = true;
+                decoder.flush().into_iter().collect()
+            }
+            Ok(len) => decoder.feed(&buffer[..len]),
+            Err(error) if is_timeout(&error) => decoder.flush().into_iter().collect(),
+            Err(error) => return Err(error),
+        };
+        for record in records {
+            if timestamps {
+                let elapsed = start.elapsed();
+                write!(
+                    out,
+                    "[{:4}.{:03}] ",
+                    elapsed.as_secs(),
+                    elapsed.subsec_millis()
+                )?;
+            }
+            writeln!(out, "{}", record)?;
+        }
+        out.flush()?;
+    }
+    Ok(())
+}
@@ -0,0 +1,485 @@
+//! SAM-BA boot monitor
+//!
+//! With the GPNVM boot mode bit clear, the microcontroller boots into the
+//! SAM-BA monitor in ROM, which takes commands on its USB device port (USB
+//! CDC, [`USB_VID`]:[`USB_PID`]) or UART0.  [`Samba`] speaks the monitor's
+//! binary protocol: word and block access to the memory, and calls into code
+//! loaded into SRAM.  [`Flasher`] programs the internal flash with it, through
+//! the enhanced embedded flash controller (EEFC) and a word copy applet, so a
+//! board can be programmed without a debugger:
+//!
+//! ```ignore
+//! let mut flasher = Flasher::new(Samba::connect(discover::open(path)?, Link::Usb)?)?;
+//! flasher.write(0, &binary, &mut |_| {})?;
+//! flasher.set_gpnvm(GPNVM_BOOT_MODE, true)?;
+//! ```
+//!
+//! On dual bank parts (SAM4SD), each bank has its own flash controller,
+//! which takes the page numbers within its bank.
+
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::ops::Range;
+use std::time::{Duration, Instant};
+
+use crate::client::is_timeout;
+
+/// USB vendor ID of the SAM-BA monitor (Atmel)
+pub const USB_VID: u16 = 0x03EB;
+/// USB product ID of the SAM-BA monitor
+pub const USB_PID: u16 = 0x6124;
+
+/// Time the monitor gets to answer, or the flash controller to finish
+pub const TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Address of the CHIPID_CIDR register
+pub const CHIPID_CIDR: u32 = 0x400E_0740;
+/// Start of the flash array
+pub const FLASH_BASE: u32 = 0x0040_0000;
+/// Flash page size, the unit of programming
+pub const PAGE_SIZE: u32 = 512;
+/// Unit of the erases, eight pages
+pub const ERASE_SIZE: u32 = 8 * PAGE_SIZE;
+/// Size of the regions the lock bits protect
+pub const LOCK_REGION_SIZE: u32 = 16 * PAGE_SIZE;
+
+/// GPNVM bit that disables the debug port and SAM-BA access to the flash
+pub const GPNVM_SECURITY: u8 = 0;
+/// GPNVM bit that boots from the flash when set, from SAM-BA when clear
+pub const GPNVM_BOOT_MODE: u8 = 1;
+
+// EEFC registers and commands.  EEFC0 controls bank 1 as well on single bank
+// parts; dual bank parts have EEFC1 for bank 1.
+const EEFC0: u32 = 0x400E_0A00;
+const EEFC1: u32 = 0x400E_0C00;
+const EEFC_FMR: u32 = 0x00;
+const EEFC_FCR: u32 = 0x04;
+const EEFC_FSR: u32 = 0x08;
+const EEFC_FRR: u32 = 0x0C;
+const FKEY: u32 = 0x5A << 24;
+const WP: u32 = 0x01;
+const EPA: u32 = 0x07;
+const CLB: u32 = 0x09;
+const SGPB: u32 = 0x0B;
+const CGPB: u32 = 0x0C;
+const GGPB: u32 = 0x0D;
+/// EPA argument selecting an erase of eight pages
+const EPA_8_PAGES: u32 = 1;
+const FRDY: u32 = 1 << 0;
+const FCMDE: u32 = 1 << 1;
+const FLOCKE: u32 = 1 << 2;
+const FLERR: u32 = 1 << 3;
+/// Six wait states, safe at any clock the monitor runs at
+const FMR_FWS_6: u32 = 6 << 8;
+
+/// Where the applet is loaded, above the SRAM the monitor uses
+pub const APPLET_ADDRESS: u32 = 0x2000_1000;
+/// Where pages are staged for the applet
+const BUFFER_ADDRESS: u32 = 0x2000_1400;
+
+/// Word copy applet.  Like a vector table, it starts with the stack pointer
+/// and entry point, which the monitor's `G` command loads before calling the
+/// entry point.  The code copies `WORDS` words from `SRC` to `DST` and
+/// returns to the monitor:
+///
+/// ```text
+///     ldr  r0, DST
+///     ldr  r1, SRC
+///     ldr  r2, WORDS
+/// 1:  ldr  r3, [r1]
+///     adds r1, #4
+///     str  r3, [r0]
+///     adds r0, #4
+///     subs r2, #1
+///     bne  1b
+///     bx   lr
+/// ```
+#[rustfmt::skip]
+pub const WORD_COPY_APPLET: [u8; 28] = [
+    0x00, 0x14, 0x00, 0x20, // stack pointer: BUFFER_ADDRESS
+    0x09, 0x10, 0x00, 0x20, // entry point: APPLET_ADDRESS + 8, Thumb
+    0x04, 0x48, 0x05, 0x49, 0x05, 0x4A, 0x0B, 0x68,
+    0x04, 0x31, 0x03, 0x60, 0x04, 0x30, 0x01, 0x3A,
+    0xF9, 0xD1, 0x70, 0x47,
+];
+// Parameters following the code
+const APPLET_DST: u32 = APPLET_ADDRESS + 28;
+const APPLET_SRC: u32 = APPLET_ADDRESS + 32;
+const APPLET_WORDS: u32 = APPLET_ADDRESS + 36;
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    /// The monitor's answer doesn't fit the command
+    Protocol,
+    /// The CHIPID doesn't give the size of the flash
+    UnknownChip(u32),
+    /// The range extends past the end of the flash or isn't aligned
+    OutOfRange,
+    /// The flash controller failed the command, with this EEFC_FSR
+    Flash(u32),
+    /// The flash doesn't read back what was written, from this address
+    Verify(u32),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(error) => error.fmt(f),
+            Error::Protocol => f.write_str("unexpected answer from the SAM-BA monitor"),
+            Error::UnknownChip(cidr) => write!(f, "unknown flash size (CIDR {:#010x})", cidr),
+            Error::OutOfRange => f.write_str("range outside of the flash"),
+            Error::Flash(status) if status & FLOCKE != 0 => f.write_str("flash region locked"),
+            Error::Flash(status) => write!(f, "flash controller error (FSR {:#x})", status),
+            Error::Verify(address) => write!(f, "verification failed at {:#010x}", address),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Self {
+        Error::Io(error)
+    }
+}
+
+/// Interface the monitor is reached through
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Link {
+    /// The USB device port, which takes blocks of data as they are
+    Usb,
+    /// UART0, which takes blocks over XMODEM; blocks go word by word instead
+    Serial,
+}
+
+/// Connection to the SAM-BA monitor
+pub struct Samba<P> {
+    port: P,
+    link: Link,
+    timeout: Duration,
+}
+
+impl<P: Read + Write> Samba<P> {
+    /// Switches the monitor to binary mode.
+    pub fn connect(port: P, link: Link) -> Result<Self, Error> {
+        let mut samba = Samba {
+            port,
+            link,
+            timeout: TIMEOUT,
+        };
+        // Drop a prompt from terminal mode.
+        samba.drain()?;
+        samba.command("N#")?;
+        let mut answer = [0u8; 2];
+        samba.read_exact(&mut answer)?;
+        if &answer != b"\n\r" {
+            return Err(Error::Protocol);
+        }
+        Ok(samba)
+    }
+
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    pub fn link(&self) -> Link {
+        self.link
+    }
+
+    pub fn into_inner(self) -> P {
+        self.port
+    }
+
+    /// Version string of the monitor
+    pub fn version(&mut self) -> Result<String, Error> {
+        self.command("V#")?;
+        let mut version = Vec::new();
+        while !version.ends_with(b"\n\r") {
+            let mut byte = [0u8];
+            self.read_exact(&mut byte)?;
+            version.push(byte[0]);
+        }
+        Ok(String::from_utf8_lossy(&version).trim().to_string())
+    }
+
+    pub fn read_word(&mut self, address: u32) -> Result<u32, Error> {
+        self.command(&format!("w{:08X},#", address))?;
+        let mut value = [0u8; 4];
+        self.read_exact(&mut value)?;
+        Ok(u32::from_le_bytes(value))
+    }
+
+    pub fn write_word(&mut self, address: u32, value: u32) -> Result<(), Error> {
+        self.command(&format!("W{:08X},{:08X}#", address, value))
+    }
+
+    /// Reads `len` bytes; both `address` and `len` must be word aligned.
+    pub fn read(&mut self, address: u32, len: usize) -> Result<Vec<u8>, Error> {
+        check_aligned(address, len)?;
+        let mut data = vec![0u8; len];
+        match self.link {
+            Link::Usb => {
+                self.command(&format!("R{:08X},{:08X}#", address, len))?;
+                self.read_exact(&mut data)?;
+            }
+            Link::Serial => {
+                for (index, word) in data.chunks_exact_mut(4).enumerate() {
+                    let value = self.read_word(address + index as u32 * 4)?;
+                    word.copy_from_slice(&value.to_le_bytes());
+                }
+            }
+        }
+        Ok(data)
+    }
+
+    /// Writes `data`; both `address` and its length must be word aligned.
+    pub fn write(&mut self, address: u32, data: &[u8]) -> Result<(), Error> {
+        check_aligned(address, data.len())?;
+        match self.link {
+            Link::Usb => {
+                self.command(&format!("S{:08X},{:08X}#", address, data.len()))?;
+                self.port.write_all(data)?;
+                self.port.flush()?;
+            }
+            Link::Serial => {
+                for (index, word) in data.chunks_exact(4).enumerate() {
+                    let value = u32::from_le_bytes([word[0], word[1], word[2], word[3]]);
+                    self.write_word(address + index as u32 * 4, value)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Calls code in SRAM through a vector at `address`: its stack pointer,
+    /// then its entry point.  The monitor answers again once it returns.
+    pub fn go(&mut self, address: u32) -> Result<(), Error> {
+        self.command(&format!("G{:08X}#", address))
+    }
+
+    fn command(&mut self, command: &str) -> Result<(), Error> {
+        self.port.write_all(command.as_bytes())?;
+        self.port.flush()?;
+        Ok(())
+    }
+
+    fn read_exact(&mut self, buffer: &mut [u8]) -> Result<(), Error> {
+        let start = Instant::now();
+        let mut len = 0;
+        while len < buffer.len() {
+            match self.port.read(&mut buffer[len..]) {
+                Ok(0) => return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into()),
+                Ok(read) => len += read,
+                Err(error) if is_timeout(&error) && start.elapsed() < self.timeout => {}
+                Err(error) => return Err(error.into()),
+            }
+        }
+        Ok(())
+    }
+
+    /// Discards what the monitor sent before the first command.
+    fn drain(&mut self) -> Result<(), Error> {
+        let mut buffer = [0u8; 64];
+        loop {
+            match self.port.read(&mut buffer) {
+                Ok(0) => return Ok(()),
+                Ok(_) => {}
+                Err(error) if is_timeout(&error) => return Ok(()),
+                Err(error) => return Err(error.into()),
+            }
+        }
+    }
+}
+
+fn check_aligned(address: u32, len: usize) -> Result<(), Error> {
+    if (address as usize | len) & 3 != 0 {
+        return Err(Error::OutOfRange);
+    }
+    Ok(())
+}
+
+/// Programs the internal flash through the monitor
+pub struct Flasher<P> {
+    samba: Samba<P>,
+    cidr: u32,
+    flash_size: u32,
+    /// Size of bank 0, the whole flash on single bank parts
+    bank_size: u32,
+}
+
+impl<P: Read + Write> Flasher<P> {
+    /// Identifies the chip and loads the applet.
+    pub fn new(mut samba: Samba<P>) -> Result<Self, Error> {
+        let cidr = samba.read_word(CHIPID_CIDR)?;
+        let flash_size = flash_size(cidr).ok_or(Error::UnknownChip(cidr))?;
+        let bank_size = if is_dual_bank(cidr) {
+            samba.write_word(EEFC1 + EEFC_FMR, FMR_FWS_6)?;
+            flash_size / 2
+        } else {
+            flash_size
+        };
+        samba.write_word(EEFC0 + EEFC_FMR, FMR_FWS_6)?;
+        samba.write(APPLET_ADDRESS, &WORD_COPY_APPLET)?;
+        Ok(Flasher {
+            samba,
+            cidr,
+            flash_size,
+            bank_size,
+        })
+    }
+
+    /// CHIPID_CIDR of the chip
+    pub fn cidr(&self) -> u32 {
+        self.cidr
+    }
+
+    /// Size of the flash in bytes, both banks together on dual bank parts
+    pub fn flash_size(&self) -> u32 {
+        self.flash_size
+    }
+
+    pub fn samba(&mut self) -> &mut Samba<P> {
+        &mut self.samba
+    }
+
+    pub fn into_inner(self) -> Samba<P> {
+        self.samba
+    }
+
+    /// Unlocks and erases the erase blocks overlapping `range` (flash
+    /// offsets).
+    pub fn erase(&mut self, range: Range<u32>) -> Result<(), Error> {
+        if range.end > self.flash_size || range.start > range.end {
+            return Err(Error::OutOfRange);
+        }
+        let start = range.start - range.start % LOCK_REGION_SIZE;
+        for region in (start..range.end).step_by(LOCK_REGION_SIZE as usize) {
+            self.page_command(CLB, region, 0)?;
+        }
+        let start = range.start - range.start % ERASE_SIZE;
+        for block in (start..range.end).step_by(ERASE_SIZE as usize) {
+            self.page_command(EPA, block, EPA_8_PAGES)?;
+        }
+        Ok(())
+    }
+
+    /// Erases the flash from `offset` on and programs `data` there, then
+    /// reads it back.  `offset` must be page aligned.  `progress` is called
+    /// with the bytes programmed so far.
+    pub fn write(
+        &mut self,
+        offset: u32,
+        data: &[u8],
+        progress: &mut dyn FnMut(usize),
+    ) -> Result<(), Error> {
+        let end = offset
+            .checked_add(data.len() as u32)
+            .filter(|&end| end <= self.flash_size && offset & (PAGE_SIZE - 1) == 0)
+            .ok_or(Error::OutOfRange)?;
+        self.erase(offset..end)?;
+
+        let mut written = 0;
+        for chunk in data.chunks(PAGE_SIZE as usize) {
+            let mut page = [0xFF; PAGE_SIZE as usize];
+            page[..chunk.len()].copy_from_slice(chunk);
+            let address = FLASH_BASE + offset + written as u32;
+            // The applet fills the page buffer, which the flash array's
+            // addresses map to, from the staged page.
+            self.samba.write(BUFFER_ADDRESS, &page)?;
+            self.samba.write_word(APPLET_DST, address)?;
+            self.samba.write_word(APPLET_SRC, BUFFER_ADDRESS)?;
+            self.samba.write_word(APPLET_WORDS, PAGE_SIZE / 4)?;
+            self.samba.go(APPLET_ADDRESS)?;
+            self.page_command(WP, address - FLASH_BASE, 0)?;
+            written += chunk.len();
+            progress(written);
+        }
+
+        self.verify(offset, data)
+    }
+
+    /// Checks that the flash at `offset` holds `data`.
+    pub fn verify(&mut self, offset: u32, data: &[u8]) -> Result<(), Error> {
+        let len = (data.len() + 3) & !3;
+        let flash = self.samba.read(FLASH_BASE + offset, len)?;
+        match flash.iter().zip(data).position(|(a, b)| a != b) {
+            Some(index) => Err(Error::Verify(FLASH_BASE + offset + index as u32)),
+            None => Ok(()),
+        }
+    }
+
+    /// Reads a general-purpose NVM bit.
+    pub fn gpnvm(&mut self, bit: u8) -> Result<bool, Error> {
+        self.command(EEFC0, GGPB, 0)?;
+        Ok(self.samba.read_word(EEFC0 + EEFC_FRR)? & 1 << bit != 0)
+    }
+
+    /// Sets or clears a general-purpose NVM bit, e.g. [`GPNVM_BOOT_MODE`] to
+    /// boot the programmed firmware from the next reset on.
+    pub fn set_gpnvm(&mut self, bit: u8, value: bool) -> Result<(), Error> {
+        self.command(EEFC0, if value { SGPB } else { CGPB }, bit as u32)
+    }
+
+    /// Runs an EEFC command on the page at flash offset `offset`, through
+    /// the controller of its bank.  `flags` go into the low bits of the page
+    /// number.
+    fn page_command(&mut self, command: u32, offset: u32, flags: u32) -> Result<(), Error> {
+        let (eefc, bank_start) = if offset < self.bank_size {
+            (EEFC0, 0)
+        } else {
+            (EEFC1, self.bank_size)
+        };
+        self.command(eefc, command, ((offset - bank_start) / PAGE_SIZE) | flags)
+    }
+
+    /// Runs an EEFC command and waits for it to complete.
+    fn command(&mut self, eefc: u32, command: u32, argument: u32) -> Result<(), Error> {
+        self.samba
+            .write_word(eefc + EEFC_FCR, FKEY | argument << 8 | command)?;
+        let start = Instant::now();
+        loop {
+            let status = self.samba.read_word(eefc + EEFC_FSR)?;
+            if status & (FCMDE | FLOCKE | FLERR) != 0 {
+                return Err(Error::Flash(status));
+            }
+            if status & FRDY != 0 {
+                return Ok(());
+            }
+            if start.elapsed() > self.samba.timeout {
+                return Err(io::Error::from(io::ErrorKind::TimedOut).into());
+            }
+        }
+    }
+}
+
+/// Size of the flash from CHIPID_CIDR.NVPSIZ, both banks together on dual
+/// bank parts
+pub fn flash_size(cidr: u32) -> Option<u32> {
+    let kib = match (cidr >> 8) & 0xF {
+        1 => 8,
+        2 => 16,
+        3 => 32,
+        5 => 64,
+        7 => 128,
+        9 => 256,
+        10 => 512,
+        12 => 1024,
+        14 => 2048,
+        _ => return None,
+    };
+    Some(kib * 1024)
+}
+
+/// Whether CHIPID_CIDR denotes a dual bank part (SAM4SD), whose flash is
+/// split into two equal banks
+pub fn is_dual_bank(cidr: u32) -> bool {
+    matches!((cidr >> 20) & 0xFF, 0x99 | 0x9A)
+}
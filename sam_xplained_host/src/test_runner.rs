@@ -0,0 +1,178 @@
+//! Runner of on-target tests
+//!
+//! [`run`] follows the report of a `sam_xplained_test` harness, on the
+//! console UART or on an RTT channel served over TCP by OpenOCD, until the
+//! firmware is done or goes quiet in the middle of a test.  [`TimedReader`]
+//! reads the report from a command instead, e.g. the test binary built for
+//! the mock board.
+
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use sam_xplained_test::report::{self, Event};
+
+use crate::client::is_timeout;
+use crate::monitor::{LogDecoder, Record};
+
+/// How long a test may run without output by default
+pub const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Outcome of a test run
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Report {
+    pub passed: Vec<String>,
+    /// Failed tests and why
+    pub failed: Vec<(String, String)>,
+    /// Whether the firmware got to the end of the tests
+    pub done: bool,
+    /// Test that was running when the output stopped: it crashed or hung
+    pub stopped_in: Option<String>,
+}
+
+impl Report {
+    pub fn success(&self) -> bool {
+        self.done && self.failed.is_empty()
+    }
+
+    fn record(&mut self, event: Event<'_>) {
+        match event {
+            Event::Suite(_) => {}
+            Event::Started(test) => self.stopped_in = Some(test.to_string()),
+            Event::Passed(test) => self.passed.push(test.to_string()),
+            Event::Failed { test, message } => {
+                self.failed.push((test.to_string(), message.to_string()))
+            }
+            Event::Done { .. } => self.done = true,
+        }
+    }
+}
+
+/// Summary, after the manner of `cargo test`
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if !self.failed.is_empty() {
+            writeln!(f, "failures:")?;
+            for (test, message) in &self.failed {
+                writeln!(f, "    {}: {}", test, message)?;
+            }
+        }
+        if let Some(test) = &self.stopped_in {
+            writeln!(f, "stopped in {}: the test crashed or hung", test)?;
+        } else if !self.done {
+            writeln!(f, "the report ended before the tests did")?;
+        }
+        write!(
+            f,
+            "test result: {}. {} passed; {} failed",
+            if self.success() { "ok" } else { "FAILED" },
+            self.passed.len(),
+            self.failed.len()
+        )
+    }
+}
+
+/// Follows the report on `source`, copying what the firmware prints to
+/// `out`, until the `done` line, or until nothing came for `timeout`.
+///
+/// `check` is called while waiting, e.g. to give up when the command
+/// flashing the firmware fails.
+pub fn run<R, W>(
+    source: &mut R,
+    out: &mut W,
+    timeout: Duration,
+    mut check: impl FnMut() -> io::Result<()>,
+) -> io::Result<Report>
+where
+    R: Read,
+    W: Write,
+{
+    let mut report = Report::default();
+    let mut decoder = LogDecoder::new();
+    let mut buffer = [0u8; 256];
+    let mut last_output = Instant::now();
+    loop {
+        check()?;
+        let records = match source.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(len) => {
+                last_output = Instant::now();
+                decoder.feed(&buffer[..len])
+            }
+            Err(error) if is_timeout(&error) => {
+                if last_output.elapsed() >= timeout {
+                    break;
+                }
+                continue;
+            }
+            Err(error) if error.kind() == io::ErrorKind::Interrupted => continue,
+            Err(error) => return Err(error),
+        };
+        for record in records {
+            writeln!(out, "{}", record)?;
+            if let Record::Line(line) = record {
+                if let Some(event) = report::parse(&line) {
+                    report.stopped_in = None;
+                    report.record(event);
+                }
+            }
+        }
+        out.flush()?;
+        if report.done {
+            return Ok(report);
+        }
+    }
+    // A test that started and never finished
+    if let Some(Record::Line(line)) = decoder.flush() {
+        writeln!(out, "{}", line)?;
+        if let Some(event) = report::parse(&line) {
+            report.record(event);
+        }
+    }
+    Ok(report)
+}
+
+/// Reads from a blocking source, like the output of a child process, on a
+/// thread, timing out like a serial port does
+pub struct TimedReader {
+    chunks: mpsc::Receiver<Vec<u8>>,
+    chunk: Vec<u8>,
+    timeout: Duration,
+}
+
+impl TimedReader {
+    pub fn new<R: Read + Send + 'static>(mut source: R, timeout: Duration) -> Self {
+        let (sender, chunks) = mpsc::channel();
+        thread::spawn(move || {
+            let mut buffer = [0u8; 256];
+            while let Ok(len @ 1..) = source.read(&mut buffer) {
+                if sender.send(buffer[..len].to_vec()).is_err() {
+                    break;
+                }
+            }
+        });
+        TimedReader {
+            chunks,
+            chunk: Vec::new(),
+            timeout,
+        }
+    }
+}
+
+impl Read for TimedReader {
+    fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        if self.chunk.is_empty() {
+            match self.chunks.recv_timeout(self.timeout) {
+                Ok(chunk) => self.chunk = chunk,
+                Err(mpsc::RecvTimeoutError::Timeout) => return Err(io::ErrorKind::TimedOut.into()),
+                Err(mpsc::RecvTimeoutError::Disconnected) => return Ok(0),
+            }
+        }
+        let len = buffer.len().min(self.chunk.len());
+        buffer[..len].copy_from_slice(&self.chunk[..len]);
+        self.chunk.drain(..len);
+        Ok(len)
+    }
+}
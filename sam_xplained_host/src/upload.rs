@@ -0,0 +1,258 @@
+//! Firmware upload through the serial bootloader
+//!
+//! [`upload`] sends an image file to `sam_xplained_bootloader` in update mode
+//! with YMODEM (1K blocks, CRC-16), as `sb` would, and reads back whether the
+//! bootloader installed it.
+
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::time::{Duration, Instant};
+
+use sam_xplained_image::{crc16, ImageFile, ImageHeader};
+
+use crate::client::is_timeout;
+
+const SOH: u8 = 0x01;
+const STX: u8 = 0x02;
+const EOT: u8 = 0x04;
+const ACK: u8 = 0x06;
+const NAK: u8 = 0x15;
+const CAN: u8 = 0x18;
+const CRC_MODE: u8 = b'C';
+const PADDING: u8 = 0x1A;
+
+const BLOCK_LEN: usize = 128;
+const LONG_BLOCK_LEN: usize = 1024;
+
+/// Time the bootloader gets to ask for the file
+pub const START_TIMEOUT: Duration = Duration::from_secs(30);
+/// Time the bootloader gets to answer a block, which may erase flash
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(10);
+/// Time the bootloader gets to install the image once it is sent
+const INSTALL_TIMEOUT: Duration = Duration::from_secs(10);
+/// Silence behind a `C` that tells the receiver's request from text
+const QUIET: Duration = Duration::from_millis(100);
+/// Attempts per block
+const MAX_ERRORS: u32 = 10;
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    /// The file isn't an image file, or its CRC doesn't match
+    NotAnImage,
+    /// The bootloader didn't ask for the file
+    NoReceiver,
+    /// The bootloader cancelled the transfer
+    Cancelled,
+    /// A block didn't get through in [`MAX_ERRORS`] attempts
+    TooManyErrors,
+    /// The bootloader refused the image, for the reason it printed
+    Rejected(String),
+    /// The bootloader didn't report the outcome
+    NoVerdict,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(error) => error.fmt(f),
+            Error::NotAnImage => f.write_str("not an image file, or its CRC doesn't match"),
+            Error::NoReceiver => f.write_str("the bootloader isn't waiting for a file"),
+            Error::Cancelled => f.write_str("transfer cancelled by the bootloader"),
+            Error::TooManyErrors => f.write_str("too many transfer errors"),
+            Error::Rejected(reason) => write!(f, "image rejected: {}", reason),
+            Error::NoVerdict => f.write_str("no response from the bootloader after the transfer"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Self {
+        Error::Io(error)
+    }
+}
+
+/// Uploads an image file to the bootloader in update mode, returning the
+/// version it installed.  `progress` is called with the bytes sent so far.
+pub fn upload<P: Read + Write>(
+    port: &mut P,
+    file: &[u8],
+    progress: &mut dyn FnMut(usize),
+) -> Result<u32, Error> {
+    let image = ImageFile::parse(file).ok_or(Error::NotAnImage)?;
+    if ImageHeader::new(image.header.version, image.image) != image.header {
+        return Err(Error::NotAnImage);
+    }
+
+    match send(port, "firmware.img", file, progress) {
+        Ok(()) => read_verdict(port),
+        // The bootloader tells why it cancelled.
+        Err(Error::Cancelled) => match read_verdict(port) {
+            Err(Error::Rejected(reason)) => Err(Error::Rejected(reason)),
+            _ => Err(Error::Cancelled),
+        },
+        Err(error) => Err(error),
+    }
+}
+
+/// Sends `data` as the file `name` in a YMODEM batch.  `progress` is called
+/// with the bytes sent so far.
+pub fn send<P: Read + Write>(
+    port: &mut P,
+    name: &str,
+    data: &[u8],
+    progress: &mut dyn FnMut(usize),
+) -> Result<(), Error> {
+    wait_for_receiver(port)?;
+
+    // File block: name, size
+    let mut header = [0u8; BLOCK_LEN];
+    let info = format!("{}\0{}", name, data.len());
+    header[..info.len()].copy_from_slice(info.as_bytes());
+    transmit(port, 0, &header)?;
+    wait_for(port, CRC_MODE)?;
+
+    for (index, chunk) in data.chunks(LONG_BLOCK_LEN).enumerate() {
+        let mut block = [PADDING; LONG_BLOCK_LEN];
+        block[..chunk.len()].copy_from_slice(chunk);
+        transmit(port, (index + 1) as u8, &block)?;
+        progress(index * LONG_BLOCK_LEN + chunk.len());
+    }
+
+    // The first EOT is NAKed.
+    let mut errors = 0;
+    loop {
+        port.write_all(&[EOT])?;
+        port.flush()?;
+        match read_response(port)? {
+            Some(ACK) => break,
+            Some(CAN) => return Err(Error::Cancelled),
+            _ => {
+                errors += 1;
+                if errors >= MAX_ERRORS {
+                    return Err(Error::TooManyErrors);
+                }
+            }
+        }
+    }
+
+    // An empty file block ends the batch.  The file is complete already, so
+    // this can't fail the transfer.
+    if wait_for(port, CRC_MODE).is_ok() {
+        send_block(port, 0, &[0; BLOCK_LEN])?;
+        read_response(port)?;
+    }
+    Ok(())
+}
+
+/// Waits for the receiver's first `C`: one that is followed by silence,
+/// rather than by more text.
+fn wait_for_receiver<P: Read>(port: &mut P) -> Result<(), Error> {
+    let start = Instant::now();
+    while let Some(left) = START_TIMEOUT.checked_sub(start.elapsed()) {
+        if read_byte(port, left)? == Some(CRC_MODE) && read_byte(port, QUIET)?.is_none() {
+            return Ok(());
+        }
+    }
+    Err(Error::NoReceiver)
+}
+
+fn wait_for<P: Read>(port: &mut P, expected: u8) -> Result<(), Error> {
+    match read_response(port)? {
+        Some(byte) if byte == expected => Ok(()),
+        Some(CAN) => Err(Error::Cancelled),
+        _ => Err(Error::TooManyErrors),
+    }
+}
+
+/// Sends a block until it is ACKed.
+fn transmit<P: Read + Write>(port: &mut P, number: u8, data: &[u8]) -> Result<(), Error> {
+    for _ in 0..MAX_ERRORS {
+        send_block(port, number, data)?;
+        match read_response(port)? {
+            Some(ACK) => return Ok(()),
+            Some(CAN) => return Err(Error::Cancelled),
+            // NAK, `C` (the file block again) or nothing
+            _ => {}
+        }
+    }
+    Err(Error::TooManyErrors)
+}
+
+fn send_block<P: Write>(port: &mut P, number: u8, data: &[u8]) -> io::Result<()> {
+    let mut block = Vec::with_capacity(data.len() + 5);
+    block.push(if data.len() == BLOCK_LEN { SOH } else { STX });
+    block.push(number);
+    block.push(!number);
+    block.extend_from_slice(data);
+    block.extend_from_slice(&crc16(data).to_be_bytes());
+    port.write_all(&block)?;
+    port.flush()
+}
+
+/// Reads the receiver's answer, skipping anything else.
+fn read_response<P: Read>(port: &mut P) -> io::Result<Option<u8>> {
+    let start = Instant::now();
+    while let Some(left) = RESPONSE_TIMEOUT.checked_sub(start.elapsed()) {
+        match read_byte(port, left)? {
+            Some(byte @ (ACK | NAK | CAN | CRC_MODE)) => return Ok(Some(byte)),
+            Some(_) => {}
+            None => break,
+        }
+    }
+    Ok(None)
+}
+
+/// Reads the bootloader's lines up to the one reporting the outcome.
+fn read_verdict<P: Read>(port: &mut P) -> Result<u32, Error> {
+    let start = Instant::now();
+    let mut line = Vec::new();
+    while let Some(left) = INSTALL_TIMEOUT.checked_sub(start.elapsed()) {
+        match read_byte(port, left)? {
+            Some(b'\n') => {
+                let text = String::from_utf8_lossy(&line).trim().to_string();
+                line.clear();
+                if let Some(reason) = text.strip_prefix("error: ") {
+                    return Err(Error::Rejected(reason.to_string()));
+                }
+                // installed version <n>, resetting
+                if let Some(rest) = text.strip_prefix("installed version ") {
+                    let version = rest.split(',').next().unwrap_or("");
+                    if let Ok(version) = version.parse() {
+                        return Ok(version);
+                    }
+                }
+            }
+            Some(byte) => line.push(byte),
+            None => break,
+        }
+    }
+    Err(Error::NoVerdict)
+}
+
+/// Reads a byte, waiting at most `timeout`.
+fn read_byte<P: Read>(port: &mut P, timeout: Duration) -> io::Result<Option<u8>> {
+    let start = Instant::now();
+    let mut byte = [0u8];
+    loop {
+        match port.read(&mut byte) {
+            Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+            Ok(_) => return Ok(Some(byte[0])),
+            Err(error) if is_timeout(&error) => {
+                if start.elapsed() >= timeout {
+                    return Ok(None);
+                }
+            }
+            Err(error) => return Err(error),
+        }
+    }
+}
@@ -0,0 +1,150 @@
+//! Runs `xplained` against the simulated board on a pseudo-terminal, as it
+//! would run against a board's virtual COM port.
+
+mod common;
+
+use std::fs::OpenOptions;
+use std::io::{self, Read, Write};
+use std::process::{Command, Output};
+use std::thread;
+
+use sam_xplained_host::rpc::Server;
+use serialport::{SerialPort, TTYPort};
+
+use common::{Hangup, Lab};
+
+/// Board side of the pseudo-terminal
+struct Pty(TTYPort);
+
+impl embedded_io::ErrorType for Pty {
+    type Error = Hangup;
+}
+
+impl embedded_io::Read for Pty {
+    fn read(&mut self, buffer: &mut [u8]) -> Result<usize, Hangup> {
+        loop {
+            match self.0.read(buffer) {
+                Ok(len) => return Ok(len),
+                Err(error) if error.kind() == io::ErrorKind::TimedOut => {}
+                Err(_) => return Err(Hangup),
+            }
+        }
+    }
+}
+
+impl embedded_io::Write for Pty {
+    fn write(&mut self, data: &[u8]) -> Result<usize, Hangup> {
+        self.0.write(data).map_err(|_| Hangup)
+    }
+
+    fn flush(&mut self) -> Result<(), Hangup> {
+        self.0.flush().map_err(|_| Hangup)
+    }
+}
+
+/// Runs `xplained` with `args` against a simulated board.
+fn xplained(args: &[&str]) -> Output {
+    let (master, mut slave) = TTYPort::pair().unwrap();
+    let path = slave.name().unwrap();
+    // Keep the board's side open, without the lock that would keep
+    // `xplained` from opening it.
+    slave.set_exclusive(false).unwrap();
+    let _slave = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(&path)
+        .unwrap();
+    drop(slave);
+    thread::spawn(move || Server::new().run(&mut Lab::new(), &mut Pty(master)));
+
+    Command::new(env!("CARGO_BIN_EXE_xplained"))
+        .args(args)
+        .args(["--port", &path])
+        .output()
+        .unwrap()
+}
+
+fn stdout(output: &Output) -> String {
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8(output.stdout.clone()).unwrap()
+}
+
+#[test]
+fn reads_the_board() {
+    assert_eq!(stdout(&xplained(&["ping"])), "OK\n");
+    assert_eq!(
+        stdout(&xplained(&["chipid"])),
+        "CIDR 0xa3cc0ce0, EXID 0x00000000: SAM4E, revision 0\n"
+    );
+    assert_eq!(stdout(&xplained(&["reset-cause"])), "Software\n");
+    assert!(stdout(&xplained(&["info"])).contains("1.2.3"));
+    assert_eq!(
+        stdout(&xplained(&["uid"])),
+        "00000001000000020000000300000004\n"
+    );
+    assert_eq!(stdout(&xplained(&["led", "toggle"])), "LED0 on\n");
+    assert!(xplained(&["reset"]).status.success());
+}
+
+#[test]
+fn accesses_the_config() {
+    // Every run starts a new board, which has an empty store.
+    let output = xplained(&["config", "get", "0x10"]);
+    assert!(!output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        "key 16 is not set\n"
+    );
+    assert!(xplained(&["config", "set", "16", "c0ffee", "--hex"])
+        .status
+        .success());
+    assert!(xplained(&["config", "remove", "16"]).status.success());
+}
+
+#[test]
+fn rejects_bad_arguments() {
+    let output = Command::new(env!("CARGO_BIN_EXE_xplained"))
+        .args(["led", "blink", "--port", "/dev/null"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).starts_with("usage:"));
+}
+
+#[test]
+fn runs_tests_from_a_command() {
+    let run = |script: &str| {
+        Command::new(env!("CARGO_BIN_EXE_xplained"))
+            .args([
+                "test",
+                "--stdout",
+                "--timeout",
+                "1",
+                "--",
+                "sh",
+                "-c",
+                script,
+            ])
+            .output()
+            .unwrap()
+    };
+    let passed = run(r"printf 'suite a\r\ntest a::b ... ok\r\ndone 1 passed, 0 failed\r\n'");
+    assert!(stdout(&passed).ends_with("\ntest result: ok. 1 passed; 0 failed\n"));
+
+    let hung = run(r"printf 'suite a\r\ntest a::b ... '; exec sleep 10");
+    assert!(!hung.status.success());
+    let report = String::from_utf8_lossy(&hung.stdout);
+    assert!(
+        report.contains("stopped in a::b: the test crashed or hung\n"),
+        "{}",
+        report
+    );
+
+    let failed = run("exit 3");
+    assert!(!failed.status.success());
+    assert!(String::from_utf8_lossy(&failed.stderr).contains("`sh -c exit 3` failed"));
+}
@@ -0,0 +1,106 @@
+mod common;
+
+use std::io;
+use std::thread;
+use std::time::Duration;
+
+use sam_xplained_host::rpc::frame::write_frame;
+use sam_xplained_host::rpc::*;
+use sam_xplained_host::{Client, Error};
+
+use common::{board, pipe, Lab, CIDR, RAM};
+
+#[test]
+fn calls_the_board() {
+    let (mut client, board) = board();
+
+    assert_eq!(client.ping(0x1234_5678).unwrap(), 0x1234_5678);
+    assert_eq!(client.chip_id().unwrap(), (CIDR, 0));
+    assert_eq!(client.reset_cause().unwrap(), ResetCause::Software);
+    assert_eq!(client.unique_id().unwrap(), [1, 2, 3, 4]);
+    let info = client.firmware_info().unwrap();
+    assert_eq!(info.version(), "1.2.3");
+    assert_eq!(info.board(), "sam4e");
+
+    assert!(client.led(LedAction::Toggle).unwrap());
+    assert!(!client.led(LedAction::Toggle).unwrap());
+    assert!(client.led(LedAction::On).unwrap());
+
+    // Memory accesses are split up into messages of MAX_DATA bytes.
+    let data: Vec<u8> = (0..600).map(|i| i as u8).collect();
+    client.write_memory(RAM + 4, &data).unwrap();
+    assert_eq!(client.read_memory(RAM + 4, 600).unwrap(), data);
+    assert!(matches!(
+        client.read_memory(RAM + 2, 4),
+        Err(Error::Remote(RemoteError::InvalidArgument))
+    ));
+
+    assert_eq!(client.config_get(1).unwrap(), None);
+    client.config_set(1, b"value").unwrap();
+    assert_eq!(client.config_get(1).unwrap().unwrap(), b"value");
+    client.config_remove(1).unwrap();
+    assert_eq!(client.config_get(1).unwrap(), None);
+    assert!(matches!(
+        client.config_set(2, &[0; MAX_DATA + 1]),
+        Err(Error::TooLong)
+    ));
+
+    client.reset().unwrap();
+
+    drop(client);
+    let lab = board.join().unwrap();
+    assert!(lab.led);
+    assert_eq!(&lab.ram[4..604], &data[..]);
+    assert_eq!(lab.resets, 1);
+}
+
+#[test]
+fn answers_unknown_requests() {
+    let (host, mut board) = pipe();
+    thread::spawn(move || Server::new().run(&mut Lab::new(), &mut board));
+
+    // Request 9, of a kind added after this firmware
+    let mut host = host;
+    write_frame(&[9, 200], |block| host.send(block).map(drop)).unwrap();
+    let mut decoder = FrameDecoder::new();
+    let mut byte = [0u8];
+    let response = loop {
+        host.receive(&mut byte, Duration::from_secs(1)).unwrap();
+        if let Some(frame) = decoder.feed(byte[0]) {
+            break frame.unwrap().to_vec();
+        }
+    };
+    assert_eq!(
+        decode::<Response<'_>>(&response),
+        Ok((9, Response::Error(RemoteError::Malformed)))
+    );
+}
+
+#[test]
+fn times_out() {
+    // Nobody answers.
+    let (host, _board) = pipe();
+    let mut client = Client::new(host);
+    client.set_timeout(Duration::from_millis(200));
+    match client.ping(1) {
+        Err(Error::Io(error)) => assert_eq!(error.kind(), io::ErrorKind::TimedOut),
+        other => panic!("unexpected result {:?}", other),
+    }
+}
+
+#[test]
+fn times_out_on_chatter() {
+    // The board keeps logging, but never answers.
+    let (host, mut board) = pipe();
+    thread::spawn(move || {
+        while board.send(b"log output\r\n\0garbage\0").is_ok() {
+            thread::sleep(Duration::from_millis(1));
+        }
+    });
+    let mut client = Client::new(host);
+    client.set_timeout(Duration::from_millis(200));
+    match client.ping(1) {
+        Err(Error::Io(error)) => assert_eq!(error.kind(), io::ErrorKind::TimedOut),
+        other => panic!("unexpected result {:?}", other),
+    }
+}
@@ -0,0 +1,244 @@
+//! Simulated board on an in-memory serial line
+
+#![allow(dead_code)]
+
+use std::collections::HashMap;
+use std::io;
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use sam_xplained_host::rpc::frame::write_frame;
+use sam_xplained_host::rpc::*;
+use sam_xplained_host::Client;
+use sam_xplained_image::FirmwareInfo;
+
+/// Read timeout of the host's end, as of a port opened by `discover::open`
+pub const READ_TIMEOUT: Duration = Duration::from_millis(50);
+
+/// One end of an in-memory serial line
+pub struct End {
+    tx: Sender<u8>,
+    rx: Receiver<u8>,
+}
+
+pub fn pipe() -> (End, End) {
+    let (host_tx, board_rx) = channel();
+    let (board_tx, host_rx) = channel();
+    (
+        End {
+            tx: host_tx,
+            rx: host_rx,
+        },
+        End {
+            tx: board_tx,
+            rx: board_rx,
+        },
+    )
+}
+
+impl End {
+    /// Waits up to `timeout` for the first byte, then takes what is there.
+    pub fn receive(
+        &mut self,
+        buffer: &mut [u8],
+        timeout: Duration,
+    ) -> Result<usize, RecvTimeoutError> {
+        buffer[0] = self.rx.recv_timeout(timeout)?;
+        let mut len = 1;
+        while len < buffer.len() {
+            match self.rx.try_recv() {
+                Ok(byte) => buffer[len] = byte,
+                Err(_) => break,
+            }
+            len += 1;
+        }
+        Ok(len)
+    }
+
+    pub fn send(&mut self, data: &[u8]) -> Result<usize, Hangup> {
+        for &byte in data {
+            self.tx.send(byte).map_err(|_| Hangup)?;
+        }
+        Ok(data.len())
+    }
+}
+
+impl io::Read for End {
+    fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        match self.receive(buffer, READ_TIMEOUT) {
+            Ok(len) => Ok(len),
+            Err(RecvTimeoutError::Timeout) => Err(io::ErrorKind::TimedOut.into()),
+            Err(RecvTimeoutError::Disconnected) => Ok(0),
+        }
+    }
+}
+
+impl io::Write for End {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        self.send(data)
+            .map_err(|_| io::ErrorKind::BrokenPipe.into())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub struct Hangup;
+
+impl embedded_io::Error for Hangup {
+    fn kind(&self) -> embedded_io::ErrorKind {
+        embedded_io::ErrorKind::BrokenPipe
+    }
+}
+
+impl embedded_io::ErrorType for End {
+    type Error = Hangup;
+}
+
+impl embedded_io::Read for End {
+    fn read(&mut self, buffer: &mut [u8]) -> Result<usize, Hangup> {
+        self.receive(buffer, Duration::from_secs(10))
+            .map_err(|_| Hangup)
+    }
+}
+
+impl embedded_io::Write for End {
+    fn write(&mut self, data: &[u8]) -> Result<usize, Hangup> {
+        self.send(data)
+    }
+
+    fn flush(&mut self) -> Result<(), Hangup> {
+        Ok(())
+    }
+}
+
+pub const RAM: u32 = 0x2000_0000;
+pub const CIDR: u32 = 0xA3CC_0CE0;
+
+/// Simulated board
+pub struct Lab {
+    pub led: bool,
+    pub ram: Vec<u8>,
+    pub config: HashMap<u16, Vec<u8>>,
+    pub info: FirmwareInfo,
+    pub reset_requested: bool,
+    pub resets: usize,
+}
+
+impl Lab {
+    pub fn new() -> Self {
+        Lab {
+            led: false,
+            ram: vec![0; 1024],
+            config: HashMap::new(),
+            info: FirmwareInfo::new("1.2.3", "0123abc", 1_700_000_000, "sam4e", "rt"),
+            reset_requested: false,
+            resets: 0,
+        }
+    }
+
+    /// RAM range of a word access
+    fn ram(&self, address: u32, len: usize) -> Option<std::ops::Range<usize>> {
+        let start = address.checked_sub(RAM)? as usize;
+        if (address as usize | len) & 3 != 0 || start + len > self.ram.len() {
+            return None;
+        }
+        Some(start..start + len)
+    }
+}
+
+impl Handler for Lab {
+    fn handle<'a>(&'a mut self, request: Request<'_>, buffer: &'a mut [u8]) -> Response<'a> {
+        match request {
+            Request::Ping(value) => Response::Pong(value),
+            Request::ChipId => Response::ChipId {
+                cidr: CIDR,
+                exid: 0,
+            },
+            Request::ResetCause => Response::ResetCause(ResetCause::Software),
+            Request::FirmwareInfo => {
+                buffer[..FirmwareInfo::LEN].copy_from_slice(&self.info.to_bytes());
+                Response::FirmwareInfo(&buffer[..FirmwareInfo::LEN])
+            }
+            Request::UniqueId => Response::UniqueId([1, 2, 3, 4]),
+            Request::Led(action) => {
+                self.led = match action {
+                    LedAction::On => true,
+                    LedAction::Off => false,
+                    LedAction::Toggle => !self.led,
+                };
+                Response::Led { on: self.led }
+            }
+            Request::ReadMemory { address, len } => match self.ram(address, len as usize) {
+                Some(range) => Response::Memory(&self.ram[range]),
+                None => Response::Error(RemoteError::InvalidArgument),
+            },
+            Request::WriteMemory { address, data } => match self.ram(address, data.len()) {
+                Some(range) => {
+                    self.ram[range].copy_from_slice(data);
+                    Response::Done
+                }
+                None => Response::Error(RemoteError::InvalidArgument),
+            },
+            Request::ConfigGet(key) => Response::Config(self.config.get(&key).map(Vec::as_slice)),
+            Request::ConfigSet { key, value } => {
+                self.config.insert(key, value.to_vec());
+                Response::Done
+            }
+            Request::ConfigRemove(key) => {
+                self.config.remove(&key);
+                Response::Done
+            }
+            Request::Reset | Request::Update => {
+                self.reset_requested = true;
+                Response::Done
+            }
+        }
+    }
+
+    fn sent(&mut self) {
+        if self.reset_requested {
+            self.reset_requested = false;
+            self.resets += 1;
+        }
+    }
+}
+
+/// Starts the simulated board, which first prints a banner and the response
+/// to a request from before it was "reset".
+pub fn board() -> (Client<End>, JoinHandle<Lab>) {
+    let (host, mut board) = pipe();
+    let thread = thread::spawn(move || {
+        board.send(b"SAM4E Xplained Pro\r\n").unwrap();
+        let mut buffer = [0u8; MAX_PAYLOAD];
+        let stale = encode(0xFFFF, &Response::Pong(7), &mut buffer).unwrap();
+        write_frame(stale, |block| board.send(block).map(drop)).unwrap();
+
+        let mut lab = Lab::new();
+        Server::new().run(&mut lab, &mut board).unwrap_err();
+        lab
+    });
+    (Client::new(host), thread)
+}
+
+impl sam_xplained_ymodem::Transport for End {
+    type Error = Hangup;
+
+    fn read(&mut self, timeout_ms: u32) -> Result<Option<u8>, Hangup> {
+        match self
+            .rx
+            .recv_timeout(Duration::from_millis(timeout_ms as u64))
+        {
+            Ok(byte) => Ok(Some(byte)),
+            Err(RecvTimeoutError::Timeout) => Ok(None),
+            Err(RecvTimeoutError::Disconnected) => Err(Hangup),
+        }
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> Result<(), Hangup> {
+        self.send(bytes).map(drop)
+    }
+}
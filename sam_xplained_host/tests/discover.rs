@@ -0,0 +1,50 @@
+use sam_xplained_host::discover::{edbg_ports, samba_ports, BoardPort};
+use serialport::{SerialPortInfo, SerialPortType, UsbPortInfo};
+
+fn usb(name: &str, vid: u16, pid: u16, serial_number: &str) -> SerialPortInfo {
+    SerialPortInfo {
+        port_name: name.to_string(),
+        port_type: SerialPortType::UsbPort(UsbPortInfo {
+            vid,
+            pid,
+            serial_number: Some(serial_number.to_string()),
+            manufacturer: Some("Atmel Corp.".to_string()),
+            product: Some("EDBG CMSIS-DAP".to_string()),
+        }),
+    }
+}
+
+#[test]
+fn finds_edbg_ports() {
+    let ports = [
+        SerialPortInfo {
+            port_name: "/dev/ttyS0".to_string(),
+            port_type: SerialPortType::Unknown,
+        },
+        usb("/dev/ttyACM0", 0x03EB, 0x2111, "ATML2433041800001234"),
+        // Another Atmel debugger
+        usb("/dev/ttyACM1", 0x03EB, 0x2141, "J42700001234"),
+        usb("/dev/ttyUSB0", 0x0403, 0x6001, "A50285BI"),
+    ];
+
+    assert_eq!(
+        edbg_ports(&ports),
+        [BoardPort {
+            path: "/dev/ttyACM0".to_string(),
+            serial_number: Some("ATML2433041800001234".to_string()),
+            product: Some("EDBG CMSIS-DAP".to_string()),
+        }]
+    );
+}
+
+#[test]
+fn finds_samba_monitors() {
+    let ports = [
+        usb("/dev/ttyACM0", 0x03EB, 0x2111, "ATML2433041800001234"),
+        usb("/dev/ttyACM1", 0x03EB, 0x6124, ""),
+    ];
+
+    let monitors = samba_ports(&ports);
+    assert_eq!(monitors.len(), 1);
+    assert_eq!(monitors[0].path, "/dev/ttyACM1");
+}
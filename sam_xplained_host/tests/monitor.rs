@@ -0,0 +1,70 @@
+mod common;
+
+use std::thread;
+
+use sam_xplained_host::monitor::{self, LogDecoder, Record};
+use sam_xplained_host::rpc::frame::write_frame;
+use sam_xplained_host::rpc::*;
+
+use common::pipe;
+
+fn frame(id: u16, response: &Response<'_>) -> Vec<u8> {
+    let mut payload = [0u8; MAX_PAYLOAD];
+    let payload = encode(id, response, &mut payload).unwrap();
+    let mut frame = Vec::new();
+    write_frame::<()>(payload, |block| {
+        frame.extend_from_slice(block);
+        Ok(())
+    })
+    .unwrap();
+    frame
+}
+
+#[test]
+fn splits_lines_and_frames() {
+    let mut decoder = LogDecoder::new();
+    let mut output = b"boot\r\nreset cause: ".to_vec();
+    output.extend(frame(5, &Response::Led { on: true }));
+    output.extend_from_slice(b"\x1b[1mbold\r\n");
+    output.extend(frame(6, &Response::Pong(1))[..4].iter());
+
+    assert_eq!(
+        decoder.feed(&output),
+        [
+            Record::Line("boot".to_string()),
+            // A frame ends the line it interrupts.
+            Record::Line("reset cause: ".to_string()),
+            Record::Frame {
+                id: 5,
+                message: "Led { on: true }".to_string()
+            },
+            Record::Line("\\u{1b}[1mbold".to_string()),
+        ]
+    );
+    // A broken frame is dropped at the next delimiter.
+    assert_eq!(
+        decoder.feed(b"\0> "),
+        [Record::BadFrame(FrameError::Malformed)]
+    );
+    assert_eq!(decoder.flush(), Some(Record::Line("> ".to_string())));
+    assert_eq!(decoder.flush(), None);
+}
+
+#[test]
+fn prints_the_output() {
+    let (mut host, mut board) = pipe();
+    thread::spawn(move || {
+        let mut output = b"hello\r\n".to_vec();
+        output.extend(frame(1, &Response::Done));
+        output.extend_from_slice(b"prompt> ");
+        board.send(&output).unwrap();
+        // The monitor stops once the board hangs up.
+    });
+
+    let mut out = Vec::new();
+    monitor::run(&mut host, &mut out, false).unwrap();
+    assert_eq!(
+        String::from_utf8(out).unwrap(),
+        "hello\n[rpc 1] Done\nprompt> \n"
+    );
+}
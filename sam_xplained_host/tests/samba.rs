@@ -0,0 +1,416 @@
+mod common;
+
+use std::collections::HashSet;
+use std::io::{self, Read, Write};
+use std::thread::{self, JoinHandle};
+
+use sam_xplained_host::samba::*;
+
+use common::{pipe, End};
+
+/// CIDR of the SAM4E16E: 1024 KiB of flash
+const CIDR: u32 = 0xA3CC_0CE0;
+const FLASH_SIZE: usize = 1024 * 1024;
+/// CIDR of the SAM4SD32C: two banks of 1024 KiB
+const DUAL_BANK_CIDR: u32 = 0x29A7_0EE0;
+const SRAM: u32 = 0x2000_0000;
+const EEFC: u32 = 0x400E_0A00;
+/// Controller of bank 1 on dual bank parts
+const EEFC1: u32 = 0x400E_0C00;
+
+/// Thumb code of the word copy applet, checked against its listing
+const APPLET_CODE: [u8; 20] = [
+    0x04, 0x48, 0x05, 0x49, 0x05, 0x4A, 0x0B, 0x68, 0x04, 0x31, 0x03, 0x60, 0x04, 0x30, 0x01, 0x3A,
+    0xF9, 0xD1, 0x70, 0x47,
+];
+
+/// Simulated SAM-BA monitor with the memory map and flash controllers of a
+/// SAM4E or, with two banks, a SAM4SD
+struct Monitor {
+    cidr: u32,
+    sram: Vec<u8>,
+    flash: Vec<u8>,
+    /// Size of each bank, which has its own controller
+    bank_size: usize,
+    /// Page buffer that writes to the flash array go to
+    latch: Vec<u8>,
+    /// Locked regions, numbered from the start of the flash
+    locked: HashSet<u32>,
+    gpnvm: u32,
+    // Registers of each controller
+    status: [u32; 2],
+    result: [u32; 2],
+    fmr: [u32; 2],
+    /// Commands received, without the data of `S` commands
+    log: Vec<String>,
+}
+
+impl Monitor {
+    /// SAM4E monitor
+    fn new() -> Self {
+        // Lock region 1, at 8 KiB
+        Monitor::with_flash(CIDR, 1, &[1])
+    }
+
+    /// SAM4SD32C monitor
+    fn dual_bank() -> Self {
+        // Lock region 1 and the first region of bank 1
+        let bank_1 = FLASH_SIZE as u32 / LOCK_REGION_SIZE;
+        Monitor::with_flash(DUAL_BANK_CIDR, 2, &[1, bank_1])
+    }
+
+    fn with_flash(cidr: u32, banks: usize, locked: &[u32]) -> Self {
+        Monitor {
+            cidr,
+            sram: vec![0; 128 * 1024],
+            flash: vec![0xFF; banks * FLASH_SIZE],
+            bank_size: FLASH_SIZE,
+            latch: vec![0xFF; PAGE_SIZE as usize],
+            locked: locked.iter().copied().collect(),
+            gpnvm: 0,
+            status: [1; 2],
+            result: [0; 2],
+            fmr: [0; 2],
+            log: Vec::new(),
+        }
+    }
+
+    /// Bank and offset of the register of a flash controller at `address`
+    fn eefc(&self, address: u32) -> Option<(usize, u32)> {
+        let banks = self.flash.len() / self.bank_size;
+        [EEFC, EEFC1][..banks]
+            .iter()
+            .position(|&eefc| (eefc..eefc + 0x10).contains(&address))
+            .map(|bank| (bank, address & 0xF))
+    }
+
+    fn read_word(&mut self, address: u32) -> u32 {
+        if address == CHIPID_CIDR {
+            return self.cidr;
+        }
+        match self.eefc(address) {
+            Some((bank, 0x0)) => self.fmr[bank],
+            // The error flags clear on read.
+            Some((bank, 0x8)) => std::mem::replace(&mut self.status[bank], 1),
+            Some((bank, 0xC)) => self.result[bank],
+            _ => {
+                let mut word = [0u8; 4];
+                for (index, byte) in word.iter_mut().enumerate() {
+                    *byte = self.read_byte(address + index as u32);
+                }
+                u32::from_le_bytes(word)
+            }
+        }
+    }
+
+    fn read_byte(&self, address: u32) -> u8 {
+        if address >= SRAM {
+            self.sram[(address - SRAM) as usize]
+        } else {
+            self.flash[(address - FLASH_BASE) as usize]
+        }
+    }
+
+    fn write_word(&mut self, address: u32, value: u32) {
+        match self.eefc(address) {
+            Some((bank, 0x0)) => self.fmr[bank] = value,
+            Some((bank, 0x4)) => self.flash_command(bank, value),
+            _ if address >= SRAM => {
+                let start = (address - SRAM) as usize;
+                self.sram[start..start + 4].copy_from_slice(&value.to_le_bytes());
+            }
+            _ => {
+                let start = ((address - FLASH_BASE) % PAGE_SIZE) as usize;
+                self.latch[start..start + 4].copy_from_slice(&value.to_le_bytes());
+            }
+        }
+    }
+
+    /// Runs a command on the controller of `bank`, which numbers the pages
+    /// from the start of its bank.
+    fn flash_command(&mut self, bank: usize, fcr: u32) {
+        let (key, argument, command) = (fcr >> 24, (fcr >> 8) & 0xFFFF, fcr & 0xFF);
+        let in_bank = (argument & !7) as usize * PAGE_SIZE as usize;
+        let page = bank * self.bank_size + argument as usize * PAGE_SIZE as usize;
+        let locked = |page: usize| self.locked.contains(&(page as u32 / LOCK_REGION_SIZE));
+        self.status[bank] = match (key, command) {
+            // Pages beyond the bank
+            (0x5A, 0x01 | 0x07 | 0x09) if in_bank >= self.bank_size => 1 | 1 << 1,
+            (0x5A, 0x01) if locked(page) => 1 | 1 << 2,
+            (0x5A, 0x01) => {
+                // Programming only clears bits.
+                for (flash, latch) in self.flash[page..].iter_mut().zip(&self.latch) {
+                    *flash &= latch;
+                }
+                self.latch.fill(0xFF);
+                1
+            }
+            // Eight pages
+            (0x5A, 0x07) if argument & 3 == 1 => {
+                let start = bank * self.bank_size + in_bank;
+                if locked(start) {
+                    1 | 1 << 2
+                } else {
+                    self.flash[start..start + ERASE_SIZE as usize].fill(0xFF);
+                    1
+                }
+            }
+            (0x5A, 0x09) => {
+                self.locked.remove(&(page as u32 / LOCK_REGION_SIZE));
+                1
+            }
+            (0x5A, 0x0B) => {
+                self.gpnvm |= 1 << argument;
+                1
+            }
+            (0x5A, 0x0C) => {
+                self.gpnvm &= !(1 << argument);
+                1
+            }
+            (0x5A, 0x0D) => {
+                self.result[bank] = self.gpnvm;
+                1
+            }
+            _ => 1 | 1 << 1,
+        };
+    }
+
+    /// Runs the word copy applet, the only code it knows.
+    fn go(&mut self, address: u32) {
+        let entry = self.read_word(address + 4);
+        let code = address + 8;
+        assert_eq!(entry, code | 1);
+        for (index, &byte) in APPLET_CODE.iter().enumerate() {
+            assert_eq!(self.read_byte(code + index as u32), byte);
+        }
+        let dst = self.read_word(code + 20);
+        let src = self.read_word(code + 24);
+        let words = self.read_word(code + 28);
+        for index in 0..words {
+            let value = self.read_word(src + index * 4);
+            self.write_word(dst + index * 4, value);
+        }
+    }
+
+    /// Serves the commands until the host hangs up.
+    fn run(mut self, port: &mut End) -> Self {
+        let mut command = Vec::new();
+        while let Some(byte) = next_byte(port) {
+            if byte != b'#' {
+                command.push(byte);
+                continue;
+            }
+            let text = String::from_utf8(command.split_off(0)).unwrap();
+            let (letter, arguments) = text.split_at(1);
+            let arguments: Vec<u32> = arguments
+                .split(',')
+                .filter(|argument| !argument.is_empty())
+                .map(|argument| u32::from_str_radix(argument, 16).unwrap())
+                .collect();
+            self.log.push(text.clone());
+            match letter {
+                "N" => port.write_all(b"\n\r").unwrap(),
+                "V" => port.write_all(b"v2.1 Apr 10 2013 11:28:39\n\r").unwrap(),
+                "w" => {
+                    let value = self.read_word(arguments[0]);
+                    port.write_all(&value.to_le_bytes()).unwrap();
+                }
+                "W" => self.write_word(arguments[0], arguments[1]),
+                "R" => {
+                    let data: Vec<u8> = (0..arguments[1])
+                        .map(|offset| self.read_byte(arguments[0] + offset))
+                        .collect();
+                    port.write_all(&data).unwrap();
+                }
+                "S" => {
+                    for offset in (0..arguments[1]).step_by(4) {
+                        let word: Vec<u8> = (0..4).map(|_| next_byte(port).unwrap()).collect();
+                        let value = u32::from_le_bytes([word[0], word[1], word[2], word[3]]);
+                        self.write_word(arguments[0] + offset, value);
+                    }
+                }
+                "G" => self.go(arguments[0]),
+                _ => panic!("unknown command {}", text),
+            }
+        }
+        self
+    }
+}
+
+fn next_byte(port: &mut End) -> Option<u8> {
+    let mut byte = [0u8];
+    loop {
+        match port.read(&mut byte) {
+            Ok(0) => return None,
+            Ok(_) => return Some(byte[0]),
+            Err(error) if error.kind() == io::ErrorKind::TimedOut => {}
+            Err(error) => panic!("{}", error),
+        }
+    }
+}
+
+/// Starts the monitor, which greets with the prompt of terminal mode.
+fn monitor() -> (End, JoinHandle<Monitor>) {
+    start(Monitor::new())
+}
+
+fn start(monitor: Monitor) -> (End, JoinHandle<Monitor>) {
+    let (host, mut board) = pipe();
+    board.send(b"\n\r>").unwrap();
+    let thread = thread::spawn(move || monitor.run(&mut board));
+    (host, thread)
+}
+
+fn binary(len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i * 13 + i / 256) as u8).collect()
+}
+
+#[test]
+fn identifies_the_chip() {
+    let (port, monitor) = monitor();
+    let mut samba = Samba::connect(port, Link::Usb).unwrap();
+    assert_eq!(samba.version().unwrap(), "v2.1 Apr 10 2013 11:28:39");
+    let flasher = Flasher::new(samba).unwrap();
+    assert_eq!(flasher.cidr(), CIDR);
+    assert_eq!(flasher.flash_size(), 1024 * 1024);
+
+    drop(flasher);
+    assert_eq!(
+        monitor.join().unwrap().log,
+        [
+            "N",
+            "V",
+            "w400E0740,",
+            "W400E0A00,00000600",
+            "S20001000,0000001C"
+        ]
+    );
+}
+
+#[test]
+fn programs_the_flash() {
+    let (port, monitor) = monitor();
+    let mut flasher = Flasher::new(Samba::connect(port, Link::Usb).unwrap()).unwrap();
+    // Across the locked region at 8 KiB
+    let data = binary(7 * 1024 + 100);
+    let mut progress = Vec::new();
+    flasher
+        .write(4096, &data, &mut |written| progress.push(written))
+        .unwrap();
+    assert_eq!(progress.len(), 15);
+    assert_eq!(progress.last(), Some(&data.len()));
+
+    assert!(!flasher.gpnvm(GPNVM_BOOT_MODE).unwrap());
+    flasher.set_gpnvm(GPNVM_BOOT_MODE, true).unwrap();
+    assert!(flasher.gpnvm(GPNVM_BOOT_MODE).unwrap());
+
+    drop(flasher);
+    let monitor = monitor.join().unwrap();
+    assert_eq!(&monitor.flash[4096..4096 + data.len()], &data[..]);
+    assert!(monitor.flash[4096 + data.len()..]
+        .iter()
+        .all(|&byte| byte == 0xFF));
+    assert!(monitor.locked.is_empty());
+    assert_eq!(monitor.gpnvm, 1 << GPNVM_BOOT_MODE);
+}
+
+#[test]
+fn programs_both_banks() {
+    let (port, monitor) = start(Monitor::dual_bank());
+    let mut flasher = Flasher::new(Samba::connect(port, Link::Usb).unwrap()).unwrap();
+    assert_eq!(flasher.cidr(), DUAL_BANK_CIDR);
+    assert_eq!(flasher.flash_size(), 2048 * 1024);
+    // From the end of bank 0 across the locked first region of bank 1, and
+    // the last page of bank 1
+    let bank_1 = FLASH_SIZE as u32;
+    let data = binary(16 * 1024 + 100);
+    flasher.write(bank_1 - 8192, &data, &mut |_| {}).unwrap();
+    flasher
+        .write(2 * bank_1 - PAGE_SIZE, &[0x42; 512], &mut |_| {})
+        .unwrap();
+    assert!(matches!(
+        flasher.write(2 * bank_1, &[0; 4], &mut |_| {}),
+        Err(Error::OutOfRange)
+    ));
+
+    drop(flasher);
+    let monitor = monitor.join().unwrap();
+    let start = FLASH_SIZE - 8192;
+    assert_eq!(&monitor.flash[start..start + data.len()], &data[..]);
+    assert!(monitor.flash[2 * FLASH_SIZE - 512..]
+        .iter()
+        .all(|&byte| byte == 0x42));
+    // Bank 1 went through its own controller: unlocked, erased and
+    // programmed from its first page on
+    assert_eq!(monitor.locked, [1].iter().copied().collect());
+    for command in [
+        "W400E0C00,00000600",
+        "W400E0C04,5A000009",
+        "W400E0C04,5A000107",
+        "W400E0C04,5A000001",
+        "W400E0C04,5A07FF01",
+    ] {
+        assert!(
+            monitor.log.iter().any(|logged| logged == command),
+            "no {}",
+            command
+        );
+    }
+}
+
+#[test]
+fn programs_over_the_uart() {
+    let (port, monitor) = monitor();
+    let mut flasher = Flasher::new(Samba::connect(port, Link::Serial).unwrap()).unwrap();
+    let data = binary(600);
+    flasher.write(0, &data, &mut |_| {}).unwrap();
+
+    drop(flasher);
+    let monitor = monitor.join().unwrap();
+    assert_eq!(&monitor.flash[..600], &data[..]);
+    // Blocks go word by word.
+    assert!(!monitor
+        .log
+        .iter()
+        .any(|command| command.starts_with(['S', 'R'])));
+}
+
+#[test]
+fn checks_the_range() {
+    let (port, _monitor) = monitor();
+    let mut flasher = Flasher::new(Samba::connect(port, Link::Usb).unwrap()).unwrap();
+    assert!(matches!(
+        flasher.write(FLASH_SIZE as u32 - 512, &[0; 1024], &mut |_| {}),
+        Err(Error::OutOfRange)
+    ));
+    assert!(matches!(
+        flasher.write(100, &[0; 4], &mut |_| {}),
+        Err(Error::OutOfRange)
+    ));
+}
+
+#[test]
+fn detects_what_doesnt_program() {
+    let (port, monitor) = monitor();
+    let mut flasher = Flasher::new(Samba::connect(port, Link::Usb).unwrap()).unwrap();
+    flasher.write(0, &[0x0F; 512], &mut |_| {}).unwrap();
+    // Without an erase, programming can't set bits.
+    flasher.samba().write(0x2000_1400, &[0xF0; 512]).unwrap();
+    flasher.samba().write_word(0x2000_101C, FLASH_BASE).unwrap();
+    flasher
+        .samba()
+        .write_word(0x2000_1020, 0x2000_1400)
+        .unwrap();
+    flasher.samba().write_word(0x2000_1024, 128).unwrap();
+    flasher.samba().go(0x2000_1000).unwrap();
+    flasher.samba().write_word(EEFC + 4, 0x5A00_0001).unwrap();
+    assert!(matches!(
+        flasher.verify(0, &[0xF0; 512]),
+        Err(Error::Verify(FLASH_BASE))
+    ));
+
+    drop(flasher);
+    assert!(monitor.join().unwrap().flash[..512]
+        .iter()
+        .all(|&byte| byte == 0));
+}
@@ -0,0 +1,105 @@
+mod common;
+
+use std::io;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use sam_xplained_host::test_runner::{self, Report, TimedReader};
+
+use common::pipe;
+
+const REPORT: &[u8] = b"boot\r\n\
+suite gpio\r\n\
+test gpio::loopback_high ... ok\r\n\
+test gpio::loopback_low ... FAILED: input reads high (src/suites/gpio.rs:40)\r\n\
+done 1 passed, 1 failed\r\n";
+
+#[test]
+fn collects_the_report() {
+    let (mut host, mut board) = pipe();
+    thread::spawn(move || {
+        board.send(REPORT).unwrap();
+        // Still running, as after the tests
+        thread::sleep(Duration::from_secs(60));
+    });
+
+    let mut out = Vec::new();
+    let report = test_runner::run(&mut host, &mut out, Duration::from_secs(10), || Ok(())).unwrap();
+    assert_eq!(
+        report,
+        Report {
+            passed: vec!["gpio::loopback_high".to_string()],
+            failed: vec![(
+                "gpio::loopback_low".to_string(),
+                "input reads high (src/suites/gpio.rs:40)".to_string()
+            )],
+            done: true,
+            stopped_in: None,
+        }
+    );
+    assert!(!report.success());
+    assert!(String::from_utf8(out)
+        .unwrap()
+        .starts_with("boot\nsuite gpio\n"));
+    assert_eq!(
+        report.to_string(),
+        "failures:\n    gpio::loopback_low: input reads high (src/suites/gpio.rs:40)\n\
+         test result: FAILED. 1 passed; 1 failed"
+    );
+}
+
+#[test]
+fn notices_a_hanging_test() {
+    let (mut host, mut board) = pipe();
+    thread::spawn(move || {
+        board
+            .send(b"suite flash\r\ntest flash::erases ... ")
+            .unwrap();
+        thread::sleep(Duration::from_secs(60));
+    });
+
+    let start = Instant::now();
+    let report = test_runner::run(
+        &mut host,
+        &mut Vec::new(),
+        Duration::from_millis(300),
+        || Ok(()),
+    )
+    .unwrap();
+    assert!(start.elapsed() < Duration::from_secs(10));
+    assert_eq!(report.stopped_in.as_deref(), Some("flash::erases"));
+    assert!(!report.done);
+    assert!(report
+        .to_string()
+        .starts_with("stopped in flash::erases: the test crashed or hung\n"));
+}
+
+#[test]
+fn stops_when_told() {
+    let (mut host, _board) = pipe();
+    let error = test_runner::run(&mut host, &mut Vec::new(), Duration::from_secs(10), || {
+        Err(io::Error::other("flashing failed"))
+    })
+    .unwrap_err();
+    assert_eq!(error.to_string(), "flashing failed");
+}
+
+#[test]
+fn reads_a_command_output() {
+    let mut source = TimedReader::new(REPORT, Duration::from_millis(50));
+    let report = test_runner::run(
+        &mut source,
+        &mut Vec::new(),
+        Duration::from_secs(10),
+        || Ok(()),
+    )
+    .unwrap();
+    assert!(report.done);
+    assert_eq!(report.passed.len(), 1);
+
+    let (_host, board) = pipe();
+    let mut silent = TimedReader::new(board, Duration::from_millis(50));
+    let mut buffer = [0u8; 16];
+    let error = io::Read::read(&mut silent, &mut buffer).unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::TimedOut);
+}
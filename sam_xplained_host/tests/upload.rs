@@ -0,0 +1,88 @@
+mod common;
+
+use std::io::Write;
+use std::thread::{self, JoinHandle};
+
+use sam_xplained_host::upload::{self, Error};
+use sam_xplained_image::ImageHeader;
+use sam_xplained_ymodem::{MemorySink, Receiver};
+
+use common::{pipe, End};
+
+const INSTALLED: u32 = 3;
+
+fn image_file(version: u32, len: usize) -> Vec<u8> {
+    let image: Vec<u8> = (0..len).map(|i| (i * 7) as u8).collect();
+    let mut file = ImageHeader::new(version, &image).to_bytes().to_vec();
+    file.extend_from_slice(&image);
+    file
+}
+
+/// Simulated bootloader in update mode, which receives a file and installs
+/// it if its version is newer
+fn bootloader() -> (End, JoinHandle<Vec<u8>>) {
+    let (host, mut board) = pipe();
+    let thread = thread::spawn(move || {
+        // The banner has a `C` that isn't a transfer request.
+        write!(
+            board,
+            "SAM4E Xplained Pro bootloader\r\nupdate mode, send the image with YMODEM (CRC-16)\r\n"
+        )
+        .unwrap();
+        let mut memory = vec![0u8; 64 * 1024];
+        let mut receiver = Receiver::new(board);
+        let len = receiver
+            .receive(&mut MemorySink::new(&mut memory))
+            .unwrap()
+            .size
+            .unwrap() as usize;
+        let mut board = receiver.into_inner();
+
+        let mut header = [0u8; ImageHeader::LEN];
+        header.copy_from_slice(&memory[..ImageHeader::LEN]);
+        let header = ImageHeader::from_bytes(&header).unwrap();
+        if header.version > INSTALLED {
+            write!(board, "installed version {}, resetting\r\n", header.version).unwrap();
+        } else {
+            write!(board, "error: version {} isn't newer\r\n", header.version).unwrap();
+        }
+        memory.truncate(len);
+        memory
+    });
+    (host, thread)
+}
+
+#[test]
+fn uploads_an_image() {
+    let (mut port, bootloader) = bootloader();
+    let file = image_file(4, 3000);
+    let mut sent = Vec::new();
+
+    let version = upload::upload(&mut port, &file, &mut |len| sent.push(len)).unwrap();
+    assert_eq!(version, 4);
+    assert_eq!(sent, [1024, 2048, file.len()]);
+    assert_eq!(bootloader.join().unwrap(), file);
+}
+
+#[test]
+fn reports_a_rejected_image() {
+    let (mut port, _bootloader) = bootloader();
+    let file = image_file(2, 100);
+
+    match upload::upload(&mut port, &file, &mut |_| {}) {
+        Err(Error::Rejected(reason)) => assert_eq!(reason, "version 2 isn't newer"),
+        other => panic!("unexpected result {:?}", other),
+    }
+}
+
+#[test]
+fn checks_the_file() {
+    let (mut port, _board) = pipe();
+    let mut file = image_file(4, 100);
+    file[ImageHeader::LEN] ^= 1;
+
+    assert!(matches!(
+        upload::upload(&mut port, &file, &mut |_| {}),
+        Err(Error::NotAnImage)
+    ));
+}
@@ -0,0 +1,356 @@
+//! Dual bank A/B images
+//!
+//! Flash with two banks, only one of which is booted from, holds an image in
+//! each bank.  [`DualBank`] installs a new image into the bank that isn't
+//! running and tells the boot stage, a small program at the start of each
+//! bank that no update replaces, what to do after a reset.
+//!
+//! Each bank holds the boot stage, the image from [`Layout::image_start`] and
+//! an erase block at [`Layout::status_block`] with the image's header and
+//! markers.  Writing the header of a new image arms its trial run: the boot
+//! stage starts it once, and abandons it for the image in the other bank if
+//! it is reset by the watchdog or started again without having confirmed
+//! itself with [`DualBank::confirm`].
+
+use core::fmt;
+
+use embedded_storage::nor_flash::NorFlash;
+
+use crate::{Crc32, ImageHeader};
+
+/// Writes go through a buffer of this many bytes, so the flash's write size
+/// must divide it.
+const UNIT: usize = 16;
+
+/// Offsets of the markers in the status block, each in its own write unit
+const STARTED_MARKER: u32 = 0x10;
+const CONFIRM_MARKER: u32 = 0x20;
+
+/// Where the boot stage, the image and its status go in each bank
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Layout {
+    /// Size of a bank, which is the flash offset of bank 1
+    pub bank_size: u32,
+    /// Bank offset of the image.  The boot stage ends at the erase block
+    /// holding it.
+    pub image_start: u32,
+    /// Bank offset of the erase block holding the header and the markers,
+    /// which ends the image
+    pub status_block: u32,
+}
+
+impl Layout {
+    /// Largest image a bank has room for
+    pub const fn image_capacity(&self) -> u32 {
+        self.status_block - self.image_start
+    }
+}
+
+/// One of the two flash banks
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Bank {
+    Bank0,
+    Bank1,
+}
+
+impl Bank {
+    /// The other bank
+    pub fn other(self) -> Bank {
+        match self {
+            Bank::Bank0 => Bank::Bank1,
+            Bank::Bank1 => Bank::Bank0,
+        }
+    }
+}
+
+/// What the boot stage does after a reset
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Boot {
+    /// Start the image in the bank booted from
+    Start,
+    /// Abandon the image on trial: boot from the other bank
+    Revert,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Error<E> {
+    /// The flash failed
+    Flash(E),
+    /// The image does not fit into a bank
+    TooLarge,
+    /// The image is not newer than the running one
+    NotNewer,
+    /// The running image is still on trial
+    NotConfirmed,
+    /// More or less data than the header announced
+    LengthMismatch,
+    /// The image in flash does not match the header's CRC
+    BadCrc,
+    /// [`DualBank::write`] or [`DualBank::finish`] without
+    /// [`DualBank::begin`]
+    NotStarted,
+}
+
+impl<E: fmt::Debug> fmt::Display for Error<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Flash(error) => write!(f, "flash error: {:?}", error),
+            Error::TooLarge => f.write_str("image does not fit into a bank"),
+            Error::NotNewer => f.write_str("image is not newer than the running one"),
+            Error::NotConfirmed => f.write_str("running image is still on trial"),
+            Error::LengthMismatch => f.write_str("image length does not match its header"),
+            Error::BadCrc => f.write_str("image CRC does not match its header"),
+            Error::NotStarted => f.write_str("no update in progress"),
+        }
+    }
+}
+
+/// An update being written
+struct Pending {
+    header: ImageHeader,
+    /// Bytes received so far
+    received: u32,
+    /// Received bytes not yet making up a whole write unit
+    buffer: [u8; UNIT],
+}
+
+/// The images in both banks, as seen from the bank booted from
+pub struct DualBank<F> {
+    flash: F,
+    layout: Layout,
+    active: Bank,
+    pending: Option<Pending>,
+}
+
+impl<F: NorFlash> DualBank<F> {
+    /// Manages the images in `flash`, which covers both banks, running from
+    /// the image in `active`.
+    ///
+    /// Panics unless the flash's write size divides 16 bytes.
+    pub fn new(flash: F, layout: Layout, active: Bank) -> Self {
+        assert!(UNIT.is_multiple_of(F::WRITE_SIZE));
+        DualBank {
+            flash,
+            layout,
+            active,
+            pending: None,
+        }
+    }
+
+    /// Bank booted from
+    pub fn active_bank(&self) -> Bank {
+        self.active
+    }
+
+    pub fn flash(&mut self) -> &mut F {
+        &mut self.flash
+    }
+
+    /// Decides what the boot stage does after a reset.  An image on trial is
+    /// started once; a second start, or a reset by the watchdog, abandons it
+    /// if the other bank holds an image to fall back to.
+    pub fn boot(&mut self, watchdog_reset: bool) -> Result<Boot, Error<F::Error>> {
+        let active = self.active;
+        if self.is_confirmed()? {
+            return Ok(Boot::Start);
+        }
+        if !watchdog_reset && !self.marker(active, STARTED_MARKER)? {
+            self.set_marker(active, STARTED_MARKER)?;
+            return Ok(Boot::Start);
+        }
+        if self.is_bootable(active.other())? {
+            Ok(Boot::Revert)
+        } else {
+            Ok(Boot::Start)
+        }
+    }
+
+    /// Header of the running image, or `None` for an image programmed
+    /// directly (e.g. with a debugger)
+    pub fn running_image(&mut self) -> Result<Option<ImageHeader>, Error<F::Error>> {
+        self.header(self.active)
+    }
+
+    /// Whether the running image is no longer on trial
+    pub fn is_confirmed(&mut self) -> Result<bool, Error<F::Error>> {
+        Ok(self.header(self.active)?.is_none() || self.marker(self.active, CONFIRM_MARKER)?)
+    }
+
+    /// Marks the running image as good, ending its trial.
+    pub fn confirm(&mut self) -> Result<(), Error<F::Error>> {
+        if !self.is_confirmed()? {
+            self.set_marker(self.active, CONFIRM_MARKER)?;
+        }
+        Ok(())
+    }
+
+    /// Starts installing the image described by `header`, erasing the image
+    /// in the other bank.  Only a confirmed image can install another one.
+    pub fn begin(&mut self, header: ImageHeader) -> Result<(), Error<F::Error>> {
+        if !self.is_confirmed()? {
+            return Err(Error::NotConfirmed);
+        }
+        if header.length == 0 || header.length > self.layout.image_capacity() {
+            return Err(Error::TooLarge);
+        }
+        let running = self.running_image()?.map_or(0, |image| image.version);
+        if header.version <= running {
+            return Err(Error::NotNewer);
+        }
+
+        let bank = self.offset(self.active.other());
+        let erase_size = F::ERASE_SIZE as u32;
+        let status = bank + self.layout.status_block;
+        let start = self.layout.image_start - self.layout.image_start % erase_size;
+        let end = (self.layout.image_start + header.length).div_ceil(erase_size) * erase_size;
+        self.pending = None;
+        self.flash
+            .erase(status, status + erase_size)
+            .map_err(Error::Flash)?;
+        self.flash
+            .erase(bank + start, bank + end)
+            .map_err(Error::Flash)?;
+
+        self.pending = Some(Pending {
+            header,
+            received: 0,
+            buffer: [0xFF; UNIT],
+        });
+        Ok(())
+    }
+
+    /// Writes the next part of the image.
+    pub fn write(&mut self, mut data: &[u8]) -> Result<(), Error<F::Error>> {
+        let image = self.offset(self.active.other()) + self.layout.image_start;
+        let pending = self.pending.as_mut().ok_or(Error::NotStarted)?;
+        if pending.received as usize + data.len() > pending.header.length as usize {
+            return Err(Error::LengthMismatch);
+        }
+
+        while !data.is_empty() {
+            let filled = pending.received as usize % UNIT;
+            let unit = pending.received - filled as u32;
+
+            if filled == 0 && data.len() >= UNIT {
+                // Whole write units go straight to the flash.
+                let count = data.len() / UNIT * UNIT;
+                self.flash
+                    .write(image + unit, &data[..count])
+                    .map_err(Error::Flash)?;
+                pending.received += count as u32;
+                data = &data[count..];
+                continue;
+            }
+
+            let count = data.len().min(UNIT - filled);
+            pending.buffer[filled..filled + count].copy_from_slice(&data[..count]);
+            pending.received += count as u32;
+            data = &data[count..];
+
+            if filled + count == UNIT {
+                self.flash
+                    .write(image + unit, &pending.buffer)
+                    .map_err(Error::Flash)?;
+                pending.buffer = [0xFF; UNIT];
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks the written image and writes its header, which arms its trial
+    /// run.  Returns the bank to boot from next.
+    pub fn finish(&mut self) -> Result<Bank, Error<F::Error>> {
+        let pending = self.pending.take().ok_or(Error::NotStarted)?;
+        if pending.received != pending.header.length {
+            return Err(Error::LengthMismatch);
+        }
+
+        let bank = self.active.other();
+        let image = self.offset(bank) + self.layout.image_start;
+        let filled = pending.received as usize % UNIT;
+        if filled != 0 {
+            let unit = pending.received - filled as u32;
+            self.flash
+                .write(image + unit, &pending.buffer)
+                .map_err(Error::Flash)?;
+        }
+
+        if self.image_crc(bank, pending.header.length)? != pending.header.crc {
+            return Err(Error::BadCrc);
+        }
+
+        let status = self.offset(bank) + self.layout.status_block;
+        self.flash
+            .write(status, &pending.header.to_bytes())
+            .map_err(Error::Flash)?;
+        Ok(bank)
+    }
+
+    /// Whether `bank` holds an image worth falling back to: a confirmed image
+    /// that still matches its CRC, or one programmed without a header.
+    pub fn is_bootable(&mut self, bank: Bank) -> Result<bool, Error<F::Error>> {
+        match self.header(bank)? {
+            Some(header) => Ok(header.length <= self.layout.image_capacity()
+                && self.marker(bank, CONFIRM_MARKER)?
+                && self.image_crc(bank, header.length)? == header.crc),
+            None => {
+                // The initial stack pointer of an image points into SRAM.
+                let mut stack_pointer = [0u8; 4];
+                self.read(
+                    self.offset(bank) + self.layout.image_start,
+                    &mut stack_pointer,
+                )?;
+                Ok(u32::from_le_bytes(stack_pointer) & 0xFFF0_0000 == 0x2000_0000)
+            }
+        }
+    }
+
+    //
+    // Banks
+    //
+
+    fn offset(&self, bank: Bank) -> u32 {
+        match bank {
+            Bank::Bank0 => 0,
+            Bank::Bank1 => self.layout.bank_size,
+        }
+    }
+
+    fn header(&mut self, bank: Bank) -> Result<Option<ImageHeader>, Error<F::Error>> {
+        let mut bytes = [0u8; ImageHeader::LEN];
+        self.read(self.offset(bank) + self.layout.status_block, &mut bytes)?;
+        Ok(ImageHeader::from_bytes(&bytes))
+    }
+
+    fn marker(&mut self, bank: Bank, marker: u32) -> Result<bool, Error<F::Error>> {
+        let mut bytes = [0u8; UNIT];
+        self.read(
+            self.offset(bank) + self.layout.status_block + marker,
+            &mut bytes,
+        )?;
+        Ok(bytes.iter().any(|&byte| byte != 0xFF))
+    }
+
+    fn set_marker(&mut self, bank: Bank, marker: u32) -> Result<(), Error<F::Error>> {
+        let offset = self.offset(bank) + self.layout.status_block + marker;
+        self.flash.write(offset, &[0; UNIT]).map_err(Error::Flash)
+    }
+
+    fn image_crc(&mut self, bank: Bank, length: u32) -> Result<u32, Error<F::Error>> {
+        let image = self.offset(bank) + self.layout.image_start;
+        let mut crc = Crc32::new();
+        let mut buffer = [0u8; 64];
+        let mut offset = 0;
+        while offset < length {
+            let count = buffer.len().min((length - offset) as usize);
+            self.read(image + offset, &mut buffer[..count])?;
+            crc.update(&buffer[..count]);
+            offset += count as u32;
+        }
+        Ok(crc.finish())
+    }
+
+    fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Error<F::Error>> {
+        self.flash.read(offset, bytes).map_err(Error::Flash)
+    }
+}
@@ -0,0 +1,183 @@
+//! Build information embedded in firmware images
+
+use core::fmt;
+use core::str;
+
+const INFO_MAGIC: u32 = 0x4946_5853; // "SXFI"
+const INFO_LAYOUT: u32 = 1;
+
+// Offsets and lengths of the encoded fields
+const TIMESTAMP: usize = 8;
+const VERSION: (usize, usize) = (16, 32);
+const GIT_COMMIT: (usize, usize) = (48, 48);
+const BOARD: (usize, usize) = (96, 32);
+const FEATURES: (usize, usize) = (128, 128);
+
+/// Version, git commit, build time, board and features of a firmware build
+///
+/// `sam_xplained` places it at [`FirmwareInfo::OFFSET`] from the start of
+/// every image it links, so tools find it in ELF files, image files and flash
+/// dumps alike.  The text fields hold UTF-8, cut short to fit if needed.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct FirmwareInfo {
+    bytes: [u8; FirmwareInfo::LEN],
+}
+
+impl FirmwareInfo {
+    /// Length of the encoded information
+    pub const LEN: usize = 256;
+    /// Offset from the start of the image (its vector table)
+    pub const OFFSET: usize = 0x200;
+
+    pub fn new(
+        version: &str,
+        git_commit: &str,
+        timestamp: u64,
+        board: &str,
+        features: &str,
+    ) -> Self {
+        let mut bytes = [0u8; Self::LEN];
+        bytes[0..4].copy_from_slice(&INFO_MAGIC.to_le_bytes());
+        bytes[4..8].copy_from_slice(&INFO_LAYOUT.to_le_bytes());
+        bytes[TIMESTAMP..TIMESTAMP + 8].copy_from_slice(&timestamp.to_le_bytes());
+        for &((offset, len), text) in &[
+            (VERSION, version),
+            (GIT_COMMIT, git_commit),
+            (BOARD, board),
+            (FEATURES, features),
+        ] {
+            let text = truncate(text, len);
+            bytes[offset..offset + text.len()].copy_from_slice(text.as_bytes());
+        }
+        FirmwareInfo { bytes }
+    }
+
+    /// Decodes the information, checking its magic (`SXFI`) and layout.
+    pub fn from_bytes(bytes: &[u8; Self::LEN]) -> Option<Self> {
+        let word = |index: usize| {
+            u32::from_le_bytes([
+                bytes[index],
+                bytes[index + 1],
+                bytes[index + 2],
+                bytes[index + 3],
+            ])
+        };
+        if word(0) != INFO_MAGIC || word(4) != INFO_LAYOUT {
+            return None;
+        }
+        Some(FirmwareInfo { bytes: *bytes })
+    }
+
+    pub fn to_bytes(&self) -> [u8; Self::LEN] {
+        self.bytes
+    }
+
+    /// Finds all the information in `data`, e.g. a flash dump holding the
+    /// bootloader and an application, with their offsets.
+    pub fn scan(data: &[u8]) -> impl Iterator<Item = (usize, FirmwareInfo)> + '_ {
+        (0..data.len().saturating_sub(Self::LEN - 1))
+            .step_by(4)
+            .filter_map(move |offset| {
+                let mut bytes = [0u8; Self::LEN];
+                bytes.copy_from_slice(&data[offset..offset + Self::LEN]);
+                Some((offset, Self::from_bytes(&bytes)?))
+            })
+    }
+
+    /// Version of the firmware, e.g. "0.1.0"
+    pub fn version(&self) -> &str {
+        self.text(VERSION)
+    }
+
+    /// Git commit the firmware was built from, with a `-dirty` suffix for
+    /// uncommitted changes
+    pub fn git_commit(&self) -> &str {
+        self.text(GIT_COMMIT)
+    }
+
+    /// Build time in seconds since the Unix epoch
+    pub fn timestamp(&self) -> u64 {
+        let mut bytes = [0u8; 8];
+        bytes.copy_from_slice(&self.bytes[TIMESTAMP..TIMESTAMP + 8]);
+        u64::from_le_bytes(bytes)
+    }
+
+    /// Name of the board the firmware was built for
+    pub fn board(&self) -> &str {
+        self.text(BOARD)
+    }
+
+    /// Enabled features, separated by commas
+    pub fn features(&self) -> &str {
+        self.text(FEATURES)
+    }
+
+    fn text(&self, (offset, len): (usize, usize)) -> &str {
+        let field = &self.bytes[offset..offset + len];
+        let end = field.iter().position(|&byte| byte == 0).unwrap_or(len);
+        match str::from_utf8(&field[..end]) {
+            Ok(text) => text,
+            Err(error) => str::from_utf8(&field[..error.valid_up_to()]).unwrap_or(""),
+        }
+    }
+}
+
+/// Longest prefix of `text` that fits into `len` bytes
+fn truncate(text: &str, len: usize) -> &str {
+    let mut end = text.len().min(len);
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    &text[..end]
+}
+
+impl fmt::Debug for FirmwareInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FirmwareInfo")
+            .field("version", &self.version())
+            .field("git_commit", &self.git_commit())
+            .field("timestamp", &self.timestamp())
+            .field("board", &self.board())
+            .field("features", &self.features())
+            .finish()
+    }
+}
+
+/// `version 0.1.0 (1a2b3c4d..., built 2024-05-01 12:00:00 UTC) for the SAM4E
+/// Xplained Pro, features: rt,sam4e`
+impl fmt::Display for FirmwareInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let seconds = self.timestamp();
+        let (year, month, day) = civil_from_days((seconds / 86_400) as i64);
+        let time = seconds % 86_400;
+        write!(
+            f,
+            "version {} ({}, built {:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC) for the {}, features: {}",
+            self.version(),
+            self.git_commit(),
+            year,
+            month,
+            day,
+            time / 3600,
+            time / 60 % 60,
+            time % 60,
+            self.board(),
+            self.features()
+        )
+    }
+}
+
+/// Gregorian calendar date of a day counted from 1970-01-01
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * month + 2) / 5 + 1) as u32;
+    let month = if month < 10 { month + 3 } else { month - 9 } as u32;
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
@@ -0,0 +1,334 @@
+//! Firmware image format
+//!
+//! Images installed by `sam_xplained_bootloader` (and by the SAM4S dual bank
+//! update manager) are described by an [`ImageHeader`]: version, length and
+//! CRC-32 of the image.  An image file is the encoded header followed by the
+//! image, the raw binary of the application.
+//!
+//! Signed image files carry a [`SignatureTrailer`] behind the image: an
+//! Ed25519 signature over the encoded header and the image.  [`verify`]
+//! checks it against the public key of whoever is allowed to release
+//! firmware, [`Verifier`] does the same over an image read in parts, e.g.
+//! from flash:
+//!
+//! ```ignore
+//! let mut verifier = Verifier::new(&PUBLIC_KEY, &header, &trailer)?;
+//! for chunk in image.chunks(512) {
+//!     verifier.update(chunk);
+//! }
+//! verifier.finish()?;
+//! ```
+//!
+//! Images are signed with a [`SigningKey`], usually by `sam_xplained_imgtool`
+//! on the host.
+//!
+//! The images themselves carry [`FirmwareInfo`] telling which build they are.
+//!
+//! [`dual_bank`] installs images into the spare bank of dual bank flash and
+//! rolls back images that fail their trial run.
+#![no_std]
+
+use core::convert::TryInto;
+use core::fmt;
+use ed25519_compact::{KeyPair, PublicKey, Seed, Signature, VerifyingState};
+
+pub mod dual_bank;
+mod info;
+
+pub use info::FirmwareInfo;
+
+const HEADER_MAGIC: u32 = 0x4D49_5853; // "SXIM"
+const SIGNATURE_MAGIC: u32 = 0x4753_5853; // "SXSG"
+
+/// Length of an Ed25519 public key
+pub const PUBLIC_KEY_LEN: usize = 32;
+/// Length of the seed an Ed25519 key pair is derived from
+pub const SEED_LEN: usize = 32;
+/// Length of an Ed25519 signature
+pub const SIGNATURE_LEN: usize = 64;
+
+/// Version, length and CRC of an image, sent ahead of the image itself
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ImageHeader {
+    pub version: u32,
+    /// Length of the image in bytes
+    pub length: u32,
+    /// CRC-32 (IEEE 802.3) of the image
+    pub crc: u32,
+}
+
+impl ImageHeader {
+    /// Length of the encoded header
+    pub const LEN: usize = 16;
+
+    /// Header of `image`
+    pub fn new(version: u32, image: &[u8]) -> Self {
+        ImageHeader {
+            version,
+            length: image.len() as u32,
+            crc: crc32(image),
+        }
+    }
+
+    /// Decodes a header (magic, version, length and CRC, all little endian)
+    pub fn from_bytes(bytes: &[u8; Self::LEN]) -> Option<Self> {
+        if word(bytes, 0) != HEADER_MAGIC {
+            return None;
+        }
+        Some(ImageHeader {
+            version: word(bytes, 4),
+            length: word(bytes, 8),
+            crc: word(bytes, 12),
+        })
+    }
+
+    pub fn to_bytes(&self) -> [u8; Self::LEN] {
+        let mut bytes = [0u8; Self::LEN];
+        bytes[0..4].copy_from_slice(&HEADER_MAGIC.to_le_bytes());
+        bytes[4..8].copy_from_slice(&self.version.to_le_bytes());
+        bytes[8..12].copy_from_slice(&self.length.to_le_bytes());
+        bytes[12..16].copy_from_slice(&self.crc.to_le_bytes());
+        bytes
+    }
+}
+
+/// Signature of a signed image, stored behind the image
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct SignatureTrailer {
+    /// Ed25519 signature over the encoded [`ImageHeader`] and the image
+    pub signature: [u8; SIGNATURE_LEN],
+}
+
+impl SignatureTrailer {
+    /// Length of the encoded trailer
+    pub const LEN: usize = 4 + SIGNATURE_LEN;
+
+    /// Decodes a trailer (magic `SXSG`, then the signature)
+    pub fn from_bytes(bytes: &[u8; Self::LEN]) -> Option<Self> {
+        if word(bytes, 0) != SIGNATURE_MAGIC {
+            return None;
+        }
+        let mut signature = [0u8; SIGNATURE_LEN];
+        signature.copy_from_slice(&bytes[4..]);
+        Some(SignatureTrailer { signature })
+    }
+
+    pub fn to_bytes(&self) -> [u8; Self::LEN] {
+        let mut bytes = [0u8; Self::LEN];
+        bytes[0..4].copy_from_slice(&SIGNATURE_MAGIC.to_le_bytes());
+        bytes[4..].copy_from_slice(&self.signature);
+        bytes
+    }
+}
+
+impl fmt::Debug for SignatureTrailer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("SignatureTrailer(")?;
+        for byte in &self.signature {
+            write!(f, "{:02x}", byte)?;
+        }
+        f.write_str(")")
+    }
+}
+
+/// Reasons a signature doesn't check out
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SignatureError {
+    /// The public key or the signature isn't a valid Ed25519 encoding
+    Malformed,
+    /// The image wasn't signed with the key, or was modified since
+    Invalid,
+}
+
+impl fmt::Display for SignatureError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SignatureError::Malformed => f.write_str("malformed key or signature"),
+            SignatureError::Invalid => f.write_str("signature does not match the image"),
+        }
+    }
+}
+
+/// Checks the signature of an image given in parts
+pub struct Verifier {
+    state: VerifyingState,
+}
+
+impl Verifier {
+    /// Starts checking the image described by `header` against `trailer`.
+    pub fn new(
+        public_key: &[u8; PUBLIC_KEY_LEN],
+        header: &ImageHeader,
+        trailer: &SignatureTrailer,
+    ) -> Result<Self, SignatureError> {
+        let mut state = PublicKey::new(*public_key)
+            .verify_incremental(&Signature::new(trailer.signature))
+            .map_err(|_| SignatureError::Malformed)?;
+        state.absorb(header.to_bytes());
+        Ok(Verifier { state })
+    }
+
+    /// Adds the next part of the image.
+    pub fn update(&mut self, data: &[u8]) {
+        self.state.absorb(data);
+    }
+
+    /// Whether the image was signed with the key.
+    pub fn finish(self) -> Result<(), SignatureError> {
+        self.state.verify().map_err(|_| SignatureError::Invalid)
+    }
+}
+
+/// Checks that `image`, described by `header`, was signed with the secret
+/// key belonging to `public_key`.
+pub fn verify(
+    public_key: &[u8; PUBLIC_KEY_LEN],
+    header: &ImageHeader,
+    image: &[u8],
+    trailer: &SignatureTrailer,
+) -> Result<(), SignatureError> {
+    let mut verifier = Verifier::new(public_key, header, trailer)?;
+    verifier.update(image);
+    verifier.finish()
+}
+
+/// Ed25519 key pair signing images
+pub struct SigningKey(KeyPair);
+
+impl SigningKey {
+    /// Derives the key pair from a secret 32 byte seed (RFC 8032).
+    pub fn from_seed(seed: &[u8; SEED_LEN]) -> Self {
+        SigningKey(KeyPair::from_seed(Seed::new(*seed)))
+    }
+
+    pub fn public_key(&self) -> [u8; PUBLIC_KEY_LEN] {
+        *self.0.pk
+    }
+
+    /// Signs an unsigned image file: the encoded header followed by the
+    /// image.  The signature is deterministic (RFC 8032).
+    pub fn sign(&self, file: &[u8]) -> SignatureTrailer {
+        SignatureTrailer {
+            signature: *self.0.sk.sign(file, None),
+        }
+    }
+}
+
+/// An image file, split into its parts
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ImageFile<'a> {
+    pub header: ImageHeader,
+    pub image: &'a [u8],
+    /// Signature behind the image, for signed image files
+    pub trailer: Option<SignatureTrailer>,
+}
+
+impl<'a> ImageFile<'a> {
+    /// Splits an image file.  Anything behind the image that isn't a
+    /// signature trailer, such as XMODEM padding, is ignored.
+    pub fn parse(file: &'a [u8]) -> Option<Self> {
+        let (header, rest) = split_array(file)?;
+        let header = ImageHeader::from_bytes(header)?;
+        let length = header.length as usize;
+        if rest.len() < length {
+            return None;
+        }
+        let (image, rest) = rest.split_at(length);
+        let trailer =
+            split_array(rest).and_then(|(trailer, _)| SignatureTrailer::from_bytes(trailer));
+        Some(ImageFile {
+            header,
+            image,
+            trailer,
+        })
+    }
+}
+
+fn split_array<const N: usize>(bytes: &[u8]) -> Option<(&[u8; N], &[u8])> {
+    if bytes.len() < N {
+        return None;
+    }
+    let (head, rest) = bytes.split_at(N);
+    Some((head.try_into().ok()?, rest))
+}
+
+fn word(bytes: &[u8], index: usize) -> u32 {
+    u32::from_le_bytes([
+        bytes[index],
+        bytes[index + 1],
+        bytes[index + 2],
+        bytes[index + 3],
+    ])
+}
+
+/// CRC-32 (IEEE 802.3), computed incrementally
+pub struct Crc32(u32);
+
+impl Crc32 {
+    pub fn new() -> Self {
+        Crc32(0xFFFF_FFFF)
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        for &byte in data {
+            self.0 = CRC_TABLE[((self.0 ^ byte as u32) & 0xFF) as usize] ^ (self.0 >> 8);
+        }
+    }
+
+    pub fn finish(&self) -> u32 {
+        !self.0
+    }
+}
+
+impl Default for Crc32 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// CRC-32 (IEEE 802.3) of `data`
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = Crc32::new();
+    crc.update(data);
+    crc.finish()
+}
+
+/// CRC-16/XMODEM (CCITT polynomial, initial value 0) of `data`, as used by
+/// YMODEM blocks and RPC frames
+pub fn crc16(data: &[u8]) -> u16 {
+    let mut crc = 0u16;
+    for &byte in data {
+        crc ^= (byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
+
+// A table keeps checking a whole image fast enough at the 4 MHz reset clock.
+const CRC_TABLE: [u32; 256] = crc_table();
+
+const fn crc_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut index = 0;
+    while index < 256 {
+        let mut crc = index as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[index] = crc;
+        index += 1;
+    }
+    table
+}
@@ -0,0 +1,248 @@
+use embedded_storage::nor_flash::{
+    ErrorType, NorFlash, NorFlashError, NorFlashErrorKind, ReadNorFlash,
+};
+use sam_xplained_image::dual_bank::{Bank, Boot, DualBank, Error, Layout};
+use sam_xplained_image::ImageHeader;
+
+const WRITE_SIZE: usize = 16;
+const ERASE_SIZE: usize = 4096;
+
+/// Space for the boot stage at the start of each bank
+const STAGE_SIZE: usize = 0x1000;
+/// Two 64K banks: the boot stage, the image behind a gap for its vector table
+/// alignment and the status block at the end
+const LAYOUT: Layout = Layout {
+    bank_size: 0x1_0000,
+    image_start: STAGE_SIZE as u32 + 0x200,
+    status_block: 0xF000,
+};
+
+#[derive(Debug, PartialEq)]
+struct NotAligned;
+
+impl NorFlashError for NotAligned {
+    fn kind(&self) -> NorFlashErrorKind {
+        NorFlashErrorKind::NotAligned
+    }
+}
+
+/// Erased flash covering both banks, with a boot stage at the start of each
+struct FakeFlash {
+    data: Vec<u8>,
+}
+
+impl FakeFlash {
+    fn new() -> Self {
+        let mut data = vec![0xFF; 2 * LAYOUT.bank_size as usize];
+        for bank in 0..2 {
+            let stage = bank * LAYOUT.bank_size as usize;
+            data[stage..stage + STAGE_SIZE].fill(0x5A);
+        }
+        FakeFlash { data }
+    }
+
+    /// Programs an image into `bank` without a header, as a debugger would.
+    fn program(&mut self, bank: Bank) {
+        let start = offset(bank) + LAYOUT.image_start as usize;
+        self.data[start..start + 4].copy_from_slice(&0x2002_8000u32.to_le_bytes());
+    }
+
+    fn image(&self, bank: Bank, length: usize) -> &[u8] {
+        let start = offset(bank) + LAYOUT.image_start as usize;
+        &self.data[start..start + length]
+    }
+
+    fn stage(&self, bank: Bank) -> &[u8] {
+        &self.data[offset(bank)..offset(bank) + STAGE_SIZE]
+    }
+}
+
+impl ErrorType for FakeFlash {
+    type Error = NotAligned;
+}
+
+impl ReadNorFlash for FakeFlash {
+    const READ_SIZE: usize = 1;
+
+    fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
+        let offset = offset as usize;
+        bytes.copy_from_slice(&self.data[offset..offset + bytes.len()]);
+        Ok(())
+    }
+
+    fn capacity(&self) -> usize {
+        self.data.len()
+    }
+}
+
+impl NorFlash for FakeFlash {
+    const WRITE_SIZE: usize = WRITE_SIZE;
+    const ERASE_SIZE: usize = ERASE_SIZE;
+
+    fn erase(&mut self, from: u32, to: u32) -> Result<(), Self::Error> {
+        let (from, to) = (from as usize, to as usize);
+        if !from.is_multiple_of(ERASE_SIZE) || !to.is_multiple_of(ERASE_SIZE) {
+            return Err(NotAligned);
+        }
+        self.data[from..to].fill(0xFF);
+        Ok(())
+    }
+
+    fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error> {
+        let offset = offset as usize;
+        if !offset.is_multiple_of(WRITE_SIZE) || !bytes.len().is_multiple_of(WRITE_SIZE) {
+            return Err(NotAligned);
+        }
+        for (index, &byte) in bytes.iter().enumerate() {
+            let cell = &mut self.data[offset + index];
+            assert!(
+                *cell == 0xFF || byte == 0xFF,
+                "programmed byte {:#x} twice",
+                offset + index
+            );
+            *cell &= byte;
+        }
+        Ok(())
+    }
+}
+
+fn offset(bank: Bank) -> usize {
+    match bank {
+        Bank::Bank0 => 0,
+        Bank::Bank1 => LAYOUT.bank_size as usize,
+    }
+}
+
+fn test_image(version: u32) -> Vec<u8> {
+    (0..1000u32).map(|index| (index * version) as u8).collect()
+}
+
+/// Installs `image` from the image running in `active`, in odd sized parts,
+/// returning the bank to boot next.
+fn install(flash: &mut FakeFlash, active: Bank, version: u32, image: &[u8]) -> Bank {
+    let mut banks = DualBank::new(flash, LAYOUT, active);
+    banks.begin(ImageHeader::new(version, image)).unwrap();
+    for part in image.chunks(37) {
+        banks.write(part).unwrap();
+    }
+    banks.finish().unwrap()
+}
+
+/// What the boot stage does after a reset into `bank`
+fn boot(flash: &mut FakeFlash, bank: Bank, watchdog_reset: bool) -> Boot {
+    DualBank::new(flash, LAYOUT, bank)
+        .boot(watchdog_reset)
+        .unwrap()
+}
+
+#[test]
+fn installs_into_other_bank() {
+    let mut flash = FakeFlash::new();
+    flash.program(Bank::Bank0);
+    let image = test_image(2);
+
+    assert_eq!(install(&mut flash, Bank::Bank0, 2, &image), Bank::Bank1);
+    assert_eq!(flash.image(Bank::Bank1, image.len()), &image[..]);
+    // The boot stages are left alone.
+    assert!(flash.stage(Bank::Bank0).iter().all(|&byte| byte != 0xFF));
+    assert!(flash.stage(Bank::Bank1).iter().all(|&byte| byte != 0xFF));
+
+    let mut banks = DualBank::new(&mut flash, LAYOUT, Bank::Bank1);
+    assert_eq!(
+        banks.running_image().unwrap(),
+        Some(ImageHeader::new(2, &image))
+    );
+    assert!(!banks.is_confirmed().unwrap());
+}
+
+#[test]
+fn reverts_image_that_never_confirms_itself() {
+    let mut flash = FakeFlash::new();
+    flash.program(Bank::Bank0);
+    let bank = install(&mut flash, Bank::Bank0, 2, &test_image(2));
+
+    // The new image runs once, but resets without confirming itself, e.g.
+    // because it crashed before getting as far as its update code.
+    assert_eq!(boot(&mut flash, bank, false), Boot::Start);
+    assert_eq!(boot(&mut flash, bank, false), Boot::Revert);
+    assert_eq!(boot(&mut flash, bank, false), Boot::Revert);
+
+    // The previous image keeps booting.
+    assert_eq!(boot(&mut flash, Bank::Bank0, false), Boot::Start);
+    assert_eq!(boot(&mut flash, Bank::Bank0, true), Boot::Start);
+}
+
+#[test]
+fn reverts_trial_image_reset_by_watchdog() {
+    let mut flash = FakeFlash::new();
+    flash.program(Bank::Bank0);
+    let bank = install(&mut flash, Bank::Bank0, 2, &test_image(2));
+
+    assert_eq!(boot(&mut flash, bank, false), Boot::Start);
+    assert_eq!(boot(&mut flash, bank, true), Boot::Revert);
+}
+
+#[test]
+fn keeps_confirmed_image() {
+    let mut flash = FakeFlash::new();
+    flash.program(Bank::Bank0);
+    let bank = install(&mut flash, Bank::Bank0, 2, &test_image(2));
+
+    assert_eq!(boot(&mut flash, bank, false), Boot::Start);
+    DualBank::new(&mut flash, LAYOUT, bank).confirm().unwrap();
+    for watchdog_reset in [false, true, false] {
+        assert_eq!(boot(&mut flash, bank, watchdog_reset), Boot::Start);
+    }
+
+    // The confirmed image installs the next one into the first bank, which
+    // is then the one to fall back from.
+    let next = install(&mut flash, bank, 3, &test_image(3));
+    assert_eq!(next, Bank::Bank0);
+    assert_eq!(boot(&mut flash, next, false), Boot::Start);
+    assert_eq!(boot(&mut flash, next, true), Boot::Revert);
+}
+
+#[test]
+fn keeps_trial_image_without_fallback() {
+    // Nothing to fall back to in the first bank
+    let mut flash = FakeFlash::new();
+    let bank = install(&mut flash, Bank::Bank0, 2, &test_image(2));
+
+    assert_eq!(boot(&mut flash, bank, false), Boot::Start);
+    assert_eq!(boot(&mut flash, bank, true), Boot::Start);
+}
+
+#[test]
+fn rejects_bad_updates() {
+    let mut flash = FakeFlash::new();
+    flash.program(Bank::Bank0);
+    let bank = install(&mut flash, Bank::Bank0, 2, &test_image(2));
+    let image = test_image(3);
+
+    // An image on trial can't install another one.
+    let mut banks = DualBank::new(&mut flash, LAYOUT, bank);
+    assert_eq!(
+        banks.begin(ImageHeader::new(3, &image)),
+        Err(Error::NotConfirmed)
+    );
+    banks.confirm().unwrap();
+
+    assert_eq!(
+        banks.begin(ImageHeader::new(2, &image)),
+        Err(Error::NotNewer)
+    );
+    let mut header = ImageHeader::new(3, &image);
+    header.length = LAYOUT.image_capacity() + 1;
+    assert_eq!(banks.begin(header), Err(Error::TooLarge));
+    assert_eq!(banks.write(&image), Err(Error::NotStarted));
+
+    let mut header = ImageHeader::new(3, &image);
+    header.crc ^= 1;
+    banks.begin(header).unwrap();
+    banks.write(&image).unwrap();
+    assert_eq!(banks.finish(), Err(Error::BadCrc));
+
+    banks.begin(ImageHeader::new(3, &image)).unwrap();
+    banks.write(&image[..100]).unwrap();
+    assert_eq!(banks.finish(), Err(Error::LengthMismatch));
+}
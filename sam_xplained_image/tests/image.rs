@@ -0,0 +1,196 @@
+use sam_xplained_image::{
+    crc16, crc32, verify, Crc32, ImageFile, ImageHeader, SignatureError, SignatureTrailer,
+    SigningKey, Verifier,
+};
+
+fn hex<const N: usize>(text: &str) -> [u8; N] {
+    let mut bytes = [0u8; N];
+    assert_eq!(text.len(), 2 * N);
+    for (index, byte) in bytes.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&text[2 * index..2 * index + 2], 16).unwrap();
+    }
+    bytes
+}
+
+// RFC 8032 section 7.1, tests 1 to 3: secret key (seed), public key, message
+// and signature
+const RFC8032: [(&str, &str, &[u8], &str); 3] = [
+    (
+        "9d61b19deffd5a60ba844af492ec2cc44449c5697b326919703bac031cae7f60",
+        "d75a980182b10ab7d54bfed3c964073a0ee172f3daa62325af021a68f707511a",
+        &[],
+        "e5564300c360ac729086e2cc806e828a84877f1eb8e5d974d873e065224901555fb8821590a33bacc61e39701cf9b46bd25bf5f0595bbe24655141438e7a100b",
+    ),
+    (
+        "4ccd089b28ff96da9db6c346ec114e0f5b8a319f35aba624da8cf6ed4fb8a6fb",
+        "3d4017c3e843895a92b70aa74d1b7ebc9c982ccf2ec4968cc0cd55f12af4660c",
+        &[0x72],
+        "92a009a9f0d4cab8720e820b5f642540a2b27b5416503f8fb3762223ebdb69da085ac1e43e15996e458f3613d0f11d8c387b2eaeb4302aeeb00d291612bb0c00",
+    ),
+    (
+        "c5aa8df43f9f837bedb7442f31dcb7b166d38535076f094b85ce3a2e0b4458f7",
+        "fc51cd8e6218a1a38da47ed00230f0580816ed13ba3303ac5deb911548908025",
+        &[0xaf, 0x82],
+        "6291d657deec24024827e69c3abe01a30ce548a284743a445e3680d7db5ac3ac18ff9b538d16f290ae67f760984dc6594a7c15e9716ed28dc027beceea1ec40a",
+    ),
+];
+
+// Image signed with the key of RFC 8032 test 1, signature computed with an
+// independent Ed25519 implementation (Python `cryptography`)
+const IMAGE_VERSION: u32 = 7;
+const IMAGE_HEADER: &str = "5358494d07000000000300002adfc0b0";
+const IMAGE_SIGNATURE: &str = "3ec9ac84e82d93e2c38a2817d79209e553f43a2099d26b8856ce5061e5827ed5fbf732c41c15c7b653b11fc0c946a0dc6821ce325b40323163fe94b217690402";
+
+fn test_key() -> SigningKey {
+    SigningKey::from_seed(&hex(RFC8032[0].0))
+}
+
+fn test_image() -> Vec<u8> {
+    (0..3).flat_map(|_| 0..=255u8).collect()
+}
+
+fn signed_file(key: &SigningKey, version: u32, image: &[u8]) -> Vec<u8> {
+    let mut file = ImageHeader::new(version, image).to_bytes().to_vec();
+    file.extend_from_slice(image);
+    let trailer = key.sign(&file);
+    file.extend_from_slice(&trailer.to_bytes());
+    file
+}
+
+#[test]
+fn crc16_check_value() {
+    // CRC-16/XMODEM
+    assert_eq!(crc16(b"123456789"), 0x31C3);
+    assert_eq!(crc16(b""), 0);
+}
+
+#[test]
+fn crc32_check_value() {
+    assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    let mut crc = Crc32::new();
+    crc.update(b"1234");
+    crc.update(b"56789");
+    assert_eq!(crc.finish(), 0xCBF4_3926);
+}
+
+#[test]
+fn header_round_trip() {
+    let header = ImageHeader::new(IMAGE_VERSION, &test_image());
+    assert_eq!(header.to_bytes(), hex(IMAGE_HEADER));
+    assert_eq!(ImageHeader::from_bytes(&header.to_bytes()), Some(header));
+
+    let mut bytes = header.to_bytes();
+    bytes[0] ^= 1;
+    assert_eq!(ImageHeader::from_bytes(&bytes), None);
+}
+
+#[test]
+fn rfc8032_public_keys() {
+    for (seed, public_key, _, _) in RFC8032 {
+        assert_eq!(
+            SigningKey::from_seed(&hex(seed)).public_key(),
+            hex(public_key)
+        );
+    }
+}
+
+#[test]
+fn rfc8032_signatures() {
+    for (seed, _, message, signature) in RFC8032 {
+        let trailer = SigningKey::from_seed(&hex(seed)).sign(message);
+        assert_eq!(trailer.signature, hex(signature));
+    }
+}
+
+#[test]
+fn signs_known_image() {
+    let file = signed_file(&test_key(), IMAGE_VERSION, &test_image());
+    let file = ImageFile::parse(&file).unwrap();
+    assert_eq!(file.header.to_bytes(), hex(IMAGE_HEADER));
+    assert_eq!(file.image, &test_image()[..]);
+    assert_eq!(file.trailer.unwrap().signature, hex(IMAGE_SIGNATURE));
+}
+
+#[test]
+fn verifies_known_image() {
+    let header = ImageHeader::from_bytes(&hex(IMAGE_HEADER)).unwrap();
+    let trailer = SignatureTrailer {
+        signature: hex(IMAGE_SIGNATURE),
+    };
+    let public_key = hex(RFC8032[0].1);
+    assert_eq!(
+        verify(&public_key, &header, &test_image(), &trailer),
+        Ok(())
+    );
+
+    // In parts, as read from flash
+    let mut verifier = Verifier::new(&public_key, &header, &trailer).unwrap();
+    for chunk in test_image().chunks(100) {
+        verifier.update(chunk);
+    }
+    assert_eq!(verifier.finish(), Ok(()));
+}
+
+#[test]
+fn rejects_modified_image() {
+    let image = test_image();
+    let file = signed_file(&test_key(), IMAGE_VERSION, &image);
+    let file = ImageFile::parse(&file).unwrap();
+    let public_key = test_key().public_key();
+    let trailer = file.trailer.unwrap();
+
+    let mut modified = image.clone();
+    modified[300] ^= 0x40;
+    assert_eq!(
+        verify(&public_key, &file.header, &modified, &trailer),
+        Err(SignatureError::Invalid)
+    );
+
+    // The header is signed too, so it can't be reused for another version.
+    let mut header = file.header;
+    header.version += 1;
+    assert_eq!(
+        verify(&public_key, &header, &image, &trailer),
+        Err(SignatureError::Invalid)
+    );
+
+    let mut signature = trailer;
+    signature.signature[10] ^= 1;
+    assert!(verify(&public_key, &file.header, &image, &signature).is_err());
+}
+
+#[test]
+fn rejects_other_key() {
+    let image = test_image();
+    let file = signed_file(&test_key(), IMAGE_VERSION, &image);
+    let file = ImageFile::parse(&file).unwrap();
+    let other = SigningKey::from_seed(&hex(RFC8032[1].0)).public_key();
+    assert_eq!(
+        verify(&other, &file.header, &image, &file.trailer.unwrap()),
+        Err(SignatureError::Invalid)
+    );
+}
+
+#[test]
+fn parses_unsigned_and_padded_files() {
+    let image = test_image();
+    let header = ImageHeader::new(1, &image);
+    let mut file = header.to_bytes().to_vec();
+    file.extend_from_slice(&image);
+    let unsigned = ImageFile::parse(&file).unwrap();
+    assert_eq!(unsigned.header, header);
+    assert_eq!(unsigned.image, &image[..]);
+    assert_eq!(unsigned.trailer, None);
+
+    // XMODEM pads the last block with SUB.
+    let mut padded = file.clone();
+    padded.extend_from_slice(&[0x1A; 100]);
+    assert_eq!(ImageFile::parse(&padded), Some(unsigned));
+
+    let mut signed = signed_file(&test_key(), 1, &image);
+    signed.extend_from_slice(&[0x1A; 60]);
+    assert!(ImageFile::parse(&signed).unwrap().trailer.is_some());
+
+    assert_eq!(ImageFile::parse(&file[..ImageHeader::LEN + 10]), None);
+    assert_eq!(ImageFile::parse(&image), None);
+}
@@ -0,0 +1,86 @@
+use sam_xplained_image::FirmwareInfo;
+
+fn info() -> FirmwareInfo {
+    FirmwareInfo::new(
+        "0.1.0",
+        "78c3005e0c1f6d5fb2a1f6b1d8f0e6c3a9b4d2e1-dirty",
+        1_714_564_800,
+        "SAM4E Xplained Pro",
+        "application,rt,sam4e",
+    )
+}
+
+#[test]
+fn round_trip() {
+    let info = info();
+    let decoded = FirmwareInfo::from_bytes(&info.to_bytes()).unwrap();
+    assert_eq!(decoded, info);
+    assert_eq!(decoded.version(), "0.1.0");
+    assert_eq!(
+        decoded.git_commit(),
+        "78c3005e0c1f6d5fb2a1f6b1d8f0e6c3a9b4d2e1-dirty"
+    );
+    assert_eq!(decoded.timestamp(), 1_714_564_800);
+    assert_eq!(decoded.board(), "SAM4E Xplained Pro");
+    assert_eq!(decoded.features(), "application,rt,sam4e");
+}
+
+#[test]
+fn known_encoding() {
+    let bytes = info().to_bytes();
+    assert_eq!(&bytes[0..8], b"SXFI\x01\x00\x00\x00");
+    assert_eq!(&bytes[8..16], &1_714_564_800u64.to_le_bytes());
+    assert_eq!(&bytes[16..22], b"0.1.0\0");
+    assert_eq!(&bytes[96..114], b"SAM4E Xplained Pro");
+
+    let mut corrupted = bytes;
+    corrupted[1] ^= 1;
+    assert_eq!(FirmwareInfo::from_bytes(&corrupted), None);
+    let mut newer = bytes;
+    newer[4] = 2;
+    assert_eq!(FirmwareInfo::from_bytes(&newer), None);
+}
+
+#[test]
+fn truncates_long_fields() {
+    let features = "feature,".repeat(40);
+    let info = FirmwareInfo::new("1.0.0-release-candidates-ünïcødé", "", 0, "", &features);
+    assert_eq!(info.features().len(), 128);
+    // Cut at a character boundary
+    assert_eq!(info.version(), "1.0.0-release-candidates-ünïc");
+    assert_eq!(info.git_commit(), "");
+}
+
+#[test]
+fn displays_build_time() {
+    assert_eq!(
+        info().to_string(),
+        "version 0.1.0 (78c3005e0c1f6d5fb2a1f6b1d8f0e6c3a9b4d2e1-dirty, built 2024-05-01 12:00:00 UTC) \
+         for the SAM4E Xplained Pro, features: application,rt,sam4e"
+    );
+    let leap_day = FirmwareInfo::new("", "", 951_825_599, "", "");
+    assert!(leap_day
+        .to_string()
+        .contains("built 2000-02-29 11:59:59 UTC"));
+}
+
+#[test]
+fn scans_flash_dumps() {
+    // Bootloader at 0 and application at 64K + 0x200, both with their info
+    // behind the vector table
+    let mut flash = vec![0xFF; 0x2_0000];
+    let bootloader = FirmwareInfo::new("0.1.0", "a", 0, "SAM4E Xplained Pro", "bootloader");
+    let application = info();
+    let app_info = 0x1_0200 + FirmwareInfo::OFFSET;
+    flash[FirmwareInfo::OFFSET..FirmwareInfo::OFFSET + FirmwareInfo::LEN]
+        .copy_from_slice(&bootloader.to_bytes());
+    flash[app_info..app_info + FirmwareInfo::LEN].copy_from_slice(&application.to_bytes());
+
+    let found: Vec<_> = FirmwareInfo::scan(&flash).collect();
+    assert_eq!(
+        found,
+        [(FirmwareInfo::OFFSET, bootloader), (app_info, application)]
+    );
+    assert_eq!(FirmwareInfo::scan(&flash[..0x2FF]).count(), 0);
+    assert_eq!(FirmwareInfo::scan(&[]).count(), 0);
+}
@@ -0,0 +1,272 @@
+//! Packaging and signing of firmware images
+//!
+//! Turns the ELF files (or raw binaries) built by the board crates into
+//! image files for `sam_xplained_bootloader`: the `ImageHeader`, the image
+//! and, when signed, the `SignatureTrailer` (see `sam_xplained_image`).
+//! [`firmware_info`] tells which build an ELF file, image file or flash dump
+//! holds.
+//!
+//! ```ignore
+//! let (address, image) = elf_to_bin(&std::fs::read("app.elf")?)?;
+//! let key = read_signing_key(&std::fs::read_to_string("release.key")?)?;
+//! std::fs::write("app.img", package(1, &image, Some(&key)))?;
+//! ```
+
+use std::convert::TryInto;
+use std::fmt;
+
+use sam_xplained_image::{
+    verify, FirmwareInfo, ImageFile, ImageHeader, SignatureError, SigningKey, PUBLIC_KEY_LEN,
+    SEED_LEN,
+};
+
+/// Largest gap between two segments filled in by [`elf_to_bin`]
+pub const MAX_GAP: u32 = 0x1_0000;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Error {
+    /// Not a 32-bit little endian ARM ELF file
+    NotArmElf,
+    /// The ELF file is cut short or inconsistent
+    Malformed,
+    /// The ELF file has nothing to load
+    Empty,
+    /// The segments are too far apart for a single image
+    Gap {
+        from: u32,
+        to: u32,
+    },
+    /// A key file isn't the hexadecimal encoding of a key
+    BadKey,
+    /// The file isn't an image file
+    NotAnImage,
+    /// The image doesn't match the CRC in its header
+    BadCrc,
+    /// The image has no signature
+    Unsigned,
+    Signature(SignatureError),
+    /// The file holds no build information
+    NoFirmwareInfo,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::NotArmElf => f.write_str("not a 32-bit little endian ARM ELF file"),
+            Error::Malformed => f.write_str("malformed ELF file"),
+            Error::Empty => f.write_str("ELF file has no loadable contents"),
+            Error::Gap { from, to } => write!(
+                f,
+                "segments at {:#010x} and {:#010x} are too far apart",
+                from, to
+            ),
+            Error::BadKey => f.write_str("key file must hold 64 hexadecimal digits"),
+            Error::NotAnImage => f.write_str("not an image file"),
+            Error::BadCrc => f.write_str("image CRC does not match its header"),
+            Error::Unsigned => f.write_str("image is not signed"),
+            Error::Signature(error) => write!(f, "bad signature: {}", error),
+            Error::NoFirmwareInfo => f.write_str("no firmware information found"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+const PT_LOAD: u32 = 1;
+const EM_ARM: u16 = 40;
+
+/// Extracts the binary the flash is programmed with from an ELF file, as
+/// `objcopy -O binary` does: the contents of the loadable segments at their
+/// load (physical) addresses, with gaps filled with `0xFF`.  Returns the
+/// address of the first byte and the binary.
+pub fn elf_to_bin(elf: &[u8]) -> Result<(u32, Vec<u8>), Error> {
+    if elf.len() < 52 || &elf[0..4] != b"\x7fELF" {
+        return Err(Error::NotArmElf);
+    }
+    // 32-bit, little endian, ARM
+    if elf[4] != 1 || elf[5] != 1 || half(elf, 18)? != EM_ARM {
+        return Err(Error::NotArmElf);
+    }
+
+    let table = word(elf, 28)? as usize;
+    let entry_size = half(elf, 42)? as usize;
+    let entries = half(elf, 44)? as usize;
+    if entry_size < 32 {
+        return Err(Error::Malformed);
+    }
+
+    let mut segments = Vec::new();
+    for index in 0..entries {
+        let entry = table + index * entry_size;
+        let (kind, offset, address, size) = (
+            word(elf, entry)?,
+            word(elf, entry + 4)? as usize,
+            word(elf, entry + 12)?,
+            word(elf, entry + 16)?,
+        );
+        if kind != PT_LOAD || size == 0 {
+            continue;
+        }
+        let data = elf
+            .get(offset..offset + size as usize)
+            .ok_or(Error::Malformed)?;
+        if address.checked_add(size).is_none() {
+            return Err(Error::Malformed);
+        }
+        segments.push((address, data));
+    }
+    segments.sort_by_key(|&(address, _)| address);
+
+    let start = segments.first().ok_or(Error::Empty)?.0;
+    let mut binary: Vec<u8> = Vec::new();
+    for (address, data) in segments {
+        let end = start + binary.len() as u32;
+        if address < end {
+            return Err(Error::Malformed);
+        }
+        if address - end > MAX_GAP {
+            return Err(Error::Gap {
+                from: end,
+                to: address,
+            });
+        }
+        binary.resize((address - start) as usize, 0xFF);
+        binary.extend_from_slice(data);
+    }
+    Ok((start, binary))
+}
+
+/// Contents and address of the section called `name` in an ELF file
+pub fn elf_section<'a>(elf: &'a [u8], name: &str) -> Result<Option<(u32, &'a [u8])>, Error> {
+    if elf.len() < 52 || &elf[0..4] != b"\x7fELF" || elf[4] != 1 || elf[5] != 1 {
+        return Err(Error::NotArmElf);
+    }
+    let table = word(elf, 32)? as usize;
+    let entry_size = half(elf, 46)? as usize;
+    let entries = half(elf, 48)? as usize;
+    let names = half(elf, 50)? as usize;
+    if entries == 0 {
+        return Ok(None);
+    }
+    if entry_size < 40 || names >= entries {
+        return Err(Error::Malformed);
+    }
+
+    // Section headers: name, type, flags, address, offset, size
+    let section = |index: usize| -> Result<(u32, u32, &'a [u8]), Error> {
+        let entry = table + index * entry_size;
+        let (offset, size) = (
+            word(elf, entry + 16)? as usize,
+            word(elf, entry + 20)? as usize,
+        );
+        let contents = elf.get(offset..offset + size).ok_or(Error::Malformed)?;
+        Ok((word(elf, entry)?, word(elf, entry + 12)?, contents))
+    };
+    let (_, _, strings) = section(names)?;
+    for index in 0..entries {
+        let (offset, address, contents) = section(index)?;
+        let rest = strings.get(offset as usize..).ok_or(Error::Malformed)?;
+        if rest.split(|&byte| byte == 0).next() == Some(name.as_bytes()) {
+            return Ok(Some((address, contents)));
+        }
+    }
+    Ok(None)
+}
+
+/// Build information in an ELF file (from its `.firmware_info` section), or
+/// everywhere in an image file or flash dump, with its address or offset
+pub fn firmware_info(file: &[u8]) -> Result<Vec<(u32, FirmwareInfo)>, Error> {
+    let found: Vec<(u32, FirmwareInfo)> = if file.starts_with(b"\x7fELF") {
+        elf_section(file, ".firmware_info")?
+            .and_then(|(address, contents)| {
+                let bytes = contents.get(..FirmwareInfo::LEN)?.try_into().ok()?;
+                Some((address, FirmwareInfo::from_bytes(bytes)?))
+            })
+            .into_iter()
+            .collect()
+    } else {
+        FirmwareInfo::scan(file)
+            .map(|(offset, info)| (offset as u32, info))
+            .collect()
+    };
+    if found.is_empty() {
+        return Err(Error::NoFirmwareInfo);
+    }
+    Ok(found)
+}
+
+fn half(bytes: &[u8], index: usize) -> Result<u16, Error> {
+    let bytes = bytes.get(index..index + 2).ok_or(Error::Malformed)?;
+    Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+}
+
+fn word(bytes: &[u8], index: usize) -> Result<u32, Error> {
+    let bytes = bytes.get(index..index + 4).ok_or(Error::Malformed)?;
+    Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+/// Builds an image file, signed if a key is given.
+pub fn package(version: u32, image: &[u8], key: Option<&SigningKey>) -> Vec<u8> {
+    let mut file = ImageHeader::new(version, image).to_bytes().to_vec();
+    file.extend_from_slice(image);
+    if let Some(key) = key {
+        let trailer = key.sign(&file);
+        file.extend_from_slice(&trailer.to_bytes());
+    }
+    file
+}
+
+/// Checks an image file against the CRC in its header and, if a public key
+/// is given, its signature.
+pub fn check<'a>(
+    file: &'a [u8],
+    public_key: Option<&[u8; PUBLIC_KEY_LEN]>,
+) -> Result<ImageFile<'a>, Error> {
+    let parsed = ImageFile::parse(file).ok_or(Error::NotAnImage)?;
+    if ImageHeader::new(parsed.header.version, parsed.image) != parsed.header {
+        return Err(Error::BadCrc);
+    }
+    if let Some(public_key) = public_key {
+        let trailer = parsed.trailer.ok_or(Error::Unsigned)?;
+        verify(public_key, &parsed.header, parsed.image, &trailer).map_err(Error::Signature)?;
+    }
+    Ok(parsed)
+}
+
+/// Generates a new signing key from the operating system's random numbers,
+/// returning its seed.
+pub fn generate_seed() -> Result<[u8; SEED_LEN], getrandom::Error> {
+    let mut seed = [0u8; SEED_LEN];
+    getrandom::fill(&mut seed)?;
+    Ok(seed)
+}
+
+/// Reads a secret key file: the seed in hexadecimal.
+pub fn read_signing_key(text: &str) -> Result<SigningKey, Error> {
+    Ok(SigningKey::from_seed(&from_hex(text)?))
+}
+
+/// Reads a public key file: the key in hexadecimal.
+pub fn read_public_key(text: &str) -> Result<[u8; PUBLIC_KEY_LEN], Error> {
+    from_hex(text)
+}
+
+/// Encodes a key for a key file.
+pub fn to_hex(bytes: &[u8]) -> String {
+    let mut text: String = bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+    text.push('\n');
+    text
+}
+
+fn from_hex<const N: usize>(text: &str) -> Result<[u8; N], Error> {
+    let text = text.trim();
+    if text.len() != 2 * N || !text.is_ascii() {
+        return Err(Error::BadKey);
+    }
+    let mut bytes = [0u8; N];
+    for (index, byte) in bytes.iter_mut().enumerate() {
+        *byte =
+            u8::from_str_radix(&text[2 * index..2 * index + 2], 16).map_err(|_| Error::BadKey)?;
+    }
+    Ok(bytes)
+}
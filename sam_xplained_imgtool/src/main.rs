@@ -0,0 +1,225 @@
+//! Command line front end of `sam_xplained_imgtool`
+
+use std::env;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process;
+
+use sam_xplained_image::SigningKey;
+use sam_xplained_imgtool::{
+    check, elf_to_bin, firmware_info, generate_seed, package, read_public_key, read_signing_key,
+    to_hex,
+};
+
+const USAGE: &str = "\
+usage: sam_xplained_imgtool keygen <name>
+       sam_xplained_imgtool create --version <n> [--key <name.key>] [--address <addr>] <input> <output>
+       sam_xplained_imgtool verify [--key <name.pub>] <image>
+       sam_xplained_imgtool info <file>
+
+keygen  writes a new signing key to <name>.key and its public key to <name>.pub
+create  packages an ELF file or a raw binary as an image file, signed with
+        --key; --address checks where the ELF file is linked
+verify  checks an image file's CRC and, with --key, its signature
+info    shows the build information in an ELF file, image file or flash dump";
+
+type Result<T> = std::result::Result<T, String>;
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let result = match args.first().map(String::as_str) {
+        Some("keygen") => keygen(&args[1..]),
+        Some("create") => create(&args[1..]),
+        Some("verify") => verify(&args[1..]),
+        Some("info") => info(&args[1..]),
+        _ => Err(USAGE.to_string()),
+    };
+    if let Err(error) = result {
+        eprintln!("{}", error);
+        process::exit(1);
+    }
+}
+
+/// Options and positional arguments of a subcommand
+struct Args {
+    options: Vec<(String, String)>,
+    positional: Vec<String>,
+}
+
+impl Args {
+    fn parse(args: &[String], names: &[&str], positional: usize) -> Result<Self> {
+        let mut parsed = Args {
+            options: Vec::new(),
+            positional: Vec::new(),
+        };
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            if let Some(name) = arg.strip_prefix("--") {
+                if !names.contains(&name) {
+                    return Err(format!("unknown option `{}`\n\n{}", arg, USAGE));
+                }
+                let value = args
+                    .next()
+                    .ok_or_else(|| format!("`{}` needs a value", arg))?;
+                parsed.options.push((name.to_string(), value.clone()));
+            } else {
+                parsed.positional.push(arg.clone());
+            }
+        }
+        if parsed.positional.len() != positional {
+            return Err(USAGE.to_string());
+        }
+        Ok(parsed)
+    }
+
+    fn option(&self, name: &str) -> Option<&str> {
+        self.options
+            .iter()
+            .rev()
+            .find(|(option, _)| option == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+fn parse_number(text: &str) -> Result<u32> {
+    let number = match text.strip_prefix("0x") {
+        Some(hex) => u32::from_str_radix(hex, 16),
+        None => text.parse(),
+    };
+    number.map_err(|_| format!("invalid number `{}`", text))
+}
+
+fn read(path: &Path) -> Result<Vec<u8>> {
+    fs::read(path).map_err(|error| format!("cannot read {}: {}", path.display(), error))
+}
+
+fn read_text(path: &Path) -> Result<String> {
+    fs::read_to_string(path).map_err(|error| format!("cannot read {}: {}", path.display(), error))
+}
+
+fn write(path: &Path, contents: &[u8]) -> Result<()> {
+    fs::write(path, contents).map_err(|error| format!("cannot write {}: {}", path.display(), error))
+}
+
+fn keygen(args: &[String]) -> Result<()> {
+    let args = Args::parse(args, &[], 1)?;
+    let name = &args.positional[0];
+    let (secret, public) = (
+        PathBuf::from(format!("{}.key", name)),
+        PathBuf::from(format!("{}.pub", name)),
+    );
+    if secret.exists() {
+        return Err(format!("{} exists already", secret.display()));
+    }
+
+    let seed = generate_seed().map_err(|error| format!("no random numbers: {}", error))?;
+    let key = SigningKey::from_seed(&seed);
+    // Only readable by the owner, and never overwriting another key
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options
+        .open(&secret)
+        .and_then(|mut file| file.write_all(to_hex(&seed).as_bytes()))
+        .map_err(|error| format!("cannot write {}: {}", secret.display(), error))?;
+    write(&public, to_hex(&key.public_key()).as_bytes())?;
+    println!(
+        "secret key: {} (keep it out of version control)\npublic key: {}",
+        secret.display(),
+        public.display()
+    );
+    Ok(())
+}
+
+fn create(args: &[String]) -> Result<()> {
+    let args = Args::parse(args, &["version", "key", "address"], 2)?;
+    let version = parse_number(args.option("version").ok_or("--version is missing")?)?;
+    let (input, output) = (
+        Path::new(&args.positional[0]),
+        Path::new(&args.positional[1]),
+    );
+
+    let contents = read(input)?;
+    let image = if contents.starts_with(b"\x7fELF") {
+        let (address, image) =
+            elf_to_bin(&contents).map_err(|error| format!("{}: {}", input.display(), error))?;
+        if let Some(expected) = args.option("address") {
+            let expected = parse_number(expected)?;
+            if address != expected {
+                return Err(format!(
+                    "{} is linked at {:#010x}, not {:#010x}",
+                    input.display(),
+                    address,
+                    expected
+                ));
+            }
+        }
+        image
+    } else {
+        contents
+    };
+    if image.is_empty() {
+        return Err(format!("{} is empty", input.display()));
+    }
+
+    let key = match args.option("key") {
+        Some(path) => Some(
+            read_signing_key(&read_text(Path::new(path))?)
+                .map_err(|error| format!("{}: {}", path, error))?,
+        ),
+        None => None,
+    };
+    let file = package(version, &image, key.as_ref());
+    write(output, &file)?;
+    println!(
+        "{}: version {}, {} bytes, {}",
+        output.display(),
+        version,
+        image.len(),
+        if key.is_some() { "signed" } else { "unsigned" }
+    );
+    Ok(())
+}
+
+fn verify(args: &[String]) -> Result<()> {
+    let args = Args::parse(args, &["key"], 1)?;
+    let path = Path::new(&args.positional[0]);
+    let public_key = match args.option("key") {
+        Some(key) => Some(
+            read_public_key(&read_text(Path::new(key))?)
+                .map_err(|error| format!("{}: {}", key, error))?,
+        ),
+        None => None,
+    };
+
+    let contents = read(path)?;
+    let file = check(&contents, public_key.as_ref())
+        .map_err(|error| format!("{}: {}", path.display(), error))?;
+    println!(
+        "{}: version {}, {} bytes, CRC {:#010x}, {}",
+        path.display(),
+        file.header.version,
+        file.header.length,
+        file.header.crc,
+        match (file.trailer, public_key) {
+            (Some(_), Some(_)) => "signature verified",
+            (Some(_), None) => "signed",
+            (None, _) => "unsigned",
+        }
+    );
+    Ok(())
+}
+
+fn info(args: &[String]) -> Result<()> {
+    let args = Args::parse(args, &[], 1)?;
+    let path = Path::new(&args.positional[0]);
+    let contents = read(path)?;
+    let found =
+        firmware_info(&contents).map_err(|error| format!("{}: {}", path.display(), error))?;
+    for (location, info) in found {
+        println!("{:#010x}: {}", location, info);
+    }
+    Ok(())
+}
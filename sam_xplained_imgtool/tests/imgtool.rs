@@ -0,0 +1,345 @@
+use std::path::PathBuf;
+use std::process::Command;
+
+use sam_xplained_image::{FirmwareInfo, ImageFile, SignatureError, SigningKey};
+use sam_xplained_imgtool::{
+    check, elf_section, elf_to_bin, firmware_info, package, read_public_key, read_signing_key,
+    to_hex, Error, MAX_GAP,
+};
+
+const PT_LOAD: u32 = 1;
+const PT_NOTE: u32 = 4;
+
+/// Program header: type, load address, contents and size in memory
+type Segment<'a> = (u32, u32, &'a [u8], u32);
+
+/// Minimal ARM ELF file with the given program headers
+fn elf(segments: &[Segment]) -> Vec<u8> {
+    let mut file = vec![0u8; 52];
+    file[0..4].copy_from_slice(b"\x7fELF");
+    file[4] = 1; // 32-bit
+    file[5] = 1; // little endian
+    file[6] = 1;
+    file[16..18].copy_from_slice(&2u16.to_le_bytes()); // executable
+    file[18..20].copy_from_slice(&40u16.to_le_bytes()); // ARM
+    file[28..32].copy_from_slice(&52u32.to_le_bytes());
+    file[42..44].copy_from_slice(&32u16.to_le_bytes());
+    file[44..46].copy_from_slice(&(segments.len() as u16).to_le_bytes());
+
+    let mut offset = 52 + 32 * segments.len() as u32;
+    for &(kind, address, data, memory_size) in segments {
+        // Virtual addresses differ from the load addresses, as for .data.
+        let words = [
+            kind,
+            offset,
+            address | 0x1000_0000,
+            address,
+            data.len() as u32,
+            memory_size,
+            5,
+            4,
+        ];
+        for word in &words {
+            file.extend_from_slice(&word.to_le_bytes());
+        }
+        offset += data.len() as u32;
+    }
+    for (_, _, data, _) in segments {
+        file.extend_from_slice(data);
+    }
+    file
+}
+
+/// Minimal ARM ELF file with the given sections: name, address and contents
+fn elf_sections(sections: &[(&str, u32, &[u8])]) -> Vec<u8> {
+    let mut file = elf(&[]);
+    let mut strings = b"\0.shstrtab\0".to_vec();
+    // Null section, the given sections, the section names
+    let mut headers = vec![[0u32; 10]];
+    for (name, address, data) in sections {
+        headers.push([
+            strings.len() as u32,
+            1,
+            2,
+            *address,
+            file.len() as u32,
+            data.len() as u32,
+            0,
+            0,
+            4,
+            0,
+        ]);
+        strings.extend_from_slice(name.as_bytes());
+        strings.push(0);
+        file.extend_from_slice(data);
+    }
+    headers.push([
+        1,
+        3,
+        0,
+        0,
+        file.len() as u32,
+        strings.len() as u32,
+        0,
+        0,
+        1,
+        0,
+    ]);
+    file.extend_from_slice(&strings);
+
+    let table = file.len() as u32;
+    file[32..36].copy_from_slice(&table.to_le_bytes());
+    file[46..48].copy_from_slice(&40u16.to_le_bytes());
+    file[48..50].copy_from_slice(&(headers.len() as u16).to_le_bytes());
+    file[50..52].copy_from_slice(&(headers.len() as u16 - 1).to_le_bytes());
+    for header in &headers {
+        for word in header {
+            file.extend_from_slice(&word.to_le_bytes());
+        }
+    }
+    file
+}
+
+fn build_info(features: &str) -> FirmwareInfo {
+    FirmwareInfo::new(
+        "0.1.0",
+        "78c3005",
+        1_714_564_800,
+        "SAM4S Xplained Pro",
+        features,
+    )
+}
+
+fn key() -> SigningKey {
+    SigningKey::from_seed(&[7; 32])
+}
+
+#[test]
+fn converts_loadable_segments() {
+    let elf = elf(&[
+        (PT_LOAD, 0x0041_0200, &[1, 2, 3, 4, 5, 6], 6),
+        (PT_NOTE, 0x0041_0000, &[9; 8], 8),
+        // .data initializers, behind a gap
+        (PT_LOAD, 0x0041_0210, &[7, 8], 2),
+        // .bss
+        (PT_LOAD, 0x2000_0000, &[], 0x100),
+    ]);
+    let (address, binary) = elf_to_bin(&elf).unwrap();
+    assert_eq!(address, 0x0041_0200);
+    let mut expected = vec![1, 2, 3, 4, 5, 6];
+    expected.resize(16, 0xFF);
+    expected.extend_from_slice(&[7, 8]);
+    assert_eq!(binary, expected);
+}
+
+#[test]
+fn rejects_unusable_elf_files() {
+    assert_eq!(elf_to_bin(b"not an ELF file at all"), Err(Error::NotArmElf));
+
+    let mut other = elf(&[(PT_LOAD, 0x0040_0000, &[1], 1)]);
+    other[18] = 62; // x86-64
+    assert_eq!(elf_to_bin(&other), Err(Error::NotArmElf));
+
+    assert_eq!(
+        elf_to_bin(&elf(&[(PT_NOTE, 0, &[1], 1)])),
+        Err(Error::Empty)
+    );
+
+    let far = elf(&[
+        (PT_LOAD, 0x0040_0000, &[1], 1),
+        (PT_LOAD, 0x0040_0001 + MAX_GAP + 1, &[2], 1),
+    ]);
+    assert_eq!(
+        elf_to_bin(&far),
+        Err(Error::Gap {
+            from: 0x0040_0001,
+            to: 0x0040_0001 + MAX_GAP + 1
+        })
+    );
+
+    let overlapping = elf(&[
+        (PT_LOAD, 0x0040_0000, &[1, 2], 2),
+        (PT_LOAD, 0x0040_0001, &[3], 1),
+    ]);
+    assert_eq!(elf_to_bin(&overlapping), Err(Error::Malformed));
+
+    let mut truncated = elf(&[(PT_LOAD, 0x0040_0000, &[1, 2, 3, 4], 4)]);
+    truncated.truncate(truncated.len() - 1);
+    assert_eq!(elf_to_bin(&truncated), Err(Error::Malformed));
+}
+
+#[test]
+fn packages_signed_images() {
+    let image: Vec<u8> = (0..1000).map(|byte| byte as u8).collect();
+    let file = package(3, &image, Some(&key()));
+    let parsed = check(&file, Some(&key().public_key())).unwrap();
+    assert_eq!(parsed.header.version, 3);
+    assert_eq!(parsed.image, &image[..]);
+    assert!(parsed.trailer.is_some());
+
+    let other = SigningKey::from_seed(&[8; 32]).public_key();
+    assert_eq!(
+        check(&file, Some(&other)).unwrap_err(),
+        Error::Signature(SignatureError::Invalid)
+    );
+
+    let mut modified = file.clone();
+    modified[100] ^= 1;
+    assert_eq!(check(&modified, None).unwrap_err(), Error::BadCrc);
+}
+
+#[test]
+fn packages_unsigned_images() {
+    let file = package(1, &[0xAA; 64], None);
+    let parsed = ImageFile::parse(&file).unwrap();
+    assert_eq!(parsed.trailer, None);
+    assert!(check(&file, None).is_ok());
+    assert_eq!(
+        check(&file, Some(&key().public_key())).unwrap_err(),
+        Error::Unsigned
+    );
+    assert_eq!(check(&file[4..], None).unwrap_err(), Error::NotAnImage);
+}
+
+#[test]
+fn reads_key_files() {
+    let text = to_hex(&[7; 32]);
+    assert_eq!(text, format!("{}\n", "07".repeat(32)));
+    assert_eq!(
+        read_signing_key(&text).unwrap().public_key(),
+        key().public_key()
+    );
+    assert_eq!(
+        read_public_key(&to_hex(&key().public_key())),
+        Ok(key().public_key())
+    );
+    assert!(read_public_key("0011").is_err());
+    assert!(read_signing_key(&"zz".repeat(32)).is_err());
+}
+
+#[test]
+fn finds_elf_sections() {
+    let elf = elf_sections(&[
+        (".text", 0x0040_0300, &[1, 2, 3]),
+        (".data", 0x2000_0000, &[4]),
+    ]);
+    assert_eq!(
+        elf_section(&elf, ".text"),
+        Ok(Some((0x0040_0300, &[1u8, 2, 3][..])))
+    );
+    assert_eq!(
+        elf_section(&elf, ".data"),
+        Ok(Some((0x2000_0000, &[4u8][..])))
+    );
+    assert_eq!(elf_section(&elf, ".dat"), Ok(None));
+    assert_eq!(
+        elf_section(&elf[..elf.len() - 20], ".text"),
+        Err(Error::Malformed)
+    );
+}
+
+#[test]
+fn reads_firmware_info() {
+    let info = build_info("rt,sam4s");
+    let elf = elf_sections(&[
+        (".vector_table", 0x0040_0000, &[0; 0xd0]),
+        (".firmware_info", 0x0040_0200, &info.to_bytes()),
+    ]);
+    assert_eq!(firmware_info(&elf), Ok(vec![(0x0040_0200, info)]));
+    assert_eq!(
+        firmware_info(&elf_sections(&[(".text", 0, &[0; 16])])),
+        Err(Error::NoFirmwareInfo)
+    );
+
+    // Image file: the header, then the image with the information behind the
+    // vector table
+    let mut image = vec![0u8; 0x400];
+    image[FirmwareInfo::OFFSET..FirmwareInfo::OFFSET + FirmwareInfo::LEN]
+        .copy_from_slice(&info.to_bytes());
+    let file = package(1, &image, None);
+    assert_eq!(
+        firmware_info(&file),
+        Ok(vec![((16 + FirmwareInfo::OFFSET) as u32, info)])
+    );
+
+    // Flash dump with the bootloader and an application
+    let bootloader = build_info("bootloader,rt,sam4s");
+    let mut flash = vec![0xFF; 0x1_0000];
+    flash[0x200..0x300].copy_from_slice(&bootloader.to_bytes());
+    flash[0x8400..0x8500].copy_from_slice(&info.to_bytes());
+    assert_eq!(
+        firmware_info(&flash),
+        Ok(vec![(0x200, bootloader), (0x8400, info)])
+    );
+    assert_eq!(firmware_info(&[0xFF; 0x1000]), Err(Error::NoFirmwareInfo));
+}
+
+#[test]
+fn command_line() {
+    let dir = std::env::temp_dir().join(format!("sam_xplained_imgtool-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = |name: &str| -> PathBuf { dir.join(name) };
+    let run = |args: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_sam_xplained_imgtool"))
+            .args(args)
+            .current_dir(&dir)
+            .output()
+            .unwrap()
+    };
+
+    assert!(run(&["keygen", "release"]).status.success());
+    assert!(!run(&["keygen", "release"]).status.success());
+    std::fs::write(
+        path("app.elf"),
+        elf(&[(PT_LOAD, 0x0041_0200, &[0x55; 300], 300)]),
+    )
+    .unwrap();
+
+    let created = run(&[
+        "create",
+        "--version",
+        "2",
+        "--key",
+        "release.key",
+        "--address",
+        "0x410200",
+        "app.elf",
+        "app.img",
+    ]);
+    assert!(created.status.success(), "{:?}", created);
+    let verified = run(&["verify", "--key", "release.pub", "app.img"]);
+    assert!(verified.status.success(), "{:?}", verified);
+    assert!(String::from_utf8_lossy(&verified.stdout).contains("signature verified"));
+
+    let misplaced = run(&[
+        "create",
+        "--version",
+        "2",
+        "--address",
+        "0x400000",
+        "app.elf",
+        "other.img",
+    ]);
+    assert!(!misplaced.status.success());
+
+    let mut dump = vec![0xFF; 0x1000];
+    dump[0x200..0x300].copy_from_slice(&build_info("rt,sam4s").to_bytes());
+    std::fs::write(path("flash.bin"), dump).unwrap();
+    let info = run(&["info", "flash.bin"]);
+    assert!(info.status.success(), "{:?}", info);
+    assert_eq!(
+        String::from_utf8_lossy(&info.stdout),
+        "0x00000200: version 0.1.0 (78c3005, built 2024-05-01 12:00:00 UTC) \
+         for the SAM4S Xplained Pro, features: rt,sam4s\n"
+    );
+    assert!(!run(&["info", "app.elf"]).status.success());
+
+    let mut image = std::fs::read(path("app.img")).unwrap();
+    image[20] ^= 1;
+    std::fs::write(path("app.img"), image).unwrap();
+    assert!(!run(&["verify", "--key", "release.pub", "app.img"])
+        .status
+        .success());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
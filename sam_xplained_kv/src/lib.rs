@@ -0,0 +1,598 @@
+//! Wear-leveled key-value store on NOR flash
+//!
+//! The store occupies a region of erase sectors used as a ring.  Records are
+//! appended to the active sector; when it fills up the next sector becomes
+//! active, the still current records of the oldest sector are copied over and
+//! the oldest sector is erased.  Every sector is therefore erased equally
+//! often, and one sector is always kept erased for the next rotation.
+//!
+//! Writes survive power loss at any point:
+//! * each record carries a CRC; a record cut short fails it and is ignored
+//!   (together with the rest of its sector, which is then no longer written)
+//! * a rotation finishes with a marker record; when the store is opened after
+//!   an unfinished rotation the copy is redone from the untouched oldest
+//!   sector
+//!
+//! so a key always reads back either its previous or its new value.
+//!
+//! ```ignore
+//! let mut store = KvStore::new(flash, 0x000F_C000..0x0010_0000)?;
+//! store.set(BOOT_COUNT, &count.to_le_bytes())?;
+//! let len = store.get(BOOT_COUNT, &mut buffer)?;
+//! ```
+#![no_std]
+
+use core::ops::Range;
+use embedded_storage::nor_flash::NorFlash;
+use sam_xplained_image::Crc32;
+
+/// Key reserved for the rotation marker
+const MARKER_KEY: u16 = 0xFFFF;
+/// Length field of a deleted key
+const TOMBSTONE: u16 = 0xFFFF;
+
+const SECTOR_MAGIC: u32 = 0x5658_4B53; // "SKXV"
+const SECTOR_HEADER_LEN: usize = 16;
+const RECORD_HEADER_LEN: usize = 8;
+
+/// Records are streamed to flash through a buffer of this size, so it bounds
+/// the supported write granularity.
+const CHUNK_LEN: usize = 64;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Error<E> {
+    /// The underlying flash failed
+    Flash(E),
+    /// The region is not aligned to erase sectors, holds fewer than two
+    /// sectors, or the flash geometry is not supported
+    InvalidRegion,
+    /// Key `0xFFFF` is reserved
+    InvalidKey,
+    /// The value does not fit into a sector
+    ValueTooLarge,
+    /// The value is longer than the buffer passed to [`KvStore::get`]
+    BufferTooSmall { len: usize },
+    /// The current values of all keys no longer leave room for the write
+    Full,
+}
+
+/// Key-value store in a region of a [`NorFlash`]
+pub struct KvStore<F> {
+    flash: F,
+    base: u32,
+    sector_len: u32,
+    sectors: u32,
+    /// Index of the sector records are appended to
+    active: u32,
+    /// Sequence number of the active sector
+    sequence: u32,
+    /// Offset of the free space within the active sector
+    offset: u32,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum SectorState {
+    Erased,
+    Valid { sequence: u32 },
+    Corrupt,
+}
+
+#[derive(Clone, Copy)]
+struct Record {
+    /// Absolute flash offset of the record header
+    offset: u32,
+    key: u16,
+    len: u16,
+    crc: u32,
+}
+
+impl Record {
+    fn data_len(&self) -> usize {
+        if self.len == TOMBSTONE {
+            0
+        } else {
+            self.len as usize
+        }
+    }
+
+    fn is_tombstone(&self) -> bool {
+        self.len == TOMBSTONE
+    }
+}
+
+enum Scan {
+    Record(Record),
+    /// Erased space starts here
+    End,
+    /// A damaged record; nothing after it can be trusted
+    Corrupt,
+}
+
+enum Source<'a> {
+    Slice(&'a [u8]),
+    Flash(u32),
+}
+
+impl<F: NorFlash> KvStore<F> {
+    /// Opens the store in `region` (flash offsets), formatting the region if
+    /// it holds no store yet and finishing an interrupted rotation.
+    pub fn new(flash: F, region: Range<u32>) -> Result<Self, Error<F::Error>> {
+        let sector_len = F::ERASE_SIZE as u32;
+        if F::READ_SIZE != 1
+            || F::WRITE_SIZE > CHUNK_LEN
+            || !CHUNK_LEN.is_multiple_of(F::WRITE_SIZE)
+            || !region.start.is_multiple_of(sector_len)
+            || !region.end.is_multiple_of(sector_len)
+            || region.end < region.start + 2 * sector_len
+            || region.end as usize > flash.capacity()
+        {
+            return Err(Error::InvalidRegion);
+        }
+
+        let mut store = KvStore {
+            flash,
+            base: region.start,
+            sector_len,
+            sectors: (region.end - region.start) / sector_len,
+            active: 0,
+            sequence: 0,
+            offset: 0,
+        };
+        store.mount()?;
+        Ok(store)
+    }
+
+    /// Releases the underlying flash
+    pub fn free(self) -> F {
+        self.flash
+    }
+
+    /// Reads the value of `key` into `buffer`, returning its length, or `None`
+    /// if the key is not set.
+    pub fn get(&mut self, key: u16, buffer: &mut [u8]) -> Result<Option<usize>, Error<F::Error>> {
+        if key == MARKER_KEY {
+            return Err(Error::InvalidKey);
+        }
+
+        match self.locate(key)? {
+            Some(record) if !record.is_tombstone() => {
+                let len = record.data_len();
+                if buffer.len() < len {
+                    return Err(Error::BufferTooSmall { len });
+                }
+                self.read(record.offset + RECORD_HEADER_LEN as u32, &mut buffer[..len])?;
+                Ok(Some(len))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Stores `value` under `key`.  Writing the value a key already has
+    /// leaves the flash untouched.
+    pub fn set(&mut self, key: u16, value: &[u8]) -> Result<(), Error<F::Error>> {
+        if key == MARKER_KEY {
+            return Err(Error::InvalidKey);
+        }
+        if value.len() >= TOMBSTONE as usize || self.record_len(value.len()) > self.capacity() {
+            return Err(Error::ValueTooLarge);
+        }
+
+        let crc = record_crc(key, value.len() as u16, value);
+        if let Some(current) = self.locate(key)? {
+            if current.len as usize == value.len()
+                && current.crc == crc
+                && self.equals(&current, value)?
+            {
+                return Ok(());
+            }
+        }
+
+        self.append(key, value.len() as u16, crc, Source::Slice(value))
+    }
+
+    /// Deletes `key`
+    pub fn remove(&mut self, key: u16) -> Result<(), Error<F::Error>> {
+        if key == MARKER_KEY {
+            return Err(Error::InvalidKey);
+        }
+
+        match self.locate(key)? {
+            Some(record) if !record.is_tombstone() => self.append(
+                key,
+                TOMBSTONE,
+                record_crc(key, TOMBSTONE, &[]),
+                Source::Slice(&[]),
+            ),
+            _ => Ok(()),
+        }
+    }
+
+    /// Erases every key
+    pub fn format(&mut self) -> Result<(), Error<F::Error>> {
+        for sector in 0..self.sectors {
+            self.erase_sector(sector)?;
+        }
+        self.activate(0, 1)
+    }
+
+    //
+    // Mounting and rotation
+    //
+
+    fn mount(&mut self) -> Result<(), Error<F::Error>> {
+        let mut newest: Option<(u32, u32)> = None;
+        for sector in 0..self.sectors {
+            if let SectorState::Valid { sequence } = self.sector_state(sector)? {
+                if newest.is_none_or(|(_, newest)| sequence > newest) {
+                    newest = Some((sector, sequence));
+                }
+            }
+        }
+
+        let (active, sequence) = match newest {
+            Some(newest) => newest,
+            None => return self.format(),
+        };
+        self.active = active;
+        self.sequence = sequence;
+        self.offset = self.find_free_space(active)?;
+
+        // The sector after the active one must be erased.  If it still holds a
+        // store sector, the rotation into the active sector did not finish:
+        // the active sector only holds copies of it, so start the copy over.
+        let next = self.next(active);
+        if !self.is_erased(next)? {
+            if let SectorState::Valid { .. } = self.sector_state(next)? {
+                if !self.has_marker(active)? {
+                    self.erase_sector(active)?;
+                    self.activate(active, sequence)?;
+                    self.collect(next)?;
+                }
+            }
+            self.erase_sector(next)?;
+        }
+        Ok(())
+    }
+
+    /// Makes the next sector active and reclaims the oldest one
+    fn rotate(&mut self) -> Result<(), Error<F::Error>> {
+        let active = self.next(self.active);
+        self.activate(active, self.sequence.wrapping_add(1))?;
+
+        let oldest = self.next(active);
+        self.collect(oldest)?;
+        self.erase_sector(oldest)
+    }
+
+    /// Copies the current records of `oldest` to the active sector and marks
+    /// the rotation as complete.
+    fn collect(&mut self, oldest: u32) -> Result<(), Error<F::Error>> {
+        if let SectorState::Valid { .. } = self.sector_state(oldest)? {
+            let mut at = self.sector_start(oldest) + self.sector_header_len();
+            while let Scan::Record(record) = self.scan(oldest, at)? {
+                at += self.record_len(record.data_len());
+
+                // Deleted keys need no copy: the values they hide are all in
+                // this sector as well.
+                if record.key == MARKER_KEY
+                    || record.is_tombstone()
+                    || self.locate(record.key)?.is_some()
+                    || self.is_superseded_within(oldest, &record, at)?
+                {
+                    continue;
+                }
+
+                let data = record.offset + RECORD_HEADER_LEN as u32;
+                self.write_record(record.key, record.len, record.crc, Source::Flash(data))?;
+            }
+        }
+
+        self.write_record(
+            MARKER_KEY,
+            0,
+            record_crc(MARKER_KEY, 0, &[]),
+            Source::Slice(&[]),
+        )
+    }
+
+    fn activate(&mut self, sector: u32, sequence: u32) -> Result<(), Error<F::Error>> {
+        let mut header = [0xFFu8; CHUNK_LEN];
+        header[0..4].copy_from_slice(&SECTOR_MAGIC.to_le_bytes());
+        header[4..8].copy_from_slice(&sequence.to_le_bytes());
+        header[8..12].copy_from_slice(&(!sequence).to_le_bytes());
+
+        let len = self.sector_header_len();
+        self.flash
+            .write(self.sector_start(sector), &header[..len as usize])
+            .map_err(Error::Flash)?;
+
+        self.active = sector;
+        self.sequence = sequence;
+        self.offset = len;
+        Ok(())
+    }
+
+    //
+    // Records
+    //
+
+    fn append(
+        &mut self,
+        key: u16,
+        len: u16,
+        crc: u32,
+        source: Source<'_>,
+    ) -> Result<(), Error<F::Error>> {
+        let needed = self.record_len(if len == TOMBSTONE { 0 } else { len as usize });
+        let mut rotations = 0;
+        while self.offset + needed > self.sector_header_len() + self.capacity() {
+            if rotations == self.sectors - 1 {
+                return Err(Error::Full);
+            }
+            self.rotate()?;
+            rotations += 1;
+        }
+
+        self.write_record(key, len, crc, source)
+    }
+
+    /// Writes a record at the free space of the active sector (which must
+    /// have room for it).
+    fn write_record(
+        &mut self,
+        key: u16,
+        len: u16,
+        crc: u32,
+        source: Source<'_>,
+    ) -> Result<(), Error<F::Error>> {
+        let data_len = if len == TOMBSTONE { 0 } else { len as usize };
+        let record_len = self.record_len(data_len);
+        let start = self.sector_start(self.active) + self.offset;
+
+        let mut chunk = [0xFFu8; CHUNK_LEN];
+        chunk[0..2].copy_from_slice(&key.to_le_bytes());
+        chunk[2..4].copy_from_slice(&len.to_le_bytes());
+        chunk[4..8].copy_from_slice(&crc.to_le_bytes());
+
+        let mut filled = RECORD_HEADER_LEN;
+        let mut written = 0;
+        let mut copied = 0;
+        loop {
+            let count = (CHUNK_LEN - filled).min(data_len - copied);
+            match source {
+                Source::Slice(data) => {
+                    chunk[filled..filled + count].copy_from_slice(&data[copied..copied + count])
+                }
+                Source::Flash(from) => {
+                    self.read(from + copied as u32, &mut chunk[filled..filled + count])?
+                }
+            }
+            filled += count;
+            copied += count;
+
+            if copied == data_len {
+                let padded = align(filled, F::WRITE_SIZE);
+                self.flash
+                    .write(start + written, &chunk[..padded])
+                    .map_err(Error::Flash)?;
+                break;
+            }
+
+            self.flash
+                .write(start + written, &chunk)
+                .map_err(Error::Flash)?;
+            written += CHUNK_LEN as u32;
+            chunk = [0xFF; CHUNK_LEN];
+            filled = 0;
+        }
+
+        self.offset += record_len;
+        Ok(())
+    }
+
+    /// Decodes the record at absolute offset `at` of `sector`
+    fn scan(&mut self, sector: u32, at: u32) -> Result<Scan, Error<F::Error>> {
+        let end = self.sector_start(sector) + self.sector_len;
+        if at + RECORD_HEADER_LEN as u32 > end {
+            return Ok(Scan::End);
+        }
+
+        let mut header = [0u8; RECORD_HEADER_LEN];
+        self.read(at, &mut header)?;
+        if header.iter().all(|&byte| byte == 0xFF) {
+            return Ok(Scan::End);
+        }
+
+        let record = Record {
+            offset: at,
+            key: u16::from_le_bytes([header[0], header[1]]),
+            len: u16::from_le_bytes([header[2], header[3]]),
+            crc: u32::from_le_bytes([header[4], header[5], header[6], header[7]]),
+        };
+        if at + self.record_len(record.data_len()) > end {
+            return Ok(Scan::Corrupt);
+        }
+
+        // Check the CRC over the data in small pieces.
+        let mut crc = Crc32::new();
+        crc.update(&header[0..4]);
+        let mut buffer = [0u8; CHUNK_LEN];
+        let mut checked = 0;
+        while checked < record.data_len() {
+            let count = CHUNK_LEN.min(record.data_len() - checked);
+            self.read(
+                at + (RECORD_HEADER_LEN + checked) as u32,
+                &mut buffer[..count],
+            )?;
+            crc.update(&buffer[..count]);
+            checked += count;
+        }
+
+        if crc.finish() == record.crc {
+            Ok(Scan::Record(record))
+        } else {
+            Ok(Scan::Corrupt)
+        }
+    }
+
+    /// Finds the newest record of `key`.  The sector after the active one is
+    /// not searched: it is either the erased spare or, during a rotation, the
+    /// sector being reclaimed.
+    fn locate(&mut self, key: u16) -> Result<Option<Record>, Error<F::Error>> {
+        let mut found = None;
+
+        // From the oldest sector to the active one
+        for step in 2..=self.sectors {
+            let sector = (self.active + step) % self.sectors;
+            if let SectorState::Valid { .. } = self.sector_state(sector)? {
+                let mut at = self.sector_start(sector) + self.sector_header_len();
+                while let Scan::Record(record) = self.scan(sector, at)? {
+                    if record.key == key {
+                        found = Some(record);
+                    }
+                    at += self.record_len(record.data_len());
+                }
+            }
+        }
+
+        Ok(found)
+    }
+
+    /// Whether `sector` holds a newer record for the key of `record` after
+    /// offset `after`
+    fn is_superseded_within(
+        &mut self,
+        sector: u32,
+        record: &Record,
+        mut after: u32,
+    ) -> Result<bool, Error<F::Error>> {
+        while let Scan::Record(later) = self.scan(sector, after)? {
+            if later.key == record.key {
+                return Ok(true);
+            }
+            after += self.record_len(later.data_len());
+        }
+        Ok(false)
+    }
+
+    /// Offset of the free space in `sector`, or the sector length if a
+    /// damaged record leaves it unusable
+    fn find_free_space(&mut self, sector: u32) -> Result<u32, Error<F::Error>> {
+        let start = self.sector_start(sector);
+        let mut at = start + self.sector_header_len();
+        loop {
+            match self.scan(sector, at)? {
+                Scan::Record(record) => at += self.record_len(record.data_len()),
+                Scan::End => return Ok(at - start),
+                Scan::Corrupt => return Ok(self.sector_len),
+            }
+        }
+    }
+
+    fn has_marker(&mut self, sector: u32) -> Result<bool, Error<F::Error>> {
+        let mut at = self.sector_start(sector) + self.sector_header_len();
+        while let Scan::Record(record) = self.scan(sector, at)? {
+            if record.key == MARKER_KEY {
+                return Ok(true);
+            }
+            at += self.record_len(record.data_len());
+        }
+        Ok(false)
+    }
+
+    fn equals(&mut self, record: &Record, value: &[u8]) -> Result<bool, Error<F::Error>> {
+        let mut buffer = [0u8; CHUNK_LEN];
+        for (index, expected) in value.chunks(CHUNK_LEN).enumerate() {
+            let at = record.offset + (RECORD_HEADER_LEN + index * CHUNK_LEN) as u32;
+            self.read(at, &mut buffer[..expected.len()])?;
+            if &buffer[..expected.len()] != expected {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    //
+    // Sectors
+    //
+
+    fn sector_state(&mut self, sector: u32) -> Result<SectorState, Error<F::Error>> {
+        let mut header = [0u8; SECTOR_HEADER_LEN];
+        self.read(self.sector_start(sector), &mut header)?;
+
+        let word = |index: usize| {
+            u32::from_le_bytes([
+                header[index],
+                header[index + 1],
+                header[index + 2],
+                header[index + 3],
+            ])
+        };
+        if header.iter().all(|&byte| byte == 0xFF) {
+            Ok(SectorState::Erased)
+        } else if word(0) == SECTOR_MAGIC && word(4) == !word(8) {
+            Ok(SectorState::Valid { sequence: word(4) })
+        } else {
+            Ok(SectorState::Corrupt)
+        }
+    }
+
+    fn is_erased(&mut self, sector: u32) -> Result<bool, Error<F::Error>> {
+        let mut buffer = [0u8; CHUNK_LEN];
+        let start = self.sector_start(sector);
+        for at in (start..start + self.sector_len).step_by(CHUNK_LEN) {
+            let count = CHUNK_LEN.min((start + self.sector_len - at) as usize);
+            self.read(at, &mut buffer[..count])?;
+            if buffer[..count].iter().any(|&byte| byte != 0xFF) {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    fn erase_sector(&mut self, sector: u32) -> Result<(), Error<F::Error>> {
+        let start = self.sector_start(sector);
+        self.flash
+            .erase(start, start + self.sector_len)
+            .map_err(Error::Flash)
+    }
+
+    fn sector_start(&self, sector: u32) -> u32 {
+        self.base + sector * self.sector_len
+    }
+
+    fn next(&self, sector: u32) -> u32 {
+        (sector + 1) % self.sectors
+    }
+
+    /// Space for records in a sector.  The room for a rotation marker is held
+    /// back, so the current records of any sector fit into a fresh one along
+    /// with the marker.
+    fn capacity(&self) -> u32 {
+        self.sector_len - self.sector_header_len() - self.record_len(0)
+    }
+
+    fn sector_header_len(&self) -> u32 {
+        align(SECTOR_HEADER_LEN, F::WRITE_SIZE) as u32
+    }
+
+    fn record_len(&self, data_len: usize) -> u32 {
+        align(RECORD_HEADER_LEN + data_len, F::WRITE_SIZE) as u32
+    }
+
+    fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Error<F::Error>> {
+        self.flash.read(offset, bytes).map_err(Error::Flash)
+    }
+}
+
+fn align(len: usize, to: usize) -> usize {
+    len.div_ceil(to) * to
+}
+
+fn record_crc(key: u16, len: u16, data: &[u8]) -> u32 {
+    let mut crc = Crc32::new();
+    crc.update(&key.to_le_bytes());
+    crc.update(&len.to_le_bytes());
+    crc.update(data);
+    crc.finish()
+}
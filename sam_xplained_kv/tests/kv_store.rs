@@ -0,0 +1,474 @@
+use embedded_storage::nor_flash::{
+    ErrorType, NorFlash, NorFlashError, NorFlashErrorKind, ReadNorFlash,
+};
+use sam_xplained_kv::{Error, KvStore};
+use std::collections::HashMap;
+
+// Same geometry as the SAM4 internal flash: 16-byte write granularity, erase
+// in blocks of eight 512-byte pages.
+const WRITE_SIZE: usize = 16;
+const ERASE_SIZE: usize = 4096;
+const SECTORS: usize = 4;
+const REGION: std::ops::Range<u32> = 0..(SECTORS * ERASE_SIZE) as u32;
+
+#[derive(Debug, PartialEq)]
+enum FakeError {
+    PowerLoss,
+    NotAligned,
+}
+
+impl NorFlashError for FakeError {
+    fn kind(&self) -> NorFlashErrorKind {
+        match self {
+            FakeError::PowerLoss => NorFlashErrorKind::Other,
+            FakeError::NotAligned => NorFlashErrorKind::NotAligned,
+        }
+    }
+}
+
+/// RAM-backed NOR flash that can lose power in the middle of an operation
+#[derive(Clone)]
+struct FakeFlash {
+    data: Vec<u8>,
+    erase_counts: Vec<u32>,
+    /// Write and erase operations left before the power fails
+    operations_left: Option<usize>,
+    /// Write and erase operations performed so far
+    operations: usize,
+}
+
+impl FakeFlash {
+    fn new() -> Self {
+        FakeFlash {
+            data: vec![0xFF; SECTORS * ERASE_SIZE],
+            erase_counts: vec![0; SECTORS],
+            operations_left: None,
+            operations: 0,
+        }
+    }
+
+    /// The same flash contents after a power cycle
+    fn reboot(&self) -> Self {
+        FakeFlash {
+            operations_left: None,
+            operations: 0,
+            ..self.clone()
+        }
+    }
+
+    fn fail_after(mut self, operations: usize) -> Self {
+        self.operations_left = Some(operations);
+        self
+    }
+
+    /// Returns false if the power fails during this operation
+    fn operation(&mut self) -> bool {
+        self.operations += 1;
+        match &mut self.operations_left {
+            Some(0) => false,
+            Some(left) => {
+                *left -= 1;
+                true
+            }
+            None => true,
+        }
+    }
+}
+
+impl ErrorType for FakeFlash {
+    type Error = FakeError;
+}
+
+impl ReadNorFlash for FakeFlash {
+    const READ_SIZE: usize = 1;
+
+    fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
+        let offset = offset as usize;
+        bytes.copy_from_slice(&self.data[offset..offset + bytes.len()]);
+        Ok(())
+    }
+
+    fn capacity(&self) -> usize {
+        self.data.len()
+    }
+}
+
+impl NorFlash for FakeFlash {
+    const WRITE_SIZE: usize = WRITE_SIZE;
+    const ERASE_SIZE: usize = ERASE_SIZE;
+
+    fn erase(&mut self, from: u32, to: u32) -> Result<(), Self::Error> {
+        let (from, to) = (from as usize, to as usize);
+        if !from.is_multiple_of(ERASE_SIZE) || !to.is_multiple_of(ERASE_SIZE) {
+            return Err(FakeError::NotAligned);
+        }
+
+        if !self.operation() {
+            // Only part of the block makes it back to the erased state.
+            let middle = from + (to - from) / 2;
+            self.data[from..middle]
+                .iter_mut()
+                .for_each(|byte| *byte = 0xFF);
+            self.data[middle..to]
+                .iter_mut()
+                .for_each(|byte| *byte &= 0x5A);
+            return Err(FakeError::PowerLoss);
+        }
+
+        self.data[from..to].iter_mut().for_each(|byte| *byte = 0xFF);
+        for sector in from / ERASE_SIZE..to / ERASE_SIZE {
+            self.erase_counts[sector] += 1;
+        }
+        Ok(())
+    }
+
+    fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error> {
+        let offset = offset as usize;
+        if !offset.is_multiple_of(WRITE_SIZE) || !bytes.len().is_multiple_of(WRITE_SIZE) {
+            return Err(FakeError::NotAligned);
+        }
+
+        let (count, result) = if self.operation() {
+            (bytes.len(), Ok(()))
+        } else {
+            (bytes.len() / 2, Err(FakeError::PowerLoss))
+        };
+
+        for (index, &byte) in bytes[..count].iter().enumerate() {
+            let cell = &mut self.data[offset + index];
+            assert!(
+                *cell == 0xFF || byte == 0xFF,
+                "programmed byte {:#x} twice",
+                offset + index
+            );
+            *cell &= byte;
+        }
+        result
+    }
+}
+
+/// Keeps the flash accessible after a failed mount
+struct Borrowed<'a>(&'a mut FakeFlash);
+
+impl ErrorType for Borrowed<'_> {
+    type Error = FakeError;
+}
+
+impl ReadNorFlash for Borrowed<'_> {
+    const READ_SIZE: usize = 1;
+
+    fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
+        self.0.read(offset, bytes)
+    }
+
+    fn capacity(&self) -> usize {
+        self.0.capacity()
+    }
+}
+
+impl NorFlash for Borrowed<'_> {
+    const WRITE_SIZE: usize = WRITE_SIZE;
+    const ERASE_SIZE: usize = ERASE_SIZE;
+
+    fn erase(&mut self, from: u32, to: u32) -> Result<(), Self::Error> {
+        self.0.erase(from, to)
+    }
+
+    fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error> {
+        self.0.write(offset, bytes)
+    }
+}
+
+fn value(key: u16, generation: usize) -> Vec<u8> {
+    let len = (key as usize * 7 + generation * 13) % 100 + 1;
+    (0..len)
+        .map(|index| (index + generation + key as usize) as u8)
+        .collect()
+}
+
+fn read(store: &mut KvStore<FakeFlash>, key: u16) -> Option<Vec<u8>> {
+    let mut buffer = [0u8; 1024];
+    store
+        .get(key, &mut buffer)
+        .unwrap()
+        .map(|len| buffer[..len].to_vec())
+}
+
+#[test]
+fn set_get_remove() {
+    let mut store = KvStore::new(FakeFlash::new(), REGION).unwrap();
+    assert_eq!(read(&mut store, 1), None);
+
+    store.set(1, b"first").unwrap();
+    store.set(2, b"").unwrap();
+    assert_eq!(read(&mut store, 1).as_deref(), Some(&b"first"[..]));
+    assert_eq!(read(&mut store, 2).as_deref(), Some(&b""[..]));
+
+    store.set(1, b"second").unwrap();
+    assert_eq!(read(&mut store, 1).as_deref(), Some(&b"second"[..]));
+
+    store.remove(1).unwrap();
+    assert_eq!(read(&mut store, 1), None);
+    assert_eq!(read(&mut store, 2).as_deref(), Some(&b""[..]));
+}
+
+#[test]
+fn values_persist_across_remount() {
+    let mut store = KvStore::new(FakeFlash::new(), REGION).unwrap();
+    store.set(7, b"calibration").unwrap();
+    store.set(8, b"network").unwrap();
+    store.remove(8).unwrap();
+
+    let mut store = KvStore::new(store.free().reboot(), REGION).unwrap();
+    assert_eq!(read(&mut store, 7).as_deref(), Some(&b"calibration"[..]));
+    assert_eq!(read(&mut store, 8), None);
+}
+
+#[test]
+fn rejects_invalid_arguments() {
+    assert_eq!(
+        KvStore::new(FakeFlash::new(), 100..(2 * ERASE_SIZE as u32)).err(),
+        Some(Error::InvalidRegion)
+    );
+    assert_eq!(
+        KvStore::new(FakeFlash::new(), 0..ERASE_SIZE as u32).err(),
+        Some(Error::InvalidRegion)
+    );
+
+    let mut store = KvStore::new(FakeFlash::new(), REGION).unwrap();
+    assert_eq!(store.set(0xFFFF, b"x"), Err(Error::InvalidKey));
+    assert_eq!(store.set(1, &[0; ERASE_SIZE]), Err(Error::ValueTooLarge));
+
+    store.set(1, &[0; 32]).unwrap();
+    let mut small = [0u8; 8];
+    assert_eq!(
+        store.get(1, &mut small),
+        Err(Error::BufferTooSmall { len: 32 })
+    );
+}
+
+#[test]
+fn rewriting_the_same_value_does_not_write() {
+    let mut store = KvStore::new(FakeFlash::new(), REGION).unwrap();
+    store.set(3, b"unchanged").unwrap();
+    let flash = store.free();
+    let operations = flash.operations;
+
+    let mut store = KvStore::new(flash, REGION).unwrap();
+    store.set(3, b"unchanged").unwrap();
+    assert_eq!(store.free().operations, operations);
+}
+
+#[test]
+fn rotation_keeps_current_values_and_levels_wear() {
+    let mut store = KvStore::new(FakeFlash::new(), REGION).unwrap();
+    let mut expected = HashMap::new();
+
+    for generation in 0..2_000 {
+        let key = (generation % 10) as u16;
+        let data = value(key, generation);
+        store.set(key, &data).unwrap();
+        expected.insert(key, data);
+
+        if generation % 97 == 0 {
+            store.remove(9).unwrap();
+            expected.remove(&9);
+        }
+    }
+
+    let mut store = KvStore::new(store.free().reboot(), REGION).unwrap();
+    for key in 0..10 {
+        assert_eq!(
+            read(&mut store, key),
+            expected.get(&key).cloned(),
+            "key {}",
+            key
+        );
+    }
+
+    let counts = store.free().erase_counts;
+    let (min, max) = (counts.iter().min().unwrap(), counts.iter().max().unwrap());
+    assert!(*min > 5, "erase counts {:?}", counts);
+    assert!(max - min <= 1, "erase counts {:?}", counts);
+}
+
+#[test]
+fn reports_full_when_live_data_exceeds_capacity() {
+    let mut store = KvStore::new(FakeFlash::new(), REGION).unwrap();
+    let large = [0xA5u8; 1000];
+
+    let mut result = Ok(());
+    let mut key = 0;
+    while result.is_ok() {
+        result = store.set(key, &large);
+        key += 1;
+    }
+    assert_eq!(result, Err(Error::Full));
+
+    // Everything stored before the store filled up is still there.
+    for stored in 0..key - 1 {
+        assert_eq!(read(&mut store, stored).as_deref(), Some(&large[..]));
+    }
+}
+
+/// Runs `updates` against a store holding `initial`, cutting the power after
+/// every possible number of flash operations, and checks that each key reads
+/// back either its old or its new value after a reboot.
+fn check_power_loss(initial: &[(u16, Vec<u8>)], updates: &[(u16, Option<Vec<u8>>)]) {
+    let mut store = KvStore::new(FakeFlash::new(), REGION).unwrap();
+    for (key, data) in initial {
+        store.set(*key, data).unwrap();
+    }
+    let baseline = store.free().reboot();
+
+    // Count the operations of an uninterrupted run.
+    let mut store = KvStore::new(baseline.reboot(), REGION).unwrap();
+    for (key, data) in updates {
+        match data {
+            Some(data) => store.set(*key, data).unwrap(),
+            None => store.remove(*key).unwrap(),
+        }
+    }
+    let total = store.free().operations;
+
+    for cut in 0..total {
+        let mut committed: HashMap<u16, Option<Vec<u8>>> = initial
+            .iter()
+            .map(|(key, data)| (*key, Some(data.clone())))
+            .collect();
+        let mut in_flight = None;
+
+        match KvStore::new(baseline.reboot().fail_after(cut), REGION) {
+            Ok(mut store) => {
+                for (key, data) in updates {
+                    let result = match data {
+                        Some(data) => store.set(*key, data),
+                        None => store.remove(*key),
+                    };
+                    match result {
+                        Ok(()) => {
+                            committed.insert(*key, data.clone());
+                        }
+                        Err(error) => {
+                            assert_eq!(error, Error::Flash(FakeError::PowerLoss));
+                            in_flight = Some((*key, data.clone()));
+                            break;
+                        }
+                    }
+                }
+                let flash = store.free();
+                check_recovered(flash.reboot(), &committed, &in_flight, cut);
+            }
+            Err(error) => panic!("mount failed without a power cut: {:?}", error),
+        }
+    }
+}
+
+fn check_recovered(
+    flash: FakeFlash,
+    committed: &HashMap<u16, Option<Vec<u8>>>,
+    in_flight: &Option<(u16, Option<Vec<u8>>)>,
+    cut: usize,
+) {
+    let mut store = KvStore::new(flash, REGION).unwrap();
+    for (key, data) in committed {
+        let found = read(&mut store, *key);
+        let accepted = match in_flight {
+            Some((in_flight_key, new)) if in_flight_key == key => found == *data || found == *new,
+            _ => found == *data,
+        };
+        assert!(
+            accepted,
+            "key {} after a power cut at operation {}: {:?}",
+            key, cut, found
+        );
+    }
+
+    // The store keeps working afterwards.
+    store.set(500, b"after recovery").unwrap();
+    let mut store = KvStore::new(store.free().reboot(), REGION).unwrap();
+    assert_eq!(
+        read(&mut store, 500).as_deref(),
+        Some(&b"after recovery"[..])
+    );
+}
+
+#[test]
+fn survives_power_loss_during_writes() {
+    let initial: Vec<_> = (0..4).map(|key| (key, value(key, 0))).collect();
+    let updates: Vec<_> = (0..40)
+        .map(|generation| {
+            let key = (generation % 5) as u16;
+            if generation % 11 == 10 {
+                (key, None)
+            } else {
+                (key, Some(value(key, generation + 1)))
+            }
+        })
+        .collect();
+
+    check_power_loss(&initial, &updates);
+}
+
+#[test]
+fn survives_power_loss_during_rotation() {
+    // Fill the store until the next writes have to rotate through every
+    // sector, reclaiming sectors that still hold current values.
+    let initial: Vec<_> = (0..20).map(|key| (key, vec![key as u8; 200])).collect();
+    let updates: Vec<_> = (0..60)
+        .map(|generation| {
+            let key = (generation * 7 % 20) as u16;
+            (key, Some(vec![generation as u8; 150 + generation]))
+        })
+        .collect();
+
+    check_power_loss(&initial, &updates);
+}
+
+#[test]
+fn survives_power_loss_during_recovery() {
+    let initial: Vec<_> = (0..20).map(|key| (key, vec![key as u8; 200])).collect();
+    let mut store = KvStore::new(FakeFlash::new(), REGION).unwrap();
+    for (key, data) in &initial {
+        store.set(*key, data).unwrap();
+    }
+    let baseline = store.free().reboot();
+
+    // Interrupt a write that rotates at each point, then interrupt the
+    // recovery on the next start-up at each point as well.
+    let update = vec![0x3C; 1500];
+    let store = KvStore::new(baseline.reboot(), REGION).unwrap();
+    let start = store.free().operations;
+    let mut store = KvStore::new(baseline.reboot(), REGION).unwrap();
+    store.set(0, &update).unwrap();
+    let total = store.free().operations;
+
+    let committed: HashMap<_, _> = initial
+        .iter()
+        .map(|(key, data)| (*key, Some(data.clone())))
+        .collect();
+    let in_flight = Some((0, Some(update.clone())));
+
+    for cut in start..total {
+        let mut store = KvStore::new(baseline.reboot().fail_after(cut), REGION).unwrap();
+        assert!(store.set(0, &update).is_err());
+        let interrupted = store.free().reboot();
+
+        // Power fails during each recovery until one finally completes.
+        let mut flash = interrupted;
+        for recovery_cut in 0.. {
+            let mut attempt = flash.reboot().fail_after(recovery_cut);
+            match KvStore::new(Borrowed(&mut attempt), REGION) {
+                Ok(_) => {
+                    check_recovered(attempt.reboot(), &committed, &in_flight, cut);
+                    break;
+                }
+                Err(error) => {
+                    assert_eq!(error, Error::Flash(FakeError::PowerLoss));
+                    check_recovered(attempt.reboot(), &committed, &in_flight, cut);
+                    flash = attempt;
+                }
+            }
+        }
+    }
+}
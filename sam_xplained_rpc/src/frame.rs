@@ -0,0 +1,150 @@
+//! Frames: COBS-encoded payloads with a CRC
+//!
+//! A frame carries its payload followed by the payload's CRC-16 (XMODEM,
+//! little endian), COBS-encoded so that it holds no zero bytes, between two
+//! zero bytes.  The leading zero ends whatever came before it on the line,
+//! e.g. log output or a frame cut short, which then fails to decode.
+
+use crate::MAX_PAYLOAD;
+
+use sam_xplained_image::crc16;
+
+const CRC_LEN: usize = 2;
+/// Longest COBS block: its code byte and 254 data bytes
+const BLOCK_LEN: usize = 255;
+
+/// Longest frame on the line, including both delimiters
+pub const MAX_FRAME: usize = 2 + (MAX_PAYLOAD + CRC_LEN) + (MAX_PAYLOAD + CRC_LEN) / 254 + 1;
+
+/// Reason a frame was dropped by [`FrameDecoder::feed`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FrameError {
+    /// More than [`MAX_PAYLOAD`] bytes
+    TooLong,
+    /// Not COBS-encoded, or shorter than the CRC
+    Malformed,
+    /// The CRC doesn't match
+    Crc,
+}
+
+/// Encodes `payload` as a frame, passing it to `write` a COBS block at a
+/// time.
+pub fn write_frame<E>(
+    payload: &[u8],
+    mut write: impl FnMut(&[u8]) -> Result<(), E>,
+) -> Result<(), E> {
+    let crc = crc16(payload).to_le_bytes();
+    write(&[0])?;
+    // block[0] is the code byte: the offset of the next (encoded) zero
+    let mut block = [0u8; BLOCK_LEN];
+    let mut len = 1;
+    for &byte in payload.iter().chain(crc.iter()) {
+        if byte == 0 {
+            block[0] = len as u8;
+            write(&block[..len])?;
+            len = 1;
+        } else {
+            block[len] = byte;
+            len += 1;
+            if len == BLOCK_LEN {
+                block[0] = BLOCK_LEN as u8;
+                write(&block)?;
+                len = 1;
+            }
+        }
+    }
+    block[0] = len as u8;
+    write(&block[..len])?;
+    write(&[0])
+}
+
+/// Decodes frames from the bytes received one at a time
+pub struct FrameDecoder {
+    buffer: [u8; MAX_PAYLOAD + CRC_LEN],
+    len: usize,
+    /// A code byte has been received since the last delimiter
+    started: bool,
+    /// Data bytes left in the current COBS block
+    remaining: u8,
+    /// The current block is followed by a zero (its code byte wasn't 0xFF)
+    zero: bool,
+    error: Option<FrameError>,
+}
+
+impl FrameDecoder {
+    pub const fn new() -> Self {
+        FrameDecoder {
+            buffer: [0; MAX_PAYLOAD + CRC_LEN],
+            len: 0,
+            started: false,
+            remaining: 0,
+            zero: false,
+            error: None,
+        }
+    }
+
+    /// Takes the next byte from the line, returning the payload of the frame
+    /// it completes.  Empty frames (consecutive delimiters) are skipped.
+    pub fn feed(&mut self, byte: u8) -> Option<Result<&[u8], FrameError>> {
+        if byte == 0 {
+            let len = self.len;
+            let complete = self.remaining == 0;
+            let started = self.started;
+            let error = self.error;
+            self.reset();
+            return match error {
+                Some(error) => Some(Err(error)),
+                None if !started => None,
+                None if !complete || len < CRC_LEN => Some(Err(FrameError::Malformed)),
+                None => {
+                    let (payload, crc) = self.buffer[..len].split_at(len - CRC_LEN);
+                    if crc16(payload).to_le_bytes() == crc {
+                        Some(Ok(payload))
+                    } else {
+                        Some(Err(FrameError::Crc))
+                    }
+                }
+            };
+        }
+        if self.error.is_some() {
+            return None;
+        }
+        if self.remaining == 0 {
+            if self.zero {
+                self.push(0);
+            }
+            self.started = true;
+            self.remaining = byte - 1;
+            self.zero = byte != BLOCK_LEN as u8;
+        } else {
+            self.push(byte);
+            self.remaining -= 1;
+        }
+        None
+    }
+
+    /// Drops the frame received so far.
+    pub fn reset(&mut self) {
+        self.len = 0;
+        self.started = false;
+        self.remaining = 0;
+        self.zero = false;
+        self.error = None;
+    }
+
+    fn push(&mut self, byte: u8) {
+        match self.buffer.get_mut(self.len) {
+            Some(slot) => {
+                *slot = byte;
+                self.len += 1;
+            }
+            None => self.error = Some(FrameError::TooLong),
+        }
+    }
+}
+
+impl Default for FrameDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
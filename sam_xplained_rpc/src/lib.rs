@@ -0,0 +1,174 @@
+//! RPC protocol between a host and the serial console of a board
+//!
+//! The host sends [`Request`]s and the board answers each with a
+//! [`Response`].  Messages are serialized with postcard behind the request
+//! ID, which the response repeats, and travel in [`frame`]s: COBS-encoded,
+//! with a CRC-16, delimited by zero bytes.  Anything else printed on the
+//! console fails to decode and is skipped by both sides.
+//!
+//! On the board a [`Server`] feeds the requests to a [`Handler`]:
+//!
+//! ```ignore
+//! let mut server = Server::new();
+//! server.run(&mut handler, &mut console)?;
+//! ```
+//!
+//! `sam_xplained_host` has the client and `sam_xplained::rpc` the handler
+//! for the boards.
+#![no_std]
+
+use core::fmt;
+use serde::{Deserialize, Serialize};
+
+pub mod frame;
+mod server;
+
+pub use frame::{FrameDecoder, FrameError};
+pub use server::{Handler, Server};
+
+/// Most data carried by a message: memory read or written, or a config value
+pub const MAX_DATA: usize = 256;
+/// Longest serialized message, with its request ID
+pub const MAX_PAYLOAD: usize = MAX_DATA + 16;
+
+/// Request from the host
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Request<'a> {
+    /// Answered with [`Response::Pong`] carrying the same value
+    Ping(u32),
+    /// CHIPID registers
+    ChipId,
+    ResetCause,
+    /// Build information of the running firmware
+    FirmwareInfo,
+    /// 128-bit unique identifier of the microcontroller
+    UniqueId,
+    Led(LedAction),
+    /// Reads `len` bytes from a word aligned `address`, a word at a time
+    ReadMemory {
+        address: u32,
+        len: u16,
+    },
+    /// Writes whole words to a word aligned `address`
+    WriteMemory {
+        address: u32,
+        data: &'a [u8],
+    },
+    /// Reads a value of the key-value store
+    ConfigGet(u16),
+    ConfigSet {
+        key: u16,
+        value: &'a [u8],
+    },
+    ConfigRemove(u16),
+    /// Resets the board once the response is sent
+    Reset,
+    /// Resets into the bootloader's update mode once the response is sent
+    Update,
+}
+
+/// What [`Request::Led`] does to LED0
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LedAction {
+    On,
+    Off,
+    Toggle,
+}
+
+/// Response of the board, by request
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Response<'a> {
+    Pong(u32),
+    ChipId {
+        cidr: u32,
+        exid: u32,
+    },
+    ResetCause(ResetCause),
+    /// `FirmwareInfo` as encoded in the image (see `sam_xplained_image`)
+    FirmwareInfo(&'a [u8]),
+    UniqueId([u32; 4]),
+    /// LED0 state after [`Request::Led`]
+    Led {
+        on: bool,
+    },
+    Memory(&'a [u8]),
+    /// Value of [`Request::ConfigGet`], if the key is set
+    Config(Option<&'a [u8]>),
+    /// The request was carried out
+    Done,
+    Error(RemoteError),
+}
+
+/// Cause of the last processor reset
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ResetCause {
+    FirstPowerUp,
+    ReturnFromBackup,
+    Watchdog,
+    Software,
+    NrstPin,
+    Reserved(u8),
+}
+
+impl fmt::Display for ResetCause {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ResetCause::FirstPowerUp => f.write_str("First power up reset"),
+            ResetCause::ReturnFromBackup => f.write_str("Return from backup mode"),
+            ResetCause::Watchdog => f.write_str("Watchdog timer"),
+            ResetCause::Software => f.write_str("Software"),
+            ResetCause::NrstPin => f.write_str("NRST pin detected low"),
+            ResetCause::Reserved(value) => write!(f, "Reserved reset type {}", value),
+        }
+    }
+}
+
+/// Request the board failed to carry out
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RemoteError {
+    /// The request couldn't be decoded, e.g. as it is newer than the firmware
+    Malformed,
+    /// The firmware doesn't handle the request
+    Unsupported,
+    /// Unaligned address, or too much data
+    InvalidArgument,
+    /// The key-value store failed
+    Storage,
+}
+
+impl fmt::Display for RemoteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            RemoteError::Malformed => "malformed request",
+            RemoteError::Unsupported => "request not supported by the firmware",
+            RemoteError::InvalidArgument => "invalid argument",
+            RemoteError::Storage => "key-value store failure",
+        })
+    }
+}
+
+/// Reason [`decode`] failed
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DecodeError {
+    /// No request ID
+    Truncated,
+    /// The message behind this request ID isn't a valid one
+    Message(u16),
+}
+
+/// Serializes a message behind its request ID, returning the frame payload
+/// or `None` if it doesn't fit in `buffer`.
+pub fn encode<'b, T: Serialize>(id: u16, message: &T, buffer: &'b mut [u8]) -> Option<&'b [u8]> {
+    postcard::to_slice(&(id, message), buffer)
+        .ok()
+        .map(|payload| &*payload)
+}
+
+/// Deserializes the request ID and message of a frame payload.
+pub fn decode<'a, T: Deserialize<'a>>(payload: &'a [u8]) -> Result<(u16, T), DecodeError> {
+    let (id, message) =
+        postcard::take_from_bytes::<u16>(payload).map_err(|_| DecodeError::Truncated)?;
+    postcard::from_bytes(message)
+        .map(|message| (id, message))
+        .map_err(|_| DecodeError::Message(id))
+}
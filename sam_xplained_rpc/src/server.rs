@@ -0,0 +1,88 @@
+use core::convert::Infallible;
+use embedded_io::{Read, Write};
+
+use crate::frame::{write_frame, FrameDecoder};
+use crate::{decode, encode, DecodeError, RemoteError, Request, Response, MAX_DATA, MAX_PAYLOAD};
+
+/// Carries out the requests received by a [`Server`]
+pub trait Handler {
+    /// Answers `request`.  Data returned in the response can be placed in
+    /// `buffer`, which holds [`MAX_DATA`] bytes.
+    fn handle<'a>(&'a mut self, request: Request<'_>, buffer: &'a mut [u8]) -> Response<'a>;
+
+    /// Called once the response has been sent, e.g. to reset the board after
+    /// [`Request::Reset`].
+    fn sent(&mut self) {}
+}
+
+/// Board side of the protocol: answers the requests framed on a serial port
+pub struct Server {
+    decoder: FrameDecoder,
+    data: [u8; MAX_DATA],
+    payload: [u8; MAX_PAYLOAD],
+}
+
+impl Server {
+    pub const fn new() -> Self {
+        Server {
+            decoder: FrameDecoder::new(),
+            data: [0; MAX_DATA],
+            payload: [0; MAX_PAYLOAD],
+        }
+    }
+
+    /// Takes the next byte received, answering the request it completes on
+    /// `serial`.
+    ///
+    /// Frames that fail to decode are dropped; requests the handler doesn't
+    /// know are answered with [`RemoteError::Malformed`].
+    pub fn feed<H: Handler, W: Write>(
+        &mut self,
+        byte: u8,
+        handler: &mut H,
+        serial: &mut W,
+    ) -> Result<(), W::Error> {
+        let payload = match self.decoder.feed(byte) {
+            Some(Ok(payload)) => payload,
+            _ => return Ok(()),
+        };
+        let (id, response) = match decode::<Request<'_>>(payload) {
+            Ok((id, request)) => (id, handler.handle(request, &mut self.data)),
+            Err(DecodeError::Message(id)) => (id, Response::Error(RemoteError::Malformed)),
+            Err(DecodeError::Truncated) => return Ok(()),
+        };
+        // Only data from somewhere else than `self.data` can be too long.
+        let len = match encode(id, &response, &mut self.payload) {
+            Some(payload) => payload.len(),
+            None => {
+                let error = Response::Error(RemoteError::InvalidArgument);
+                encode(id, &error, &mut self.payload).unwrap().len()
+            }
+        };
+        write_frame(&self.payload[..len], |block| serial.write_all(block))?;
+        serial.flush()?;
+        handler.sent();
+        Ok(())
+    }
+
+    /// Answers the requests received on `serial` until it fails.
+    pub fn run<H: Handler, S: Read + Write>(
+        &mut self,
+        handler: &mut H,
+        serial: &mut S,
+    ) -> Result<Infallible, S::Error> {
+        let mut buffer = [0u8; 32];
+        loop {
+            let len = serial.read(&mut buffer)?;
+            for &byte in &buffer[..len] {
+                self.feed(byte, handler, serial)?;
+            }
+        }
+    }
+}
+
+impl Default for Server {
+    fn default() -> Self {
+        Self::new()
+    }
+}
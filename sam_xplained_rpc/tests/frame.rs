@@ -0,0 +1,129 @@
+use sam_xplained_rpc::frame::{write_frame, MAX_FRAME};
+use sam_xplained_rpc::*;
+
+fn frame(payload: &[u8]) -> Vec<u8> {
+    let mut line = Vec::new();
+    write_frame::<()>(payload, |block| {
+        line.extend_from_slice(block);
+        Ok(())
+    })
+    .unwrap();
+    line
+}
+
+/// Feeds `line` to `decoder`, collecting the frames it completes
+fn receive(decoder: &mut FrameDecoder, line: &[u8]) -> Vec<Result<Vec<u8>, FrameError>> {
+    line.iter()
+        .filter_map(|&byte| decoder.feed(byte).map(|frame| frame.map(<[u8]>::to_vec)))
+        .collect()
+}
+
+#[test]
+fn frames_payloads() {
+    let payloads: Vec<Vec<u8>> = vec![
+        vec![],
+        vec![0],
+        vec![0, 0, 0],
+        vec![1, 2, 0, 3],
+        (1..=254).collect(),
+        (1..=255).collect(),
+        (0..MAX_PAYLOAD).map(|i| i as u8).collect(),
+        vec![0xFF; MAX_PAYLOAD],
+    ];
+    let mut decoder = FrameDecoder::new();
+    for payload in &payloads {
+        let line = frame(payload);
+        assert_eq!(line.first(), Some(&0));
+        assert_eq!(line.last(), Some(&0));
+        assert!(!line[1..line.len() - 1].contains(&0));
+        assert!(line.len() <= MAX_FRAME);
+        assert_eq!(receive(&mut decoder, &line), [Ok(payload.clone())]);
+    }
+}
+
+#[test]
+fn skips_other_output() {
+    let mut decoder = FrameDecoder::new();
+    let mut line = b"Boot count: 3\r\n".to_vec();
+    line.extend(frame(b"first"));
+    line.extend_from_slice(b"log line\r\n");
+    line.extend(frame(b"second"));
+    // The log lines end up in frames of their own, which fail to decode.
+    assert_eq!(
+        receive(&mut decoder, &line),
+        [
+            Err(FrameError::Malformed),
+            Ok(b"first".to_vec()),
+            Err(FrameError::Malformed),
+            Ok(b"second".to_vec())
+        ]
+    );
+}
+
+#[test]
+fn rejects_damaged_frames() {
+    let mut decoder = FrameDecoder::new();
+
+    let mut line = frame(b"payload");
+    line[3] ^= 0x10;
+    assert_eq!(receive(&mut decoder, &line), [Err(FrameError::Crc)]);
+
+    // Cut short within a COBS block, and shorter than the CRC
+    let line = frame(b"payload");
+    let mut cut = line[..5].to_vec();
+    cut.push(0);
+    assert_eq!(receive(&mut decoder, &cut), [Err(FrameError::Malformed)]);
+    assert_eq!(
+        receive(&mut decoder, &[0, 1, 0]),
+        [Err(FrameError::Malformed)]
+    );
+
+    // Too long, after which the decoder recovers
+    let mut line = frame(&vec![7; MAX_PAYLOAD + 1]);
+    line.extend(frame(b"next"));
+    assert_eq!(
+        receive(&mut decoder, &line),
+        [Err(FrameError::TooLong), Ok(b"next".to_vec())]
+    );
+}
+
+#[test]
+fn encodes_messages() {
+    let mut buffer = [0u8; MAX_PAYLOAD];
+    let data = [0xA5; MAX_DATA];
+    let requests = [
+        Request::Ping(0xDEAD_BEEF),
+        Request::Led(LedAction::Toggle),
+        Request::ReadMemory {
+            address: 0x400E_0740,
+            len: 8,
+        },
+        Request::WriteMemory {
+            address: 0x2000_0000,
+            data: &data,
+        },
+        Request::ConfigSet {
+            key: u16::MAX,
+            value: &data,
+        },
+    ];
+    for (id, request) in requests.iter().enumerate() {
+        let id = u16::MAX - id as u16;
+        let payload = encode(id, request, &mut buffer).unwrap();
+        assert_eq!(decode::<Request<'_>>(payload), Ok((id, *request)));
+    }
+
+    let response = Response::Memory(&data);
+    let payload = encode(7, &response, &mut buffer).unwrap();
+    assert_eq!(decode::<Response<'_>>(payload), Ok((7, response)));
+
+    // Data that doesn't fit in a message
+    let long = [0u8; MAX_PAYLOAD];
+    assert_eq!(encode(1, &Response::Memory(&long), &mut buffer), None);
+
+    assert_eq!(decode::<Request<'_>>(&[]), Err(DecodeError::Truncated));
+    assert_eq!(
+        decode::<Request<'_>>(&[5, 200]),
+        Err(DecodeError::Message(5))
+    );
+}
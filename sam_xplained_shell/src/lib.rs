@@ -0,0 +1,515 @@
+//! Interactive command shell for a serial console
+//!
+//! [`Shell`] edits a command line from the bytes typed on a terminal
+//! (backspace, Ctrl-C, history on the up and down arrows, completion of
+//! command names with tab) and runs the registered [`Command`]s on it.
+//! Commands receive the application's context `C`, their [`Args`] and the
+//! terminal to write to, with `\n` sent as `\r\n`.
+//!
+//! ```ignore
+//! let mut shell: Shell<Board, 16> = Shell::new("> ");
+//! shell.register(Command {
+//!     name: "hello",
+//!     usage: "[name]",
+//!     help: "Greets someone",
+//!     handler: |_, args, out| {
+//!         writeln!(out, "Hello {}!", args.next().unwrap_or("world")).map_err(Error::from)
+//!     },
+//! })?;
+//! shell.run(&mut board, &mut console)?;
+//! ```
+//!
+//! `help` is built in and lists the registered commands.
+#![no_std]
+
+use core::convert::Infallible;
+use core::fmt::{self, Write as _};
+use core::str::{self, SplitAsciiWhitespace};
+use embedded_io::{Read, Write};
+
+/// Longest command line in bytes
+pub const MAX_LINE: usize = 128;
+/// Number of command lines kept in the history
+pub const HISTORY: usize = 8;
+
+const BACKSPACE: u8 = 0x08;
+const DELETE: u8 = 0x7F;
+const CTRL_C: u8 = 0x03;
+const TAB: u8 = b'\t';
+const ESCAPE: u8 = 0x1B;
+const BELL: &str = "\x07";
+const CLEAR_TO_END: &str = "\x1b[K";
+
+/// Runs a command: `handler(context, args, terminal)`
+pub type Handler<C> = fn(&mut C, &mut Args<'_>, &mut dyn fmt::Write) -> Result<(), Error>;
+
+/// Command registered with [`Shell::register`]
+pub struct Command<C> {
+    /// Name typed to run the command
+    pub name: &'static str,
+    /// Arguments, e.g. `on|off|toggle`, as shown by `help` and on
+    /// [`Error::Usage`]
+    pub usage: &'static str,
+    /// One line description shown by `help`
+    pub help: &'static str,
+    pub handler: Handler<C>,
+}
+
+impl<C> Clone for Command<C> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<C> Copy for Command<C> {}
+
+impl<C> fmt::Debug for Command<C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Command")
+            .field("name", &self.name)
+            .field("usage", &self.usage)
+            .finish()
+    }
+}
+
+/// Failure of a command, reported on the terminal by the shell
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// Missing, extra or unrecognized arguments; the shell shows the usage.
+    Usage,
+    /// The command could not be carried out, for the given reason.
+    Failed(&'static str),
+    /// Writing to the terminal failed.
+    Output,
+}
+
+impl From<fmt::Error> for Error {
+    fn from(_: fmt::Error) -> Self {
+        Error::Output
+    }
+}
+
+/// [`Shell::register`] failed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RegisterError {
+    /// All the shell's command slots are in use.
+    Full,
+    /// A command with the same name is registered already (or is `help`).
+    Duplicate,
+}
+
+/// Arguments following the command name, separated by whitespace
+#[derive(Clone, Debug)]
+pub struct Args<'a> {
+    words: SplitAsciiWhitespace<'a>,
+}
+
+impl<'a> Args<'a> {
+    pub fn new(text: &'a str) -> Self {
+        Args {
+            words: text.split_ascii_whitespace(),
+        }
+    }
+
+    /// Next argument, or [`Error::Usage`] if there is none
+    pub fn required(&mut self) -> Result<&'a str, Error> {
+        self.words.next().ok_or(Error::Usage)
+    }
+
+    /// Next argument as a decimal or `0x` hexadecimal number
+    pub fn number(&mut self) -> Result<u32, Error> {
+        parse_number(self.required()?).ok_or(Error::Usage)
+    }
+
+    /// Like [`Args::number`], but the argument may be left out.
+    pub fn optional_number(&mut self) -> Result<Option<u32>, Error> {
+        match self.words.next() {
+            Some(word) => parse_number(word).map(Some).ok_or(Error::Usage),
+            None => Ok(None),
+        }
+    }
+
+    /// Fails with [`Error::Usage`] if arguments are left over.
+    pub fn finish(&mut self) -> Result<(), Error> {
+        match self.words.next() {
+            Some(_) => Err(Error::Usage),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<'a> Iterator for Args<'a> {
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<&'a str> {
+        self.words.next()
+    }
+}
+
+/// Parses a decimal or `0x` hexadecimal number, with optional `_`
+/// separators.
+pub fn parse_number(text: &str) -> Option<u32> {
+    let (digits, radix) = match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(hex) => (hex, 16),
+        None => (text, 10),
+    };
+    if digits.is_empty() || digits.starts_with('_') {
+        return None;
+    }
+    digits
+        .bytes()
+        .filter(|&byte| byte != b'_')
+        .try_fold(0u32, |value, byte| {
+            let digit = (byte as char).to_digit(radix)?;
+            value.checked_mul(radix)?.checked_add(digit)
+        })
+}
+
+#[derive(Clone, Copy)]
+struct Line {
+    bytes: [u8; MAX_LINE],
+    len: usize,
+}
+
+impl Line {
+    const EMPTY: Line = Line {
+        bytes: [0; MAX_LINE],
+        len: 0,
+    };
+
+    fn as_str(&self) -> &str {
+        // Only printable ASCII is ever stored.
+        str::from_utf8(&self.bytes[..self.len]).unwrap_or("")
+    }
+}
+
+/// Progress through an escape sequence
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Input {
+    Normal,
+    /// After `\r`, which a following `\n` belongs to
+    Return,
+    Escape,
+    /// After `ESC [`
+    Csi,
+}
+
+/// Command line editor and interpreter holding up to `COMMANDS` commands
+pub struct Shell<C, const COMMANDS: usize> {
+    prompt: &'static str,
+    commands: [Option<Command<C>>; COMMANDS],
+    line: Line,
+    input: Input,
+    history: [Line; HISTORY],
+    /// Number of lines stored in the history
+    history_len: usize,
+    /// Slot the next history line goes to
+    history_next: usize,
+    /// Lines back in the history being shown, 0 for the line being typed
+    recall: usize,
+}
+
+impl<C, const COMMANDS: usize> Shell<C, COMMANDS> {
+    pub fn new(prompt: &'static str) -> Self {
+        Shell {
+            prompt,
+            commands: [None; COMMANDS],
+            line: Line::EMPTY,
+            input: Input::Normal,
+            history: [Line::EMPTY; HISTORY],
+            history_len: 0,
+            history_next: 0,
+            recall: 0,
+        }
+    }
+
+    /// Adds `command` to the commands the shell runs.
+    pub fn register(&mut self, command: Command<C>) -> Result<(), RegisterError> {
+        if command.name == "help" || self.find(command.name).is_some() {
+            return Err(RegisterError::Duplicate);
+        }
+        let slot = self
+            .commands
+            .iter_mut()
+            .find(|slot| slot.is_none())
+            .ok_or(RegisterError::Full)?;
+        *slot = Some(command);
+        Ok(())
+    }
+
+    /// Registered commands, in the order they were registered
+    pub fn commands(&self) -> impl Iterator<Item = &Command<C>> + '_ {
+        self.commands.iter().flatten()
+    }
+
+    fn find(&self, name: &str) -> Option<Command<C>> {
+        self.commands()
+            .find(|command| command.name == name)
+            .copied()
+    }
+
+    /// Shows the prompt.
+    pub fn start(&mut self, out: &mut impl fmt::Write) -> fmt::Result {
+        out.write_str(self.prompt)
+    }
+
+    /// Handles a byte typed on the terminal, echoing it and running the
+    /// command line on return.
+    pub fn feed(&mut self, byte: u8, context: &mut C, out: &mut impl fmt::Write) -> fmt::Result {
+        let input = self.input;
+        self.input = Input::Normal;
+        match (input, byte) {
+            // `ESC O` starts the arrow keys in application cursor mode.
+            (Input::Escape, b'[') | (Input::Escape, b'O') => self.input = Input::Csi,
+            (Input::Escape, _) => {}
+            (Input::Csi, b'A') => self.recall(self.recall + 1, out)?,
+            (Input::Csi, b'B') => self.recall(self.recall.saturating_sub(1), out)?,
+            // Parameters of a sequence we don't handle
+            (Input::Csi, b'0'..=b'9') | (Input::Csi, b';') => self.input = Input::Csi,
+            (Input::Csi, _) => {}
+            (Input::Return, b'\n') => {}
+            (_, b'\r') | (_, b'\n') => {
+                if byte == b'\r' {
+                    self.input = Input::Return;
+                }
+                out.write_str("\r\n")?;
+                let line = self.line;
+                self.line.len = 0;
+                self.recall = 0;
+                self.remember(&line);
+                self.execute(line.as_str(), context, out)?;
+                out.write_str(self.prompt)?;
+            }
+            (_, BACKSPACE) | (_, DELETE) if self.line.len > 0 => {
+                self.line.len -= 1;
+                out.write_str("\x08 \x08")?;
+            }
+            (_, CTRL_C) => {
+                self.line.len = 0;
+                self.recall = 0;
+                write!(out, "^C\r\n{}", self.prompt)?;
+            }
+            (_, TAB) => self.complete(out)?,
+            (_, ESCAPE) => self.input = Input::Escape,
+            (_, b' '..=b'~') => {
+                if self.line.len < MAX_LINE {
+                    self.line.bytes[self.line.len] = byte;
+                    self.line.len += 1;
+                    out.write_char(byte as char)?;
+                } else {
+                    out.write_str(BELL)?;
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Runs a command line, reporting errors on `out`.
+    pub fn execute(
+        &mut self,
+        line: &str,
+        context: &mut C,
+        out: &mut impl fmt::Write,
+    ) -> fmt::Result {
+        let mut terminal = Terminal(out);
+        let mut args = Args::new(line);
+        let name = match args.next() {
+            Some(name) => name,
+            None => return Ok(()),
+        };
+        if name == "help" {
+            return self.help(&mut terminal);
+        }
+        let command = match self.find(name) {
+            Some(command) => command,
+            None => {
+                return writeln!(
+                    terminal,
+                    "unknown command `{}`, `help` lists the commands",
+                    name
+                )
+            }
+        };
+        match (command.handler)(context, &mut args, &mut terminal) {
+            Ok(()) => Ok(()),
+            Err(Error::Usage) => writeln!(terminal, "usage: {} {}", command.name, command.usage),
+            Err(Error::Failed(reason)) => writeln!(terminal, "{}: {}", command.name, reason),
+            Err(Error::Output) => Err(fmt::Error),
+        }
+    }
+
+    /// Runs the shell on a serial port until reading from or writing to it
+    /// fails.
+    pub fn run<S: Read + Write>(
+        &mut self,
+        context: &mut C,
+        serial: &mut S,
+    ) -> Result<Infallible, S::Error> {
+        let mut out = IoWriter {
+            serial: &mut *serial,
+            error: None,
+        };
+        self.start(&mut out).ok();
+        out.check()?;
+        loop {
+            let mut byte = [0];
+            if out.serial.read(&mut byte)? == 0 {
+                continue;
+            }
+            self.feed(byte[0], context, &mut out).ok();
+            out.check()?;
+        }
+    }
+
+    fn help(&self, out: &mut impl fmt::Write) -> fmt::Result {
+        let width = self
+            .commands()
+            .map(|command| command.name.len() + command.usage.len() + 1)
+            .max()
+            .unwrap_or(0);
+        for command in self.commands() {
+            let usage = command.name.len() + command.usage.len() + 1;
+            writeln!(
+                out,
+                "{} {}{:pad$}  {}",
+                command.name,
+                command.usage,
+                "",
+                command.help,
+                pad = width - usage
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Completes the command name being typed as far as it is unambiguous,
+    /// or lists the candidates.
+    fn complete(&mut self, out: &mut impl fmt::Write) -> fmt::Result {
+        let line = self.line;
+        let prefix = line.as_str();
+        if prefix.contains(' ') {
+            return out.write_str(BELL);
+        }
+        let candidates = || {
+            self.commands()
+                .map(|command| command.name)
+                .chain(core::iter::once("help"))
+                .filter(move |name| name.starts_with(prefix))
+        };
+        let first = match candidates().next() {
+            Some(first) => first,
+            None => return out.write_str(BELL),
+        };
+        // Longest prefix shared by all the candidates
+        let common = candidates().fold(first.len(), |common, name| {
+            first
+                .bytes()
+                .zip(name.bytes())
+                .take(common)
+                .take_while(|(a, b)| a == b)
+                .count()
+        });
+        let unique = candidates().nth(1).is_none();
+
+        if unique {
+            self.append(&first[prefix.len()..], out)?;
+            self.append(" ", out)
+        } else if common > prefix.len() {
+            self.append(&first[prefix.len()..common], out)
+        } else {
+            out.write_str("\r\n")?;
+            for name in candidates() {
+                write!(out, "{}  ", name)?;
+            }
+            write!(out, "\r\n{}{}", self.prompt, prefix)
+        }
+    }
+
+    fn append(&mut self, text: &str, out: &mut impl fmt::Write) -> fmt::Result {
+        if self.line.len + text.len() > MAX_LINE {
+            return out.write_str(BELL);
+        }
+        self.line.bytes[self.line.len..self.line.len + text.len()].copy_from_slice(text.as_bytes());
+        self.line.len += text.len();
+        out.write_str(text)
+    }
+
+    /// Adds a command line to the history, unless it is empty or repeats the
+    /// previous one.
+    fn remember(&mut self, line: &Line) {
+        let text = line.as_str().trim();
+        if text.is_empty() || (self.history_len > 0 && self.history(1).as_str().trim() == text) {
+            return;
+        }
+        self.history[self.history_next] = *line;
+        self.history_next = (self.history_next + 1) % HISTORY;
+        self.history_len = (self.history_len + 1).min(HISTORY);
+    }
+
+    /// History line `back` lines before the newest (1)
+    fn history(&self, back: usize) -> &Line {
+        &self.history[(self.history_next + HISTORY - back) % HISTORY]
+    }
+
+    /// Replaces the line being edited with the one `back` lines back in the
+    /// history, or clears it for 0.
+    fn recall(&mut self, back: usize, out: &mut impl fmt::Write) -> fmt::Result {
+        if back > self.history_len || back == self.recall {
+            return out.write_str(BELL);
+        }
+        self.recall = back;
+        self.line = match back {
+            0 => Line::EMPTY,
+            _ => *self.history(back),
+        };
+        write!(
+            out,
+            "\r{}{}{}",
+            self.prompt,
+            self.line.as_str(),
+            CLEAR_TO_END
+        )
+    }
+}
+
+/// Sends `\n` as `\r\n`
+struct Terminal<'a, W: ?Sized>(&'a mut W);
+
+impl<W: fmt::Write + ?Sized> fmt::Write for Terminal<'_, W> {
+    fn write_str(&mut self, text: &str) -> fmt::Result {
+        let mut lines = text.split('\n');
+        if let Some(first) = lines.next() {
+            self.0.write_str(first)?;
+        }
+        for line in lines {
+            self.0.write_str("\r\n")?;
+            self.0.write_str(line)?;
+        }
+        Ok(())
+    }
+}
+
+/// `fmt::Write` on an embedded-io serial port, keeping the port's error
+struct IoWriter<'a, S: Write> {
+    serial: &'a mut S,
+    error: Option<S::Error>,
+}
+
+impl<S: Write> IoWriter<'_, S> {
+    fn check(&mut self) -> Result<(), S::Error> {
+        match self.error.take() {
+            Some(error) => Err(error),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<S: Write> fmt::Write for IoWriter<'_, S> {
+    fn write_str(&mut self, text: &str) -> fmt::Result {
+        self.serial.write_all(text.as_bytes()).map_err(|error| {
+            self.error = Some(error);
+            fmt::Error
+        })
+    }
+}
@@ -0,0 +1,326 @@
+use sam_xplained_shell::*;
+
+/// Application state the test commands work on
+#[derive(Default)]
+struct Lab {
+    led: bool,
+    calls: Vec<String>,
+}
+
+fn shell() -> Shell<Lab, 4> {
+    let mut shell: Shell<Lab, 4> = Shell::new("> ");
+    shell
+        .register(Command {
+            name: "led",
+            usage: "on|off|toggle",
+            help: "Switches the LED",
+            handler: |lab, args, out| {
+                lab.led = match args.required()? {
+                    "on" => true,
+                    "off" => false,
+                    "toggle" => !lab.led,
+                    _ => return Err(Error::Usage),
+                };
+                args.finish()?;
+                writeln!(out, "LED {}", if lab.led { "on" } else { "off" })?;
+                Ok(())
+            },
+        })
+        .unwrap();
+    shell
+        .register(Command {
+            name: "peek",
+            usage: "<address> [count]",
+            help: "Reads memory",
+            handler: |lab, args, _| {
+                let address = args.number()?;
+                let count = args.optional_number()?.unwrap_or(1);
+                args.finish()?;
+                lab.calls.push(format!("peek {:#x} {}", address, count));
+                Ok(())
+            },
+        })
+        .unwrap();
+    shell
+        .register(Command {
+            name: "poke",
+            usage: "<address> <value>",
+            help: "Writes memory",
+            handler: |_, _, _| Err(Error::Failed("bus fault")),
+        })
+        .unwrap();
+    shell
+}
+
+/// Types `input` and returns what the shell printed
+fn type_in(shell: &mut Shell<Lab, 4>, lab: &mut Lab, input: &str) -> String {
+    let mut out = String::new();
+    for byte in input.bytes() {
+        shell.feed(byte, lab, &mut out).unwrap();
+    }
+    out
+}
+
+#[test]
+fn runs_commands() {
+    let mut shell = shell();
+    let mut lab = Lab::default();
+    let mut out = String::new();
+    shell.start(&mut out).unwrap();
+    assert_eq!(out, "> ");
+
+    assert_eq!(
+        type_in(&mut shell, &mut lab, "led on\r"),
+        "led on\r\nLED on\r\n> "
+    );
+    assert!(lab.led);
+    assert_eq!(
+        type_in(&mut shell, &mut lab, "  led   toggle \r\n"),
+        "  led   toggle \r\nLED off\r\n> "
+    );
+    assert!(!lab.led);
+
+    type_in(&mut shell, &mut lab, "peek 0x2000_0000\npeek 400 16\n");
+    assert_eq!(lab.calls, ["peek 0x20000000 1", "peek 0x190 16"]);
+
+    // Empty lines only show the prompt again.
+    assert_eq!(type_in(&mut shell, &mut lab, "\r\r"), "\r\n> \r\n> ");
+}
+
+#[test]
+fn reports_errors() {
+    let mut shell = shell();
+    let mut lab = Lab::default();
+    let mut run = |line: &str| {
+        let mut out = String::new();
+        shell.execute(line, &mut lab, &mut out).unwrap();
+        out
+    };
+    assert_eq!(run("led"), "usage: led on|off|toggle\r\n");
+    assert_eq!(run("led blink"), "usage: led on|off|toggle\r\n");
+    assert_eq!(run("led on off"), "usage: led on|off|toggle\r\n");
+    assert_eq!(run("peek 0xZZ"), "usage: peek <address> [count]\r\n");
+    assert_eq!(run("poke 0 0"), "poke: bus fault\r\n");
+    assert_eq!(
+        run("blink"),
+        "unknown command `blink`, `help` lists the commands\r\n"
+    );
+    assert_eq!(
+        run("help"),
+        "led on|off|toggle       Switches the LED\r\n\
+         peek <address> [count]  Reads memory\r\n\
+         poke <address> <value>  Writes memory\r\n"
+    );
+}
+
+#[test]
+fn registers_commands() {
+    let mut shell = shell();
+    let command = Command {
+        name: "reset",
+        usage: "",
+        help: "Resets the board",
+        handler: |_, _, _| Ok(()),
+    };
+    assert_eq!(shell.register(command), Ok(()));
+    assert_eq!(shell.register(command), Err(RegisterError::Duplicate));
+    assert_eq!(
+        shell.register(Command {
+            name: "help",
+            ..command
+        }),
+        Err(RegisterError::Duplicate)
+    );
+    assert_eq!(
+        shell.register(Command {
+            name: "gpio",
+            ..command
+        }),
+        Err(RegisterError::Full)
+    );
+    let names: Vec<_> = shell.commands().map(|command| command.name).collect();
+    assert_eq!(names, ["led", "peek", "poke", "reset"]);
+}
+
+#[test]
+fn edits_lines() {
+    let mut shell = shell();
+    let mut lab = Lab::default();
+
+    // Backspace and delete
+    assert_eq!(
+        type_in(&mut shell, &mut lab, "led onn\x08"),
+        "led onn\x08 \x08"
+    );
+    assert_eq!(
+        type_in(&mut shell, &mut lab, "\x7f\x7fon\r"),
+        "\x08 \x08\x08 \x08on\r\nLED on\r\n> "
+    );
+    // Nothing left to erase
+    assert_eq!(type_in(&mut shell, &mut lab, "\x08"), "");
+
+    // Ctrl-C discards the line.
+    assert_eq!(
+        type_in(&mut shell, &mut lab, "led off\x03\r"),
+        "led off^C\r\n> \r\n> "
+    );
+    assert!(lab.led);
+
+    // Control characters and unknown escape sequences are ignored.
+    assert_eq!(type_in(&mut shell, &mut lab, "\x01\x1b[1;5C\x1bx"), "");
+
+    // Lines are limited to MAX_LINE bytes.
+    let long = "x".repeat(MAX_LINE + 1);
+    let out = type_in(&mut shell, &mut lab, &long);
+    assert_eq!(out, format!("{}\x07", &long[..MAX_LINE]));
+}
+
+#[test]
+fn recalls_history() {
+    let mut shell = shell();
+    let mut lab = Lab::default();
+    type_in(&mut shell, &mut lab, "led on\rpeek 1\rpeek 1\r\r");
+
+    // Repeated and empty lines are kept once.
+    const UP: &str = "\x1b[A";
+    const DOWN: &str = "\x1b[B";
+    assert_eq!(type_in(&mut shell, &mut lab, UP), "\r> peek 1\x1b[K");
+    assert_eq!(type_in(&mut shell, &mut lab, UP), "\r> led on\x1b[K");
+    assert_eq!(type_in(&mut shell, &mut lab, UP), "\x07");
+    assert_eq!(type_in(&mut shell, &mut lab, DOWN), "\r> peek 1\x1b[K");
+    assert_eq!(type_in(&mut shell, &mut lab, DOWN), "\r> \x1b[K");
+    assert_eq!(type_in(&mut shell, &mut lab, DOWN), "\x07");
+
+    // Recalled lines can be edited and run.
+    type_in(&mut shell, &mut lab, &format!("{}{}\x08\x08off\r", UP, UP));
+    assert!(!lab.led);
+    assert_eq!(type_in(&mut shell, &mut lab, UP), "\r> led off\x1b[K");
+
+    // Only the last HISTORY lines are kept.
+    type_in(&mut shell, &mut lab, "\x03");
+    for count in 0..HISTORY + 2 {
+        type_in(&mut shell, &mut lab, &format!("peek {}\r", count));
+    }
+    let mut oldest = String::new();
+    for _ in 0..HISTORY + 1 {
+        oldest = type_in(&mut shell, &mut lab, UP);
+    }
+    assert_eq!(oldest, "\x07");
+    assert_eq!(type_in(&mut shell, &mut lab, DOWN), "\r> peek 3\x1b[K");
+}
+
+#[test]
+fn completes_command_names() {
+    let mut shell = shell();
+    let mut lab = Lab::default();
+
+    // Unique: completed with a space
+    assert_eq!(type_in(&mut shell, &mut lab, "l\t"), "led ");
+    assert_eq!(type_in(&mut shell, &mut lab, "on\r"), "on\r\nLED on\r\n> ");
+
+    // Candidates without a longer common prefix are listed.
+    assert_eq!(
+        type_in(&mut shell, &mut lab, "p\t"),
+        "p\r\npeek  poke  \r\n> p"
+    );
+    assert_eq!(type_in(&mut shell, &mut lab, "e\t"), "eek ");
+    type_in(&mut shell, &mut lab, "\x03");
+
+    // Common prefix first
+    shell
+        .register(Command {
+            name: "pokeall",
+            usage: "<value>",
+            help: "Fills memory",
+            handler: |_, _, _| Ok(()),
+        })
+        .unwrap();
+    assert_eq!(type_in(&mut shell, &mut lab, "po\t"), "poke");
+    assert_eq!(
+        type_in(&mut shell, &mut lab, "\t"),
+        "\r\npoke  pokeall  \r\n> poke"
+    );
+    type_in(&mut shell, &mut lab, "\x03");
+
+    // `help` completes too; arguments and unknown names don't.
+    assert_eq!(type_in(&mut shell, &mut lab, "h\t"), "help ");
+    type_in(&mut shell, &mut lab, "\x03");
+    assert_eq!(type_in(&mut shell, &mut lab, "x\t"), "x\x07");
+    type_in(&mut shell, &mut lab, "\x03");
+    assert_eq!(type_in(&mut shell, &mut lab, "led o\t"), "led o\x07");
+}
+
+#[test]
+fn parses_numbers() {
+    assert_eq!(parse_number("0"), Some(0));
+    assert_eq!(parse_number("4294967295"), Some(u32::MAX));
+    assert_eq!(parse_number("4294967296"), None);
+    assert_eq!(parse_number("0x400E_0A00"), Some(0x400E_0A00));
+    assert_eq!(parse_number("0XfF"), Some(255));
+    assert_eq!(parse_number("0x"), None);
+    assert_eq!(parse_number("_1"), None);
+    assert_eq!(parse_number("12a"), None);
+    assert_eq!(parse_number("-1"), None);
+
+    let mut args = Args::new(" 0x10  two ");
+    assert_eq!(args.number(), Ok(16));
+    assert_eq!(args.number(), Err(Error::Usage));
+    assert_eq!(args.optional_number(), Ok(None));
+    assert_eq!(args.required(), Err(Error::Usage));
+}
+
+/// Serial port fed from a script, collecting the output
+struct Script {
+    input: Vec<u8>,
+    output: Vec<u8>,
+}
+
+#[derive(Debug, PartialEq)]
+struct Hangup;
+
+impl embedded_io::Error for Hangup {
+    fn kind(&self) -> embedded_io::ErrorKind {
+        embedded_io::ErrorKind::BrokenPipe
+    }
+}
+
+impl embedded_io::ErrorType for Script {
+    type Error = Hangup;
+}
+
+impl embedded_io::Read for Script {
+    fn read(&mut self, buffer: &mut [u8]) -> Result<usize, Hangup> {
+        if self.input.is_empty() {
+            return Err(Hangup);
+        }
+        buffer[0] = self.input.remove(0);
+        Ok(1)
+    }
+}
+
+impl embedded_io::Write for Script {
+    fn write(&mut self, buffer: &[u8]) -> Result<usize, Hangup> {
+        self.output.extend_from_slice(buffer);
+        Ok(buffer.len())
+    }
+
+    fn flush(&mut self) -> Result<(), Hangup> {
+        Ok(())
+    }
+}
+
+#[test]
+fn runs_on_serial_ports() {
+    let mut shell = shell();
+    let mut lab = Lab::default();
+    let mut serial = Script {
+        input: b"led on\r\n".to_vec(),
+        output: Vec::new(),
+    };
+    assert_eq!(shell.run(&mut lab, &mut serial).unwrap_err(), Hangup);
+    assert_eq!(
+        String::from_utf8(serial.output).unwrap(),
+        "> led on\r\nLED on\r\n> "
+    );
+    assert!(lab.led);
+}
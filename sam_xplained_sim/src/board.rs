@@ -0,0 +1,209 @@
+//! Simulated board and its front panel
+
+use std::convert::Infallible;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use embedded_hal::delay::DelayNs;
+use embedded_hal::digital::{self, InputPin, OutputPin, StatefulOutputPin};
+use sam_xplained::chip::ChipInfo;
+use sam_xplained::mock::MockFlash;
+use sam_xplained::{ResetCause, XplainedBoard};
+
+use crate::console::SimConsole;
+use crate::sram::{ExternalSram, SRAM_BASES, SRAM_SIZE};
+
+/// How long a key press holds SW0 down
+pub const PRESS_TIME: Duration = Duration::from_millis(300);
+
+/// CIDR and EXID of the SAM4E16E
+const CIDR: u32 = 0xA3CC_0CE0;
+const EXID: u32 = 0x0012_0200;
+/// Size of the SAM4E16E's flash
+const FLASH_SIZE: usize = 1024 * 1024;
+
+#[derive(Default)]
+struct PanelState {
+    led: AtomicBool,
+    held: AtomicBool,
+    pressed_until: Mutex<Option<Instant>>,
+}
+
+/// LED0 and SW0 as seen from outside the board, shared with the
+/// application's [`SimLed`] and [`SimButton`]
+#[derive(Clone, Default)]
+pub struct FrontPanel(Arc<PanelState>);
+
+impl FrontPanel {
+    pub fn led_on(&self) -> bool {
+        self.0.led.load(Ordering::SeqCst)
+    }
+
+    /// Presses SW0 for `duration`.
+    pub fn press(&self, duration: Duration) {
+        *self.0.pressed_until.lock().unwrap() = Some(Instant::now() + duration);
+    }
+
+    /// Holds SW0 down, or releases it.
+    pub fn hold(&self, held: bool) {
+        self.0.held.store(held, Ordering::SeqCst);
+        if !held {
+            *self.0.pressed_until.lock().unwrap() = None;
+        }
+    }
+
+    pub fn is_held(&self) -> bool {
+        self.0.held.load(Ordering::SeqCst)
+    }
+
+    pub fn sw0_pressed(&self) -> bool {
+        self.is_held()
+            || matches!(*self.0.pressed_until.lock().unwrap(), Some(until) if Instant::now() < until)
+    }
+}
+
+/// Active low LED0
+pub struct SimLed {
+    panel: FrontPanel,
+}
+
+impl digital::ErrorType for SimLed {
+    type Error = Infallible;
+}
+
+impl OutputPin for SimLed {
+    fn set_low(&mut self) -> Result<(), Infallible> {
+        self.panel.0.led.store(true, Ordering::SeqCst);
+        Ok(())
+    }
+
+    fn set_high(&mut self) -> Result<(), Infallible> {
+        self.panel.0.led.store(false, Ordering::SeqCst);
+        Ok(())
+    }
+}
+
+impl StatefulOutputPin for SimLed {
+    fn is_set_high(&mut self) -> Result<bool, Infallible> {
+        Ok(!self.panel.led_on())
+    }
+
+    fn is_set_low(&mut self) -> Result<bool, Infallible> {
+        Ok(self.panel.led_on())
+    }
+}
+
+/// Active low SW0
+pub struct SimButton {
+    panel: FrontPanel,
+}
+
+impl digital::ErrorType for SimButton {
+    type Error = Infallible;
+}
+
+impl InputPin for SimButton {
+    fn is_high(&mut self) -> Result<bool, Infallible> {
+        Ok(!self.panel.sw0_pressed())
+    }
+
+    fn is_low(&mut self) -> Result<bool, Infallible> {
+        Ok(self.panel.sw0_pressed())
+    }
+}
+
+/// Delay that sleeps the application's thread
+#[derive(Default)]
+pub struct SimDelay;
+
+impl DelayNs for SimDelay {
+    fn delay_ns(&mut self, ns: u32) {
+        thread::sleep(Duration::from_nanos(ns as u64));
+    }
+}
+
+/// SAM4E Xplained Pro simulated on the host
+pub struct SimBoard {
+    pub led0: SimLed,
+    pub sw0: SimButton,
+    pub console: SimConsole,
+    pub delay: SimDelay,
+    pub flash: MockFlash,
+    /// Chips on NCS1 and NCS3 of the static memory controller
+    pub sram: [ExternalSram; 2],
+    pub chip_id: ChipInfo,
+    pub reset_cause: ResetCause,
+    pub unique_id: [u32; 4],
+}
+
+impl SimBoard {
+    /// A board out of power up, LED0 off and its flash erased, with the
+    /// console on `console`
+    pub fn new(console: SimConsole) -> Self {
+        let panel = FrontPanel::default();
+        SimBoard {
+            led0: SimLed {
+                panel: panel.clone(),
+            },
+            sw0: SimButton { panel },
+            console,
+            delay: SimDelay,
+            flash: MockFlash::new(FLASH_SIZE),
+            sram: [
+                ExternalSram::new(SRAM_BASES[0], SRAM_SIZE),
+                ExternalSram::new(SRAM_BASES[1], SRAM_SIZE),
+            ],
+            chip_id: ChipInfo::from_registers(CIDR, EXID),
+            reset_cause: ResetCause::FirstPowerUp,
+            unique_id: [0x3436_3730, 0x3136_3031, 0x3138_3030, 0x3531_3538],
+        }
+    }
+
+    pub fn panel(&self) -> FrontPanel {
+        self.led0.panel.clone()
+    }
+}
+
+impl XplainedBoard for SimBoard {
+    type Led = SimLed;
+    type Button = SimButton;
+    type Console = SimConsole;
+    type Delay = SimDelay;
+    type Flash = MockFlash;
+
+    const NAME: &'static str = "SAM4E Xplained Pro (simulated)";
+
+    fn led(&mut self) -> &mut Self::Led {
+        &mut self.led0
+    }
+
+    fn button(&mut self) -> &mut Self::Button {
+        &mut self.sw0
+    }
+
+    fn console(&mut self) -> &mut Self::Console {
+        &mut self.console
+    }
+
+    fn delay(&mut self) -> &mut Self::Delay {
+        &mut self.delay
+    }
+
+    fn chip_id(&self) -> &ChipInfo {
+        &self.chip_id
+    }
+
+    fn reset_cause(&self) -> ResetCause {
+        self.reset_cause
+    }
+
+    fn unique_id(&self) -> [u32; 4] {
+        self.unique_id
+    }
+
+    fn flash(&mut self) -> &mut Self::Flash {
+        &mut self.flash
+    }
+}
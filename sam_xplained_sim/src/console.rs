@@ -0,0 +1,195 @@
+//! Console UART on a TCP socket or a pseudo terminal
+
+use std::collections::VecDeque;
+use std::convert::Infallible;
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, ToSocketAddrs};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use serialport::{SerialPort, TTYPort};
+
+/// How long a write waits for a pseudo terminal nobody reads before the
+/// output is dropped
+const PTY_WRITE_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// Where the output goes, with a number that tells the connections apart
+#[derive(Default)]
+struct Output {
+    connection: u64,
+    stream: Option<Box<dyn Write + Send>>,
+}
+
+#[derive(Default)]
+struct Shared {
+    input: Mutex<VecDeque<u8>>,
+    received: Condvar,
+    output: Mutex<Output>,
+}
+
+impl Shared {
+    /// Queues what `reader` receives until it closes or fails.
+    fn receive(&self, mut reader: impl Read, connection: u64) {
+        let mut buffer = [0u8; 256];
+        loop {
+            match reader.read(&mut buffer) {
+                Ok(0) => break,
+                Ok(len) => {
+                    self.input.lock().unwrap().extend(&buffer[..len]);
+                    self.received.notify_all();
+                }
+                Err(error)
+                    if matches!(
+                        error.kind(),
+                        io::ErrorKind::TimedOut | io::ErrorKind::Interrupted
+                    ) => {}
+                Err(_) => break,
+            }
+        }
+        let mut output = self.output.lock().unwrap();
+        if output.connection == connection {
+            output.stream = None;
+        }
+    }
+
+    fn connect(&self, stream: Box<dyn Write + Send>) -> u64 {
+        let mut output = self.output.lock().unwrap();
+        output.connection += 1;
+        output.stream = Some(stream);
+        output.connection
+    }
+}
+
+/// Console UART of the simulated board.
+///
+/// Like the real UART it never fails: output is dropped while nobody is
+/// connected, and reads wait for input.
+pub struct SimConsole {
+    shared: Arc<Shared>,
+    endpoint: String,
+    /// Keeps the pseudo terminal open between the programs using it
+    _slave: Option<File>,
+}
+
+impl SimConsole {
+    /// Listens on `address` for a client at a time, e.g. a telnet or netcat
+    /// session.  A new client takes over from the previous one.
+    pub fn tcp(address: impl ToSocketAddrs) -> io::Result<Self> {
+        let listener = TcpListener::bind(address)?;
+        let endpoint = format!("tcp://{}", listener.local_addr()?);
+        let shared = Arc::new(Shared::default());
+        let accepting = shared.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let reader = match stream.try_clone() {
+                    Ok(reader) => reader,
+                    Err(_) => continue,
+                };
+                stream.set_nodelay(true).ok();
+                let connection = accepting.connect(Box::new(stream));
+                let shared = accepting.clone();
+                thread::spawn(move || shared.receive(reader, connection));
+            }
+        });
+        Ok(SimConsole {
+            shared,
+            endpoint,
+            _slave: None,
+        })
+    }
+
+    /// Opens a pseudo terminal, for terminal programs and the `xplained`
+    /// tool to open like a serial port.
+    pub fn pty() -> io::Result<Self> {
+        let (mut master, slave) = TTYPort::pair().map_err(io::Error::from)?;
+        let endpoint = slave
+            .name()
+            .ok_or_else(|| io::Error::other("pseudo terminal without a name"))?;
+        // Without a program on it, reads of the master fail.  Holding it
+        // through a plain file, rather than the TTYPort, leaves it free for
+        // programs that want it exclusively.
+        let held = OpenOptions::new().read(true).write(true).open(&endpoint)?;
+        drop(slave);
+
+        master.set_timeout(PTY_WRITE_TIMEOUT)?;
+        let reader = master.try_clone_native().map_err(io::Error::from)?;
+        let shared = Arc::new(Shared::default());
+        let connection = shared.connect(Box::new(master));
+        let receiving = shared.clone();
+        thread::spawn(move || receiving.receive(reader, connection));
+        Ok(SimConsole {
+            shared,
+            endpoint,
+            _slave: Some(held),
+        })
+    }
+
+    /// Where to connect to the console: `tcp://<address>` or the path of
+    /// the pseudo terminal
+    pub fn endpoint(&self) -> &str {
+        &self.endpoint
+    }
+}
+
+impl fmt::Debug for SimConsole {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SimConsole")
+            .field("endpoint", &self.endpoint)
+            .finish()
+    }
+}
+
+impl embedded_io::ErrorType for SimConsole {
+    type Error = Infallible;
+}
+
+impl embedded_io::Read for SimConsole {
+    fn read(&mut self, buffer: &mut [u8]) -> Result<usize, Infallible> {
+        if buffer.is_empty() {
+            return Ok(0);
+        }
+        let mut input = self.shared.input.lock().unwrap();
+        while input.is_empty() {
+            input = self.shared.received.wait(input).unwrap();
+        }
+        let len = buffer.len().min(input.len());
+        for (byte, received) in buffer.iter_mut().zip(input.drain(..len)) {
+            *byte = received;
+        }
+        Ok(len)
+    }
+}
+
+impl embedded_io::ReadReady for SimConsole {
+    fn read_ready(&mut self) -> Result<bool, Infallible> {
+        Ok(!self.shared.input.lock().unwrap().is_empty())
+    }
+}
+
+impl embedded_io::Write for SimConsole {
+    fn write(&mut self, data: &[u8]) -> Result<usize, Infallible> {
+        let mut output = self.shared.output.lock().unwrap();
+        if let Some(stream) = &mut output.stream {
+            match stream.write_all(data).and_then(|()| stream.flush()) {
+                Ok(()) => {}
+                // Nobody reads the pseudo terminal.
+                Err(error) if error.kind() == io::ErrorKind::TimedOut => {}
+                Err(_) => output.stream = None,
+            }
+        }
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> Result<(), Infallible> {
+        Ok(())
+    }
+}
+
+impl embedded_io::WriteReady for SimConsole {
+    fn write_ready(&mut self) -> Result<bool, Infallible> {
+        Ok(true)
+    }
+}
@@ -0,0 +1,106 @@
+//! Virtual SAM4 XPlained Pro board
+//!
+//! [`SimBoard`] implements `sam_xplained::XplainedBoard` on the host, so
+//! application code written against the trait runs unchanged:
+//!
+//! * LED0 and SW0 are on a [`FrontPanel`], which [`run`] shows in the
+//!   terminal and presses SW0 on from the keyboard
+//! * the console UART is a TCP socket or a pseudo terminal ([`SimConsole`])
+//! * the delay sleeps for real
+//! * the flash is held in memory (`sam_xplained::mock::MockFlash`), with the
+//!   key-value store region at `sam_xplained::STORAGE`
+//! * the external SRAM of the SAM4E Xplained Pro is simulated as two
+//!   [`ExternalSram`] chips
+//!
+//! ```ignore
+//! let board = SimBoard::new(SimConsole::pty()?);
+//! sam_xplained_sim::run(board, |board| app::run(board))?;
+//! ```
+//!
+//! The `xplained-sim` binary runs a demo application on it.
+//!
+//! `sam_xplained` is built with its `mock` feature for this, which replaces
+//! the board selection.
+
+mod board;
+mod console;
+mod sram;
+mod terminal;
+
+use std::io::{self, Read, Write};
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+use sam_xplained::XplainedBoard;
+
+pub use board::{FrontPanel, SimBoard, SimButton, SimDelay, SimLed, PRESS_TIME};
+pub use console::SimConsole;
+pub use sram::{ExternalSram, SRAM_BASES, SRAM_SIZE};
+
+/// Keys of the front panel, as shown by [`run`]
+pub const KEYS: &str = "[space] press SW0  [h] hold/release SW0  [q] quit";
+
+/// Runs `app` on `board` until it returns or `q` (or Ctrl-C) is typed.
+///
+/// The terminal shows the state of LED0 and SW0 on a status line and takes
+/// single key presses: space presses SW0 for [`PRESS_TIME`], `h` holds it
+/// down until pressed again.
+pub fn run<F>(mut board: SimBoard, app: F) -> io::Result<()>
+where
+    F: FnOnce(&mut SimBoard) + Send + 'static,
+{
+    let panel = board.panel();
+    let mut stdout = io::stdout();
+    writeln!(
+        stdout,
+        "{}, console on {}\n{}",
+        SimBoard::NAME,
+        board.console.endpoint(),
+        KEYS
+    )?;
+
+    let _raw_mode = terminal::RawMode::enter();
+    let (keys, typed) = mpsc::channel();
+    thread::spawn(move || {
+        let mut stdin = io::stdin();
+        let mut typed = [0u8; 16];
+        while let Ok(len @ 1..) = stdin.read(&mut typed) {
+            if typed[..len].iter().any(|&key| keys.send(key).is_err()) {
+                break;
+            }
+        }
+    });
+    let app = thread::spawn(move || app(&mut board));
+
+    let mut shown = None;
+    let result = loop {
+        if app.is_finished() {
+            break match app.join() {
+                Ok(()) => "the application returned",
+                Err(_) => "the application panicked",
+            };
+        }
+        match typed.recv_timeout(Duration::from_millis(20)) {
+            Ok(b' ') => panel.press(PRESS_TIME),
+            Ok(b'h') => panel.hold(!panel.is_held()),
+            // Ctrl-C and Ctrl-D, which raw mode hands over as keys
+            Ok(b'q') | Ok(3) | Ok(4) => break "",
+            // Closing the input doesn't stop the board.
+            Ok(_) | Err(mpsc::RecvTimeoutError::Timeout) => {}
+            Err(mpsc::RecvTimeoutError::Disconnected) => thread::sleep(Duration::from_millis(20)),
+        }
+        let state = (panel.led_on(), panel.sw0_pressed());
+        if shown != Some(state) {
+            write!(stdout, "\r\x1b[K{}", terminal::status(state.0, state.1))?;
+            stdout.flush()?;
+            shown = Some(state);
+        }
+    };
+    if result.is_empty() {
+        writeln!(stdout, "\r")?;
+    } else {
+        writeln!(stdout, "\r\n{}\r", result)?;
+    }
+    Ok(())
+}
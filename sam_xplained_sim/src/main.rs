@@ -0,0 +1,163 @@
+//! `xplained-sim`: a demo application on the virtual board
+
+use std::env;
+use std::process;
+
+use embedded_hal::{
+    delay::DelayNs,
+    digital::{InputPin, OutputPin, StatefulOutputPin},
+};
+use embedded_io::{Read, ReadReady, Write};
+use sam_xplained::XplainedBoard;
+use sam_xplained_sim::{ExternalSram, SimBoard, SimConsole};
+
+const USAGE: &str = "\
+usage: xplained-sim [--tcp <address>|--pty]
+
+Runs a demo application on a simulated SAM4E Xplained Pro: LED0 blinks while
+SW0 is released, SW0 presses are reported on the console, and the console
+takes the commands `blink on|off`, `uid` and `help`.  The console UART is on a
+pseudo terminal, or with --tcp listens on the address given (e.g.
+127.0.0.1:4000).";
+
+const HELP: &str = "commands: blink on|off, uid, help\r\n";
+/// Period of the application loop, in ms
+const TICK: u32 = 10;
+/// LED0 toggles every this many ticks.
+const BLINK_TICKS: u32 = 25;
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let console = match args.iter().map(String::as_str).collect::<Vec<_>>()[..] {
+        [] | ["--pty"] => SimConsole::pty(),
+        ["--tcp", address] => SimConsole::tcp(address),
+        _ => {
+            eprintln!("{}", USAGE);
+            process::exit(1);
+        }
+    };
+    let result = console.and_then(|console| {
+        sam_xplained_sim::run(SimBoard::new(console), |board| {
+            for index in 0..board.sram.len() {
+                let result = test_memory(&mut board.sram[index]);
+                board.console.write_all(result.as_bytes()).ok();
+            }
+            demo(board)
+        })
+    });
+    if let Err(error) = result {
+        eprintln!("{}", error);
+        process::exit(1);
+    }
+}
+
+/// Writes a pattern over the whole chip and reads it back, like the
+/// external_memory example of `sam_xplained`.
+fn test_memory(sram: &mut ExternalSram) -> String {
+    let pattern = |offset: usize| (offset as u8).wrapping_add(1);
+    for (offset, byte) in sram.iter_mut().enumerate() {
+        *byte = pattern(offset);
+    }
+    let result = match sram
+        .iter()
+        .enumerate()
+        .find(|&(offset, &byte)| byte != pattern(offset))
+    {
+        Some((offset, _)) => format!("failed at {:#010x}", sram.base() + offset as u32),
+        None => "OK".to_string(),
+    };
+    format!(
+        "External SRAM at {:#010x} ({} KiB): {}\r\n",
+        sram.base(),
+        sram.len() / 1024,
+        result
+    )
+}
+
+/// Application code that would run on the board as well
+fn demo<B: XplainedBoard>(board: &mut B) -> !
+where
+    B::Console: ReadReady,
+{
+    let (reset_cause, serial_number) = (board.reset_cause(), board.serial_number());
+    write!(
+        board.console(),
+        "{} (reset cause: {})\r\nSerial number: {}\r\n{}> ",
+        B::NAME,
+        reset_cause,
+        serial_number,
+        HELP
+    )
+    .ok();
+
+    let mut line = [0u8; 64];
+    let mut len = 0;
+    let mut blink = true;
+    let mut pressed = false;
+    let mut ticks = 0;
+    loop {
+        let is_pressed = board.button().is_low().unwrap_or(false);
+        if is_pressed && !pressed {
+            board.console().write_all(b"SW0 pressed\r\n").ok();
+        }
+        pressed = is_pressed;
+
+        ticks += 1;
+        if ticks == BLINK_TICKS {
+            ticks = 0;
+            if blink && !pressed {
+                board.led().toggle().ok();
+            }
+        }
+
+        while board.console().read_ready().unwrap_or(false) {
+            let mut byte = [0u8];
+            if board.console().read(&mut byte).unwrap_or(0) == 0 {
+                break;
+            }
+            match byte[0] {
+                b'\r' | b'\n' => {
+                    board.console().write_all(b"\r\n").ok();
+                    run_command(board, &line[..len], &mut blink);
+                    board.console().write_all(b"> ").ok();
+                    len = 0;
+                }
+                // Backspace and delete
+                8 | 0x7F if len > 0 => {
+                    len -= 1;
+                    board.console().write_all(b"\x08 \x08").ok();
+                }
+                byte @ 0x20..=0x7E if len < line.len() => {
+                    line[len] = byte;
+                    len += 1;
+                    board.console().write_all(&[byte]).ok();
+                }
+                _ => {}
+            }
+        }
+
+        board.delay().delay_ms(TICK);
+    }
+}
+
+fn run_command<B: XplainedBoard>(board: &mut B, line: &[u8], blink: &mut bool) {
+    let command = core::str::from_utf8(line).unwrap_or("").trim();
+    match command {
+        "" => {}
+        "blink on" => *blink = true,
+        "blink off" => {
+            *blink = false;
+            board.led().set_high().ok();
+        }
+        "uid" => {
+            let serial_number = board.serial_number();
+            write!(board.console(), "{}\r\n", serial_number).ok();
+        }
+        "help" => {
+            board.console().write_all(HELP.as_bytes()).ok();
+        }
+        _ => {
+            write!(board.console(), "unknown command `{}`\r\n", command).ok();
+        }
+    }
+}
@@ -0,0 +1,57 @@
+//! External SRAM on the static memory controller
+
+use std::ops::{Deref, DerefMut, Range};
+
+/// Addresses of the chips on NCS1 and NCS3 of the SAM4E Xplained Pro
+pub const SRAM_BASES: [u32; 2] = [0x6100_0000, 0x6300_0000];
+/// Size of each chip
+pub const SRAM_SIZE: usize = 512 * 1024;
+
+/// SRAM chip, which dereferences to its contents.
+///
+/// On the board the same application code gets the chip as a slice from
+/// `core::slice::from_raw_parts_mut(smc.base_address(1) as *mut u8, size)`.
+pub struct ExternalSram {
+    base: u32,
+    memory: Vec<u8>,
+}
+
+impl ExternalSram {
+    /// A chip of `size` bytes at `base`, filled with garbage like after
+    /// power up
+    pub fn new(base: u32, size: usize) -> Self {
+        let memory = (0..size)
+            .map(|index| (index as u32 ^ base).wrapping_mul(0x9E37_79B9) as u8)
+            .collect();
+        ExternalSram { base, memory }
+    }
+
+    pub fn base(&self) -> u32 {
+        self.base
+    }
+
+    /// Addresses the chip is mapped at
+    pub fn addresses(&self) -> Range<u32> {
+        self.base..self.base + self.memory.len() as u32
+    }
+
+    /// Contents from `address` on, if the chip is mapped there
+    pub fn at(&mut self, address: u32) -> Option<&mut [u8]> {
+        let offset = address.checked_sub(self.base)? as usize;
+        self.memory.get_mut(offset..)
+    }
+}
+
+impl Deref for ExternalSram {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.memory
+    }
+}
+
+impl DerefMut for ExternalSram {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        &mut self.memory
+    }
+}
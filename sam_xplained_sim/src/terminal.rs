@@ -0,0 +1,53 @@
+//! Front panel on the terminal
+
+use std::mem;
+
+/// Single key input without echo on standard input, while it lives
+pub(crate) struct RawMode {
+    original: libc::termios,
+}
+
+impl RawMode {
+    /// Switches standard input to raw mode, if it is a terminal.
+    pub(crate) fn enter() -> Option<Self> {
+        // The termios structure is plain data, filled in by tcgetattr.
+        unsafe {
+            if libc::isatty(libc::STDIN_FILENO) == 0 {
+                return None;
+            }
+            let mut original: libc::termios = mem::zeroed();
+            if libc::tcgetattr(libc::STDIN_FILENO, &mut original) != 0 {
+                return None;
+            }
+            let mut raw = original;
+            raw.c_lflag &= !(libc::ICANON | libc::ECHO | libc::ISIG);
+            raw.c_cc[libc::VMIN] = 1;
+            raw.c_cc[libc::VTIME] = 0;
+            if libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &raw) != 0 {
+                return None;
+            }
+            Some(RawMode { original })
+        }
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        unsafe {
+            libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &self.original);
+        }
+    }
+}
+
+/// Status line showing LED0 and SW0
+pub(crate) fn status(led_on: bool, sw0_pressed: bool) -> String {
+    format!(
+        "LED0 {}  SW0 {}",
+        if led_on {
+            "\x1b[1;33m(*)\x1b[0m on "
+        } else {
+            "( ) off"
+        },
+        if sw0_pressed { "pressed" } else { "released" }
+    )
+}
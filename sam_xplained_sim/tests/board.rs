@@ -0,0 +1,128 @@
+use std::fs::OpenOptions;
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use embedded_hal::digital::{InputPin, StatefulOutputPin};
+use embedded_io::{Read as _, ReadReady, Write as _};
+use sam_xplained::kv::KvStore;
+use sam_xplained::{XplainedBoard, STORAGE};
+use sam_xplained_sim::{SimBoard, SimConsole, SRAM_BASES, SRAM_SIZE};
+
+/// Reads from the client until `expected` has arrived.
+fn expect(client: &mut impl Read, expected: &[u8]) {
+    let mut received = Vec::new();
+    let mut buffer = [0u8; 64];
+    while !received.ends_with(expected) {
+        match client.read(&mut buffer) {
+            Ok(0) => panic!("closed after {:?}", String::from_utf8_lossy(&received)),
+            Ok(len) => received.extend_from_slice(&buffer[..len]),
+            Err(error) if error.kind() == io::ErrorKind::Interrupted => {}
+            Err(error) => panic!("{} after {:?}", error, String::from_utf8_lossy(&received)),
+        }
+    }
+}
+
+/// Reads from the console until `len` bytes have arrived.
+fn receive(console: &mut SimConsole, len: usize) -> Vec<u8> {
+    let mut received = vec![0u8; len];
+    console.read_exact(&mut received).unwrap();
+    received
+}
+
+#[test]
+fn drives_the_front_panel() {
+    let mut board = SimBoard::new(SimConsole::tcp("127.0.0.1:0").unwrap());
+    let panel = board.panel();
+    assert!(!panel.led_on());
+    board.led().toggle().unwrap();
+    assert!(panel.led_on());
+    assert!(board.led().is_set_low().unwrap());
+
+    assert!(board.button().is_high().unwrap());
+    panel.press(Duration::from_millis(100));
+    assert!(board.button().is_low().unwrap());
+    thread::sleep(Duration::from_millis(150));
+    assert!(board.button().is_high().unwrap());
+
+    panel.hold(true);
+    thread::sleep(Duration::from_millis(50));
+    assert!(board.button().is_low().unwrap());
+    panel.hold(false);
+    assert!(board.button().is_high().unwrap());
+}
+
+#[test]
+fn serves_the_console_over_tcp() {
+    let mut board = SimBoard::new(SimConsole::tcp("127.0.0.1:0").unwrap());
+    // Dropped, as nobody is connected
+    board.console.write_all(b"lost").unwrap();
+
+    let address = board.console.endpoint()["tcp://".len()..].to_string();
+    let mut client = TcpStream::connect(&address).unwrap();
+    client.write_all(b"ping\r").unwrap();
+    assert_eq!(receive(&mut board.console, 5), b"ping\r");
+    assert!(!board.console.read_ready().unwrap());
+    board.console.write_all(b"pong\r\n").unwrap();
+    expect(&mut client, b"pong\r\n");
+
+    // A new client takes over.
+    let mut second = TcpStream::connect(&address).unwrap();
+    second.write_all(b"x").unwrap();
+    assert_eq!(receive(&mut board.console, 1), b"x");
+    board.console.write_all(b"to the second").unwrap();
+    expect(&mut second, b"to the second");
+}
+
+#[test]
+fn serves_the_console_on_a_pty() {
+    let mut board = SimBoard::new(SimConsole::pty().unwrap());
+    let mut terminal = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(board.console.endpoint())
+        .unwrap();
+    terminal.write_all(b"hello\r").unwrap();
+    assert_eq!(receive(&mut board.console, 6), b"hello\r");
+    board.console.write_all(b"world\r\n").unwrap();
+    expect(&mut terminal, b"world\r\n");
+}
+
+#[test]
+fn doesnt_block_on_an_unread_pty() {
+    let mut board = SimBoard::new(SimConsole::pty().unwrap());
+    let start = Instant::now();
+    for _ in 0..16 {
+        board.console.write_all(&[b'.'; 1024]).unwrap();
+    }
+    assert!(start.elapsed() < Duration::from_secs(30));
+}
+
+#[test]
+fn maps_the_external_sram() {
+    let mut board = SimBoard::new(SimConsole::tcp("127.0.0.1:0").unwrap());
+    let [cs1, cs3] = &mut board.sram;
+    assert_eq!(
+        cs1.addresses(),
+        SRAM_BASES[0]..SRAM_BASES[0] + SRAM_SIZE as u32
+    );
+    assert_eq!(cs3.len(), SRAM_SIZE);
+
+    cs1.at(SRAM_BASES[0] + 0x100).unwrap()[..4].copy_from_slice(b"SRAM");
+    assert_eq!(&cs1[0x100..0x104], b"SRAM");
+    assert!(cs1.at(SRAM_BASES[0] - 1).is_none());
+    assert!(cs3.at(SRAM_BASES[1] + SRAM_SIZE as u32).unwrap().is_empty());
+    assert!(cs3.at(SRAM_BASES[1] + SRAM_SIZE as u32 + 1).is_none());
+}
+
+#[test]
+fn keeps_the_store_in_flash() {
+    let mut board = SimBoard::new(SimConsole::tcp("127.0.0.1:0").unwrap());
+    assert!(board.chip_id().verify().is_ok());
+    let mut store = KvStore::new(board.flash(), STORAGE).unwrap();
+    store.set(1, b"simulated").unwrap();
+    let mut value = [0u8; 16];
+    assert_eq!(store.get(1, &mut value).unwrap(), Some(9));
+    assert_eq!(&value[..9], b"simulated");
+}
@@ -0,0 +1,73 @@
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpStream;
+use std::process::{Command, Stdio};
+use std::time::Duration;
+
+/// Reads from the console until `expected` has arrived, and returns all of
+/// it.
+fn expect(console: &mut TcpStream, expected: &str) -> String {
+    let mut received = Vec::new();
+    let mut buffer = [0u8; 256];
+    while !String::from_utf8_lossy(&received).contains(expected) {
+        match console.read(&mut buffer) {
+            Ok(0) => panic!("closed after {:?}", String::from_utf8_lossy(&received)),
+            Ok(len) => received.extend_from_slice(&buffer[..len]),
+            Err(error) => panic!("{} after {:?}", error, String::from_utf8_lossy(&received)),
+        }
+    }
+    String::from_utf8(received).unwrap()
+}
+
+#[test]
+fn runs_the_demo() {
+    let mut sim = Command::new(env!("CARGO_BIN_EXE_xplained-sim"))
+        .args(["--tcp", "127.0.0.1:0"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut keys = sim.stdin.take().unwrap();
+    let mut panel = BufReader::new(sim.stdout.take().unwrap());
+    let mut header = String::new();
+    panel.read_line(&mut header).unwrap();
+    let address = header
+        .trim()
+        .rsplit("console on tcp://")
+        .next()
+        .unwrap()
+        .to_string();
+    assert!(header.starts_with("SAM4E Xplained Pro (simulated), console on tcp://"));
+
+    // What the demo wrote before the connection may be lost, like on a UART.
+    let mut console = TcpStream::connect(&address).unwrap();
+    console
+        .set_read_timeout(Some(Duration::from_secs(10)))
+        .unwrap();
+    console.write_all(b"uid\r").unwrap();
+    expect(
+        &mut console,
+        "uid\r\n34363730313630313138303035313538\r\n> ",
+    );
+
+    console.write_all(b"bogus\r").unwrap();
+    expect(&mut console, "unknown command `bogus`\r\n> ");
+
+    keys.write_all(b" ").unwrap();
+    expect(&mut console, "SW0 pressed\r\n");
+
+    keys.write_all(b"q").unwrap();
+    assert!(sim.wait().unwrap().success());
+    let mut status = String::new();
+    panel.read_to_string(&mut status).unwrap();
+    assert!(status.contains("SW0 pressed"), "{:?}", status);
+}
+
+#[test]
+fn rejects_bad_arguments() {
+    let output = Command::new(env!("CARGO_BIN_EXE_xplained-sim"))
+        .arg("--tcp")
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).starts_with("usage: xplained-sim"));
+}
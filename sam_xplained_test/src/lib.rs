@@ -0,0 +1,207 @@
+//! On-target test harness for the SAM4 XPlained Pro board crates
+//!
+//! Test binaries run on the board (or, against the `sam_xplained` mock board,
+//! on the host) and report through a [`Harness`] on any `embedded_io::Write`:
+//! the console UART, or RTT read by the debug probe.  Tests are closures
+//! returning an [`Outcome`], failing through [`check!`], [`check_eq!`] and
+//! [`OrFail::or_fail`]:
+//!
+//! ```ignore
+//! let mut harness = Harness::new(console);
+//! harness.suite("flash");
+//! harness.test("erases", || {
+//!     flash.erase(0, 4096).or_fail("erase")?;
+//!     check_eq!(read_byte(&mut flash, 0), 0xFF);
+//!     Ok(())
+//! });
+//! let passed = harness.finish();
+//! ```
+//!
+//! The report is one line per event, which [`report::parse`] reads back on
+//! the host:
+//!
+//! ```text
+//! suite flash
+//! test flash::erases ... ok
+//! test flash::writes ... FAILED: 0x00 != 0xff (src/suites/flash.rs:42)
+//! done 1 passed, 1 failed
+//! ```
+//!
+//! [`suites`] holds the board crates' test suites, generic over the
+//! embedded-hal, embedded-io and embedded-storage traits.
+#![no_std]
+
+use core::fmt::{self, Write as _};
+use core::panic::Location;
+
+use embedded_io::Write;
+
+pub mod report;
+pub mod suites;
+
+/// Longest failure message kept, in bytes (at most 255)
+pub const MESSAGE_LEN: usize = 96;
+
+/// Result of a test
+pub type Outcome = Result<(), Failure>;
+
+/// Why a test failed, and where
+pub struct Failure {
+    message: [u8; MESSAGE_LEN],
+    len: u8,
+    file: &'static str,
+    line: u32,
+}
+
+impl Failure {
+    /// Formats the message, cut short at [`MESSAGE_LEN`] bytes.
+    pub fn new(message: fmt::Arguments<'_>, file: &'static str, line: u32) -> Self {
+        let mut failure = Failure {
+            message: [0; MESSAGE_LEN],
+            len: 0,
+            file,
+            line,
+        };
+        failure.write_fmt(message).ok();
+        failure
+    }
+
+    pub fn message(&self) -> &str {
+        // Only whole characters are copied in.
+        core::str::from_utf8(&self.message[..self.len as usize]).unwrap_or("")
+    }
+}
+
+impl fmt::Write for Failure {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for c in s.chars() {
+            // A line of the report per test
+            let c = if c.is_control() { ' ' } else { c };
+            let len = self.len as usize;
+            if len + c.len_utf8() > MESSAGE_LEN {
+                return Err(fmt::Error);
+            }
+            c.encode_utf8(&mut self.message[len..]);
+            self.len += c.len_utf8() as u8;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({}:{})", self.message(), self.file, self.line)
+    }
+}
+
+impl fmt::Debug for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+/// Fails the test unless the condition holds.
+#[macro_export]
+macro_rules! check {
+    ($condition:expr) => {
+        $crate::check!($condition, "check failed: {}", stringify!($condition))
+    };
+    ($condition:expr, $($message:tt)+) => {
+        if !$condition {
+            return Err($crate::Failure::new(format_args!($($message)+), file!(), line!()));
+        }
+    };
+}
+
+/// Fails the test unless both values are equal.
+#[macro_export]
+macro_rules! check_eq {
+    ($left:expr, $right:expr) => {
+        match (&$left, &$right) {
+            (left, right) => $crate::check!(*left == *right, "{:?} != {:?}", left, right),
+        }
+    };
+}
+
+/// Turns the errors of the code under test into failures.
+pub trait OrFail<T> {
+    /// Fails with `what` and the error.
+    fn or_fail(self, what: &str) -> Result<T, Failure>;
+}
+
+impl<T, E: fmt::Debug> OrFail<T> for Result<T, E> {
+    #[track_caller]
+    fn or_fail(self, what: &str) -> Result<T, Failure> {
+        let location = Location::caller();
+        self.map_err(|error| {
+            Failure::new(
+                format_args!("{} failed: {:?}", what, error),
+                location.file(),
+                location.line(),
+            )
+        })
+    }
+}
+
+/// Runs tests and reports them on `out`
+pub struct Harness<W> {
+    out: W,
+    suite: &'static str,
+    passed: u32,
+    failed: u32,
+}
+
+impl<W: Write> Harness<W> {
+    pub fn new(out: W) -> Self {
+        Harness {
+            out,
+            suite: "",
+            passed: 0,
+            failed: 0,
+        }
+    }
+
+    /// Starts a suite, which names the tests up to the next one.
+    pub fn suite(&mut self, name: &'static str) {
+        self.suite = name;
+        write!(self.out, "suite {}\r\n", name).ok();
+    }
+
+    /// Runs a test, returning whether it passed.
+    ///
+    /// The name goes out first, so that a test which never returns can be
+    /// told from the report.
+    pub fn test(&mut self, name: &str, test: impl FnOnce() -> Outcome) -> bool {
+        write!(self.out, "test {}::{} ... ", self.suite, name).ok();
+        self.out.flush().ok();
+        match test() {
+            Ok(()) => {
+                self.passed += 1;
+                self.out.write_all(b"ok\r\n").ok();
+                true
+            }
+            Err(failure) => {
+                self.failed += 1;
+                write!(self.out, "FAILED: {}\r\n", failure).ok();
+                false
+            }
+        }
+    }
+
+    /// Output the tests report on, e.g. for diagnostics between tests
+    pub fn out(&mut self) -> &mut W {
+        &mut self.out
+    }
+
+    /// Ends the report, returning whether every test passed.
+    pub fn finish(mut self) -> bool {
+        write!(
+            self.out,
+            "done {} passed, {} failed\r\n",
+            self.passed, self.failed
+        )
+        .ok();
+        self.out.flush().ok();
+        self.failed == 0
+    }
+}
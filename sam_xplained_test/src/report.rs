@@ -0,0 +1,46 @@
+//! Parsing of the [`Harness`](crate::Harness) report
+
+/// A line of the report
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Event<'a> {
+    Suite(&'a str),
+    /// A test without its outcome: the report was cut short while it ran.
+    Started(&'a str),
+    Passed(&'a str),
+    Failed {
+        test: &'a str,
+        message: &'a str,
+    },
+    Done {
+        passed: u32,
+        failed: u32,
+    },
+}
+
+/// Parses a line of the report, without its line ending.  Anything else the
+/// firmware prints in between gives `None`.
+pub fn parse(line: &str) -> Option<Event<'_>> {
+    let line = line.trim_end_matches(['\r', '\n']);
+    if let Some(name) = line.strip_prefix("suite ") {
+        return Some(Event::Suite(name));
+    }
+    if let Some(test) = line.strip_prefix("test ") {
+        let (test, outcome) = match test.split_once(" ... ") {
+            Some(split) => split,
+            None => return test.strip_suffix(" ...").map(Event::Started),
+        };
+        return match outcome {
+            "ok" => Some(Event::Passed(test)),
+            "" => Some(Event::Started(test)),
+            _ => outcome
+                .strip_prefix("FAILED: ")
+                .map(|message| Event::Failed { test, message }),
+        };
+    }
+    let counts = line.strip_prefix("done ")?.strip_suffix(" failed")?;
+    let (passed, failed) = counts.split_once(" passed, ")?;
+    Some(Event::Done {
+        passed: passed.parse().ok()?,
+        failed: failed.parse().ok()?,
+    })
+}
@@ -0,0 +1,72 @@
+//! Clocks suite: delays against the core clock, and the core clock against a
+//! reference clock
+
+use embedded_hal::delay::DelayNs;
+use embedded_io::Write;
+
+use crate::{check, Failure, Harness};
+
+/// A free-running, wrapping counter of clock ticks
+pub trait Counter {
+    fn count(&mut self) -> u32;
+    /// Ticks per second
+    fn frequency(&self) -> u32;
+}
+
+/// Checks that `measured` ticks are within `percent` of `expected`.
+fn within(what: &str, measured: u64, expected: u64, percent: u64) -> Result<(), Failure> {
+    let error = measured.abs_diff(expected) * 100;
+    check!(
+        error <= expected * percent,
+        "{} took {} ticks instead of {} (±{}%)",
+        what,
+        measured,
+        expected,
+        percent
+    );
+    Ok(())
+}
+
+/// Runs the suite.  `cycles` counts the core clock; `reference` counts an
+/// independent clock, trusted to within `tolerance` percent.
+pub fn run<W, D, C, R>(
+    harness: &mut Harness<W>,
+    delay: &mut D,
+    cycles: &mut C,
+    reference: &mut R,
+    tolerance: u32,
+) where
+    W: Write,
+    D: DelayNs,
+    C: Counter,
+    R: Counter,
+{
+    harness.suite("clocks");
+    let frequency = cycles.frequency() as u64;
+
+    harness.test("delay_ms", || {
+        let start = cycles.count();
+        delay.delay_ms(10);
+        let elapsed = cycles.count().wrapping_sub(start) as u64;
+        within("delay_ms(10)", elapsed, frequency / 100, 2)
+    });
+    harness.test("delay_us", || {
+        let start = cycles.count();
+        delay.delay_us(1000);
+        let elapsed = cycles.count().wrapping_sub(start) as u64;
+        within("delay_us(1000)", elapsed, frequency / 1000, 2)
+    });
+    harness.test("core_clock", || {
+        // A tenth of a second on the reference
+        let ticks = reference.frequency() / 10;
+        let expected = frequency / 10;
+        let start = reference.count();
+        let first = cycles.count();
+        let mut elapsed = 0;
+        while reference.count().wrapping_sub(start) < ticks {
+            elapsed = cycles.count().wrapping_sub(first) as u64;
+            check!(elapsed < 4 * expected, "reference clock stopped");
+        }
+        within("reference 100 ms", elapsed, expected, tolerance as u64)
+    });
+}
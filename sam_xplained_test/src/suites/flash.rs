@@ -0,0 +1,81 @@
+//! Flash suite: raw NOR flash operations, then the key-value store on top
+
+use core::ops::Range;
+
+use embedded_io::Write;
+use embedded_storage::nor_flash::NorFlash;
+use sam_xplained_kv::KvStore;
+
+use crate::{check, check_eq, Harness, OrFail};
+
+/// Bytes programmed and read back at once
+const CHUNK: usize = 64;
+
+const KEY: u16 = 0x7E57;
+
+/// Runs the suite on `region`, which must hold at least two erase sectors.
+/// Its contents are lost.
+pub fn run<W, F>(harness: &mut Harness<W>, flash: &mut F, region: Range<u32>)
+where
+    W: Write,
+    F: NorFlash,
+{
+    harness.suite("flash");
+    let sector = region.start..region.start + F::ERASE_SIZE as u32;
+    let pattern: [u8; CHUNK] = core::array::from_fn(|i| (i as u8).wrapping_mul(37) ^ 0xA5);
+
+    harness.test("erases", || {
+        flash.erase(sector.start, sector.end).or_fail("erase")?;
+        let mut read = [0u8; CHUNK];
+        for offset in sector.clone().step_by(CHUNK) {
+            flash.read(offset, &mut read).or_fail("read")?;
+            let programmed = read.iter().position(|&byte| byte != 0xFF);
+            check!(
+                programmed.is_none(),
+                "{:#010x} not erased",
+                offset as usize + programmed.unwrap_or(0)
+            );
+        }
+        Ok(())
+    });
+    harness.test("programs", || {
+        check!(
+            CHUNK.is_multiple_of(F::WRITE_SIZE),
+            "write size {} not supported",
+            F::WRITE_SIZE
+        );
+        flash.write(sector.start, &pattern).or_fail("write")?;
+        let mut read = [0u8; CHUNK];
+        flash.read(sector.start, &mut read).or_fail("read")?;
+        check_eq!(read, pattern);
+        Ok(())
+    });
+    harness.test("rejects_unaligned_writes", || {
+        if F::WRITE_SIZE > 1 {
+            let unaligned = flash.write(sector.start + CHUNK as u32 + 1, &pattern[..F::WRITE_SIZE]);
+            check!(unaligned.is_err(), "unaligned write accepted");
+        }
+        Ok(())
+    });
+    harness.test("kv_store", || {
+        flash.erase(region.start, region.end).or_fail("erase")?;
+        let mut store = KvStore::new(&mut *flash, region.clone()).or_fail("open")?;
+        let mut value = [0u8; CHUNK];
+        check_eq!(store.get(KEY, &mut value).or_fail("get")?, None);
+        store.set(KEY, b"first").or_fail("set")?;
+        store.set(KEY, b"second").or_fail("set")?;
+        check_eq!(store.get(KEY, &mut value).or_fail("get")?, Some(6));
+        check_eq!(&value[..6], b"second");
+        store.remove(KEY).or_fail("remove")?;
+        check_eq!(store.get(KEY, &mut value).or_fail("get")?, None);
+        store.set(KEY, &pattern).or_fail("set")?;
+        Ok(())
+    });
+    harness.test("kv_store_persists", || {
+        let mut store = KvStore::new(&mut *flash, region.clone()).or_fail("reopen")?;
+        let mut value = [0u8; CHUNK];
+        check_eq!(store.get(KEY, &mut value).or_fail("get")?, Some(CHUNK));
+        check_eq!(value, pattern);
+        Ok(())
+    });
+}
@@ -0,0 +1,74 @@
+//! GPIO suite: an output looped back to an input with a jumper, LED0 and SW0
+
+use embedded_hal::delay::DelayNs;
+use embedded_hal::digital::{InputPin, OutputPin, StatefulOutputPin};
+use embedded_io::Write;
+
+use crate::{check, Harness, OrFail};
+
+/// Time for the loopback to settle, in microseconds
+const SETTLE_US: u32 = 10;
+
+/// Runs the suite.  `output` must be wired to `input`, and SW0 left alone.
+pub fn run<W, O, I, L, B, D>(
+    harness: &mut Harness<W>,
+    output: &mut O,
+    input: &mut I,
+    led: &mut L,
+    button: &mut B,
+    delay: &mut D,
+) where
+    W: Write,
+    O: OutputPin,
+    I: InputPin,
+    L: StatefulOutputPin,
+    B: InputPin,
+    D: DelayNs,
+{
+    harness.suite("gpio");
+    harness.test("loopback_high", || {
+        output.set_high().or_fail("set_high")?;
+        delay.delay_us(SETTLE_US);
+        check!(input.is_high().or_fail("is_high")?, "input reads low");
+        Ok(())
+    });
+    harness.test("loopback_low", || {
+        output.set_low().or_fail("set_low")?;
+        delay.delay_us(SETTLE_US);
+        check!(input.is_low().or_fail("is_low")?, "input reads high");
+        Ok(())
+    });
+    harness.test("loopback_follows", || {
+        for level in (0..16).map(|i| i % 2 == 1) {
+            if level {
+                output.set_high().or_fail("set_high")?;
+            } else {
+                output.set_low().or_fail("set_low")?;
+            }
+            delay.delay_us(SETTLE_US);
+            let read = input.is_high().or_fail("is_high")?;
+            check!(
+                read == level,
+                "input reads {} after writing {}",
+                read,
+                level
+            );
+        }
+        Ok(())
+    });
+    harness.test("led_reads_back", || {
+        led.set_low().or_fail("set_low")?;
+        check!(led.is_set_low().or_fail("is_set_low")?, "LED0 not set low");
+        led.set_high().or_fail("set_high")?;
+        check!(
+            led.is_set_high().or_fail("is_set_high")?,
+            "LED0 not set high"
+        );
+        Ok(())
+    });
+    harness.test("sw0_released", || {
+        // Pulled up, low while pressed
+        check!(button.is_high().or_fail("is_high")?, "SW0 reads pressed");
+        Ok(())
+    });
+}
@@ -0,0 +1,11 @@
+//! Test suites for the board hardware
+//!
+//! Each suite is a `run` function taking the [`Harness`](crate::Harness) and
+//! the hardware under test through the embedded-hal, embedded-io and
+//! embedded-storage traits, so a board's test binary only wires them up.
+
+pub mod clocks;
+pub mod flash;
+pub mod gpio;
+pub mod sram;
+pub mod uart;
@@ -0,0 +1,97 @@
+//! SRAM suite: data bus, address bus and cell tests of external memory
+//!
+//! Every access is volatile, so that it reaches the chip.
+
+use core::ptr;
+
+use embedded_io::Write;
+
+use crate::{check, Harness};
+
+fn read(words: &[u32], index: usize) -> u32 {
+    unsafe { ptr::read_volatile(&words[index]) }
+}
+
+fn write(words: &mut [u32], index: usize, value: u32) {
+    unsafe { ptr::write_volatile(&mut words[index], value) }
+}
+
+/// Runs the suite on `memory`, which is mapped at `base`.  Its contents are
+/// lost.
+pub fn run<W: Write>(harness: &mut Harness<W>, base: u32, memory: &mut [u8]) {
+    harness.suite("sram");
+    // Any byte will do as a word
+    let (head, words, _) = unsafe { memory.align_to_mut::<u32>() };
+    let base = base + head.len() as u32;
+    let address = |index: usize| base + 4 * index as u32;
+
+    harness.test("has_memory", || {
+        check!(!words.is_empty(), "no words at {:#010x}", base);
+        Ok(())
+    });
+    if words.is_empty() {
+        return;
+    }
+    harness.test("data_bus", || {
+        for bit in 0..32 {
+            write(words, 0, 1 << bit);
+            let read = read(words, 0);
+            check!(
+                read == 1 << bit,
+                "{:#010x} reads {:#010x} after walking bit {}",
+                base,
+                read,
+                bit
+            );
+        }
+        Ok(())
+    });
+    harness.test("address_bus", || {
+        // Word 0 and the words at each power of two
+        let len = words.len();
+        let offsets = || {
+            core::iter::once(0).chain(
+                (0..usize::BITS)
+                    .map(|bit| 1 << bit)
+                    .take_while(|&offset| offset < len),
+            )
+        };
+        const PATTERN: u32 = 0xAAAA_AAAA;
+        for offset in offsets() {
+            write(words, offset, PATTERN);
+        }
+        // Each address line in turn, high then low
+        for tested in offsets() {
+            write(words, tested, !PATTERN);
+            for offset in offsets().filter(|&offset| offset != tested) {
+                let read = read(words, offset);
+                check!(
+                    read == PATTERN,
+                    "writing {:#010x} changes {:#010x}",
+                    address(tested),
+                    address(offset)
+                );
+            }
+            write(words, tested, PATTERN);
+        }
+        Ok(())
+    });
+    harness.test("every_word", || {
+        // Each word holds its address, then its inverse.
+        for pass in [0, u32::MAX] {
+            for index in 0..words.len() {
+                write(words, index, address(index) ^ pass);
+            }
+            for index in 0..words.len() {
+                let read = read(words, index);
+                check!(
+                    read == address(index) ^ pass,
+                    "{:#010x} reads {:#010x}",
+                    address(index),
+                    read
+                );
+            }
+        }
+        Ok(())
+    });
+}
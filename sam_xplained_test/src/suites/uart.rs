@@ -0,0 +1,71 @@
+//! UART suite: round trips through a UART in loopback
+
+use embedded_hal::delay::DelayNs;
+use embedded_io::{Read, ReadReady, Write};
+
+use crate::{check_eq, Failure, Harness, OrFail, Outcome};
+
+/// Polls for a received byte, every 10 µs for 10 ms.
+fn receive<U, D>(uart: &mut U, delay: &mut D) -> Result<u8, Failure>
+where
+    U: Read + ReadReady,
+    D: DelayNs,
+{
+    for _ in 0..1000 {
+        if uart.read_ready().or_fail("read_ready")? {
+            let mut byte = [0u8];
+            uart.read_exact(&mut byte).or_fail("read")?;
+            return Ok(byte[0]);
+        }
+        delay.delay_us(10);
+    }
+    Err(Failure::new(
+        format_args!("nothing received"),
+        file!(),
+        line!(),
+    ))
+}
+
+/// Sends a byte and checks it comes back.
+fn round_trip<U, D>(uart: &mut U, delay: &mut D, byte: u8) -> Outcome
+where
+    U: Read + ReadReady + Write,
+    D: DelayNs,
+{
+    uart.write_all(&[byte]).or_fail("write")?;
+    uart.flush().or_fail("flush")?;
+    check_eq!(receive(uart, delay)?, byte);
+    Ok(())
+}
+
+/// Runs the suite.  What `uart` sends must come back on its receiver, and
+/// nothing else.
+pub fn run<W, U, D>(harness: &mut Harness<W>, uart: &mut U, delay: &mut D)
+where
+    W: Write,
+    U: Read + ReadReady + Write,
+    D: DelayNs,
+{
+    harness.suite("uart");
+    // Whatever came in before the loopback was set up
+    while let Ok(true) = uart.read_ready() {
+        let mut stale = [0u8; 16];
+        if uart.read(&mut stale).is_err() {
+            break;
+        }
+    }
+    harness.test("echoes_a_byte", || round_trip(uart, delay, 0x55));
+    harness.test("echoes_every_value", || {
+        (0..=255).try_for_each(|byte| round_trip(uart, delay, byte))
+    });
+    harness.test("echoes_a_line", || {
+        b"The quick brown fox\r\n"
+            .iter()
+            .try_for_each(|&byte| round_trip(uart, delay, byte))
+    });
+    harness.test("stays_quiet", || {
+        delay.delay_ms(1);
+        check_eq!(uart.read_ready().or_fail("read_ready")?, false);
+        Ok(())
+    });
+}
@@ -0,0 +1,132 @@
+use std::convert::Infallible;
+
+use sam_xplained_test::report::{parse, Event};
+use sam_xplained_test::{check, check_eq, Harness, OrFail, MESSAGE_LEN};
+
+/// Collects the report
+#[derive(Default)]
+struct Report(Vec<u8>);
+
+impl embedded_io::ErrorType for Report {
+    type Error = Infallible;
+}
+
+impl embedded_io::Write for Report {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Infallible> {
+        self.0.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> Result<(), Infallible> {
+        Ok(())
+    }
+}
+
+fn lines(report: &Report) -> Vec<String> {
+    String::from_utf8(report.0.clone())
+        .unwrap()
+        .split_terminator("\r\n")
+        .map(str::to_string)
+        .collect()
+}
+
+#[test]
+fn reports_outcomes() {
+    let mut report = Report::default();
+    let mut harness = Harness::new(&mut report);
+    harness.suite("demo");
+    assert!(harness.test("passes", || Ok(())));
+    assert!(!harness.test("fails", || {
+        check_eq!(1 + 1, 3);
+        Ok(())
+    }));
+    assert!(!harness.test("errs", || {
+        Err::<(), _>("busy").or_fail("erase")?;
+        Ok(())
+    }));
+    assert!(!harness.finish());
+
+    let lines = lines(&report);
+    assert_eq!(lines[0], "suite demo");
+    assert_eq!(lines[1], "test demo::passes ... ok");
+    assert!(lines[2].starts_with("test demo::fails ... FAILED: 2 != 3 (tests/harness.rs:"));
+    assert!(lines[3]
+        .starts_with("test demo::errs ... FAILED: erase failed: \"busy\" (tests/harness.rs:"));
+    assert_eq!(lines[4], "done 1 passed, 2 failed");
+}
+
+#[test]
+fn keeps_failures_on_one_line() {
+    let mut report = Report::default();
+    let mut harness = Harness::new(&mut report);
+    harness.suite("demo");
+    harness.test("long", || {
+        check!(false, "line\r\nbreak {}", "x".repeat(200));
+        Ok(())
+    });
+    assert!(!harness.finish());
+
+    let lines = lines(&report);
+    assert_eq!(lines.len(), 3);
+    let message = lines[1]
+        .strip_prefix("test demo::long ... FAILED: ")
+        .unwrap();
+    assert!(message.starts_with("line  break xxx"));
+    let (message, location) = message.rsplit_once(" (").unwrap();
+    assert_eq!(message.len(), MESSAGE_LEN);
+    assert!(location.starts_with("tests/harness.rs:"));
+}
+
+#[test]
+fn parses_the_report() {
+    let mut report = Report::default();
+    let mut harness = Harness::new(&mut report);
+    harness.suite("demo");
+    harness.test("passes", || Ok(()));
+    harness.test("fails", || {
+        check!(false, "broken");
+        Ok(())
+    });
+    harness.finish();
+
+    let lines = lines(&report);
+    let events: Vec<_> = lines.iter().filter_map(|line| parse(line)).collect();
+    assert_eq!(events.len(), 4);
+    assert_eq!(events[0], Event::Suite("demo"));
+    assert_eq!(events[1], Event::Passed("demo::passes"));
+    match events[2] {
+        Event::Failed { test, message } => {
+            assert_eq!(test, "demo::fails");
+            assert!(message.starts_with("broken (tests/harness.rs:"));
+        }
+        other => panic!("{:?}", other),
+    }
+    assert_eq!(
+        events[3],
+        Event::Done {
+            passed: 1,
+            failed: 1
+        }
+    );
+}
+
+#[test]
+fn parses_cut_short_reports() {
+    assert_eq!(
+        parse("test demo::hangs ... "),
+        Some(Event::Started("demo::hangs"))
+    );
+    assert_eq!(
+        parse("test demo::hangs ..."),
+        Some(Event::Started("demo::hangs"))
+    );
+    assert_eq!(parse("Booting...\r\n"), None);
+    assert_eq!(parse("done 3 passed"), None);
+    assert_eq!(
+        parse("done 3 passed, 0 failed\r"),
+        Some(Event::Done {
+            passed: 3,
+            failed: 0
+        })
+    );
+}
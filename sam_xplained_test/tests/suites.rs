@@ -0,0 +1,389 @@
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
+use std::convert::Infallible;
+use std::rc::Rc;
+
+use embedded_hal::delay::DelayNs;
+use embedded_hal::digital::{ErrorType, InputPin, OutputPin, StatefulOutputPin};
+use embedded_storage::nor_flash::{
+    ErrorType as FlashErrorType, NorFlash, NorFlashError, NorFlashErrorKind, ReadNorFlash,
+};
+use sam_xplained_test::report::{parse, Event};
+use sam_xplained_test::suites::clocks::Counter;
+use sam_xplained_test::suites::{clocks, flash, gpio, sram, uart};
+use sam_xplained_test::Harness;
+
+/// Collects the report
+#[derive(Default)]
+struct Report(Vec<u8>);
+
+impl embedded_io::ErrorType for Report {
+    type Error = Infallible;
+}
+
+impl embedded_io::Write for Report {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Infallible> {
+        self.0.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> Result<(), Infallible> {
+        Ok(())
+    }
+}
+
+impl Report {
+    /// The failed tests
+    fn failures(&self) -> Vec<String> {
+        let report = String::from_utf8(self.0.clone()).unwrap();
+        let done = report
+            .lines()
+            .filter_map(parse)
+            .any(|event| matches!(event, Event::Done { .. }));
+        assert!(done, "{}", report);
+        report
+            .lines()
+            .filter_map(|line| match parse(line) {
+                Some(Event::Failed { test, .. }) => Some(test.to_string()),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+/// Runs a suite and returns the failed tests.
+fn run(suite: impl FnOnce(&mut Harness<&mut Report>)) -> Vec<String> {
+    let mut report = Report::default();
+    let mut harness = Harness::new(&mut report);
+    suite(&mut harness);
+    harness.finish();
+    report.failures()
+}
+
+/// A pin, whose level is shared by its clones
+#[derive(Clone, Default)]
+struct Pin(Rc<Cell<bool>>);
+
+impl ErrorType for Pin {
+    type Error = Infallible;
+}
+
+impl OutputPin for Pin {
+    fn set_low(&mut self) -> Result<(), Infallible> {
+        self.0.set(false);
+        Ok(())
+    }
+
+    fn set_high(&mut self) -> Result<(), Infallible> {
+        self.0.set(true);
+        Ok(())
+    }
+}
+
+impl StatefulOutputPin for Pin {
+    fn is_set_high(&mut self) -> Result<bool, Infallible> {
+        Ok(self.0.get())
+    }
+
+    fn is_set_low(&mut self) -> Result<bool, Infallible> {
+        Ok(!self.0.get())
+    }
+}
+
+impl InputPin for Pin {
+    fn is_high(&mut self) -> Result<bool, Infallible> {
+        Ok(self.0.get())
+    }
+
+    fn is_low(&mut self) -> Result<bool, Infallible> {
+        Ok(!self.0.get())
+    }
+}
+
+/// Time, in nanoseconds, advanced by delays and counted by its clones
+#[derive(Clone, Default)]
+struct Clock(Rc<Cell<u64>>);
+
+impl DelayNs for Clock {
+    fn delay_ns(&mut self, ns: u32) {
+        self.0.set(self.0.get() + ns as u64);
+    }
+}
+
+/// Counts ticks of a clock at `frequency`, a tick passing per read.
+struct Ticks {
+    clock: Clock,
+    frequency: u32,
+}
+
+impl Counter for Ticks {
+    fn count(&mut self) -> u32 {
+        let now = self.clock.0.get() + 1_000_000_000 / self.frequency as u64;
+        self.clock.0.set(now);
+        (now * self.frequency as u64 / 1_000_000_000) as u32
+    }
+
+    fn frequency(&self) -> u32 {
+        self.frequency
+    }
+}
+
+/// A UART with its transmitter looped back to its receiver, through at most
+/// `capacity` bytes
+struct Loopback {
+    received: VecDeque<u8>,
+    capacity: usize,
+}
+
+impl embedded_io::ErrorType for Loopback {
+    type Error = Infallible;
+}
+
+impl embedded_io::Read for Loopback {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Infallible> {
+        let len = buf.len().min(self.received.len());
+        for byte in &mut buf[..len] {
+            *byte = self.received.pop_front().unwrap();
+        }
+        Ok(len)
+    }
+}
+
+impl embedded_io::ReadReady for Loopback {
+    fn read_ready(&mut self) -> Result<bool, Infallible> {
+        Ok(!self.received.is_empty())
+    }
+}
+
+impl embedded_io::Write for Loopback {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Infallible> {
+        for &byte in buf {
+            if self.received.len() < self.capacity {
+                self.received.push_back(byte);
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> Result<(), Infallible> {
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+struct FlashError(NorFlashErrorKind);
+
+impl NorFlashError for FlashError {
+    fn kind(&self) -> NorFlashErrorKind {
+        self.0
+    }
+}
+
+/// NOR flash in memory, programming by clearing bits
+struct Flash(RefCell<Vec<u8>>);
+
+impl FlashErrorType for Flash {
+    type Error = FlashError;
+}
+
+impl ReadNorFlash for Flash {
+    const READ_SIZE: usize = 1;
+
+    fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), FlashError> {
+        let offset = offset as usize;
+        let memory = self.0.borrow();
+        let stored = memory
+            .get(offset..offset + bytes.len())
+            .ok_or(FlashError(NorFlashErrorKind::OutOfBounds))?;
+        bytes.copy_from_slice(stored);
+        Ok(())
+    }
+
+    fn capacity(&self) -> usize {
+        self.0.borrow().len()
+    }
+}
+
+impl NorFlash for Flash {
+    const WRITE_SIZE: usize = 8;
+    const ERASE_SIZE: usize = 512;
+
+    fn erase(&mut self, from: u32, to: u32) -> Result<(), FlashError> {
+        if !(from as usize).is_multiple_of(Self::ERASE_SIZE)
+            || !(to as usize).is_multiple_of(Self::ERASE_SIZE)
+        {
+            return Err(FlashError(NorFlashErrorKind::NotAligned));
+        }
+        self.0.borrow_mut()[from as usize..to as usize].fill(0xFF);
+        Ok(())
+    }
+
+    fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), FlashError> {
+        let offset = offset as usize;
+        if !offset.is_multiple_of(Self::WRITE_SIZE) || !bytes.len().is_multiple_of(Self::WRITE_SIZE)
+        {
+            return Err(FlashError(NorFlashErrorKind::NotAligned));
+        }
+        for (stored, byte) in self.0.borrow_mut()[offset..].iter_mut().zip(bytes) {
+            *stored &= byte;
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn gpio_passes_with_a_jumper() {
+    let wire = Pin::default();
+    let failures = run(|harness| {
+        gpio::run(
+            harness,
+            &mut wire.clone(),
+            &mut wire.clone(),
+            &mut Pin::default(),
+            &mut Pin(Rc::new(Cell::new(true))),
+            &mut Clock::default(),
+        )
+    });
+    assert!(failures.is_empty(), "{:?}", failures);
+}
+
+#[test]
+fn gpio_fails_without_a_jumper() {
+    let failures = run(|harness| {
+        gpio::run(
+            harness,
+            &mut Pin::default(),
+            &mut Pin::default(),
+            &mut Pin::default(),
+            // Pressed
+            &mut Pin::default(),
+            &mut Clock::default(),
+        )
+    });
+    assert_eq!(
+        failures,
+        [
+            "gpio::loopback_high",
+            "gpio::loopback_follows",
+            "gpio::sw0_released"
+        ]
+    );
+}
+
+#[test]
+fn uart_passes_in_loopback() {
+    let mut uart = Loopback {
+        received: VecDeque::from(vec![0u8; 20]),
+        capacity: 1,
+    };
+    let failures = run(|harness| uart::run(harness, &mut uart, &mut Clock::default()));
+    assert!(failures.is_empty(), "{:?}", failures);
+}
+
+#[test]
+fn uart_fails_when_disconnected() {
+    let mut uart = Loopback {
+        received: VecDeque::new(),
+        capacity: 0,
+    };
+    let clock = Clock::default();
+    let failures = run(|harness| uart::run(harness, &mut uart, &mut clock.clone()));
+    assert_eq!(
+        failures,
+        [
+            "uart::echoes_a_byte",
+            "uart::echoes_every_value",
+            "uart::echoes_a_line"
+        ]
+    );
+    // Each gave up after 10 ms.
+    assert!(clock.0.get() >= 30_000_000);
+}
+
+#[test]
+fn sram_passes_on_memory() {
+    let mut memory = vec![0u8; 64 * 1024 + 3];
+    let failures = run(|harness| sram::run(harness, 0x6000_0000, &mut memory[1..]));
+    assert!(failures.is_empty(), "{:?}", failures);
+}
+
+#[test]
+fn sram_fails_without_memory() {
+    let failures = run(|harness| sram::run(harness, 0x6000_0000, &mut []));
+    assert_eq!(failures, ["sram::has_memory"]);
+}
+
+#[test]
+fn flash_passes_on_nor_flash() {
+    let mut flash = Flash(RefCell::new(vec![0; 4096]));
+    let failures = run(|harness| flash::run(harness, &mut flash, 1024..3072));
+    assert!(failures.is_empty(), "{:?}", failures);
+    assert!(flash.0.borrow()[..1024].iter().all(|&byte| byte == 0));
+    assert!(flash.0.borrow()[3072..].iter().all(|&byte| byte == 0));
+}
+
+#[test]
+fn flash_fails_on_a_bad_region() {
+    let mut flash = Flash(RefCell::new(vec![0; 4096]));
+    let failures = run(|harness| flash::run(harness, &mut flash, 1024..1536));
+    assert_eq!(failures, ["flash::kv_store", "flash::kv_store_persists"]);
+}
+
+#[test]
+fn clocks_pass_on_matching_clocks() {
+    let clock = Clock::default();
+    let failures = run(|harness| {
+        clocks::run(
+            harness,
+            &mut clock.clone(),
+            &mut Ticks {
+                clock: clock.clone(),
+                frequency: 120_000_000,
+            },
+            &mut Ticks {
+                clock: clock.clone(),
+                frequency: 32_768,
+            },
+            5,
+        )
+    });
+    assert!(failures.is_empty(), "{:?}", failures);
+}
+
+#[test]
+fn clocks_fail_on_a_wrong_core_clock() {
+    let clock = Clock::default();
+    let failures = run(|harness| {
+        clocks::run(
+            harness,
+            &mut clock.clone(),
+            // Believed to run at 120 MHz
+            &mut Slow(Ticks {
+                clock: clock.clone(),
+                frequency: 100_000_000,
+            }),
+            &mut Ticks {
+                clock: clock.clone(),
+                frequency: 32_768,
+            },
+            5,
+        )
+    });
+    assert_eq!(
+        failures,
+        ["clocks::delay_ms", "clocks::delay_us", "clocks::core_clock"]
+    );
+}
+
+/// Reports the wrong frequency for its counter
+struct Slow(Ticks);
+
+impl Counter for Slow {
+    fn count(&mut self) -> u32 {
+        self.0.count()
+    }
+
+    fn frequency(&self) -> u32 {
+        120_000_000
+    }
+}
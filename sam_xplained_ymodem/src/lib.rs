@@ -0,0 +1,611 @@
+//! XMODEM/YMODEM receiver
+//!
+//! [`Receiver`] implements the receiving side of YMODEM batch transfers with
+//! 128 and 1024 byte blocks and CRC-16, as sent by `sb`, Tera Term or
+//! ExtraPuTTY.  Senders that answer the initial `C` with block 1 instead of
+//! the YMODEM file block are received as XMODEM-CRC (without a name or size,
+//! so the last block keeps its padding).
+//!
+//! The received file is streamed into a [`Sink`] block by block:
+//! [`FlashSink`] programs it into a region of a [`NorFlash`], e.g. the
+//! internal flash, and [`MemorySink`] copies it into a byte slice, e.g. over
+//! external SRAM.  The bytes travel over a [`Transport`]; [`SerialTransport`]
+//! runs it on an embedded-io serial port, timed with an embedded-hal delay.
+//!
+//! ```ignore
+//! let transport = SerialTransport::new(console, delay);
+//! let mut sink = FlashSink::new(&mut board.flash, 0x0008_0000..0x000F_C000);
+//! let file = Receiver::new(transport).receive(&mut sink)?;
+//! ```
+//!
+//! Corrupted or missing blocks are requested again, up to [`MAX_ERRORS`]
+//! times in a row; after that, on a [`Sink`] error or an out of sequence
+//! block the transfer is cancelled.
+#![no_std]
+
+use core::fmt;
+use core::ops::Range;
+use embedded_hal::delay::DelayNs;
+use embedded_io::{Read, ReadReady, Write};
+use embedded_storage::nor_flash::NorFlash;
+use sam_xplained_image::crc16;
+
+// Protocol bytes
+pub const SOH: u8 = 0x01;
+pub const STX: u8 = 0x02;
+pub const EOT: u8 = 0x04;
+pub const ACK: u8 = 0x06;
+pub const NAK: u8 = 0x15;
+pub const CAN: u8 = 0x18;
+/// Requests a transfer with CRC-16 instead of the XMODEM checksum
+pub const CRC_MODE: u8 = b'C';
+
+/// Time to wait for the start of a block (and between `C` requests)
+pub const PACKET_TIMEOUT_MS: u32 = 3_000;
+/// Time to wait for each further byte of a block
+pub const BYTE_TIMEOUT_MS: u32 = 1_000;
+/// Time without input after which the line counts as quiet again
+const PURGE_TIMEOUT_MS: u32 = 100;
+/// Failed attempts in a row after which the transfer is given up
+pub const MAX_ERRORS: u32 = 10;
+
+const MAX_NAME_LEN: usize = 64;
+const BLOCK_LEN: usize = 128;
+const LONG_BLOCK_LEN: usize = 1024;
+
+/// Byte stream with timeouts that the protocol runs on
+pub trait Transport {
+    type Error;
+
+    /// Reads a byte, waiting at most `timeout_ms`.  Returns `None` on timeout.
+    fn read(&mut self, timeout_ms: u32) -> Result<Option<u8>, Self::Error>;
+
+    /// Writes all of `bytes`.
+    fn write(&mut self, bytes: &[u8]) -> Result<(), Self::Error>;
+}
+
+/// Destination of a received file
+pub trait Sink {
+    type Error;
+
+    /// Starts a file.  Its size is only known for YMODEM transfers.
+    fn begin(&mut self, file: &FileInfo) -> Result<(), Self::Error>;
+
+    /// Appends the next part of the file.
+    fn write(&mut self, data: &[u8]) -> Result<(), Self::Error>;
+
+    /// Completes the file, after the sender ended it.
+    fn finish(&mut self) -> Result<(), Self::Error>;
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Error<T, S> {
+    /// The transport failed
+    Transport(T),
+    /// The sink failed
+    Sink(S),
+    /// The sender cancelled the transfer
+    Cancelled,
+    /// No block got through within [`MAX_ERRORS`] attempts
+    TooManyErrors,
+    /// The sender skipped a block
+    Sequence,
+    /// The sender ended the file short of the size it announced
+    Incomplete,
+    /// The sender ended the batch without sending a file
+    NoFile,
+}
+
+impl<T: fmt::Debug, S: fmt::Display> fmt::Display for Error<T, S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Transport(error) => write!(f, "transport error: {:?}", error),
+            Error::Sink(error) => error.fmt(f),
+            Error::Cancelled => f.write_str("transfer cancelled by the sender"),
+            Error::TooManyErrors => f.write_str("too many transfer errors"),
+            Error::Sequence => f.write_str("block out of sequence"),
+            Error::Incomplete => f.write_str("file ended short of its size"),
+            Error::NoFile => f.write_str("no file sent"),
+        }
+    }
+}
+
+/// Name and size of a file, as announced by a YMODEM sender
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FileInfo {
+    name: [u8; MAX_NAME_LEN],
+    name_len: usize,
+    /// Size in bytes; `None` for XMODEM transfers
+    pub size: Option<u32>,
+}
+
+impl FileInfo {
+    fn unnamed() -> Self {
+        FileInfo {
+            name: [0; MAX_NAME_LEN],
+            name_len: 0,
+            size: None,
+        }
+    }
+
+    /// Decodes the YMODEM file block: the name, a NUL, and the size in
+    /// decimal, followed by optional fields.  `None` for the empty block
+    /// ending a batch.
+    fn parse(block: &[u8]) -> Option<Self> {
+        let name_len = block.iter().position(|&byte| byte == 0)?;
+        if name_len == 0 {
+            return None;
+        }
+
+        let mut file = FileInfo::unnamed();
+        file.name_len = name_len.min(MAX_NAME_LEN);
+        file.name[..file.name_len].copy_from_slice(&block[..file.name_len]);
+
+        let digits = block[name_len + 1..]
+            .iter()
+            .take_while(|byte| byte.is_ascii_digit());
+        for &digit in digits {
+            let size = file.size.unwrap_or(0);
+            file.size = Some(
+                size.saturating_mul(10)
+                    .saturating_add((digit - b'0') as u32),
+            );
+        }
+        Some(file)
+    }
+
+    /// File name as sent; empty for XMODEM transfers
+    pub fn name(&self) -> &[u8] {
+        &self.name[..self.name_len]
+    }
+}
+
+/// A block read from the transport
+enum Packet {
+    Block {
+        number: u8,
+        len: usize,
+    },
+    Eot,
+    Cancel,
+    /// A timeout or a corrupted block
+    Error,
+}
+
+/// Receives files over a [`Transport`]
+pub struct Receiver<T> {
+    transport: T,
+    buffer: [u8; LONG_BLOCK_LEN + 2],
+}
+
+impl<T: Transport> Receiver<T> {
+    pub fn new(transport: T) -> Self {
+        Receiver {
+            transport,
+            buffer: [0; LONG_BLOCK_LEN + 2],
+        }
+    }
+
+    /// Gives back the transport.
+    pub fn into_inner(self) -> T {
+        self.transport
+    }
+
+    /// Receives a file into `sink`, returning its name and the number of
+    /// bytes written to the sink as its size.  Only the first file of a
+    /// YMODEM batch is received; the sender is stopped before any others.
+    pub fn receive<S: Sink>(
+        &mut self,
+        sink: &mut S,
+    ) -> Result<FileInfo, Error<T::Error, S::Error>> {
+        // Request the transfer until the first block arrives.
+        let mut errors = 0;
+        // The first XMODEM block is data, already in the buffer.
+        let (mut file, first_block) = loop {
+            self.send(CRC_MODE)?;
+            match self.read_packet().map_err(Error::Transport)? {
+                Packet::Block { number: 0, len } => {
+                    self.send(ACK)?;
+                    match FileInfo::parse(&self.buffer[..len]) {
+                        Some(file) => break (file, None),
+                        None => return Err(Error::NoFile),
+                    }
+                }
+                Packet::Block { number: 1, len } => break (FileInfo::unnamed(), Some(len)),
+                Packet::Cancel => return Err(Error::Cancelled),
+                _ => {
+                    errors += 1;
+                    if errors >= MAX_ERRORS {
+                        return Err(self.cancel(Error::TooManyErrors));
+                    }
+                }
+            }
+        };
+
+        if let Err(error) = sink.begin(&file) {
+            return Err(self.cancel(Error::Sink(error)));
+        }
+
+        let mut received = 0u32;
+        let mut expected = 1u8;
+        let mut errors = 0;
+        let mut eot = false;
+        match first_block {
+            Some(len) => {
+                received += self.store(sink, &file, received, len)?;
+                self.send(ACK)?;
+                expected = 2;
+            }
+            // Start the data blocks.
+            None => self.send(CRC_MODE)?,
+        }
+
+        loop {
+            match self.read_packet().map_err(Error::Transport)? {
+                Packet::Block { number, len } if number == expected => {
+                    received += self.store(sink, &file, received, len)?;
+                    self.send(ACK)?;
+                    expected = expected.wrapping_add(1);
+                    errors = 0;
+                    eot = false;
+                }
+                // Our ACK got lost, so the sender repeats the last block.
+                Packet::Block { number, .. } if number == expected.wrapping_sub(1) => {
+                    self.send(ACK)?;
+                }
+                Packet::Block { .. } => return Err(self.cancel(Error::Sequence)),
+                // The first EOT is NAKed, so a corrupted data block can't
+                // pass for the end of the file.
+                Packet::Eot if !eot => {
+                    self.send(NAK)?;
+                    eot = true;
+                }
+                Packet::Eot => {
+                    self.send(ACK)?;
+                    break;
+                }
+                Packet::Cancel => return Err(Error::Cancelled),
+                Packet::Error => {
+                    errors += 1;
+                    if errors >= MAX_ERRORS {
+                        return Err(self.cancel(Error::TooManyErrors));
+                    }
+                    self.purge().map_err(Error::Transport)?;
+                    self.send(NAK)?;
+                }
+            }
+        }
+
+        if let Err(error) = sink.finish() {
+            return Err(Error::Sink(error));
+        }
+        if file.size.is_some_and(|size| received < size) {
+            return Err(Error::Incomplete);
+        }
+        file.size = Some(received);
+
+        if first_block.is_none() {
+            self.end_batch()?;
+        }
+        Ok(file)
+    }
+
+    /// Receives the block ending the batch.  The file is already complete,
+    /// so errors here only end the session.
+    fn end_batch<S>(&mut self) -> Result<(), Error<T::Error, S>> {
+        for _ in 0..MAX_ERRORS {
+            self.send(CRC_MODE)?;
+            match self.read_packet().map_err(Error::Transport)? {
+                Packet::Block { number: 0, len } => {
+                    if FileInfo::parse(&self.buffer[..len]).is_some() {
+                        // Another file follows; stop the sender.
+                        self.transport.write(&[CAN, CAN]).ok();
+                    } else {
+                        self.send(ACK)?;
+                    }
+                    return Ok(());
+                }
+                // Our ACK of the EOT got lost.
+                Packet::Eot => self.send(ACK)?,
+                Packet::Cancel => return Ok(()),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Passes block data to the sink, without the padding past the announced
+    /// size.
+    fn store<S: Sink>(
+        &mut self,
+        sink: &mut S,
+        file: &FileInfo,
+        received: u32,
+        len: usize,
+    ) -> Result<u32, Error<T::Error, S::Error>> {
+        let len = match file.size {
+            Some(size) => len.min(size.saturating_sub(received) as usize),
+            None => len,
+        };
+        if let Err(error) = sink.write(&self.buffer[..len]) {
+            return Err(self.cancel(Error::Sink(error)));
+        }
+        Ok(len as u32)
+    }
+
+    fn read_packet(&mut self) -> Result<Packet, T::Error> {
+        let len = match self.transport.read(PACKET_TIMEOUT_MS)? {
+            Some(SOH) => BLOCK_LEN,
+            Some(STX) => LONG_BLOCK_LEN,
+            Some(EOT) => return Ok(Packet::Eot),
+            // Cancelling takes two CANs in a row.
+            Some(CAN) => {
+                return match self.transport.read(BYTE_TIMEOUT_MS)? {
+                    Some(CAN) => Ok(Packet::Cancel),
+                    _ => Ok(Packet::Error),
+                }
+            }
+            _ => return Ok(Packet::Error),
+        };
+
+        let (number, complement) = match (self.read_byte()?, self.read_byte()?) {
+            (Some(number), Some(complement)) => (number, complement),
+            _ => return Ok(Packet::Error),
+        };
+        for index in 0..len + 2 {
+            match self.read_byte()? {
+                Some(byte) => self.buffer[index] = byte,
+                None => return Ok(Packet::Error),
+            }
+        }
+
+        let crc = u16::from_be_bytes([self.buffer[len], self.buffer[len + 1]]);
+        if number != !complement || crc16(&self.buffer[..len]) != crc {
+            return Ok(Packet::Error);
+        }
+        Ok(Packet::Block { number, len })
+    }
+
+    fn read_byte(&mut self) -> Result<Option<u8>, T::Error> {
+        self.transport.read(BYTE_TIMEOUT_MS)
+    }
+
+    /// Drops input until the line is quiet, i.e. the rest of a bad block.
+    fn purge(&mut self) -> Result<(), T::Error> {
+        while self.transport.read(PURGE_TIMEOUT_MS)?.is_some() {}
+        Ok(())
+    }
+
+    fn send<S>(&mut self, byte: u8) -> Result<(), Error<T::Error, S>> {
+        self.transport.write(&[byte]).map_err(Error::Transport)
+    }
+
+    /// Tells the sender to stop, returning `error`.  The sender may hang up
+    /// as soon as it sees the first CAN, so write errors are ignored.
+    fn cancel<S>(&mut self, error: Error<T::Error, S>) -> Error<T::Error, S> {
+        self.transport.write(&[CAN, CAN]).ok();
+        error
+    }
+}
+
+/// [`Transport`] on an embedded-io serial port, polled with `delay` to time
+/// out
+pub struct SerialTransport<S, D> {
+    serial: S,
+    delay: D,
+}
+
+/// Polling interval, below the time a byte takes at 115200 baud
+const POLL_INTERVAL_US: u32 = 10;
+
+impl<S, D> SerialTransport<S, D>
+where
+    S: Read + ReadReady + Write,
+    D: DelayNs,
+{
+    pub fn new(serial: S, delay: D) -> Self {
+        SerialTransport { serial, delay }
+    }
+
+    /// Gives back the serial port and the delay.
+    pub fn into_inner(self) -> (S, D) {
+        (self.serial, self.delay)
+    }
+}
+
+impl<S, D> Transport for SerialTransport<S, D>
+where
+    S: Read + ReadReady + Write,
+    D: DelayNs,
+{
+    type Error = S::Error;
+
+    fn read(&mut self, timeout_ms: u32) -> Result<Option<u8>, Self::Error> {
+        let mut waited_us = 0;
+        loop {
+            if self.serial.read_ready()? {
+                let mut byte = [0];
+                self.serial
+                    .read_exact(&mut byte)
+                    .map_err(|error| match error {
+                        embedded_io::ReadExactError::Other(error) => error,
+                        embedded_io::ReadExactError::UnexpectedEof => unreachable!(),
+                    })?;
+                return Ok(Some(byte[0]));
+            }
+            if waited_us >= timeout_ms.saturating_mul(1_000) {
+                return Ok(None);
+            }
+            self.delay.delay_us(POLL_INTERVAL_US);
+            waited_us += POLL_INTERVAL_US;
+        }
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> Result<(), Self::Error> {
+        self.serial.write_all(bytes)?;
+        self.serial.flush()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SinkError<E = core::convert::Infallible> {
+    /// The file doesn't fit
+    Full,
+    /// The flash failed
+    Flash(E),
+}
+
+impl<E: fmt::Debug> fmt::Display for SinkError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SinkError::Full => f.write_str("file does not fit"),
+            SinkError::Flash(error) => write!(f, "flash error: {:?}", error),
+        }
+    }
+}
+
+/// [`Sink`] writing into a byte slice, e.g. over external SRAM
+pub struct MemorySink<'a> {
+    memory: &'a mut [u8],
+    len: usize,
+}
+
+impl<'a> MemorySink<'a> {
+    pub fn new(memory: &'a mut [u8]) -> Self {
+        MemorySink { memory, len: 0 }
+    }
+
+    /// The file received so far
+    pub fn data(&self) -> &[u8] {
+        &self.memory[..self.len]
+    }
+}
+
+impl Sink for MemorySink<'_> {
+    type Error = SinkError;
+
+    fn begin(&mut self, file: &FileInfo) -> Result<(), Self::Error> {
+        if file
+            .size
+            .is_some_and(|size| size as usize > self.memory.len())
+        {
+            return Err(SinkError::Full);
+        }
+        self.len = 0;
+        Ok(())
+    }
+
+    fn write(&mut self, data: &[u8]) -> Result<(), Self::Error> {
+        let end = self.len + data.len();
+        if end > self.memory.len() {
+            return Err(SinkError::Full);
+        }
+        self.memory[self.len..end].copy_from_slice(data);
+        self.len = end;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+/// Data is programmed through a buffer of this size, so it bounds the
+/// supported write size.
+const CHUNK_LEN: usize = 64;
+
+/// [`Sink`] programming a region of a [`NorFlash`]
+///
+/// Erase sectors are erased as the file reaches them, including any part of
+/// the first sector ahead of the region.  The region must start at a multiple
+/// of the write size, which must divide 64.  The last write is padded with
+/// `0xFF`.
+pub struct FlashSink<F> {
+    flash: F,
+    region: Range<u32>,
+    /// Bytes programmed so far
+    written: u32,
+    erased_to: u32,
+    buffer: [u8; CHUNK_LEN],
+    buffered: usize,
+}
+
+impl<F: NorFlash> FlashSink<F> {
+    pub fn new(flash: F, region: Range<u32>) -> Self {
+        FlashSink {
+            erased_to: region.start,
+            flash,
+            region,
+            written: 0,
+            buffer: [0xFF; CHUNK_LEN],
+            buffered: 0,
+        }
+    }
+
+    /// Gives back the flash.
+    pub fn into_inner(self) -> F {
+        self.flash
+    }
+
+    /// Programs the buffered data, padded to whole write units.
+    fn program(&mut self) -> Result<(), SinkError<F::Error>> {
+        let len = self.buffered.div_ceil(F::WRITE_SIZE) * F::WRITE_SIZE;
+        let start = self.region.start + self.written;
+        let end = start + len as u32;
+        if end > self.region.end {
+            return Err(SinkError::Full);
+        }
+
+        while self.erased_to < end {
+            let sector = self.erased_to - self.erased_to % F::ERASE_SIZE as u32;
+            let sector_end = sector + F::ERASE_SIZE as u32;
+            self.flash
+                .erase(sector, sector_end)
+                .map_err(SinkError::Flash)?;
+            self.erased_to = sector_end;
+        }
+
+        self.buffer[self.buffered..len].fill(0xFF);
+        self.flash
+            .write(start, &self.buffer[..len])
+            .map_err(SinkError::Flash)?;
+        self.written += len as u32;
+        self.buffered = 0;
+        Ok(())
+    }
+}
+
+impl<F: NorFlash> Sink for FlashSink<F> {
+    type Error = SinkError<F::Error>;
+
+    fn begin(&mut self, file: &FileInfo) -> Result<(), Self::Error> {
+        let capacity = self.region.end - self.region.start;
+        if file.size.is_some_and(|size| size > capacity) {
+            return Err(SinkError::Full);
+        }
+        self.written = 0;
+        self.erased_to = self.region.start;
+        self.buffered = 0;
+        Ok(())
+    }
+
+    fn write(&mut self, mut data: &[u8]) -> Result<(), Self::Error> {
+        while !data.is_empty() {
+            let count = data.len().min(CHUNK_LEN - self.buffered);
+            self.buffer[self.buffered..self.buffered + count].copy_from_slice(&data[..count]);
+            self.buffered += count;
+            data = &data[count..];
+            if self.buffered == CHUNK_LEN {
+                self.program()?;
+            }
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), Self::Error> {
+        if self.buffered > 0 {
+            self.program()?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,555 @@
+//! Runs the receiver against a sender on another thread, connected through an
+//! in-memory loopback transport.
+
+use std::sync::mpsc::{channel, Receiver as Channel, RecvTimeoutError, Sender};
+use std::thread;
+use std::time::Duration;
+
+use embedded_storage::nor_flash::{
+    ErrorType, NorFlash, NorFlashError, NorFlashErrorKind, ReadNorFlash,
+};
+use sam_xplained_image::crc16;
+use sam_xplained_ymodem::*;
+
+/// The receiver's timeouts run this much faster over the loopback.
+const TIME_SCALE: u64 = 100;
+
+/// The other end hung up.
+#[derive(Debug, PartialEq)]
+struct Disconnected;
+
+/// One end of an in-memory byte pipe
+struct Loopback {
+    rx: Channel<u8>,
+    tx: Sender<u8>,
+}
+
+fn loopback() -> (Loopback, Loopback) {
+    let (a_tx, b_rx) = channel();
+    let (b_tx, a_rx) = channel();
+    (
+        Loopback { rx: a_rx, tx: a_tx },
+        Loopback { rx: b_rx, tx: b_tx },
+    )
+}
+
+impl Transport for Loopback {
+    type Error = Disconnected;
+
+    fn read(&mut self, timeout_ms: u32) -> Result<Option<u8>, Disconnected> {
+        let timeout = Duration::from_millis(timeout_ms as u64 / TIME_SCALE);
+        match self.rx.recv_timeout(timeout) {
+            Ok(byte) => Ok(Some(byte)),
+            Err(RecvTimeoutError::Timeout) => Ok(None),
+            Err(RecvTimeoutError::Disconnected) => Err(Disconnected),
+        }
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> Result<(), Disconnected> {
+        for &byte in bytes {
+            self.tx.send(byte).map_err(|_| Disconnected)?;
+        }
+        Ok(())
+    }
+}
+
+//
+// Sender
+//
+
+/// Misbehaviour of the sender, by data block number (starting at 1)
+#[derive(Clone, Default)]
+struct Faults {
+    /// Send these blocks with a bad CRC the first time
+    corrupt: Vec<u32>,
+    /// Send only half of these blocks the first time, then stall
+    stall: Vec<u32>,
+    /// Ignore the first ACK of these blocks and send them again
+    lose_ack: Vec<u32>,
+    /// Leave out this block
+    skip: Option<u32>,
+    /// Cancel instead of sending this block
+    cancel: Option<u32>,
+    /// Start with block 1, without the YMODEM file block
+    xmodem: bool,
+    /// Announce this size instead of the real one
+    announce: Option<usize>,
+}
+
+/// What the sender saw
+#[derive(Debug, PartialEq)]
+enum Outcome {
+    Done,
+    /// The receiver cancelled the transfer
+    Cancelled,
+    /// The receiver stopped answering
+    NoResponse,
+}
+
+struct YmodemSender {
+    port: Loopback,
+    faults: Faults,
+    /// NAKs received
+    naks: u32,
+}
+
+impl YmodemSender {
+    fn run(
+        port: Loopback,
+        name: &str,
+        data: Vec<u8>,
+        faults: Faults,
+    ) -> thread::JoinHandle<(Outcome, u32)> {
+        let name = name.to_string();
+        thread::spawn(move || {
+            let mut sender = YmodemSender {
+                port,
+                faults,
+                naks: 0,
+            };
+            let outcome = sender.send(&name, &data);
+            (outcome, sender.naks)
+        })
+    }
+
+    fn read(&mut self) -> Option<u8> {
+        self.port.rx.recv_timeout(Duration::from_secs(2)).ok()
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        self.port.write(bytes).ok();
+    }
+
+    /// Waits for the receiver to ask for CRC mode.
+    fn wait_for_c(&mut self) -> Result<(), Outcome> {
+        loop {
+            match self.read() {
+                Some(CRC_MODE) => return Ok(()),
+                Some(CAN) => return Err(Outcome::Cancelled),
+                Some(_) => {}
+                None => return Err(Outcome::NoResponse),
+            }
+        }
+    }
+
+    fn packet(number: u8, data: &[u8]) -> Vec<u8> {
+        let len = if data.len() <= 128 { 128 } else { 1024 };
+        let mut packet = vec![if len == 128 { SOH } else { STX }, number, !number];
+        let mut payload = data.to_vec();
+        payload.resize(len, 0x1A);
+        let crc = crc16(&payload);
+        packet.extend_from_slice(&payload);
+        packet.extend_from_slice(&crc.to_be_bytes());
+        packet
+    }
+
+    /// Sends a packet until it is ACKed.
+    fn send_packet(&mut self, packet: &[u8]) -> Result<(), Outcome> {
+        loop {
+            self.write(packet);
+            match self.read() {
+                Some(ACK) => return Ok(()),
+                Some(NAK) => self.naks += 1,
+                Some(CAN) => return Err(Outcome::Cancelled),
+                Some(_) => {}
+                None => return Err(Outcome::NoResponse),
+            }
+        }
+    }
+
+    fn send(&mut self, name: &str, data: &[u8]) -> Outcome {
+        match self.send_file(name, data) {
+            Ok(()) => Outcome::Done,
+            Err(outcome) => outcome,
+        }
+    }
+
+    fn send_file(&mut self, name: &str, data: &[u8]) -> Result<(), Outcome> {
+        self.wait_for_c()?;
+        let block_len = if self.faults.xmodem { 128 } else { 1024 };
+
+        if !self.faults.xmodem {
+            let size = self.faults.announce.unwrap_or(data.len());
+            let mut info = name.as_bytes().to_vec();
+            info.push(0);
+            info.extend_from_slice(format!("{} 0 100644", size).as_bytes());
+            info.push(0);
+            self.send_packet(&Self::packet(0, &info))?;
+            self.wait_for_c()?;
+        }
+
+        let mut number = 1u32;
+        for chunk in data.chunks(block_len) {
+            if self.faults.cancel == Some(number) {
+                self.write(&[CAN, CAN]);
+                return Err(Outcome::Cancelled);
+            }
+            let packet = Self::packet(number as u8, chunk);
+            if self.faults.skip == Some(number) {
+                number += 1;
+                continue;
+            }
+
+            if self.faults.corrupt.contains(&number) {
+                let mut corrupted = packet.clone();
+                corrupted[10] ^= 0xFF;
+                self.write(&corrupted);
+                match self.read() {
+                    Some(NAK) => self.naks += 1,
+                    Some(CAN) => return Err(Outcome::Cancelled),
+                    _ => panic!("corrupted block {} not NAKed", number),
+                }
+            }
+            if self.faults.stall.contains(&number) {
+                self.write(&packet[..packet.len() / 2]);
+                match self.read() {
+                    Some(NAK) => self.naks += 1,
+                    Some(CAN) => return Err(Outcome::Cancelled),
+                    _ => panic!("stalled block {} not NAKed", number),
+                }
+            }
+            if self.faults.lose_ack.contains(&number) {
+                self.write(&packet);
+                assert_eq!(self.read(), Some(ACK));
+            }
+
+            self.send_packet(&packet)?;
+            number += 1;
+        }
+
+        // The receiver NAKs the first EOT.
+        self.write(&[EOT]);
+        match self.read() {
+            Some(NAK) => {}
+            Some(CAN) => return Err(Outcome::Cancelled),
+            other => panic!("first EOT answered with {:?}", other),
+        }
+        self.send_packet(&[EOT])?;
+
+        if !self.faults.xmodem {
+            self.wait_for_c()?;
+            self.send_packet(&Self::packet(0, &[]))?;
+        }
+        Ok(())
+    }
+}
+
+fn test_data(len: usize) -> Vec<u8> {
+    (0..len)
+        .map(|index| (index * 7 + index / 251) as u8)
+        .collect()
+}
+
+/// Result of the receiver, the data it received, and the sender's outcome
+/// and NAK count
+type Transfer = (
+    Result<FileInfo, Error<Disconnected, SinkError>>,
+    Vec<u8>,
+    Outcome,
+    u32,
+);
+
+/// Receives `data` sent with `faults` into a memory sink.
+fn transfer(data: &[u8], capacity: usize, faults: Faults) -> Transfer {
+    let (device, host) = loopback();
+    let sender = YmodemSender::run(host, "firmware.bin", data.to_vec(), faults);
+
+    let mut memory = vec![0u8; capacity];
+    let mut sink = MemorySink::new(&mut memory);
+    let result = Receiver::new(device).receive(&mut sink);
+    let received = sink.data().to_vec();
+
+    let (outcome, naks) = sender.join().unwrap();
+    (result, received, outcome, naks)
+}
+
+#[test]
+fn receives_a_file() {
+    let data = test_data(5000);
+    let (result, received, outcome, naks) = transfer(&data, 8192, Faults::default());
+
+    let file = result.unwrap();
+    assert_eq!(file.name(), b"firmware.bin");
+    assert_eq!(file.size, Some(5000));
+    assert_eq!(received, data);
+    assert_eq!(outcome, Outcome::Done);
+    assert_eq!(naks, 0);
+}
+
+#[test]
+fn receives_short_and_empty_files() {
+    for len in [0, 1, 128, 129, 1024, 1025] {
+        let data = test_data(len);
+        let (result, received, outcome, _) = transfer(&data, 2048, Faults::default());
+        assert_eq!(result.unwrap().size, Some(len as u32), "length {}", len);
+        assert_eq!(received, data, "length {}", len);
+        assert_eq!(outcome, Outcome::Done);
+    }
+}
+
+#[test]
+fn xmodem_keeps_the_padding() {
+    let data = test_data(300);
+    let faults = Faults {
+        xmodem: true,
+        ..Faults::default()
+    };
+    let (result, received, outcome, _) = transfer(&data, 1024, faults);
+
+    let file = result.unwrap();
+    assert_eq!(file.name(), b"");
+    assert_eq!(file.size, Some(384));
+    assert_eq!(&received[..300], &data[..]);
+    assert!(received[300..].iter().all(|&byte| byte == 0x1A));
+    assert_eq!(outcome, Outcome::Done);
+}
+
+#[test]
+fn corrupted_blocks_are_sent_again() {
+    let data = test_data(4096);
+    let faults = Faults {
+        corrupt: vec![1, 3],
+        ..Faults::default()
+    };
+    let (result, received, outcome, naks) = transfer(&data, 4096, faults);
+
+    result.unwrap();
+    assert_eq!(received, data);
+    assert_eq!(outcome, Outcome::Done);
+    assert_eq!(naks, 2);
+}
+
+#[test]
+fn stalled_blocks_time_out_and_are_sent_again() {
+    let data = test_data(3000);
+    let faults = Faults {
+        stall: vec![2],
+        ..Faults::default()
+    };
+    let (result, received, outcome, naks) = transfer(&data, 4096, faults);
+
+    result.unwrap();
+    assert_eq!(received, data);
+    assert_eq!(outcome, Outcome::Done);
+    assert_eq!(naks, 1);
+}
+
+#[test]
+fn repeated_blocks_are_acknowledged_but_not_stored_again() {
+    let data = test_data(3000);
+    let faults = Faults {
+        lose_ack: vec![1, 3],
+        ..Faults::default()
+    };
+    let (result, received, outcome, _) = transfer(&data, 4096, faults);
+
+    result.unwrap();
+    assert_eq!(received, data);
+    assert_eq!(outcome, Outcome::Done);
+}
+
+#[test]
+fn skipped_blocks_cancel_the_transfer() {
+    let data = test_data(4096);
+    let faults = Faults {
+        skip: Some(2),
+        ..Faults::default()
+    };
+    let (result, _, outcome, _) = transfer(&data, 4096, faults);
+
+    assert_eq!(result, Err(Error::Sequence));
+    assert_eq!(outcome, Outcome::Cancelled);
+}
+
+#[test]
+fn the_sender_can_cancel() {
+    let data = test_data(4096);
+    let faults = Faults {
+        cancel: Some(3),
+        ..Faults::default()
+    };
+    let (result, _, outcome, _) = transfer(&data, 4096, faults);
+
+    assert_eq!(result, Err(Error::Cancelled));
+    assert_eq!(outcome, Outcome::Cancelled);
+}
+
+#[test]
+fn files_too_large_for_the_sink_are_refused() {
+    let data = test_data(4096);
+    let (result, _, outcome, _) = transfer(&data, 2048, Faults::default());
+    assert_eq!(result, Err(Error::Sink(SinkError::Full)));
+    assert_eq!(outcome, Outcome::Cancelled);
+
+    // Data past the announced size is dropped.
+    let faults = Faults {
+        announce: Some(2048),
+        ..Faults::default()
+    };
+    let (result, _, outcome, _) = transfer(&data, 2048, faults);
+    assert_eq!(result.unwrap().size, Some(2048));
+    assert_eq!(outcome, Outcome::Done);
+}
+
+#[test]
+fn files_ended_early_are_incomplete() {
+    let data = test_data(2000);
+    let faults = Faults {
+        announce: Some(3000),
+        ..Faults::default()
+    };
+    let (result, received, _, _) = transfer(&data, 4096, faults);
+
+    assert_eq!(result, Err(Error::Incomplete));
+    // The padding of the last block counts towards the announced size.
+    assert_eq!(received.len(), 2048);
+    assert!(received[..2000] == data[..]);
+}
+
+#[test]
+fn gives_up_on_a_silent_sender() {
+    let (mut device, mut host) = loopback();
+    let mut memory = [0u8; 128];
+    let mut sink = MemorySink::new(&mut memory);
+
+    let result = Receiver::new(&mut device).receive(&mut sink);
+    assert_eq!(result, Err(Error::TooManyErrors));
+
+    let mut requests = Vec::new();
+    while let Ok(Some(byte)) = host.read(0) {
+        requests.push(byte);
+    }
+    let mut expected = vec![CRC_MODE; MAX_ERRORS as usize];
+    expected.extend_from_slice(&[CAN, CAN]);
+    assert_eq!(requests, expected);
+}
+
+impl Transport for &mut Loopback {
+    type Error = Disconnected;
+
+    fn read(&mut self, timeout_ms: u32) -> Result<Option<u8>, Disconnected> {
+        (**self).read(timeout_ms)
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> Result<(), Disconnected> {
+        (**self).write(bytes)
+    }
+}
+
+//
+// Flash sink
+//
+
+/// RAM-backed NOR flash that only clears bits when programming
+struct FakeFlash {
+    memory: Vec<u8>,
+    erases: Vec<u32>,
+}
+
+impl FakeFlash {
+    fn new(size: usize) -> Self {
+        FakeFlash {
+            memory: vec![0; size],
+            erases: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+struct FlashError(NorFlashErrorKind);
+
+impl NorFlashError for FlashError {
+    fn kind(&self) -> NorFlashErrorKind {
+        self.0
+    }
+}
+
+impl ErrorType for FakeFlash {
+    type Error = FlashError;
+}
+
+impl ReadNorFlash for FakeFlash {
+    const READ_SIZE: usize = 1;
+
+    fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), FlashError> {
+        let offset = offset as usize;
+        bytes.copy_from_slice(&self.memory[offset..offset + bytes.len()]);
+        Ok(())
+    }
+
+    fn capacity(&self) -> usize {
+        self.memory.len()
+    }
+}
+
+impl NorFlash for FakeFlash {
+    const WRITE_SIZE: usize = 16;
+    const ERASE_SIZE: usize = 4096;
+
+    fn erase(&mut self, from: u32, to: u32) -> Result<(), FlashError> {
+        if !from.is_multiple_of(4096) || !to.is_multiple_of(4096) {
+            return Err(FlashError(NorFlashErrorKind::NotAligned));
+        }
+        self.memory[from as usize..to as usize].fill(0xFF);
+        self.erases.push(from);
+        Ok(())
+    }
+
+    fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), FlashError> {
+        if !offset.is_multiple_of(16) || !bytes.len().is_multiple_of(16) {
+            return Err(FlashError(NorFlashErrorKind::NotAligned));
+        }
+        let offset = offset as usize;
+        for (cell, &byte) in self.memory[offset..offset + bytes.len()]
+            .iter_mut()
+            .zip(bytes)
+        {
+            assert_eq!(*cell, 0xFF, "programmed without an erase");
+            *cell = byte;
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn streams_into_flash() {
+    let data = test_data(10_000);
+    let (device, host) = loopback();
+    let sender = YmodemSender::run(host, "firmware.bin", data.clone(), Faults::default());
+
+    // The region starts behind a header page inside the first sector.
+    let mut flash = FakeFlash::new(0x10000);
+    let mut sink = FlashSink::new(&mut flash, 0x4200..0xC000);
+    let file = Receiver::new(device).receive(&mut sink).unwrap();
+    assert_eq!(sender.join().unwrap().0, Outcome::Done);
+    assert_eq!(file.size, Some(10_000));
+
+    assert_eq!(flash.erases, vec![0x4000, 0x5000, 0x6000]);
+    assert!(flash.memory[0x4000..0x4200]
+        .iter()
+        .all(|&byte| byte == 0xFF));
+    assert_eq!(&flash.memory[0x4200..0x4200 + 10_000], &data[..]);
+    assert!(flash.memory[0x4200 + 10_000..0x7000]
+        .iter()
+        .all(|&byte| byte == 0xFF));
+    assert!(flash.memory[0x7000..].iter().all(|&byte| byte == 0));
+}
+
+#[test]
+fn flash_sink_refuses_files_past_its_region() {
+    let data = test_data(5000);
+    let (device, host) = loopback();
+    let sender = YmodemSender::run(host, "firmware.bin", data, Faults::default());
+
+    let mut flash = FakeFlash::new(0x10000);
+    let mut sink = FlashSink::new(&mut flash, 0x1000..0x2000);
+    let result = Receiver::new(device).receive(&mut sink);
+    assert_eq!(result, Err(Error::Sink(SinkError::Full)));
+    assert_eq!(sender.join().unwrap().0, Outcome::Cancelled);
+    assert!(flash.erases.is_empty());
+}
+
+#[test]
+fn crc16_matches_the_xmodem_check_value() {
+    assert_eq!(crc16(b"123456789"), 0x31C3);
+}